@@ -0,0 +1 @@
+cat <<<"$(jq '.canisters.cycles_ledger={"type":"motoko","main":"cycles_ledger.mo"} | .defaults.cycles.ledger.local="cycles_ledger"' dfx.json)" >dfx.json
@@ -0,0 +1 @@
+cat <<<"$(jq '.canisters.icrc1_ledger={"type":"motoko","main":"icrc1_ledger.mo"}' dfx.json)" >dfx.json
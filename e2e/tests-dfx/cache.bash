@@ -0,0 +1,47 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+
+    VERSION=$(dfx --version | awk '{ print $2 }')
+    dfx cache install
+}
+
+teardown() {
+    standard_teardown
+}
+
+@test "cache du shows the size of each version and binary" {
+    assert_command dfx cache du
+    assert_match "^$VERSION "
+    assert_match "  dfx "
+    assert_match "  moc "
+    assert_match "total"
+}
+
+@test "cache prune keeps the running version and deletes unused ones" {
+    CACHE_ROOT="${DFX_CACHE_ROOT:-$HOME}/.cache/dfinity/versions"
+    cp -R "$CACHE_ROOT/$VERSION" "$CACHE_ROOT/0.1.0"
+
+    assert_command dfx cache prune --dry-run
+    assert_match "Keeping $VERSION \(running\)"
+    assert_match "Would delete 0.1.0"
+    assert_file_exists "$CACHE_ROOT/0.1.0/dfx"
+
+    assert_command dfx cache prune
+    assert_match "Deleted 0.1.0"
+    assert_file_not_exists "$CACHE_ROOT/0.1.0/dfx"
+    assert_file_exists "$CACHE_ROOT/$VERSION/dfx"
+}
+
+@test "cache prune keeps the latest versions" {
+    CACHE_ROOT="${DFX_CACHE_ROOT:-$HOME}/.cache/dfinity/versions"
+    cp -R "$CACHE_ROOT/$VERSION" "$CACHE_ROOT/0.1.0"
+    cp -R "$CACHE_ROOT/$VERSION" "$CACHE_ROOT/0.0.1"
+
+    assert_command dfx cache prune --keep-latest 2
+    assert_match "Keeping 0.1.0 \(one of the 2 latest versions\)"
+    assert_match "Deleted 0.0.1"
+}
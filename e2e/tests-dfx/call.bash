@@ -26,15 +26,57 @@ teardown() {
     CANISTER_ID=$(dfx canister id hello)
     rm .dfx/local/canister_ids.json
 
-    # if no candid file known, then no field names
+    # if no candid file known, the candid:service metadata of the canister is used
     assert_command dfx canister call "$CANISTER_ID" make_struct '("A", "B")'
-    assert_eq '(record { 99 = "A"; 100 = "B" })'
+    assert_eq '(record { c = "A"; d = "B" })'
 
     # if passing the candid file, field names available
     assert_command dfx canister call --candid .dfx/local/canisters/hello/hello.did "$CANISTER_ID" make_struct '("A", "B")'
     assert_eq '(record { c = "A"; d = "B" })'
 }
 
+@test "call uses candid:service metadata for canisters outside the project" {
+    install_asset greet
+    dfx_start
+    dfx deploy
+    CANISTER_ID=$(dfx canister id hello)
+    rm .dfx/local/canister_ids.json
+
+    # text argument without quotes is only accepted if the method type is known
+    assert_command dfx canister call "$CANISTER_ID" greet Names
+    assert_match '("Hello, Names!")'
+}
+
+@test "canister metadata prints a public custom section" {
+    install_asset greet
+    dfx_start
+    dfx deploy
+    assert_command dfx canister metadata hello candid:service
+    assert_match "greet: \(text\) -> \(text\)"
+
+    assert_command_fail dfx canister metadata hello nonexistent
+}
+
+@test "call --output json" {
+    install_asset call
+    dfx_start
+    dfx deploy
+    assert_command dfx canister call hello make_struct '("A", "B")' --output json
+    assert_eq '{
+  "c": "A",
+  "d": "B"
+}'
+
+    assert_command_fail dfx canister call hello make_struct '("A", "B")' --blob-format base64
+    assert_match "requires"
+    assert_command_fail dfx canister call hello make_struct '("A", "B")' --output pp --blob-format base64
+    assert_match "can only be used with --output json"
+
+    # the combination is rejected before the canister is even looked up
+    assert_command_fail dfx canister call nonexistent make_struct '("A", "B")' --output pp --blob-format base64
+    assert_match "can only be used with --output json"
+}
+
 @test "call subcommand accepts canister identifier as canister name" {
     install_asset greet
     dfx_start
@@ -230,3 +230,47 @@ teardown() {
     assert_command dfx --identity alice canister create --all --controller alice --controller bob
 }
 
+
+@test "create --subnet creates the canister on the replica of the subnet" {
+    cat <<<"$(jq '.defaults.replica.subnets={"sys":{"type":"system"}}' dfx.json)" >dfx.json
+    dfx_start
+
+    assert_command_fail dfx canister create e2e_project --subnet nope
+    assert_match "Subnet 'nope' is not configured"
+
+    assert_command dfx canister create e2e_project --subnet sys
+    assert_command jq -r .e2e_project.local .dfx/local/canister_subnets.json
+    assert_eq "sys"
+
+    dfx build e2e_project
+    assert_command dfx canister install e2e_project
+    assert_command dfx canister call e2e_project greet '("subnet")'
+    assert_match "Hello, subnet!"
+
+    # commands for all canisters reach the canister at the replica of its subnet too
+    assert_command dfx canister install --all --mode upgrade
+    assert_command dfx canister call e2e_project greet '("again")'
+    assert_match "Hello, again!"
+}
+
+@test "deploy installs a canister created with --subnet on the replica of the subnet" {
+    cat <<<"$(jq '.defaults.replica.subnets={"sys":{"type":"system"}}' dfx.json)" >dfx.json
+    dfx_start
+
+    assert_command dfx canister create e2e_project --subnet sys
+    SUBNET_ID=$(dfx canister id e2e_project)
+    # the main replica hands out ids from the same range, to its wallet and canisters
+    assert_command dfx canister create e2e_project_assets
+
+    assert_command dfx deploy
+    assert_command dfx canister id e2e_project
+    assert_eq "$SUBNET_ID"
+    assert_command dfx canister call e2e_project greet '("subnet")'
+    assert_match "Hello, subnet!"
+    assert_command dfx canister info e2e_project
+    assert_match "Module hash: 0x"
+
+    # deploying again upgrades the canister on the subnet
+    assert_command dfx deploy e2e_project
+    assert_match "Upgrading code for canister e2e_project"
+}
@@ -0,0 +1,65 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+
+    dfx_new hello
+}
+
+teardown() {
+    dfx_stop
+
+    standard_teardown
+}
+
+deposit_to_identity() {
+    dfx canister --wallet "$(dfx identity get-wallet)" call --with-cycles "$1" cycles_ledger deposit \
+        "(record { to = record { owner = principal \"$(dfx identity get-principal)\"; subaccount = null }; memo = null })"
+}
+
+@test "canister create and deposit-cycles spend the identity's cycles" {
+    install_asset cycles_ledger
+    dfx_start
+    assert_command dfx deploy cycles_ledger
+    assert_command deposit_to_identity 5000000000000
+    assert_command dfx token --ledger cycles_ledger balance
+    assert_eq "5 TCYCLES"
+
+    assert_command dfx canister create hello --cycles-source identity
+    assert_command dfx canister info hello
+    assert_match "Controllers: $(dfx identity get-principal)"
+    # 4T cycles for the canister and the ledger's fee of 0.0001T.
+    assert_command dfx token --ledger cycles_ledger balance
+    assert_eq "0.9999 TCYCLES"
+
+    assert_command dfx canister deposit-cycles 500000000000 hello --cycles-source identity
+    assert_command dfx token --ledger cycles_ledger balance
+    assert_eq "0.4998 TCYCLES"
+
+    assert_command_fail dfx canister deposit-cycles 500000000000 hello --cycles-source identity
+    assert_match "Insufficient cycles"
+}
+
+@test "defaults.cycles.source selects the cycles source of a network" {
+    install_asset cycles_ledger
+    dfx_start
+    assert_command dfx deploy cycles_ledger
+    assert_command deposit_to_identity 5000000000000
+    cat <<<"$(jq '.defaults.cycles.source.local="identity" | .canisters.hello2=.canisters.hello' dfx.json)" >dfx.json
+
+    assert_command dfx deploy hello
+    assert_command dfx token --ledger cycles_ledger balance
+    assert_eq "0.9999 TCYCLES"
+
+    assert_command dfx canister create hello2 --cycles-source wallet
+    assert_command dfx token --ledger cycles_ledger balance
+    assert_eq "0.9999 TCYCLES"
+}
+
+@test "the identity cycles source needs a cycles ledger" {
+    dfx_start
+    assert_command_fail dfx canister create hello --cycles-source identity
+    assert_match "No cycles ledger is configured for network 'local'"
+}
@@ -43,3 +43,31 @@ teardown() {
     assert_not_match "Controllers: ($WALLET $PRINCIPAL|$PRINCIPAL $WALLET)"
     assert_match "Controllers: $PRINCIPAL"
 }
+
+@test "deploy fails when the estimated cycles cost exceeds the budget" {
+    dfx_start
+    cat <<<"$(jq '.defaults.cycles.max_cycles_per_deploy.local=1000000000000' dfx.json)" >dfx.json
+
+    assert_command_fail dfx deploy hello
+    assert_match "create hello: 4000000000000 cycles"
+    assert_match "install hello \(not built yet"
+    assert_match "exceeds the budget of 1000000000000 cycles"
+    assert_command_fail dfx canister id hello
+
+    assert_command dfx deploy hello --budget 5000000000000
+    assert_command dfx canister id hello
+
+    # The canister exists now, only installing it is estimated.
+    assert_command dfx deploy hello --upgrade-unchanged
+    assert_match "install hello \([0-9]* bytes\)"
+    assert_not_match "create hello"
+}
+
+@test "deploy --yes deploys over budget" {
+    dfx_start
+    cat <<<"$(jq '.defaults.cycles.max_cycles_per_deploy.local=1' dfx.json)" >dfx.json
+
+    assert_command dfx deploy hello --yes
+    assert_match "exceeds the budget of 1 cycles"
+    assert_command dfx canister id hello
+}
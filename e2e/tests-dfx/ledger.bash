@@ -0,0 +1,38 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+}
+
+teardown() {
+    standard_teardown
+}
+
+@test "ledger account-id accepts named subaccounts" {
+    assert_command dfx ledger subaccounts add treasury
+    assert_command dfx ledger subaccounts add payroll --subaccount 0000000000000000000000000000000000000000000000000000000000000007
+    assert_command_fail dfx ledger subaccounts add treasury
+    assert_match "already exists"
+    assert_command_fail dfx ledger subaccounts add alias --subaccount treasury
+    assert_match "not a valid hex string"
+
+    assert_command dfx ledger account-id --subaccount 0000000000000000000000000000000000000000000000000000000000000001
+    TREASURY="$stdout"
+    assert_command dfx ledger account-id --subaccount treasury
+    assert_eq "$TREASURY"
+
+    assert_command dfx ledger account-id --subaccount 0000000000000000000000000000000000000000000000000000000000000007
+    PAYROLL="$stdout"
+    assert_command dfx ledger account-id --subaccount payroll
+    assert_eq "$PAYROLL"
+
+    assert_command_fail dfx ledger account-id --of-principal aaaaa-aa --subaccount treasury
+    assert_match "not a subaccount in hex"
+    assert_command dfx ledger account-id --of-principal aaaaa-aa --subaccount 0000000000000000000000000000000000000000000000000000000000000001
+
+    assert_command dfx ledger subaccounts remove payroll
+    assert_command_fail dfx ledger account-id --subaccount payroll
+    assert_match "neither a named subaccount nor a subaccount in hex"
+}
@@ -0,0 +1,89 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+
+    dfx_new
+}
+
+teardown() {
+    dfx_stop
+
+    standard_teardown
+}
+
+@test "dfx start --with-ledger runs the ledger on a system subnet" {
+    [ "$USE_IC_REF" ] && skip "the ledger is only installed on the replica"
+
+    assert_command dfx start --clean --background --with-ledger
+    assert_match "Installed the ledger at ryjl3-tyaaa-aaaaa-aaaba-cai and the cycles minting canister at rkp4c-7iaaa-aaaaa-aaaca-cai."
+    assert_command ps -o args= -C ic-starter
+    assert_match "--subnet-type system"
+
+    assert_command dfx ledger balance
+    assert_eq "1000.00000000 ICP"
+
+    # the wasms were downloaded into the cache once
+    assert_command dfx nns install
+    assert_match "The ledger is already installed"
+    assert_not_match "Downloading"
+}
+
+@test "nns install warns on a replica that is not a system subnet" {
+    [ "$USE_IC_REF" ] && skip "the ledger is only installed on the replica"
+
+    dfx_start
+    assert_command dfx nns install
+    assert_match "not a system subnet"
+    assert_match "Installed the ledger at ryjl3-tyaaa-aaaaa-aaaba-cai and the cycles minting canister at rkp4c-7iaaa-aaaaa-aaaca-cai."
+
+    assert_command dfx ledger balance
+    assert_eq "1000.00000000 ICP"
+
+    CACHE_DIR=$(dfx cache show)
+    assert_command dfx nns install --ledger-wasm "$CACHE_DIR/ledger.wasm" --cycles-minting-wasm "$CACHE_DIR/cycles-minting-canister.wasm"
+    assert_match "The ledger is already installed"
+}
+
+@test "ledger block and history show a transfer" {
+    [ "$USE_IC_REF" ] && skip "the ledger is only installed on the replica"
+
+    dfx start --clean --background --with-ledger
+    assert_command dfx ledger transfer --amount 1.5 --memo 7 03e3d86f29a069c6f2c5c48e01bc084e4ea18ad02b0eec8fccadf4487183c223
+    HEIGHT=$(echo "$stdout" | sed -n 's/Transfer sent at BlockHeight: //p')
+
+    assert_command dfx ledger block "$HEIGHT"
+    assert_match "Operation: transfer"
+    assert_match "To: 03e3d86f29a069c6f2c5c48e01bc084e4ea18ad02b0eec8fccadf4487183c223"
+    assert_match "Amount: 1.50000000 ICP"
+
+    assert_command dfx ledger history --limit 1 --output json
+    assert_match '"amount_e8s": 150000000'
+    assert_match '"memo": 7'
+
+    # the window of scanned blocks
+    assert_command dfx ledger history --max-blocks 1 --output json
+    assert_match '"memo": 7'
+    assert_match "Pass --start $((HEIGHT - 1)) to scan older blocks"
+    assert_command dfx ledger history --start $((HEIGHT - 1)) --output json
+    assert_not_match '"memo": 7'
+    assert_not_match "to scan older blocks"
+}
+
+@test "ledger pending lists and discards journal entries" {
+    assert_command dfx ledger pending
+    assert_eq "No pending ledger operations."
+
+    dfx identity get-principal
+    cat > "$DFX_CONFIG_ROOT/.config/dfx/identity/default/ledger-journal.json" <<JOURNAL
+[{"network":"local","ledger":"ryjl3-tyaaa-aaaaa-aaaba-cai","operation":{"kind":"top-up","canister":"rkp4c-7iaaa-aaaaa-aaaca-cai"},"memo":1347768404,"created_at_time":42,"amount_e8s":150000000,"fee_e8s":10000,"to":"03e3d86f29a069c6f2c5c48e01bc084e4ea18ad02b0eec8fccadf4487183c223","max_fee_e8s":10000,"block_height":7}]
+JOURNAL
+    assert_command dfx ledger pending
+    assert_match "42 top-up of rkp4c-7iaaa-aaaaa-aaaca-cai of 1.50000000 ICP, recorded at BlockHeight 7"
+
+    assert_command dfx ledger pending --discard 42
+    assert_command dfx ledger pending
+    assert_eq "No pending ledger operations."
+}
@@ -73,3 +73,45 @@ teardown() {
     assert_match 'sh.*command-that-fails.bash'
     assert_match 'exit (code|status): 3'
 }
+
+@test "build resolves packages declared in dfx.json" {
+    install_asset packtool
+    dfx config canisters/e2e_project/main packtool.mo
+
+    git init --quiet describe-repo
+    cp -R vessel/describe/v1.0.1/src describe-repo/src
+    git -C describe-repo add .
+    git -C describe-repo -c user.name=e2e -c user.email=e2e@example.com commit --quiet -m "describe"
+    git -C describe-repo tag v1.0.1
+    cat <<<"$(jq '.defaults.build.packages=[{"name":"rate","path":"vessel/rate/v1.0.0"},{"name":"describe","repo":"file://'"$(pwd)"'/describe-repo","version":"v1.0.1"}]' dfx.json)" >dfx.json
+
+    dfx_start
+    dfx canister create --all
+    dfx build
+    assert_command jq -r '.packages[0].name' motoko.lock
+    assert_eq "describe"
+
+    dfx canister install e2e_project
+    assert_command dfx canister call e2e_project rate '("rust")'
+    assert_eq '("rust: So hot right now.")'
+}
+
+@test "build fails if a locked package was modified" {
+    install_asset packtool
+    dfx config canisters/e2e_project/main packtool.mo
+
+    git init --quiet describe-repo
+    cp -R vessel/describe/v1.0.1/src describe-repo/src
+    git -C describe-repo add .
+    git -C describe-repo -c user.name=e2e -c user.email=e2e@example.com commit --quiet -m "describe"
+    cat <<<"$(jq '.defaults.build.packages=[{"name":"rate","path":"vessel/rate/v1.0.0"},{"name":"describe","repo":"file://'"$(pwd)"'/describe-repo","rev":"HEAD"}]' dfx.json)" >dfx.json
+
+    dfx_start
+    dfx canister create --all
+    dfx build
+
+    COMMIT=$(jq -r '.packages[0].commit' motoko.lock)
+    echo "// tampered" >>"$DFX_CACHE_ROOT/.cache/dfinity/packages/describe/$COMMIT/src/rating.mo"
+    assert_command_fail dfx build
+    assert_match "Checksum mismatch for package 'describe'"
+}
@@ -0,0 +1,57 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+
+    dfx_new hello
+}
+
+teardown() {
+    dfx_stop
+
+    standard_teardown
+}
+
+@test "dfx --record writes requests and responses" {
+    dfx_start
+    dfx deploy
+
+    assert_command dfx --record recording canister call hello greet '("Alpha")'
+    assert_command ls recording
+    assert_match "-00000-status.json"
+    assert_match "-call.json"
+    assert_match "-read_state.json"
+
+    assert_command jq -r .decoded_envelope.content.method_name recording/*-call.json
+    assert_eq "greet"
+    assert_command jq -r '.certificate.tree | length' "$(ls recording/*-read_state.json | tail -1)"
+    assert_eq "3"
+}
+
+@test "dfx replay sends recorded calls again and compares the responses" {
+    dfx_start
+    dfx deploy
+
+    dfx --record recording canister call hello greet '("Alpha")'
+    dfx --record recording canister call --query hello greet '("Beta")'
+
+    assert_command dfx replay recording
+    assert_match "call $(dfx canister id hello) greet: same"
+    assert_match "query $(dfx canister id hello) greet: same"
+
+    # calls are signed again by the selected identity, not resent as recorded
+    CALL=$(ls recording/*-call.json)
+    cat <<<"$(jq '.envelope = "00"' "$CALL")" >"$CALL"
+    dfx identity new --disable-encryption alice
+    assert_command dfx --identity alice replay recording
+    assert_match "call $(dfx canister id hello) greet: same"
+
+    # a recorded response that differs is reported
+    QUERY=$(ls recording/*-query.json)
+    cat <<<"$(jq '.decoded_response.status = "rejected"' "$QUERY")" >"$QUERY"
+    assert_command_fail dfx replay recording
+    assert_match "query $(dfx canister id hello) greet: different"
+    assert_match "1 of 2 replayed calls returned a different response."
+}
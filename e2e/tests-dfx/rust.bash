@@ -42,3 +42,61 @@ teardown() {
     assert_command dfx canister call rust_deps read
     assert_match '(9 : nat)'
 }
+
+@test "rust canister can be built with a custom profile, target dir and rustflags" {
+    dfx_new_rust hello
+    cat <<<"$(jq '.canisters.hello.profile="dev" | .canisters.hello.target_dir="custom-target" | .canisters.hello.rustflags="-C opt-level=1"' dfx.json)" >dfx.json
+
+    dfx_start
+    dfx canister create --all
+    assert_command dfx build hello
+    assert_match "Executing: cargo build --target wasm32-unknown-unknown -p hello --target-dir"
+    assert_file_exists custom-target/wasm32-unknown-unknown/debug/hello.wasm
+    assert_command dfx canister install hello
+    assert_command dfx canister call hello greet dfinity
+    assert_match '("Hello, dfinity!")'
+}
+
+@test "rust canister build reports a broken manifest_path" {
+    dfx_new_rust hello
+    cat <<<"$(jq '.canisters.hello.manifest_path="src/nonexistent/Cargo.toml"' dfx.json)" >dfx.json
+
+    dfx_start
+    dfx canister create --all
+    assert_command_fail dfx build hello
+    assert_match "cargo metadata' failed"
+    assert_not_match "wasm not found"
+}
+
+@test "rust canister candid interface can be extracted from the wasm" {
+    dfx_new_rust hello
+    cat >src/hello/src/lib.rs <<'RUST'
+#[ic_cdk_macros::query]
+#[candid::candid_method(query)]
+fn greet(name: String) -> String {
+    format!("Hello, {}!", name)
+}
+
+candid::export_service!();
+
+#[ic_cdk_macros::query(name = "__get_candid_interface_tmp_hack")]
+fn export_candid() -> String {
+    __export_service()
+}
+RUST
+    cat <<<"$(jq '.canisters.hello.extract_candid=true' dfx.json)" >dfx.json
+
+    dfx_start
+    dfx canister create --all
+    assert_command dfx build hello
+    assert_match "Writing extracted candid interface"
+    assert_command cat .dfx/local/canisters/hello/hello.did
+    assert_match "greet"
+
+    # the checked-in did file must agree with the canister
+    echo "service : { greet : (text) -> (text) }" >src/hello/hello.did
+    assert_command_fail dfx build hello
+    assert_match "does not match the one extracted from the canister"
+    # and is left as it is
+    assert_eq "service : { greet : (text) -> (text) }" "$(cat src/hello/hello.did)"
+}
@@ -61,6 +61,36 @@ teardown() {
     assert_eq '("Hello, Omega!")'
 }
 
+@test "dfx writes a crash report and recovers when the replica is killed" {
+    [ "$USE_IC_REF" ] && skip "skip for ic-ref"
+
+    dfx_new hello
+    cat <<<"$(jq '.defaults.replica.restart={"initial_backoff_secs":1,"max_backoff_secs":2}' dfx.json)" >dfx.json
+    dfx_start
+
+    assert_command dfx ping
+    [ -z "$(ls -A .dfx/local/crashes 2>/dev/null)" ] || (echo "unexpected crash report" && exit 1)
+
+    REPLICA_PID=$(cat .dfx/replica-configuration/replica-pid)
+    kill -KILL "$REPLICA_PID"
+    assert_process_exits "$REPLICA_PID" 15s
+
+    timeout 15s sh -c \
+      'until dfx ping; do echo waiting for replica to restart; sleep 1; done' \
+      || (echo "replica did not restart" && ps aux && exit 1)
+    wait_until_replica_healthy
+
+    NEW_REPLICA_PID=$(cat .dfx/replica-configuration/replica-pid)
+    assert_neq "$REPLICA_PID" "$NEW_REPLICA_PID"
+
+    REPORT=$(find .dfx/local/crashes -name 'replica-*-1.json' | head -n 1)
+    [ -n "$REPORT" ] || (echo "no crash report was written" && exit 1)
+    assert_command jq -r .crash_number "$REPORT"
+    assert_eq "1"
+
+    assert_command dfx ping
+}
+
 @test "dfx restarts icx-proxy" {
     [ "$USE_IC_REF" ] && skip "skip for ic-ref"
 
@@ -133,6 +163,16 @@ teardown() {
     assert_command curl --fail http://localhost:"$(cat .dfx/webserver-port)"/sample-asset.txt?canisterId="$ID"
 }
 
+@test "dfx starts a replica for each named subnet" {
+    dfx_new
+    cat <<<"$(jq '.defaults.replica.subnets={"sys":{"type":"system"}}' dfx.json)" >dfx.json
+
+    assert_command dfx start --background
+    assert_match "subnet_type: Application"
+    assert_match "subnet_type: System"
+    assert_file_not_empty .dfx/replica-configuration/subnets/sys/replica-1.port
+}
+
 @test "dfx starts replica with subnet_type application" {
     install_asset subnet_type/application
 
@@ -157,6 +197,68 @@ teardown() {
 
 }
 
+@test "dfx start rejects unknown and managed replica flags" {
+    dfx_new
+
+    assert_command_fail dfx start --replica-arg "--log-levle debug"
+    assert_match "Unknown replica flag '--log-levle'"
+
+    assert_command_fail dfx start --replica-arg "--state-dir /tmp"
+    assert_match "is set by dfx and cannot be overridden"
+
+    assert_command_fail dfx start --replica-arg "-s /tmp"
+    assert_match "Unexpected replica argument '-s'"
+
+    assert_command_fail dfx start --replica-arg "--log-level"
+    assert_match "The replica flag '--log-level' requires a value."
+
+    cat <<<"$(jq '.defaults.replica.config.extra_args=["--http-port", "1234"]' dfx.json)" >dfx.json
+    assert_command_fail dfx start
+    assert_match "is set by dfx and cannot be overridden"
+}
+
+@test "dfx start passes replica settings to ic-starter" {
+    dfx_new
+    cat <<<"$(jq '.defaults.replica.config={"log_level":"debug","max_ingress_bytes_per_message":4194304}' dfx.json)" >dfx.json
+
+    dfx_start --replica-arg "--unit-delay-millis 500"
+    assert_command ps -o args= -C ic-starter
+    assert_match "--log-level debug"
+    assert_match "--max-ingress-bytes-per-message 4194304"
+    assert_match "--unit-delay-millis 500"
+}
+
+@test "dfx start --replica-arg replaces the same flag from dfx.json" {
+    dfx_new
+    cat <<<"$(jq '.defaults.replica.config={"log_level":"debug","unit_delay_millis":1000}' dfx.json)" >dfx.json
+
+    dfx_start --replica-arg "--log-level warning"
+    assert_command ps -o args= -C ic-starter
+    assert_match "--log-level warning"
+    assert_not_match "--log-level debug"
+    assert_match "--unit-delay-millis 1000"
+}
+
+@test "dfx start serves metrics at /_/metrics" {
+    dfx_new hello
+    dfx_start
+    dfx canister create --all
+    dfx build
+    dfx canister install --all
+    dfx canister call hello greet '("Alpha")'
+
+    # Canister gauges are refreshed in the background.
+    timeout 30s sh -c \
+      'until curl --fail --silent http://localhost:$(cat .dfx/webserver-port)/_/metrics | grep -q "dfx_canister_cycles{canister=\"hello\""; do echo waiting for canister gauges; sleep 1; done' \
+      || (echo "canister gauges were not reported" && exit 1)
+
+    assert_command curl --fail http://localhost:"$(cat .dfx/webserver-port)"/_/metrics
+    assert_match "dfx_replica_starts_total 1"
+    assert_match "dfx_replica_requests_total\\{canister=\"$(dfx canister id hello)\",kind=\"call\""
+    assert_match "dfx_canister_cycles\\{canister=\"hello\""
+    assert_match "dfx_command_duration_seconds_count\\{command=\"build\"\\} 1"
+}
+
 @test "dfx start detects if dfx is already running" {
     dfx_new hello
     dfx_start
@@ -164,3 +266,30 @@ teardown() {
     assert_command_fail dfx start
     assert_match "dfx is already running"
 }
+
+@test "dfx start creates wallets for defaults.replica.bootstrap_identities" {
+    dfx_new hello
+    dfx identity new --disable-encryption alice
+    dfx identity new --disable-encryption bob
+    cat <<<"$(jq '.defaults.replica.bootstrap_identities=["alice","bob"]' dfx.json)" >dfx.json
+
+    dfx_start
+    assert_command jq -r '.identities | keys | join(",")' .dfx/local/wallets.json
+    assert_eq "alice,bob"
+    assert_command jq -r '.__Candid_UI.local' .dfx/local/canister_ids.json
+    assert_match "^[a-z0-9-]+$"
+
+    assert_command dfx --identity alice wallet balance --precise
+    assert_match "^[0-9]+ cycles.$"
+    assert_command dfx --identity bob identity get-wallet
+    assert_not_match "Creating a wallet canister"
+}
+
+@test "dfx start --no-bootstrap does not create wallets" {
+    dfx_new hello
+    dfx identity new --disable-encryption alice
+    cat <<<"$(jq '.defaults.replica.bootstrap_identities=["alice"]' dfx.json)" >dfx.json
+
+    dfx_start --no-bootstrap
+    assert_file_not_exists .dfx/local/wallets.json
+}
@@ -0,0 +1,55 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+
+    dfx_new
+}
+
+teardown() {
+    dfx_stop
+
+    standard_teardown
+}
+
+@test "token reports a ledger that is not a canister name or id" {
+    assert_command_fail dfx token --ledger no_such_ledger balance
+    assert_match "no_such_ledger"
+}
+
+@test "token transfer rejects an account with a bad checksum" {
+    dfx_start
+    assert_command_fail dfx token --ledger ryjl3-tyaaa-aaaaa-aaaba-cai transfer --amount 1 "$(dfx identity get-principal)-aaaaaaa.1"
+    assert_match "the checksum does not match"
+}
+
+@test "token transfer moves tokens on a local ledger" {
+    install_asset icrc1_ledger
+    dfx_start
+    assert_command dfx deploy icrc1_ledger
+    assert_command dfx canister call icrc1_ledger mint "(record { owner = principal \"$(dfx identity get-principal)\"; subaccount = null }, 100_000_000_000 : nat)"
+
+    assert_command dfx token --ledger icrc1_ledger balance
+    assert_eq "1000 TOK"
+    assert_command dfx token --ledger icrc1_ledger fee
+    assert_match "0.0001 TOK"
+
+    dfx identity new --disable-encryption alice
+    ALICE=$(dfx --identity alice identity get-principal)
+    assert_command dfx token --ledger icrc1_ledger transfer --amount 1.5 --memo 7 "$ALICE"
+    assert_match "Transfer sent at block index: 1"
+
+    assert_command dfx token --ledger icrc1_ledger balance "$ALICE"
+    assert_eq "1.5 TOK"
+    assert_command dfx token --ledger icrc1_ledger balance
+    assert_eq "998.4999 TOK"
+    assert_command dfx token --ledger icrc1_ledger supply
+    assert_match "999.9999 TOK"
+
+    assert_command_fail dfx --identity alice token --ledger icrc1_ledger transfer --amount 2 "$(dfx identity get-principal)"
+    assert_match "doesn't have enough funds"
+    assert_command dfx token --ledger icrc1_ledger balance "$ALICE"
+    assert_eq "1.5 TOK"
+}
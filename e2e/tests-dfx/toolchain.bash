@@ -0,0 +1,181 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+
+    VERSION=$(dfx --version | awk '{ print $2 }')
+}
+
+teardown() {
+    standard_teardown
+}
+
+# Serves a signed release of the running dfx at $RELEASE_ROOT.
+serve_release() {
+    use_test_release_key
+    arch="x86_64-$(uname -s | tr '[:upper:]' '[:lower:]')"
+    mkdir -p release/downloads/dfx/"$VERSION"/"$arch" release/dfx
+    cp "$(which dfx)" release/dfx/
+    tar -czf release/downloads/dfx/"$VERSION"/"$arch"/dfx-"$VERSION".tar.gz -C release/dfx dfx
+    checksum=$(sha256sum release/downloads/dfx/"$VERSION"/"$arch"/dfx-"$VERSION".tar.gz | cut -d' ' -f1)
+    echo "{
+      \"tags\": { \"latest\": \"$VERSION\" },
+      \"versions\": [ \"$VERSION\" ],
+      \"checksums\": { \"$VERSION\": { \"$arch\": \"$checksum\" } }
+    }" >release/manifest.json
+    sign_release_manifest release/manifest.json
+
+    python3 -m http.server "$RANDOM_EMPHEMERAL_PORT" --directory release &
+    WEB_SERVER_PID=$!
+    while ! nc -z localhost "$RANDOM_EMPHEMERAL_PORT"; do
+        sleep 1
+    done
+    RELEASE_ROOT="http://localhost:$RANDOM_EMPHEMERAL_PORT"
+}
+
+@test "toolchain bundle packages a verified release with its signed manifest" {
+    serve_release
+    assert_command dfx toolchain bundle "$VERSION" -o bundle.tar.gz --release-root "$RELEASE_ROOT"
+    assert_command tar -tzf bundle.tar.gz
+    assert_match "bundle.json"
+    assert_match "manifest.json"
+    assert_match "manifest.json.sig"
+    assert_match "dfx-$VERSION.tar.gz"
+
+    tar -xzf bundle.tar.gz bundle.json
+    assert_command jq -r .version bundle.json
+    assert_eq "$VERSION"
+    checksum=$(sha256sum release/downloads/dfx/"$VERSION"/"$arch"/dfx-"$VERSION".tar.gz | cut -d' ' -f1)
+    assert_command jq -r ".files[\"dfx-$VERSION.tar.gz\"]" bundle.json
+    assert_eq "$checksum"
+
+    # a release that does not match the signed manifest is not bundled
+    echo "tampered" >>release/dfx/dfx
+    tar -czf release/downloads/dfx/"$VERSION"/"$arch"/dfx-"$VERSION".tar.gz -C release/dfx dfx
+    assert_command_fail dfx toolchain bundle "$VERSION" -o tampered.tar.gz --release-root "$RELEASE_ROOT"
+    assert_match "Checksum mismatch for the release of version $VERSION"
+
+    kill "$WEB_SERVER_PID"
+}
+
+@test "toolchain install --from-bundle installs the cache and toolchain symlink" {
+    serve_release
+    dfx toolchain bundle "$VERSION" -o bundle.tar.gz --release-root "$RELEASE_ROOT"
+    kill "$WEB_SERVER_PID"
+
+    # a machine that has never installed dfx
+    export DFX_CACHE_ROOT="$DFX_E2E_TEMP_DIR/offline-cache-root"
+    export HOME="$DFX_E2E_TEMP_DIR/offline-home-dir"
+    mkdir "$DFX_CACHE_ROOT" "$HOME"
+
+    assert_command dfx toolchain install --from-bundle bundle.tar.gz
+    assert_match "SDK version $VERSION installed from bundle"
+    assert_match "Toolchain $VERSION installed"
+    assert_file_exists "$DFX_CACHE_ROOT/.cache/dfinity/versions/$VERSION/moc"
+
+    assert_command readlink "$HOME/.dfinity/toolchains/$VERSION"
+    assert_eq "$DFX_CACHE_ROOT/.cache/dfinity/versions/$VERSION"
+    assert_command "$HOME/.dfinity/toolchains/$VERSION/dfx" --version
+    assert_match "$VERSION"
+
+    assert_command_fail dfx toolchain install --from-bundle bundle.tar.gz latest
+    assert_match "Toolchain latest cannot be installed from a bundle"
+}
+
+@test "toolchain install --from-bundle rejects a modified bundle" {
+    serve_release
+    dfx toolchain bundle "$VERSION" -o bundle.tar.gz --release-root "$RELEASE_ROOT"
+    kill "$WEB_SERVER_PID"
+    export DFX_CACHE_ROOT="$DFX_E2E_TEMP_DIR/offline-cache-root"
+    mkdir "$DFX_CACHE_ROOT"
+
+    # a release that does not match the checksum in bundle.json
+    mkdir -p unpacked/dfx
+    tar -xzf bundle.tar.gz -C unpacked
+    cp release/dfx/dfx unpacked/dfx/
+    echo "tampered" >>unpacked/dfx/dfx
+    tar -czf unpacked/dfx-"$VERSION".tar.gz -C unpacked/dfx dfx
+    rm -r unpacked/dfx
+    tar -czf tampered.tar.gz -C unpacked .
+    assert_command_fail dfx toolchain install --from-bundle tampered.tar.gz
+    assert_match "Checksum mismatch for dfx-$VERSION.tar.gz in the bundle"
+    assert_command_fail dfx toolchain install --from-bundle tampered.tar.gz --insecure
+    assert_match "Checksum mismatch for dfx-$VERSION.tar.gz in the bundle"
+
+    # a release whose checksum was also changed in bundle.json, but not in the signed manifest
+    checksum=$(sha256sum unpacked/dfx-"$VERSION".tar.gz | cut -d' ' -f1)
+    cat <<<"$(jq ".files[\"dfx-$VERSION.tar.gz\"]=\"$checksum\"" unpacked/bundle.json)" >unpacked/bundle.json
+    tar -czf tampered.tar.gz -C unpacked .
+    assert_command_fail dfx toolchain install --from-bundle tampered.tar.gz
+    assert_match "Checksum mismatch for the release of version $VERSION"
+
+    # a release whose checksum was also changed in the manifest
+    cat <<<"$(jq ".checksums[\"$VERSION\"][\"$arch\"]=\"$checksum\"" unpacked/manifest.json)" >unpacked/manifest.json
+    manifest_checksum=$(sha256sum unpacked/manifest.json | cut -d' ' -f1)
+    cat <<<"$(jq ".files[\"manifest.json\"]=\"$manifest_checksum\"" unpacked/bundle.json)" >unpacked/bundle.json
+    tar -czf tampered.tar.gz -C unpacked .
+    assert_command_fail dfx toolchain install --from-bundle tampered.tar.gz
+    assert_match "The signature does not match the release key."
+
+    assert_command ls "$DFX_CACHE_ROOT/.cache/dfinity/versions"
+    assert_not_match "$VERSION"
+}
+
+@test "toolchain which explains a semver range in dfx.json" {
+    dfx_new
+    MAJOR_MINOR=$(echo "$VERSION" | cut -d. -f1,2)
+    cat <<<"$(jq ".dfx=\"^$MAJOR_MINOR\"" dfx.json)" >dfx.json
+
+    assert_command dfx toolchain which
+    assert_match "dfx $VERSION"
+    assert_match "(running)"
+    assert_match "dfx.json asks for dfx \^$MAJOR_MINOR and the running dfx matches"
+}
+
+@test "a missing pinned dfx is not installed without confirmation" {
+    dfx_new
+    cat <<<"$(jq '.dfx="0.1.0"' dfx.json)" >dfx.json
+
+    assert_command dfx toolchain which
+    assert_match "dfx 0.1.0"
+    assert_match "not installed"
+
+    assert_command_fail dfx build </dev/null
+    assert_match "Install it now\? \[y/N\]"
+    assert_match "dfx 0.1.0 is not installed. Install it with \`dfx toolchain install 0.1.0\`"
+
+    # the toolchain and cache commands are not forwarded to the pinned dfx
+    assert_command dfx toolchain which </dev/null
+    assert_not_match "Install it now"
+    assert_not_match "Warning: The version of DFX used"
+    assert_match "dfx asks to install it the next time it runs here"
+
+    assert_command dfx cache du </dev/null
+    assert_not_match "Install it now"
+    assert_not_match "Warning: The version of DFX used"
+}
+
+@test "a semver range that no installed dfx matches is resolved only after confirmation" {
+    dfx_new
+    cat <<<"$(jq '.dfx="^0.1"' dfx.json)" >dfx.json
+
+    assert_command dfx toolchain which
+    assert_match "dfx \^0.1"
+    assert_match "dfx.json asks for dfx \^0.1 and no installed version matches"
+    assert_not_match "Fetching manifest"
+
+    assert_command_fail dfx build </dev/null
+    assert_match "Install it now\? \[y/N\]"
+    assert_match "dfx \^0.1 is not installed."
+    assert_not_match "Fetching manifest"
+}
+
+@test "warns when DFX_VERSION runs a dfx the project is not pinned to" {
+    dfx_new
+    cat <<<"$(jq '.dfx="0.1.0"' dfx.json)" >dfx.json
+
+    DFX_VERSION="" assert_command dfx identity whoami
+    assert_match "This project is pinned to dfx 0.1.0, but dfx $VERSION is running because DFX_VERSION is set."
+}
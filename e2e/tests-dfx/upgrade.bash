@@ -33,15 +33,15 @@ setup() {
         sleep 1
     done
 
-    # Override current version to force upgrade
-    assert_command ./dfx upgrade \
+    # Override current version to force upgrade. The manifest is not signed.
+    assert_command ./dfx upgrade --insecure \
         --current-version 0.4.6 \
         --release-root "http://localhost:$RANDOM_EMPHEMERAL_PORT"
     assert_match "Current version: .*"
     assert_match "Fetching manifest .*"
     assert_match "New version available: .*"
 
-    assert_command ./dfx upgrade \
+    assert_command ./dfx upgrade --insecure \
         --release-root "http://localhost:$RANDOM_EMPHEMERAL_PORT"
     assert_match "Already up to date"
 
@@ -49,3 +49,59 @@ setup() {
     assert_command ./dfx --version
     assert_match "$version"
 }
+
+@test "upgrade verifies a signed manifest and the checksum of the download" {
+    use_test_release_key
+    latest_version="0.4.7"
+    arch="x86_64-$(uname -s | tr '[:upper:]' '[:lower:]')"
+    latest_version_dir="downloads/dfx/$latest_version/$arch/"
+    dfx_archive_file_name="dfx-$latest_version.tar.gz"
+    mkdir -p "$latest_version_dir"
+    cp "$(which dfx)" .
+    tar -czf "$latest_version_dir/$dfx_archive_file_name" dfx
+    checksum=$(sha256sum "$latest_version_dir/$dfx_archive_file_name" | cut -d' ' -f1)
+    echo "{
+      \"tags\": { \"latest\": \"$latest_version\" },
+      \"versions\": [ \"$latest_version\" ],
+      \"checksums\": { \"$latest_version\": { \"$arch\": \"$checksum\" } }
+    }" > manifest.json
+    python3 -m http.server "$RANDOM_EMPHEMERAL_PORT" &
+    WEB_SERVER_PID=$!
+
+    while ! nc -z localhost "$RANDOM_EMPHEMERAL_PORT"; do
+        sleep 1
+    done
+
+    # an unsigned manifest is refused
+    assert_command_fail ./dfx upgrade \
+        --current-version 0.4.6 \
+        --release-root "http://localhost:$RANDOM_EMPHEMERAL_PORT"
+    assert_match "manifest.json.sig"
+
+    # a manifest signed with the release key is accepted
+    sign_release_manifest manifest.json
+    assert_command ./dfx upgrade \
+        --current-version 0.4.6 \
+        --release-root "http://localhost:$RANDOM_EMPHEMERAL_PORT"
+    assert_match "New version available: $latest_version"
+
+    # a manifest changed after signing is refused
+    sed -i.bak "s/$checksum/$(echo "$checksum" | tr '0-9a-f' 'f0-9a-e')/" manifest.json
+    assert_command_fail ./dfx upgrade \
+        --current-version 0.4.6 \
+        --release-root "http://localhost:$RANDOM_EMPHEMERAL_PORT"
+    assert_match "The signature does not match the release key."
+
+    # a download that does not match the signed checksum is refused
+    mv manifest.json.bak manifest.json
+    mkdir tampered
+    cp dfx tampered/
+    echo "tampered" >>tampered/dfx
+    tar -czf "$latest_version_dir/$dfx_archive_file_name" -C tampered dfx
+    assert_command_fail ./dfx upgrade \
+        --current-version 0.4.6 \
+        --release-root "http://localhost:$RANDOM_EMPHEMERAL_PORT"
+    assert_match "Checksum mismatch for the release of version $latest_version"
+
+    kill "$WEB_SERVER_PID"
+}
@@ -170,3 +170,31 @@ teardown() {
     dfx --identity alice deploy --no-wallet hello
     assert_command dfx canister --wallet "$(dfx identity get-wallet)" deposit-cycles 1 hello
 }
+
+@test "dfx wallet events and chart show the canisters the wallet created" {
+    dfx_new hello
+    dfx_start
+    dfx deploy hello
+
+    assert_command dfx wallet events --from 0
+    assert_match "address-added"
+    assert_match "canister-created  Created canister hello \\($(dfx canister id hello)\\)"
+
+    assert_command dfx wallet events --from 0 --output json
+    assert_eq 1 "$(jq -r '[.[] | select(.kind == "canister-created")] | length' <<<"$stdout")"
+
+    assert_command dfx wallet chart --period month
+    assert_match "hello \\($(dfx canister id hello)\\) +#+ +[0-9]+ cycles"
+}
+
+@test "dfx wallet events works with a 64-bit wallet" {
+    use_wallet_wasm 0.8.2
+    dfx_new hello
+    dfx_start
+    dfx deploy hello
+
+    assert_command dfx wallet events --from 0
+    assert_match "canister-created"
+    assert_command dfx wallet chart
+    assert_match "hello"
+}
@@ -0,0 +1,48 @@
+#!/usr/bin/env bats
+
+load ../utils/_
+
+setup() {
+    standard_setup
+
+    dfx_new hello
+}
+
+teardown() {
+    dfx_stop
+
+    standard_teardown
+}
+
+@test "watch-cycles reports canisters below their threshold" {
+    dfx_start
+    assert_command dfx deploy hello
+
+    assert_command dfx canister watch-cycles --threshold 1000000000
+    assert_match "hello: [0-9]+ cycles, threshold 1000000000 cycles"
+
+    assert_command_fail dfx canister watch-cycles --threshold 100000000000000
+    assert_match "hello has [0-9]+ cycles, below its threshold of 100000000000000 cycles."
+    assert_match "1 canister\(s\) are below their cycles threshold"
+}
+
+@test "watch-cycles tops up canisters from the wallet" {
+    dfx_start
+    assert_command dfx deploy hello
+    cat <<<"$(jq '.canisters.hello.watch_cycles={"threshold":100000000000000,"top_up":1000000000000}' dfx.json)" >dfx.json
+
+    assert_command dfx canister watch-cycles --top-up
+    assert_match "below its threshold of 100000000000000 cycles. Topped up with 1000000000000 cycles."
+}
+
+@test "watch-cycles watches the canisters listed in a file" {
+    dfx_start
+    assert_command dfx deploy hello
+    cat >canisters.txt <<EOF_CANISTERS
+# canisters to watch
+$(dfx canister id hello)
+EOF_CANISTERS
+
+    assert_command_fail dfx canister watch-cycles --canisters-file canisters.txt --threshold 100000000000000
+    assert_match "$(dfx canister id hello) has [0-9]+ cycles"
+}
@@ -268,3 +268,14 @@ use_wallet_wasm() {
     # shellcheck disable=SC2154
     export DFX_WALLET_WASM="${archive}/wallet/$1/wallet.wasm"
 }
+
+# Uses the stand-in for the release key that dfx is built with for the e2e tests, with
+# DFX_RELEASE_PUBLIC_KEY set to e2e/assets/release_key/test-release-key.pub.
+use_test_release_key() {
+    cp "${BATS_TEST_DIRNAME}/../assets/release_key/test-release-key.pem" "$DFX_E2E_TEMP_DIR/release-key.pem"
+}
+
+# Signs a release manifest with the key from use_test_release_key, writing <manifest>.sig.
+sign_release_manifest() {
+    openssl pkeyutl -sign -rawin -inkey "$DFX_E2E_TEMP_DIR/release-key.pem" -in "$1" | base64 | tr -d '\n' >"$1.sig"
+}
@@ -44,6 +44,9 @@ pub struct IcxProxyConfig {
 
     /// does the icx-proxy need to fetch the root key
     pub fetch_root_key: bool,
+
+    /// send replica requests through the webserver, which counts them for /_/metrics
+    pub replica_via_webserver: bool,
 }
 
 /// The configuration for the icx_proxy actor.
@@ -159,7 +162,12 @@ impl Handler<PortReadySignal> for IcxProxy {
 
         self.stop_icx_proxy();
 
-        let replica_url = format!("http://localhost:{}", msg.port);
+        let replica_port = if self.config.icx_proxy_config.replica_via_webserver {
+            self.config.icx_proxy_config.proxy_port
+        } else {
+            msg.port
+        };
+        let replica_url = format!("http://localhost:{}", replica_port);
         let replica_urls = vec![Url::parse(&replica_url).unwrap()];
 
         self.start_icx_proxy(replica_urls)
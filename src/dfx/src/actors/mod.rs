@@ -1,10 +1,11 @@
 use crate::actors;
 use crate::actors::emulator::Emulator;
-use crate::actors::replica::Replica;
+use crate::actors::replica::{Replica, RestartPolicy};
 use crate::actors::shutdown_controller::ShutdownController;
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
 use crate::lib::replica_config::ReplicaConfig;
+use crate::lib::subnets;
 
 use crate::actors::btc_adapter::signals::BtcAdapterReadySubscribe;
 use crate::actors::btc_adapter::BtcAdapter;
@@ -14,6 +15,7 @@ use crate::actors::icx_proxy::signals::PortReadySubscribe;
 use crate::actors::icx_proxy::{IcxProxy, IcxProxyConfig};
 use actix::{Actor, Addr, Recipient};
 use anyhow::Context;
+use crossbeam::channel::Sender;
 use fn_error_context::context;
 use std::fs;
 use std::path::PathBuf;
@@ -115,7 +117,10 @@ pub fn start_emulator_actor(
 #[context("Failed to setup replica environment.")]
 fn setup_replica_env(env: &dyn Environment, replica_config: &ReplicaConfig) -> DfxResult<PathBuf> {
     // create replica config dir
-    let replica_configuration_dir = env.get_temp_dir().join("replica-configuration");
+    let replica_configuration_dir = match &replica_config.subnet_name {
+        Some(subnet) => subnets::configuration_dir(env.get_temp_dir(), subnet),
+        None => env.get_temp_dir().join("replica-configuration"),
+    };
     fs::create_dir_all(&replica_configuration_dir).with_context(|| {
         format!(
             "Failed to create replica config direcory {}.",
@@ -137,8 +142,8 @@ fn setup_replica_env(env: &dyn Environment, replica_config: &ReplicaConfig) -> D
     }
 
     // create replica state dir
-    let state_dir = env.get_state_dir().join("replicated_state");
-    fs::create_dir_all(&state_dir).with_context(|| {
+    let state_dir = &replica_config.state_manager.state_root;
+    fs::create_dir_all(state_dir).with_context(|| {
         format!(
             "Failed to create replica state directory {}.",
             state_dir.to_string_lossy()
@@ -155,12 +160,34 @@ pub fn start_replica_actor(
     shutdown_controller: Addr<ShutdownController>,
     btc_adapter_ready_subscribe: Option<Recipient<BtcAdapterReadySubscribe>>,
     canister_http_adapter_ready_subscribe: Option<Recipient<CanisterHttpAdapterReadySubscribe>>,
+    crash_limit_sender: Option<Sender<String>>,
 ) -> DfxResult<Addr<Replica>> {
     // get binary path
     let replica_path = env.get_cache().get_binary_command_path("replica")?;
     let ic_starter_path = env.get_cache().get_binary_command_path("ic-starter")?;
 
     let replica_configuration_dir = setup_replica_env(env, &replica_config)?;
+    let restart_policy = env
+        .get_config()
+        .map_or_else(RestartPolicy::default, |config| {
+            RestartPolicy::from_config(
+                config
+                    .get_config()
+                    .get_defaults()
+                    .get_replica()
+                    .restart
+                    .as_ref(),
+            )
+        });
+    let crash_reports_dir = match &replica_config.subnet_name {
+        Some(subnet) => env
+            .get_temp_dir()
+            .join("local")
+            .join("crashes")
+            .join("subnets")
+            .join(subnet),
+        None => env.get_temp_dir().join("local").join("crashes"),
+    };
 
     let actor_config = replica::Config {
         ic_starter_path,
@@ -171,6 +198,9 @@ pub fn start_replica_actor(
         replica_configuration_dir,
         btc_adapter_ready_subscribe,
         canister_http_adapter_ready_subscribe,
+        restart_policy,
+        crash_reports_dir,
+        crash_limit_sender,
     };
     Ok(Replica::new(actor_config).start())
 }
@@ -1,9 +1,11 @@
 use crate::actors::icx_proxy::signals::{PortReadySignal, PortReadySubscribe};
 use crate::actors::replica::signals::ReplicaRestarted;
 use crate::actors::shutdown_controller::signals::outbound::Shutdown;
-use crate::actors::shutdown_controller::signals::ShutdownSubscribe;
+use crate::actors::shutdown_controller::signals::{ShutdownSubscribe, ShutdownTrigger};
 use crate::actors::shutdown_controller::ShutdownController;
+use crate::config::dfinity::ConfigDefaultsReplicaRestart;
 use crate::lib::error::{DfxError, DfxResult};
+use crate::lib::metrics::METRICS;
 use crate::lib::replica_config::ReplicaConfig;
 
 use crate::actors::btc_adapter::signals::{BtcAdapterReady, BtcAdapterReadySubscribe};
@@ -15,13 +17,18 @@ use actix::{
     Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, Handler, Recipient,
     ResponseActFuture, Running, WrapFuture,
 };
-use anyhow::anyhow;
+use anyhow::{anyhow, Context as _};
 use crossbeam::channel::{unbounded, Receiver, Sender};
 use garcon::{Delay, Waiter};
-use slog::{debug, info, Logger};
+use serde::Serialize;
+use slog::{debug, error, info, warn, Logger};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub mod signals {
     use actix::prelude::*;
@@ -33,6 +40,86 @@ pub mod signals {
     pub(super) struct ReplicaRestarted {
         pub port: u16,
     }
+
+    /// A message sent to the Replica when the process crashed more often than
+    /// the restart policy allows.
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    pub(super) struct ReplicaCrashLimitReached {
+        pub crashes: u32,
+        pub last_report: Option<std::path::PathBuf>,
+    }
+}
+
+/// How the replica is restarted when its process exits unexpectedly.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    /// Consecutive crashes after which the replica is no longer restarted.
+    pub max_restarts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// A replica that stayed up this long is considered healthy, and its crash does not
+    /// count towards the restart limit of earlier crashes.
+    pub healthy_uptime: Duration,
+    /// Lines of stderr kept for crash reports.
+    pub crash_report_lines: usize,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            healthy_uptime: Duration::from_secs(60),
+            crash_report_lines: 100,
+        }
+    }
+}
+
+impl RestartPolicy {
+    pub fn from_config(config: Option<&ConfigDefaultsReplicaRestart>) -> Self {
+        let default = RestartPolicy::default();
+        match config {
+            None => default,
+            Some(config) => RestartPolicy {
+                max_restarts: config.max_restarts.unwrap_or(default.max_restarts),
+                initial_backoff: config
+                    .initial_backoff_secs
+                    .map_or(default.initial_backoff, Duration::from_secs),
+                max_backoff: config
+                    .max_backoff_secs
+                    .map_or(default.max_backoff, Duration::from_secs),
+                healthy_uptime: config
+                    .healthy_uptime_secs
+                    .map_or(default.healthy_uptime, Duration::from_secs),
+                crash_report_lines: config
+                    .crash_report_lines
+                    .unwrap_or(default.crash_report_lines),
+            },
+        }
+    }
+
+    /// The delay before restarting after the given number of consecutive crashes.
+    fn backoff(&self, crashes: u32) -> Duration {
+        let factor = 2u32.saturating_pow(crashes.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// Written to the crash reports directory every time the replica process exits unexpectedly.
+#[derive(Serialize)]
+struct CrashReport<'a> {
+    time: String,
+    /// Consecutive crashes, including this one.
+    crash_number: u32,
+    exit_status: String,
+    exit_code: Option<i32>,
+    uptime_secs: u64,
+    stderr: Vec<String>,
+    replica_config: &'a ReplicaConfig,
 }
 
 /// The configuration for the replica actor.
@@ -45,6 +132,10 @@ pub struct Config {
     pub replica_configuration_dir: PathBuf,
     pub btc_adapter_ready_subscribe: Option<Recipient<BtcAdapterReadySubscribe>>,
     pub canister_http_adapter_ready_subscribe: Option<Recipient<CanisterHttpAdapterReadySubscribe>>,
+    pub restart_policy: RestartPolicy,
+    pub crash_reports_dir: PathBuf,
+    /// Receives a summary when the replica crashed more often than the restart policy allows.
+    pub crash_limit_sender: Option<Sender<String>>,
 }
 
 /// A replica actor. Starts the replica, can subscribe to a Ready signal and a
@@ -59,6 +150,10 @@ pub struct Config {
 ///     times (e.g. if the replica crashes).
 ///     If a replica is already started and another actor sends this message, a
 ///     PortReadySignal will be sent free of charge in the same thread.
+///
+/// When the replica process exits unexpectedly, it is restarted according to the
+/// restart policy, and a crash report is written. Once the restart limit is reached,
+/// the replica is left stopped and a shutdown of all actors is triggered.
 pub struct Replica {
     logger: Logger,
     config: Config,
@@ -134,23 +229,25 @@ impl Replica {
         let write_port_to = config.http_handler.write_port_to.clone();
         let replica_path = self.config.replica_path.to_path_buf();
         let ic_starter_path = self.config.ic_starter_path.to_path_buf();
+        let restart_policy = self.config.restart_policy.clone();
+        let crash_reports_dir = self.config.crash_reports_dir.clone();
 
         let (sender, receiver) = unbounded();
 
-        let handle = anyhow::Context::context(
-            replica_start_thread(
-                logger,
-                config.clone(),
-                port,
-                write_port_to,
-                ic_starter_path,
-                replica_path,
-                replica_pid_path,
-                addr,
-                receiver,
-            ),
-            "Failed to start replica thread.",
-        )?;
+        let handle = replica_start_thread(
+            logger,
+            config.clone(),
+            port,
+            write_port_to,
+            ic_starter_path,
+            replica_path,
+            replica_pid_path,
+            restart_policy,
+            crash_reports_dir,
+            addr,
+            receiver,
+        )
+        .context("Failed to start replica thread.")?;
 
         self.thread_join = Some(handle);
         self.stop_sender = Some(sender);
@@ -178,6 +275,15 @@ impl Replica {
     }
 }
 
+fn set_adapter_ready(adapter: &str, ready: bool) {
+    METRICS.set_gauge(
+        "dfx_adapter_ready",
+        "Whether an adapter the replica waits for is ready (1) or not (0).",
+        &[("adapter", adapter)],
+        if ready { 1.0 } else { 0.0 },
+    );
+}
+
 impl Actor for Replica {
     type Context = Context<Self>;
 
@@ -186,10 +292,12 @@ impl Actor for Replica {
             let _ = btc_adapter_ready_subscribe
                 .do_send(BtcAdapterReadySubscribe(ctx.address().recipient()));
             self.awaiting_btc_adapter_ready = true;
+            set_adapter_ready("btc", false);
         }
         if let Some(subscribe) = &self.config.canister_http_adapter_ready_subscribe {
             let _ = subscribe.do_send(CanisterHttpAdapterReadySubscribe(ctx.address().recipient()));
             self.awaiting_canister_http_adapter_ready = true;
+            set_adapter_ready("canister_http", false);
         }
 
         self.restart_replica_if_all_ready(ctx.address());
@@ -226,16 +334,53 @@ impl Handler<signals::ReplicaRestarted> for Replica {
 
     fn handle(&mut self, msg: ReplicaRestarted, _ctx: &mut Self::Context) -> Self::Result {
         self.port = Some(msg.port);
+        METRICS.inc_counter(
+            "dfx_replica_starts_total",
+            "Times the replica was started, including restarts after crashes.",
+            &[],
+        );
         self.send_ready_signal(msg.port);
     }
 }
 
+impl Handler<signals::ReplicaCrashLimitReached> for Replica {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: signals::ReplicaCrashLimitReached,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let mut summary = format!(
+            "The replica crashed {} times in a row and will not be restarted.",
+            msg.crashes
+        );
+        if let Some(last_report) = &msg.last_report {
+            summary.push_str(&format!(
+                " The last crash report is {}.",
+                last_report.to_string_lossy()
+            ));
+        }
+        summary.push_str(&format!(
+            " All crash reports are in {}.",
+            self.config.crash_reports_dir.to_string_lossy()
+        ));
+        error!(self.logger, "{}", summary);
+
+        if let Some(sender) = &self.config.crash_limit_sender {
+            let _ = sender.send(summary);
+        }
+        self.config.shutdown_controller.do_send(ShutdownTrigger());
+    }
+}
+
 impl Handler<BtcAdapterReady> for Replica {
     type Result = ();
 
     fn handle(&mut self, _msg: BtcAdapterReady, ctx: &mut Self::Context) {
         debug!(self.logger, "btc adapter ready");
         self.awaiting_btc_adapter_ready = false;
+        set_adapter_ready("btc", true);
 
         self.restart_replica_if_all_ready(ctx.address());
     }
@@ -247,6 +392,7 @@ impl Handler<CanisterHttpAdapterReady> for Replica {
     fn handle(&mut self, _msg: CanisterHttpAdapterReady, ctx: &mut Self::Context) {
         debug!(self.logger, "canister http adapter ready");
         self.awaiting_canister_http_adapter_ready = false;
+        set_adapter_ready("canister_http", true);
 
         self.restart_replica_if_all_ready(ctx.address());
     }
@@ -268,6 +414,64 @@ impl Handler<Shutdown> for Replica {
     }
 }
 
+/// Forwards the stderr of the replica process to our stderr, keeping the last lines
+/// for crash reports. The returned receiver is signalled once the stream is closed.
+/// Lines that are not valid UTF-8 are forwarded lossily, so that the pipe is drained
+/// until the replica closes it and the replica never blocks writing to it.
+fn forward_stderr(
+    stderr: std::process::ChildStderr,
+    tail: Arc<Mutex<VecDeque<String>>>,
+    max_lines: usize,
+) -> Receiver<()> {
+    let (sender, receiver) = unbounded();
+    let _ = std::thread::Builder::new()
+        .name("replica-stderr".to_owned())
+        .spawn(move || {
+            let mut reader = BufReader::new(stderr);
+            let mut buffer = vec![];
+            loop {
+                buffer.clear();
+                match reader.read_until(b'\n', &mut buffer) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+                let line = String::from_utf8_lossy(&buffer)
+                    .trim_end_matches(&['\n', '\r'][..])
+                    .to_string();
+                eprintln!("{}", line);
+                if let Ok(mut tail) = tail.lock() {
+                    if tail.len() >= max_lines {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line);
+                }
+            }
+            let _ = sender.send(());
+        });
+    receiver
+}
+
+fn write_crash_report(crash_reports_dir: &Path, report: &CrashReport) -> DfxResult<PathBuf> {
+    std::fs::create_dir_all(crash_reports_dir).with_context(|| {
+        format!(
+            "Failed to create crash reports directory {}.",
+            crash_reports_dir.to_string_lossy()
+        )
+    })?;
+    let path = crash_reports_dir.join(format!(
+        "replica-{}-{}.json",
+        chrono::Local::now().format("%Y%m%dT%H%M%S"),
+        report.crash_number
+    ));
+    let content =
+        serde_json::to_string_pretty(report).context("Failed to serialize crash report.")?;
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write crash report {}.", path.to_string_lossy()))?;
+    Ok(path)
+}
+
 #[allow(clippy::too_many_arguments)]
 fn replica_start_thread(
     logger: Logger,
@@ -277,17 +481,12 @@ fn replica_start_thread(
     ic_starter_path: PathBuf,
     replica_path: PathBuf,
     replica_pid_path: PathBuf,
+    restart_policy: RestartPolicy,
+    crash_reports_dir: PathBuf,
     addr: Addr<Replica>,
     receiver: Receiver<()>,
 ) -> DfxResult<std::thread::JoinHandle<()>> {
     let thread_handler = move || {
-        // Use a Waiter for waiting for the file to be created.
-        let mut waiter = Delay::builder()
-            .throttle(Duration::from_millis(1000))
-            .exponential_backoff(Duration::from_secs(1), 1.2)
-            .build();
-        waiter.start();
-
         // Start the process, then wait for the file.
         let ic_starter_path = ic_starter_path.as_os_str();
 
@@ -300,8 +499,6 @@ fn replica_start_thread(
             config.state_manager.state_root.to_str().unwrap_or_default(),
             "--create-funds-whitelist",
             "*",
-            "--consensus-pool-backend",
-            "rocksdb",
             "--subnet-type",
             &config.subnet_type.as_ic_starter_string(),
         ]);
@@ -310,7 +507,7 @@ fn replica_start_thread(
         }
         if config.btc_adapter.enabled {
             cmd.args(&["--subnet-features", "bitcoin_testnet_feature"]);
-            if let Some(socket_path) = config.btc_adapter.socket_path {
+            if let Some(socket_path) = &config.btc_adapter.socket_path {
                 cmd.args(&[
                     "--bitcoin-testnet-uds-path",
                     socket_path.to_str().unwrap_or_default(),
@@ -319,7 +516,7 @@ fn replica_start_thread(
         }
         if config.canister_http_adapter.enabled {
             cmd.args(&["--subnet-features", "http_requests"]);
-            if let Some(socket_path) = config.canister_http_adapter.socket_path {
+            if let Some(socket_path) = &config.canister_http_adapter.socket_path {
                 cmd.args(&[
                     "--canister-http-uds-path",
                     socket_path.to_str().unwrap_or_default(),
@@ -333,30 +530,47 @@ fn replica_start_thread(
                 &write_port_to.to_string_lossy().to_string(),
             ]);
         }
-        cmd.args(&[
-            "--initial-notary-delay-millis",
-            // The intial notary delay is set to 2500ms in the replica's
-            // default subnet configuration to help running tests.
-            // For our production network, we actually set them to 600ms.
-            "600",
-        ]);
+        if !config.has_ic_starter_flag("--consensus-pool-backend") {
+            cmd.args(&["--consensus-pool-backend", "rocksdb"]);
+        }
+        if !config.has_ic_starter_flag("--initial-notary-delay-millis") {
+            cmd.args(&[
+                "--initial-notary-delay-millis",
+                // The intial notary delay is set to 2500ms in the replica's
+                // default subnet configuration to help running tests.
+                // For our production network, we actually set them to 600ms.
+                "600",
+            ]);
+        }
+        cmd.args(&config.ic_starter_args);
 
         // This should agree with the value at
         // at https://gitlab.com/dfinity-lab/core/ic/-/blob/master/ic-os/guestos/rootfs/etc/systemd/system/ic-replica.service
         cmd.env("RUST_MIN_STACK", "8192000");
 
         cmd.stdout(std::process::Stdio::inherit());
-        cmd.stderr(std::process::Stdio::inherit());
+        // We forward stderr ourselves, to keep its last lines for crash reports.
+        cmd.stderr(std::process::Stdio::piped());
 
-        let mut done = false;
-        while !done {
+        let mut crashes = 0;
+        let mut last_report = None;
+        loop {
             if let Some(port_path) = write_port_to.as_ref() {
                 let _ = std::fs::remove_file(port_path);
             }
-            let last_start = std::time::Instant::now();
+            let last_start = Instant::now();
             debug!(logger, "Starting replica...");
             let mut child = cmd.spawn().expect("Could not start replica.");
 
+            let stderr_tail = Arc::new(Mutex::new(VecDeque::new()));
+            let stderr_closed = child.stderr.take().map(|stderr| {
+                forward_stderr(
+                    stderr,
+                    stderr_tail.clone(),
+                    restart_policy.crash_report_lines,
+                )
+            });
+
             std::fs::write(&replica_pid_path, "").expect("Could not write to replica-pid file.");
             std::fs::write(&replica_pid_path, child.id().to_string())
                 .expect("Could not write to replica-pid file.");
@@ -367,28 +581,82 @@ fn replica_start_thread(
             addr.do_send(signals::ReplicaRestarted { port });
 
             // This waits for the child to stop, or the receiver to receive a message.
-            // We don't restart the replica if done = true.
+            // We don't restart the replica once we are asked to stop.
             match wait_for_child_or_receiver(&mut child, &receiver) {
                 ChildOrReceiver::Receiver => {
                     debug!(logger, "Got signal to stop. Killing replica process...");
                     let _ = child.kill();
                     let _ = child.wait();
-                    done = true;
+                    break;
                 }
                 ChildOrReceiver::Child => {
-                    debug!(logger, "Replica process failed.");
-                    // Reset waiter if last start was over 2 seconds ago, and do not wait.
-                    if std::time::Instant::now().duration_since(last_start)
-                        >= Duration::from_secs(2)
-                    {
+                    let uptime = last_start.elapsed();
+                    let status = child.wait().ok();
+                    // Give the forwarding thread a moment to read the last lines.
+                    if let Some(stderr_closed) = &stderr_closed {
+                        let _ = stderr_closed.recv_timeout(Duration::from_secs(1));
+                    }
+
+                    if uptime >= restart_policy.healthy_uptime {
                         debug!(
                             logger,
-                            "Last replica seemed to have been healthy, not waiting..."
+                            "Last replica seemed to have been healthy, resetting crash count."
                         );
-                        waiter.start();
-                    } else {
-                        // Wait before we start it again.
-                        let _ = waiter.wait();
+                        crashes = 0;
+                    }
+                    crashes += 1;
+                    METRICS.inc_counter(
+                        "dfx_replica_crashes_total",
+                        "Times the replica exited unexpectedly.",
+                        &[],
+                    );
+
+                    let exit_status = status
+                        .as_ref()
+                        .map_or_else(|| "unknown".to_string(), ExitStatus::to_string);
+                    warn!(
+                        logger,
+                        "The replica process exited unexpectedly ({}).", exit_status
+                    );
+                    let report = CrashReport {
+                        time: chrono::Local::now().to_rfc3339(),
+                        crash_number: crashes,
+                        exit_status,
+                        exit_code: status.and_then(|status| status.code()),
+                        uptime_secs: uptime.as_secs(),
+                        stderr: stderr_tail
+                            .lock()
+                            .map(|tail| tail.iter().cloned().collect())
+                            .unwrap_or_default(),
+                        replica_config: &config,
+                    };
+                    match write_crash_report(&crash_reports_dir, &report) {
+                        Ok(path) => {
+                            info!(logger, "Crash report written to {}", path.to_string_lossy());
+                            last_report = Some(path);
+                        }
+                        Err(err) => warn!(logger, "{:#}", err),
+                    }
+
+                    if crashes > restart_policy.max_restarts {
+                        addr.do_send(signals::ReplicaCrashLimitReached {
+                            crashes,
+                            last_report,
+                        });
+                        break;
+                    }
+
+                    let backoff = restart_policy.backoff(crashes);
+                    info!(
+                        logger,
+                        "Restarting the replica in {:.1}s (restart {} of {}).",
+                        backoff.as_secs_f64(),
+                        crashes,
+                        restart_policy.max_restarts
+                    );
+                    // Wait before we start it again, unless we are asked to stop.
+                    if receiver.recv_timeout(backoff).is_ok() {
+                        break;
                     }
                 }
             }
@@ -400,3 +668,24 @@ fn replica_start_thread(
         .spawn(thread_handler)
         .map_err(DfxError::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let policy = RestartPolicy {
+            max_restarts: 10,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            healthy_uptime: Duration::from_secs(60),
+            crash_report_lines: 10,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(4), Duration::from_secs(5));
+        assert_eq!(policy.backoff(40), Duration::from_secs(5));
+    }
+}
@@ -112,6 +112,7 @@ pub fn exec(env: &dyn Environment, opts: BootstrapOpts) -> DfxResult {
                 proxy_port: webserver_bind.port(),
                 providers,
                 fetch_root_key: !network_descriptor.is_ic,
+                replica_via_webserver: false,
             };
 
             run_webserver(
@@ -119,6 +120,7 @@ pub fn exec(env: &dyn Environment, opts: BootstrapOpts) -> DfxResult {
                 build_output_root,
                 network_descriptor,
                 webserver_bind,
+                None,
             )?;
 
             let port_ready_subscribe = None;
@@ -1,11 +1,13 @@
 use crate::lib::builders::BuildConfig;
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
+use crate::lib::metrics::record_command_duration;
 use crate::lib::models::canister::CanisterPool;
 use crate::lib::models::canister_id_store::CanisterIdStore;
 use crate::lib::provider::create_agent_environment;
 
 use clap::Parser;
+use std::time::Instant;
 
 /// Builds all or specific canisters from the code in your project. By default, all canisters are built.
 #[derive(Parser)]
@@ -32,6 +34,7 @@ pub struct CanisterBuildOpts {
 
 pub fn exec(env: &dyn Environment, opts: CanisterBuildOpts) -> DfxResult {
     let env = create_agent_environment(env, opts.network)?;
+    let start = Instant::now();
 
     let logger = env.get_logger();
 
@@ -65,7 +68,7 @@ pub fn exec(env: &dyn Environment, opts: CanisterBuildOpts) -> DfxResult {
         let store = CanisterIdStore::for_env(&env)?;
         for canister in canister_pool.get_canister_list() {
             let canister_name = canister.get_name();
-            store.get(canister_name)?;
+            store.get_on_any_subnet(canister_name)?;
         }
     }
 
@@ -75,5 +78,9 @@ pub fn exec(env: &dyn Environment, opts: CanisterBuildOpts) -> DfxResult {
         &BuildConfig::from_config(&config)?.with_build_mode_check(build_mode_check),
     )?;
 
+    if let Err(e) = record_command_duration(&env, "build", start.elapsed()) {
+        slog::warn!(logger, "{:#}", e);
+    }
+
     Ok(())
 }
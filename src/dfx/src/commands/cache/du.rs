@@ -0,0 +1,53 @@
+use crate::config::cache;
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+
+use clap::Parser;
+
+/// Shows the disk space used by each cached version of dfx, and by each of its binaries.
+#[derive(Parser)]
+#[clap(name("du"))]
+pub struct CacheDuOpts {}
+
+/// Formats a number of bytes with a binary unit, such as `12.3 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+pub fn exec(_env: &dyn Environment, _opts: CacheDuOpts) -> DfxResult {
+    let mut versions = cache::list_versions()?;
+    versions.sort();
+    let mut total = 0;
+    for version in versions {
+        let (size, entries) = cache::version_disk_usage(&version.to_string())?;
+        total += size;
+        println!("{:<24}{:>12}", version, format_size(size));
+        for (name, size) in entries {
+            println!("  {:<22}{:>12}", name, format_size(size));
+        }
+    }
+    println!("{:<24}{:>12}", "total", format_size(total));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -4,8 +4,10 @@ use crate::lib::error::DfxResult;
 use clap::Parser;
 
 mod delete;
+mod du;
 mod install;
 mod list;
+mod prune;
 mod show;
 
 /// Manages the dfx version cache.
@@ -19,16 +21,20 @@ pub struct CacheOpts {
 #[derive(Parser)]
 pub enum SubCommand {
     Delete(delete::CacheDeleteOpts),
+    Du(du::CacheDuOpts),
     Install(install::CacheInstall),
     List(list::CacheListOpts),
+    Prune(prune::CachePruneOpts),
     Show(show::CacheShowOpts),
 }
 
 pub fn exec(env: &dyn Environment, opts: CacheOpts) -> DfxResult {
     match opts.subcmd {
         SubCommand::Delete(v) => delete::exec(env, v),
+        SubCommand::Du(v) => du::exec(env, v),
         SubCommand::Install(v) => install::exec(env, v),
         SubCommand::List(v) => list::exec(env, v),
+        SubCommand::Prune(v) => prune::exec(env, v),
         SubCommand::Show(v) => show::exec(env, v),
     }
 }
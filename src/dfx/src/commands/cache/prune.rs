@@ -0,0 +1,73 @@
+use crate::commands::cache::du::format_size;
+use crate::config::{cache, dfx_version};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::toolchain::{self, parse_version_requirement};
+
+use clap::Parser;
+use semver::Version;
+use std::collections::BTreeMap;
+
+/// Deletes the cached versions of dfx that are not in use. A version is in use if a toolchain
+/// points to it, if the current project asks for it, or if it is the running dfx.
+#[derive(Parser)]
+#[clap(name("prune"))]
+pub struct CachePruneOpts {
+    /// Also keeps this many of the latest cached versions.
+    #[clap(long, default_value("0"))]
+    keep_latest: usize,
+
+    /// Shows the versions that would be deleted, without deleting them.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+pub fn exec(env: &dyn Environment, opts: CachePruneOpts) -> DfxResult {
+    let mut versions = cache::list_versions()?;
+    versions.sort();
+
+    // The reason to keep a version, by version.
+    let mut keep: BTreeMap<Version, String> = BTreeMap::new();
+    for version in versions.iter().rev().take(opts.keep_latest) {
+        keep.entry(version.clone())
+            .or_insert_with(|| format!("one of the {} latest versions", opts.keep_latest));
+    }
+    for toolchain in toolchain::list_installed_toolchains()? {
+        if let Some(version) = toolchain.installed_version()? {
+            keep.insert(version, format!("used by toolchain {}", toolchain));
+        }
+    }
+    if let Some(requirement) = env.get_config().and_then(|c| c.get_config().get_dfx()) {
+        let req = parse_version_requirement(&requirement)?;
+        for version in versions.iter().filter(|v| req.matches(v)) {
+            keep.insert(
+                version.clone(),
+                format!("dfx.json asks for dfx {}", requirement),
+            );
+        }
+    }
+    keep.insert(env.get_version().clone(), "used here".to_string());
+    keep.insert(dfx_version().clone(), "running".to_string());
+
+    let mut freed = 0;
+    for version in &versions {
+        if let Some(reason) = keep.get(version) {
+            eprintln!("Keeping {} ({})", version, reason);
+            continue;
+        }
+        let (size, _) = cache::version_disk_usage(&version.to_string())?;
+        freed += size;
+        if opts.dry_run {
+            eprintln!("Would delete {} ({})", version, format_size(size));
+        } else {
+            cache::delete_version(&version.to_string())?;
+            eprintln!("Deleted {} ({})", version, format_size(size));
+        }
+    }
+    if opts.dry_run {
+        eprintln!("Pruning would free {}.", format_size(freed));
+    } else {
+        eprintln!("Freed {}.", format_size(freed));
+    }
+    Ok(())
+}
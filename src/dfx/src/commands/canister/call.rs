@@ -7,7 +7,10 @@ use crate::lib::operations::canister::get_local_cid_and_candid_path;
 use crate::lib::root_key::fetch_root_key_if_needed;
 use crate::lib::waiter::waiter_with_exponential_backoff;
 use crate::util::clap::validators::cycle_amount_validator;
-use crate::util::{blob_from_arguments, expiry_duration, get_candid_type, print_idl_blob};
+use crate::util::{
+    blob_from_arguments, check_blob_format, expiry_duration, get_candid_type,
+    get_candid_type_from_str, print_idl_blob, read_module_metadata,
+};
 
 use anyhow::{anyhow, bail, Context};
 use candid::{CandidType, Decode, Deserialize, Principal};
@@ -28,7 +31,7 @@ use std::str::FromStr;
 pub struct CanisterCallOpts {
     /// Specifies the name of the canister to build.
     /// You must specify either a canister name or the --all option.
-    canister_name: String,
+    pub(super) canister_name: String,
 
     /// Specifies the method name to call on the canister.
     method_name: String,
@@ -60,16 +63,21 @@ pub struct CanisterCallOpts {
 
     /// Specifies the format for displaying the method's return result.
     #[clap(long, conflicts_with("async"),
-        possible_values(&["idl", "raw", "pp"]))]
+        possible_values(&["idl", "raw", "pp", "json", "table"]))]
     output: Option<String>,
 
+    /// Specifies how blobs are encoded with `--output json`.
+    #[clap(long, requires("output"), possible_values(&["hex", "base64"]))]
+    blob_format: Option<String>,
+
     /// Specifies the amount of cycles to send on the call.
     /// Deducted from the wallet.
     #[clap(long, validator(cycle_amount_validator))]
     with_cycles: Option<String>,
 
     /// Provide the .did file with which to decode the response.  Overrides value from dfx.json
-    /// for project canisters.
+    /// for project canisters. If neither is available, the `candid:service` metadata of the
+    /// deployed canister is used.
     #[clap(long)]
     candid: Option<PathBuf>,
 }
@@ -193,6 +201,7 @@ pub async fn exec(
     opts: CanisterCallOpts,
     call_sender: &CallSender,
 ) -> DfxResult {
+    check_blob_format(opts.output.as_deref(), opts.blob_format.as_deref())?;
     let callee_canister = opts.canister_name.as_str();
     let method_name = opts.method_name.as_str();
     let canister_id_store = CanisterIdStore::for_env(env)?;
@@ -202,7 +211,6 @@ pub async fn exec(
             if let Some(canister_name) = canister_id_store.get_name(callee_canister) {
                 get_local_cid_and_candid_path(env, canister_name, Some(id))?
             } else {
-                // TODO fetch candid file from remote canister
                 (id, None)
             }
         }
@@ -215,7 +223,23 @@ pub async fn exec(
 
     let is_management_canister = canister_id == CanisterId::management_canister();
 
-    let method_type = maybe_candid_path.and_then(|path| get_candid_type(&path, method_name));
+    let agent = env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    fetch_root_key_if_needed(env).await?;
+
+    let method_type = match maybe_candid_path {
+        Some(path) => get_candid_type(&path, method_name),
+        None if is_management_canister => None,
+        None => {
+            // Canisters outside of the project may still expose their interface
+            // through the `candid:service` metadata section.
+            read_module_metadata(agent, canister_id, "candid:service")
+                .await
+                .and_then(|candid| get_candid_type_from_str(&candid, method_name))
+        }
+    };
     let is_query_method = method_type.as_ref().map(|(_, f)| f.is_query());
 
     let arguments = opts.argument.as_deref();
@@ -243,11 +267,6 @@ pub async fn exec(
     // Get the argument, get the type, convert the argument to the type and return
     // an error if any of it doesn't work.
     let arg_value = blob_from_arguments(arguments, opts.random.as_deref(), arg_type, &method_type)?;
-    let agent = env
-        .get_agent()
-        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
-
-    fetch_root_key_if_needed(env).await?;
 
     let timeout = expiry_duration();
 
@@ -293,7 +312,12 @@ pub async fn exec(
                 .context("Failed wallet call.")?
             }
         };
-        print_idl_blob(&blob, output_type, &method_type)?;
+        print_idl_blob(
+            &blob,
+            output_type,
+            opts.blob_format.as_deref(),
+            &method_type,
+        )?;
     } else if opts.r#async {
         let request_id = match call_sender {
             CallSender::SelectedId => {
@@ -357,7 +381,12 @@ pub async fn exec(
             }
         };
 
-        print_idl_blob(&blob, output_type, &method_type)?;
+        print_idl_blob(
+            &blob,
+            output_type,
+            opts.blob_format.as_deref(),
+            &method_type,
+        )?;
     }
 
     Ok(())
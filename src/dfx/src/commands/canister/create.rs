@@ -1,3 +1,4 @@
+use crate::lib::cycles_ledger::{get_cycles_ledger, CyclesSource};
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
 use crate::lib::ic_attributes::{
@@ -6,6 +7,7 @@ use crate::lib::ic_attributes::{
 use crate::lib::identity::identity_manager::IdentityManager;
 use crate::lib::identity::identity_utils::CallSender;
 use crate::lib::identity::Identity;
+use crate::lib::models::canister_id_store::CanisterIdStore;
 use crate::lib::operations::canister::create_canister;
 use crate::lib::root_key::fetch_root_key_if_needed;
 use crate::util::clap::validators::cycle_amount_validator;
@@ -25,7 +27,7 @@ use slog::info;
 #[derive(Parser)]
 pub struct CanisterCreateOpts {
     /// Specifies the canister name. Either this or the --all flag are required.
-    canister_name: Option<String>,
+    pub(super) canister_name: Option<String>,
 
     /// Creates all canisters configured in dfx.json.
     #[clap(long, required_unless_present("canister-name"))]
@@ -60,6 +62,23 @@ pub struct CanisterCreateOpts {
     /// Bypasses the Wallet canister.
     #[clap(long)]
     no_wallet: bool,
+
+    /// Where the cycles for the new canister come from: the selected identity's balance
+    /// on the cycles ledger, or its wallet. Defaults to defaults.cycles.source in dfx.json
+    /// for the network, or to the wallet.
+    #[clap(long, possible_values(&["identity", "wallet"]), conflicts_with("no-wallet"))]
+    cycles_source: Option<CyclesSource>,
+
+    /// Creates the canister on the named subnet of the local replica, as configured in
+    /// defaults.replica.subnets in dfx.json. The canister is created by the selected
+    /// identity rather than its wallet, which lives on the main subnet.
+    #[clap(
+        long,
+        requires("canister-name"),
+        conflicts_with("all"),
+        conflicts_with("cycles-source")
+    )]
+    pub(super) subnet: Option<String>,
 }
 
 pub async fn exec(
@@ -77,8 +96,18 @@ pub async fn exec(
     let config_interface = config.get_config();
     let network = env.get_network_descriptor().unwrap();
 
+    // On a named subnet, the dispatcher connects to its replica and sends as the selected identity.
+    let subnet = network.subnet.as_deref();
+    let no_wallet = opts.no_wallet || subnet.is_some();
+
+    let cycles_ledger = if no_wallet {
+        None
+    } else {
+        get_cycles_ledger(env, opts.cycles_source)?
+    };
+
     let proxy_sender;
-    if !opts.no_wallet && !matches!(call_sender, CallSender::Wallet(_)) {
+    if cycles_ledger.is_none() && !no_wallet && !matches!(call_sender, CallSender::Wallet(_)) {
         let wallet = Identity::get_or_create_wallet_canister(
             env,
             env.get_network_descriptor()
@@ -145,6 +174,7 @@ pub async fn exec(
             Some(canister_name),
         )
         .with_context(|| format!("Failed to read freezing threshold of {}.", canister_name))?;
+        check_subnet(env, canister_name, subnet)?;
         create_canister(
             env,
             canister_name,
@@ -157,8 +187,12 @@ pub async fn exec(
                 memory_allocation,
                 freezing_threshold,
             },
+            cycles_ledger,
         )
         .await?;
+        if let Some(subnet) = subnet {
+            CanisterIdStore::for_env(env)?.set_subnet(canister_name, subnet)?;
+        }
         Ok(())
     } else if opts.all {
         // Create all canisters.
@@ -213,6 +247,7 @@ pub async fn exec(
                         memory_allocation,
                         freezing_threshold,
                     },
+                    cycles_ledger,
                 )
                 .await?;
             }
@@ -222,3 +257,21 @@ pub async fn exec(
         unreachable!()
     }
 }
+
+/// A canister that was already created stays on the subnet it was created on.
+fn check_subnet(env: &dyn Environment, canister_name: &str, subnet: Option<&str>) -> DfxResult {
+    let store = CanisterIdStore::for_env(env)?;
+    if store.find(canister_name).is_some()
+        && store.get_subnet(canister_name).map(String::as_str) != subnet
+    {
+        bail!(
+            "Canister '{}' was already created on {}.",
+            canister_name,
+            store.get_subnet(canister_name).map_or_else(
+                || "the main subnet".to_string(),
+                |subnet| format!("subnet '{}'", subnet)
+            )
+        );
+    }
+    Ok(())
+}
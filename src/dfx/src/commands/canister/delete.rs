@@ -41,7 +41,7 @@ const MAX_MEMORY_ALLOCATION: u64 = 8589934592;
 pub struct CanisterDeleteOpts {
     /// Specifies the name of the canister to delete.
     /// You must specify either a canister name/id or the --all flag.
-    canister: Option<String>,
+    pub(super) canister: Option<String>,
 
     /// Deletes all of the canisters configured in the dfx.json file.
     #[clap(long, required_unless_present("canister"))]
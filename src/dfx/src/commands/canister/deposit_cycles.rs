@@ -1,3 +1,4 @@
+use crate::lib::cycles_ledger::{self, get_cycles_ledger, CyclesSource};
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
 use crate::lib::identity::identity_utils::CallSender;
@@ -7,7 +8,7 @@ use crate::lib::root_key::fetch_root_key_if_needed;
 use crate::util::clap::validators::cycle_amount_validator;
 use crate::util::expiry_duration;
 
-use anyhow::{bail, Context};
+use anyhow::{anyhow, bail, Context};
 use clap::Parser;
 use ic_types::Principal;
 use slog::info;
@@ -17,17 +18,23 @@ use std::time::Duration;
 #[derive(Parser)]
 pub struct DepositCyclesOpts {
     /// Specifies the amount of cycles to send on the call.
-    /// Deducted from the wallet.
+    /// Deducted from the wallet, or from the identity's balance on the cycles ledger.
     #[clap(validator(cycle_amount_validator))]
     cycles: String,
 
     /// Specifies the name or id of the canister to receive the cycles deposit.
     /// You must specify either a canister name/id or the --all option.
-    canister: Option<String>,
+    pub(super) canister: Option<String>,
 
     /// Deposit cycles to all of the canisters configured in the dfx.json file.
     #[clap(long, required_unless_present("canister"))]
     all: bool,
+
+    /// Where the cycles come from: the selected identity's balance on the cycles ledger,
+    /// or its wallet. Defaults to defaults.cycles.source in dfx.json for the network,
+    /// or to the wallet.
+    #[clap(long, possible_values(&["identity", "wallet"]))]
+    cycles_source: Option<CyclesSource>,
 }
 
 async fn deposit_cycles(
@@ -36,6 +43,7 @@ async fn deposit_cycles(
     timeout: Duration,
     call_sender: &CallSender,
     cycles: u128,
+    cycles_ledger: Option<Principal>,
 ) -> DfxResult {
     let log = env.get_logger();
     let canister_id_store = CanisterIdStore::for_env(env)?;
@@ -44,7 +52,15 @@ async fn deposit_cycles(
 
     info!(log, "Depositing {} cycles onto {}", cycles, canister,);
 
-    canister::deposit_cycles(env, canister_id, timeout, call_sender, cycles).await?;
+    match cycles_ledger {
+        Some(ledger) => {
+            let agent = env
+                .get_agent()
+                .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+            cycles_ledger::withdraw(agent, &ledger, canister_id, cycles, timeout).await?;
+        }
+        None => canister::deposit_cycles(env, canister_id, timeout, call_sender, cycles).await?,
+    }
 
     let status = canister::get_canister_status(env, canister_id, timeout, call_sender).await;
     if let Ok(status) = status {
@@ -64,7 +80,8 @@ pub async fn exec(
     opts: DepositCyclesOpts,
     call_sender: &CallSender,
 ) -> DfxResult {
-    if call_sender == &CallSender::SelectedId {
+    let cycles_ledger = get_cycles_ledger(env, opts.cycles_source)?;
+    if cycles_ledger.is_none() && call_sender == &CallSender::SelectedId {
         bail!("The deposit cycles call needs to proxied via the wallet canister. Please run this command using 'dfx canister --wallet <your wallet id> deposit-cycles <other arguments>'.");
     }
 
@@ -77,11 +94,11 @@ pub async fn exec(
     let timeout = expiry_duration();
 
     if let Some(canister) = opts.canister.as_deref() {
-        deposit_cycles(env, canister, timeout, call_sender, cycles).await
+        deposit_cycles(env, canister, timeout, call_sender, cycles, cycles_ledger).await
     } else if opts.all {
         if let Some(canisters) = &config.get_config().canisters {
             for canister in canisters.keys() {
-                deposit_cycles(env, canister, timeout, call_sender, cycles)
+                deposit_cycles(env, canister, timeout, call_sender, cycles, cycles_ledger)
                     .await
                     .with_context(|| format!("Failed to deposit cycles into {}.", canister))?;
             }
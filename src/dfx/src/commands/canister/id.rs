@@ -9,7 +9,7 @@ use ic_types::principal::Principal;
 #[derive(Parser)]
 pub struct CanisterIdOpts {
     /// Specifies the name of the canister.
-    canister: String,
+    pub(super) canister: String,
 }
 
 pub async fn exec(env: &dyn Environment, opts: CanisterIdOpts) -> DfxResult {
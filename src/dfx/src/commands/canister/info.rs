@@ -14,7 +14,7 @@ use std::convert::TryFrom;
 #[derive(Parser)]
 pub struct InfoOpts {
     /// Specifies the name or id of the canister to get its certified canister information.
-    canister: String,
+    pub(super) canister: String,
 }
 
 pub async fn exec(env: &dyn Environment, opts: InfoOpts) -> DfxResult {
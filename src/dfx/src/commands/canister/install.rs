@@ -5,6 +5,7 @@ use crate::lib::identity::identity_utils::CallSender;
 use crate::lib::models::canister_id_store::CanisterIdStore;
 use crate::lib::operations::canister::{install_canister, install_canister_wasm};
 use crate::lib::root_key::fetch_root_key_if_needed;
+use crate::lib::subnets::canister_subnet_environment;
 use crate::util::{blob_from_arguments, expiry_duration, get_candid_init_type};
 
 use anyhow::{anyhow, bail, Context};
@@ -22,7 +23,7 @@ use std::str::FromStr;
 #[derive(Parser, Clone)]
 pub struct CanisterInstallOpts {
     /// Specifies the canister to deploy. You must specify either canister name/id or the --all option.
-    canister: Option<String>,
+    pub(super) canister: Option<String>,
 
     /// Deploys all canisters configured in the project dfx.json files.
     #[clap(long, required_unless_present("canister"))]
@@ -132,6 +133,7 @@ pub async fn exec(
     } else if opts.all {
         // Install all canisters.
         let config = env.get_config_or_anyhow()?;
+        let selected_id = CallSender::SelectedId;
         if let Some(canisters) = &config.get_config().canisters {
             for canister in canisters.keys() {
                 let canister_is_remote = config
@@ -147,6 +149,18 @@ pub async fn exec(
 
                     continue;
                 }
+                // A canister on a named subnet of the local replica is installed at the
+                // replica of that subnet, by the selected identity.
+                let subnet_env = canister_subnet_environment(env, canister, timeout).await?;
+                let (env, agent, call_sender) = match &subnet_env {
+                    Some(subnet_env) => (
+                        subnet_env as &dyn Environment,
+                        subnet_env.get_agent().unwrap(),
+                        &selected_id,
+                    ),
+                    None => (env, agent, call_sender),
+                };
+                let canister_id_store = CanisterIdStore::for_env(env)?;
                 let canister_id =
                     Principal::from_text(canister).or_else(|_| canister_id_store.get(canister))?;
                 let canister_info = CanisterInfo::load(&config, canister, Some(canister_id))?;
@@ -0,0 +1,48 @@
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::models::canister_id_store::CanisterIdStore;
+use crate::lib::root_key::fetch_root_key_if_needed;
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use ic_types::Principal;
+use std::io::Write;
+
+/// Displays metadata in a canister.
+#[derive(Parser)]
+pub struct CanisterMetadataOpts {
+    /// Specifies the name or id of the canister to read the metadata from.
+    pub(super) canister: String,
+
+    /// Specifies the name of the metadata section to retrieve, e.g. `candid:service`.
+    metadata_name: String,
+}
+
+pub async fn exec(env: &dyn Environment, opts: CanisterMetadataOpts) -> DfxResult {
+    let agent = env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    let callee_canister = opts.canister.as_str();
+    let canister_id_store = CanisterIdStore::for_env(env)?;
+
+    let canister_id = Principal::from_text(callee_canister)
+        .or_else(|_| canister_id_store.get(callee_canister))?;
+
+    fetch_root_key_if_needed(env).await?;
+    let metadata = agent
+        .read_state_canister_metadata(canister_id, &opts.metadata_name, false)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to read `{}` metadata of canister {}.",
+                opts.metadata_name, canister_id
+            )
+        })?;
+
+    std::io::stdout()
+        .write_all(&metadata)
+        .context("Failed to write metadata to stdout.")?;
+
+    Ok(())
+}
@@ -1,8 +1,11 @@
-use crate::lib::environment::Environment;
+use crate::lib::environment::{AgentEnvironment, Environment};
 use crate::lib::error::DfxResult;
-use crate::lib::identity::identity_utils::call_sender;
-use crate::lib::provider::create_agent_environment;
+use crate::lib::identity::identity_utils::{call_sender, CallSender};
+use crate::lib::provider::get_network_descriptor;
+use crate::lib::subnets::{get_canister_subnet, subnet_network_descriptor};
+use crate::util::expiry_duration;
 
+use anyhow::bail;
 use clap::{Parser, Subcommand};
 use tokio::runtime::Runtime;
 
@@ -13,6 +16,7 @@ mod deposit_cycles;
 mod id;
 mod info;
 mod install;
+mod metadata;
 mod request_status;
 mod send;
 mod sign;
@@ -21,6 +25,7 @@ mod status;
 mod stop;
 mod uninstall_code;
 mod update_settings;
+mod watch_cycles;
 
 /// Manages canisters deployed on a network replica.
 #[derive(Parser)]
@@ -51,6 +56,7 @@ enum SubCommand {
     Id(id::CanisterIdOpts),
     Info(info::InfoOpts),
     Install(install::CanisterInstallOpts),
+    Metadata(metadata::CanisterMetadataOpts),
     RequestStatus(request_status::RequestStatusOpts),
     Send(send::CanisterSendOpts),
     Sign(sign::CanisterSignOpts),
@@ -59,14 +65,60 @@ enum SubCommand {
     Stop(stop::CanisterStopOpts),
     UninstallCode(uninstall_code::UninstallCodeOpts),
     UpdateSettings(update_settings::UpdateSettingsOpts),
+    WatchCycles(watch_cycles::WatchCyclesOpts),
+}
+
+impl SubCommand {
+    /// The canister the subcommand operates on, if it names a single one.
+    fn canister(&self) -> Option<&str> {
+        match self {
+            SubCommand::Call(v) => Some(&v.canister_name),
+            SubCommand::Create(v) => v.canister_name.as_deref(),
+            SubCommand::Delete(v) => v.canister.as_deref(),
+            SubCommand::DepositCycles(v) => v.canister.as_deref(),
+            SubCommand::Id(v) => Some(&v.canister),
+            SubCommand::Info(v) => Some(&v.canister),
+            SubCommand::Install(v) => v.canister.as_deref(),
+            SubCommand::Metadata(v) => Some(&v.canister),
+            SubCommand::RequestStatus(v) => Some(&v.canister),
+            SubCommand::Sign(v) => Some(&v.canister_name),
+            SubCommand::Start(v) => v.canister.as_deref(),
+            SubCommand::Status(v) => v.canister.as_deref(),
+            SubCommand::Stop(v) => v.canister.as_deref(),
+            SubCommand::UninstallCode(v) => v.canister.as_deref(),
+            SubCommand::UpdateSettings(v) => v.canister.as_deref(),
+            SubCommand::Send(_) | SubCommand::WatchCycles(_) => None,
+        }
+    }
 }
 
 pub fn exec(env: &dyn Environment, opts: CanisterOpts) -> DfxResult {
-    let agent_env = create_agent_environment(env, opts.network.clone())?;
+    let network_descriptor = get_network_descriptor(env, opts.network.clone())?;
+    // A canister created on a named subnet of the local replica is reached at the replica
+    // of that subnet, which has its own root key and knows nothing of the wallets.
+    let subnet = match &opts.subcmd {
+        SubCommand::Create(v) if v.subnet.is_some() => v.subnet.clone(),
+        subcmd => match subcmd.canister() {
+            Some(canister) => get_canister_subnet(&network_descriptor, canister)?,
+            None => None,
+        },
+    };
+    let network_descriptor = match &subnet {
+        Some(subnet) => subnet_network_descriptor(env, &network_descriptor, subnet)?,
+        None => network_descriptor,
+    };
+    let agent_env = AgentEnvironment::new(env, network_descriptor, expiry_duration())?;
     let runtime = Runtime::new().expect("Unable to create a runtime");
 
     runtime.block_on(async {
-        let call_sender = call_sender(&agent_env, &opts.wallet).await?;
+        let call_sender = match &subnet {
+            Some(subnet) if opts.wallet.is_some() => bail!(
+                "--wallet cannot be used on subnet '{}', because wallets live on the main subnet.",
+                subnet
+            ),
+            Some(_) => CallSender::SelectedId,
+            None => call_sender(&agent_env, &opts.wallet).await?,
+        };
         match opts.subcmd {
             SubCommand::Call(v) => call::exec(&agent_env, v, &call_sender).await,
             SubCommand::Create(v) => create::exec(&agent_env, v, &call_sender).await,
@@ -75,6 +127,7 @@ pub fn exec(env: &dyn Environment, opts: CanisterOpts) -> DfxResult {
             SubCommand::Id(v) => id::exec(&agent_env, v).await,
             SubCommand::Install(v) => install::exec(&agent_env, v, &call_sender).await,
             SubCommand::Info(v) => info::exec(&agent_env, v).await,
+            SubCommand::Metadata(v) => metadata::exec(&agent_env, v).await,
             SubCommand::RequestStatus(v) => request_status::exec(&agent_env, v).await,
             SubCommand::Send(v) => send::exec(&agent_env, v, &call_sender).await,
             SubCommand::Sign(v) => sign::exec(&agent_env, v, &call_sender).await,
@@ -85,6 +138,7 @@ pub fn exec(env: &dyn Environment, opts: CanisterOpts) -> DfxResult {
             SubCommand::UpdateSettings(v) => {
                 update_settings::exec(&agent_env, v, &call_sender).await
             }
+            SubCommand::WatchCycles(v) => watch_cycles::exec(&agent_env, v, &call_sender).await,
         }
     })
 }
@@ -4,7 +4,7 @@ use crate::lib::models::canister_id_store::CanisterIdStore;
 use crate::lib::root_key::fetch_root_key_if_needed;
 use crate::lib::waiter::waiter_with_exponential_backoff;
 use crate::util::clap::validators;
-use crate::util::print_idl_blob;
+use crate::util::{check_blob_format, print_idl_blob};
 
 use anyhow::{anyhow, Context};
 use clap::Parser;
@@ -28,15 +28,20 @@ pub struct RequestStatusOpts {
     /// If the call was proxied by the wallet,
     /// i.e. a `dfx canister --wallet=<ID> call --async` flag,
     /// specify the wallet canister id.
-    canister: String,
+    pub(super) canister: String,
 
     /// Specifies the format for displaying the method's return result.
     #[clap(long,
-        possible_values(&["idl", "raw", "pp"]))]
+        possible_values(&["idl", "raw", "pp", "json", "table"]))]
     output: Option<String>,
+
+    /// Specifies how blobs are encoded with `--output json`.
+    #[clap(long, requires("output"), possible_values(&["hex", "base64"]))]
+    blob_format: Option<String>,
 }
 
 pub async fn exec(env: &dyn Environment, opts: RequestStatusOpts) -> DfxResult {
+    check_blob_format(opts.output.as_deref(), opts.blob_format.as_deref())?;
     let request_id =
         RequestId::from_str(&opts.request_id[2..]).context("Invalid argument: request_id")?;
     let agent = env
@@ -101,6 +106,6 @@ pub async fn exec(env: &dyn Environment, opts: RequestStatusOpts) -> DfxResult {
     .map_err(DfxError::from)?;
 
     let output_type = opts.output.as_deref();
-    print_idl_blob(&blob, output_type, &None)?;
+    print_idl_blob(&blob, output_type, opts.blob_format.as_deref(), &None)?;
     Ok(())
 }
@@ -29,7 +29,7 @@ use std::time::SystemTime;
 #[derive(Parser)]
 pub struct CanisterSignOpts {
     /// Specifies the name of the canister to call.
-    canister_name: String,
+    pub(super) canister_name: String,
 
     /// Specifies the method name to call on the canister.
     method_name: String,
@@ -15,7 +15,7 @@ use std::time::Duration;
 #[derive(Parser)]
 pub struct CanisterStartOpts {
     /// Specifies the name or id of the canister to start. You must specify either a canister name/id or the --all flag.
-    canister: Option<String>,
+    pub(super) canister: Option<String>,
 
     /// Starts all of the canisters configured in the dfx.json file.
     #[clap(long, required_unless_present("canister"))]
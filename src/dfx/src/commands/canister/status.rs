@@ -17,7 +17,7 @@ use std::time::Duration;
 pub struct CanisterStatusOpts {
     /// Specifies the name of the canister to return information for.
     /// You must specify either a canister name or the --all flag.
-    canister: Option<String>,
+    pub(super) canister: Option<String>,
 
     /// Returns status information for all of the canisters configured in the dfx.json file.
     #[clap(long, required_unless_present("canister"))]
@@ -16,7 +16,7 @@ use std::time::Duration;
 pub struct UninstallCodeOpts {
     /// Specifies the name or id of the canister to uinstall.
     /// You must specify either a canister name/id or the --all option.
-    canister: Option<String>,
+    pub(super) canister: Option<String>,
 
     /// Uninstalls all of the canisters configured in the dfx.json file.
     #[clap(long, required_unless_present("canister"))]
@@ -23,7 +23,7 @@ use ic_types::principal::Principal as CanisterId;
 #[derive(Parser)]
 pub struct UpdateSettingsOpts {
     /// Specifies the canister name or id to update. You must specify either canister name/id or the --all option.
-    canister: Option<String>,
+    pub(super) canister: Option<String>,
 
     /// Updates the settings of all canisters configured in the project dfx.json files.
     #[clap(long, required_unless_present("canister"))]
@@ -0,0 +1,326 @@
+use crate::lib::cycles_ledger::{self, get_cycles_ledger, CyclesSource};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::identity::identity_utils::CallSender;
+use crate::lib::identity::Identity;
+use crate::lib::models::canister_id_store::CanisterIdStore;
+use crate::lib::operations::canister;
+use crate::lib::root_key::fetch_root_key_if_needed;
+use crate::util::clap::validators::cycle_amount_validator;
+use crate::util::expiry_duration;
+
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use fn_error_context::context;
+use ic_types::Principal;
+use num_traits::cast::ToPrimitive;
+use serde::Serialize;
+use slog::{info, warn};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Checks the cycles balance of canisters against their thresholds, and alerts or
+/// tops them up when they run low. Thresholds are set per canister in the
+/// watch_cycles field in dfx.json.
+#[derive(Parser)]
+pub struct WatchCyclesOpts {
+    /// Watches the canisters listed in this file, one canister name or id per line,
+    /// instead of all canisters in dfx.json.
+    #[clap(long)]
+    canisters_file: Option<PathBuf>,
+
+    /// The threshold in cycles of canisters that don't set watch_cycles.threshold.
+    #[clap(long, validator(cycle_amount_validator))]
+    threshold: Option<String>,
+
+    /// Tops up the canisters that are below their threshold.
+    #[clap(long)]
+    top_up: bool,
+
+    /// How many cycles to top up with when a canister doesn't set
+    /// watch_cycles.top_up. Defaults to its threshold.
+    #[clap(long, requires("top-up"), validator(cycle_amount_validator))]
+    top_up_amount: Option<String>,
+
+    /// Where the cycles for top-ups come from: the selected identity's wallet, or its
+    /// balance on the cycles ledger. Defaults to defaults.cycles.source in dfx.json
+    /// for the network, or to the wallet.
+    #[clap(long, requires("top-up"), possible_values(&["identity", "wallet"]))]
+    cycles_source: Option<CyclesSource>,
+
+    /// Posts an alert as JSON to this URL for each canister below its threshold.
+    #[clap(long)]
+    webhook: Option<String>,
+
+    /// Keeps watching, checking the canisters every this many seconds.
+    /// By default, the canisters are checked once, and the command fails if one of
+    /// them is left below its threshold or cannot be checked.
+    #[clap(long)]
+    interval: Option<u64>,
+}
+
+/// A canister that is watched, with its thresholds.
+struct WatchedCanister {
+    name: String,
+    canister_id: Principal,
+    threshold: Option<u128>,
+    top_up: Option<u128>,
+}
+
+/// The body of a webhook alert. The text field makes it readable by chat webhooks.
+#[derive(Serialize)]
+struct Alert {
+    text: String,
+    network: String,
+    canister: String,
+    canister_id: String,
+    cycles: String,
+    threshold: String,
+    topped_up: Option<String>,
+}
+
+pub async fn exec(
+    env: &dyn Environment,
+    opts: WatchCyclesOpts,
+    call_sender: &CallSender,
+) -> DfxResult {
+    fetch_root_key_if_needed(env).await?;
+
+    let canisters = get_watched_canisters(env, &opts)?;
+    if canisters.is_empty() {
+        bail!("There are no canisters to watch.");
+    }
+    let cycles_ledger = if opts.top_up {
+        get_cycles_ledger(env, opts.cycles_source)?
+    } else {
+        None
+    };
+    let wallet_sender;
+    let top_up_sender = if opts.top_up
+        && cycles_ledger.is_none()
+        && !matches!(call_sender, CallSender::Wallet(_))
+    {
+        let wallet = Identity::get_or_create_wallet(
+            env,
+            env.get_network_descriptor()
+                .expect("Couldn't get the network descriptor"),
+            env.get_selected_identity().expect("No selected identity"),
+            false,
+        )
+        .await?;
+        wallet_sender = CallSender::Wallet(wallet);
+        &wallet_sender
+    } else {
+        call_sender
+    };
+
+    match opts.interval {
+        None => {
+            let (low, failed) = check_canisters(
+                env,
+                &opts,
+                &canisters,
+                call_sender,
+                top_up_sender,
+                cycles_ledger,
+            )
+            .await?;
+            if failed > 0 {
+                bail!(
+                    "Failed to check {} canister(s), and {} canister(s) are below their cycles threshold.",
+                    failed,
+                    low
+                );
+            }
+            if low > 0 {
+                bail!("{} canister(s) are below their cycles threshold.", low);
+            }
+            Ok(())
+        }
+        Some(interval) => loop {
+            if let Err(err) = check_canisters(
+                env,
+                &opts,
+                &canisters,
+                call_sender,
+                top_up_sender,
+                cycles_ledger,
+            )
+            .await
+            {
+                warn!(env.get_logger(), "{:#}", err);
+            }
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        },
+    }
+}
+
+#[context("Failed to determine the canisters to watch.")]
+fn get_watched_canisters(
+    env: &dyn Environment,
+    opts: &WatchCyclesOpts,
+) -> DfxResult<Vec<WatchedCanister>> {
+    let config = env.get_config();
+    let names: Vec<String> = match &opts.canisters_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}.", path.display()))?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect(),
+        None => config
+            .as_ref()
+            .ok_or_else(|| anyhow!("Cannot find dfx configuration file in the current working directory. Pass --canisters-file to watch canisters outside of a project."))?
+            .get_config()
+            .canisters
+            .as_ref()
+            .map(|canisters| canisters.keys().cloned().collect())
+            .unwrap_or_default(),
+    };
+
+    // amounts have been validated by cycle_amount_validator
+    let default_threshold = opts
+        .threshold
+        .as_deref()
+        .map(|t| t.parse::<u128>().unwrap());
+    let default_top_up = opts
+        .top_up_amount
+        .as_deref()
+        .map(|t| t.parse::<u128>().unwrap());
+    let canister_id_store = CanisterIdStore::for_env(env)?;
+    names
+        .into_iter()
+        .map(|name| {
+            let canister_id =
+                Principal::from_text(&name).or_else(|_| canister_id_store.get(&name))?;
+            let watch_cycles = match &config {
+                Some(config) => config.get_config().get_watch_cycles(&name)?,
+                None => Default::default(),
+            };
+            Ok(WatchedCanister {
+                name,
+                canister_id,
+                threshold: watch_cycles.threshold.or(default_threshold),
+                top_up: watch_cycles.top_up.or(default_top_up),
+            })
+        })
+        .collect()
+}
+
+/// Checks each canister once. Returns how many canisters are left below their threshold,
+/// and how many could not be checked.
+async fn check_canisters(
+    env: &dyn Environment,
+    opts: &WatchCyclesOpts,
+    canisters: &[WatchedCanister],
+    call_sender: &CallSender,
+    top_up_sender: &CallSender,
+    cycles_ledger: Option<Principal>,
+) -> DfxResult<(usize, usize)> {
+    let log = env.get_logger();
+    let timeout = expiry_duration();
+    let mut low = 0;
+    let mut failed = 0;
+    for canister in canisters {
+        let status =
+            match canister::get_canister_status(env, canister.canister_id, timeout, call_sender)
+                .await
+            {
+                Ok(status) => status,
+                Err(err) => {
+                    warn!(
+                        log,
+                        "Failed to get the status of {}: {:#}", canister.name, err
+                    );
+                    failed += 1;
+                    continue;
+                }
+            };
+        let cycles = status.cycles.0.to_u128().unwrap_or(u128::MAX);
+        let threshold = match canister.threshold {
+            Some(threshold) => threshold,
+            None => {
+                info!(log, "{}: {} cycles", canister.name, cycles);
+                continue;
+            }
+        };
+        if cycles >= threshold {
+            info!(
+                log,
+                "{}: {} cycles, threshold {} cycles", canister.name, cycles, threshold
+            );
+            continue;
+        }
+
+        let mut topped_up = None;
+        if opts.top_up {
+            let amount = canister.top_up.unwrap_or(threshold);
+            let result = match cycles_ledger {
+                Some(ledger) => {
+                    let agent = env
+                        .get_agent()
+                        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+                    cycles_ledger::withdraw(agent, &ledger, canister.canister_id, amount, timeout)
+                        .await
+                        .map(|_| ())
+                }
+                None => {
+                    canister::deposit_cycles(
+                        env,
+                        canister.canister_id,
+                        timeout,
+                        top_up_sender,
+                        amount,
+                    )
+                    .await
+                }
+            };
+            match result {
+                Ok(()) => topped_up = Some(amount),
+                Err(err) => warn!(log, "Failed to top up {}: {:#}", canister.name, err),
+            }
+        }
+
+        let text = match topped_up {
+            Some(amount) => format!(
+                "{} had {} cycles, below its threshold of {} cycles. Topped up with {} cycles.",
+                canister.name, cycles, threshold, amount
+            ),
+            None => {
+                low += 1;
+                format!(
+                    "{} has {} cycles, below its threshold of {} cycles.",
+                    canister.name, cycles, threshold
+                )
+            }
+        };
+        println!("{}", text);
+        if let Some(url) = &opts.webhook {
+            let alert = Alert {
+                text,
+                network: env.get_network_descriptor().unwrap().name.clone(),
+                canister: canister.name.clone(),
+                canister_id: canister.canister_id.to_text(),
+                cycles: cycles.to_string(),
+                threshold: threshold.to_string(),
+                topped_up: topped_up.map(|amount| amount.to_string()),
+            };
+            if let Err(err) = post_alert(url, &alert).await {
+                warn!(log, "{:#}", err);
+            }
+        }
+    }
+    Ok((low, failed))
+}
+
+#[context("Failed to post an alert to {}.", url)]
+async fn post_alert(url: &str, alert: &Alert) -> DfxResult {
+    reqwest::Client::new()
+        .post(url)
+        .json(alert)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -1,10 +1,14 @@
+use crate::lib::cycles_ledger::{get_cycles_ledger, CyclesSource};
 use crate::lib::error::DfxResult;
 use crate::lib::identity::identity_utils::{call_sender, CallSender};
-use crate::lib::operations::canister::deploy_canisters;
+use crate::lib::metrics::record_command_duration;
+use crate::lib::nns_types::icpts::{ICPTs, ICP_SUBDIVIDABLE_BY};
+use crate::lib::operations::canister::{deploy_canisters, estimate_deploy_cost, DeployCostItem};
 use crate::lib::provider::create_agent_environment;
 use crate::lib::root_key::fetch_root_key_if_needed;
 use crate::lib::{environment::Environment, identity::Identity, named_canister};
 use crate::util::clap::validators::cycle_amount_validator;
+use crate::util::currency_conversion::as_cycles_with_current_exchange_rate;
 use crate::util::expiry_duration;
 use std::collections::BTreeMap;
 
@@ -17,8 +21,10 @@ use console::Style;
 use fn_error_context::context;
 use ic_types::Principal;
 use ic_utils::interfaces::management_canister::builders::InstallMode;
-use slog::info;
+use slog::{info, warn};
+use std::convert::TryFrom;
 use std::str::FromStr;
+use std::time::Instant;
 use tokio::runtime::Runtime;
 use url::Host::Domain;
 use url::Url;
@@ -74,10 +80,26 @@ pub struct DeployOpts {
     /// Bypasses the Wallet canister.
     #[clap(long, conflicts_with("wallet"))]
     no_wallet: bool,
+
+    /// Where the cycles for new canisters come from: the selected identity's balance
+    /// on the cycles ledger, or its wallet. Defaults to defaults.cycles.source in dfx.json
+    /// for the network, or to the wallet.
+    #[clap(long, possible_values(&["identity", "wallet"]), conflicts_with("no-wallet"))]
+    cycles_source: Option<CyclesSource>,
+
+    /// The most cycles the deploy may spend, by dfx's estimate. Overrides
+    /// defaults.cycles.max_cycles_per_deploy in dfx.json for the network.
+    #[clap(long, validator(cycle_amount_validator))]
+    budget: Option<String>,
+
+    /// Deploys even if the estimated cycles cost exceeds the budget.
+    #[clap(long)]
+    yes: bool,
 }
 
 pub fn exec(env: &dyn Environment, opts: DeployOpts) -> DfxResult {
     let env = create_agent_environment(env, opts.network)?;
+    let start = Instant::now();
 
     let timeout = expiry_duration();
     let canister_name = opts.canister_name.as_deref();
@@ -107,8 +129,16 @@ pub fn exec(env: &dyn Environment, opts: DeployOpts) -> DfxResult {
     let runtime = Runtime::new().expect("Unable to create a runtime");
 
     let call_sender = runtime.block_on(call_sender(&env, &opts.wallet))?;
+    let cycles_ledger = if opts.no_wallet {
+        None
+    } else {
+        get_cycles_ledger(&env, opts.cycles_source)?
+    };
     let proxy_sender;
-    let create_call_sender = if !opts.no_wallet && !matches!(call_sender, CallSender::Wallet(_)) {
+    let create_call_sender = if cycles_ledger.is_none()
+        && !opts.no_wallet
+        && !matches!(call_sender, CallSender::Wallet(_))
+    {
         let wallet = runtime.block_on(Identity::get_or_create_wallet_canister(
             &env,
             env.get_network_descriptor()
@@ -121,6 +151,29 @@ pub fn exec(env: &dyn Environment, opts: DeployOpts) -> DfxResult {
     } else {
         &call_sender
     };
+    let network = env.get_network_descriptor().unwrap();
+    // amount has been validated by cycle_amount_validator
+    let budget = match opts.budget.as_deref() {
+        Some(budget) => Some(budget.parse::<u128>().unwrap()),
+        None => env.get_config().and_then(|config| {
+            config
+                .get_config()
+                .get_defaults()
+                .get_cycles()
+                .get_max_cycles_per_deploy(&network.name)
+        }),
+    };
+    if budget.is_some() || network.is_ic {
+        let cost = estimate_deploy_cost(
+            &env,
+            canister_name,
+            with_cycles,
+            create_call_sender,
+            cycles_ledger,
+        )?;
+        runtime.block_on(check_cycles_budget(&env, &cost, budget, opts.yes))?;
+    }
+
     runtime.block_on(fetch_root_key_if_needed(&env))?;
 
     runtime.block_on(deploy_canisters(
@@ -134,11 +187,65 @@ pub fn exec(env: &dyn Environment, opts: DeployOpts) -> DfxResult {
         with_cycles,
         &call_sender,
         create_call_sender,
+        cycles_ledger,
     ))?;
 
+    if let Err(e) = record_command_duration(&env, "deploy", start.elapsed()) {
+        warn!(env.get_logger(), "{:#}", e);
+    }
+
     display_urls(&env)
 }
 
+/// Shows the estimated cycles cost of the deploy, and fails if it exceeds the budget
+/// unless `yes` is set.
+async fn check_cycles_budget(
+    env: &dyn Environment,
+    cost: &[DeployCostItem],
+    budget: Option<u128>,
+    yes: bool,
+) -> DfxResult {
+    let log = env.get_logger();
+    let total: u128 = cost.iter().map(|item| item.cycles).sum();
+
+    info!(log, "Estimated cycles cost of the deploy:");
+    for item in cost {
+        info!(log, "  {}: {} cycles", item.description, item.cycles);
+    }
+    let one_icp = ICPTs::from_e8s(ICP_SUBDIVIDABLE_BY);
+    match as_cycles_with_current_exchange_rate(&one_icp).await {
+        Ok(cycles_per_icp) if cycles_per_icp > 0 => {
+            let e8s = total * u128::from(ICP_SUBDIVIDABLE_BY) / cycles_per_icp;
+            let icp = u64::try_from(e8s).map_or(ICPTs::MAX, ICPTs::from_e8s);
+            info!(log, "  Total: {} cycles, about {}", total, icp);
+        }
+        Ok(_) => info!(log, "  Total: {} cycles", total),
+        Err(err) => {
+            info!(log, "  Total: {} cycles", total);
+            warn!(log, "Cannot show the cost in ICP: {:#}", err);
+        }
+    }
+
+    if let Some(budget) = budget {
+        if total > budget {
+            if !yes {
+                bail!(
+                    "The deploy is estimated to cost {} cycles, which exceeds the budget of {} cycles. Raise the budget with --budget, or pass --yes to deploy anyway.",
+                    total,
+                    budget
+                );
+            }
+            warn!(
+                log,
+                "The deploy is estimated to cost {} cycles, which exceeds the budget of {} cycles.",
+                total,
+                budget
+            );
+        }
+    }
+    Ok(())
+}
+
 fn display_urls(env: &dyn Environment) -> DfxResult {
     let config = env.get_config_or_anyhow()?;
     let network: &NetworkDescriptor = env.get_network_descriptor().unwrap();
@@ -39,7 +39,7 @@ pub fn exec(env: &dyn Environment, opts: GenerateOpts) -> DfxResult {
     let mut build_before_generate = false;
     for canister in canister_pool.get_canister_list() {
         let canister_name = canister.get_name();
-        let canister_id = store.get(canister_name)?;
+        let canister_id = store.get_on_any_subnet(canister_name)?;
         if let Some(info) = canister_pool.get_canister_info(&canister_id) {
             if info.get_type() == "motoko" {
                 build_before_generate = true;
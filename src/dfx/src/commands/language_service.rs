@@ -34,12 +34,13 @@ pub fn exec(env: &dyn Environment, opts: LanguageServiceOpts) -> DfxResult {
         Err(anyhow!("The `_language-service` command is meant to be run by editors to start a language service. You probably don't want to run it from a terminal.\nIf you _really_ want to, you can pass the --force-tty flag."))
     } else if let Some(config) = env.get_config() {
         let main_path = get_main_path(config.get_config(), opts.canister)?;
-        let packtool = &config
-            .get_config()
-            .get_defaults()
-            .get_build()
-            .get_packtool();
-        let package_arguments = package_arguments::load(env.get_cache().as_ref(), packtool)?;
+        let build_defaults = config.get_config().get_defaults().get_build();
+        let package_arguments = package_arguments::load(
+            env.get_cache().as_ref(),
+            &build_defaults.get_packtool(),
+            &build_defaults.get_packages(),
+            config.get_project_root(),
+        )?;
         run_ide(env, main_path, package_arguments)
     } else {
         Err(anyhow!("Cannot find dfx configuration file in the current working directory. Did you forget to create one?"))
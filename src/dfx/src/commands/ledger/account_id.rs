@@ -1,10 +1,10 @@
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
+use crate::lib::identity::subaccounts::{self, SubaccountStore};
 use crate::lib::models::canister_id_store::CanisterIdStore;
-use crate::lib::nns_types::account_identifier::{AccountIdentifier, Subaccount};
+use crate::lib::nns_types::account_identifier::AccountIdentifier;
 use anyhow::{anyhow, Context};
 use ic_types::Principal;
-use std::convert::TryFrom;
 
 use clap::Parser;
 
@@ -20,19 +20,27 @@ pub struct AccountIdOpts {
     pub of_canister: Option<String>,
 
     #[clap(long, value_name = "SUBACCOUNT")]
-    /// Subaccount identifier (64 character long hex string).
+    /// Subaccount identifier (64 character long hex string), or the name of a
+    /// subaccount of the selected identity from `dfx ledger subaccounts`.
+    /// Names are not accepted with --of-principal or --of-canister.
     pub subaccount: Option<String>,
 }
 
 pub async fn exec(env: &dyn Environment, opts: AccountIdOpts) -> DfxResult {
+    // Names belong to the selected identity, so they say nothing about the subaccounts
+    // of another principal or canister.
+    let of_other = opts.of_principal.is_some() || opts.of_canister.is_some();
     let subaccount = match opts.subaccount {
-        Some(sub_hex) => {
-            let sub_bytes = hex::decode(&sub_hex)
-                .with_context(|| format!("Subaccount '{}' is not a valid hex string", sub_hex))?;
-            let sub = Subaccount::try_from(&sub_bytes[..])
-                .with_context(|| format!("Subaccount '{}' is not 64 characters long", sub_hex))?;
-            Some(sub)
+        Some(subaccount) if of_other => {
+            let parsed = subaccounts::parse_hex(&subaccount).with_context(|| {
+                format!(
+                    "'{}' is not a subaccount in hex. Named subaccounts only apply to the selected identity.",
+                    subaccount
+                )
+            })?;
+            Some(parsed)
         }
+        Some(subaccount) => Some(SubaccountStore::for_env(env)?.resolve(&subaccount)?),
         None => None,
     };
     let principal = if let Some(principal) = opts.of_principal {
@@ -1,23 +1,26 @@
+use crate::commands::ledger::account_balance;
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
-use crate::lib::ledger_types::{AccountBalanceArgs, MAINNET_LEDGER_CANISTER_ID};
+use crate::lib::identity::subaccounts::SubaccountStore;
+use crate::lib::ledger_types::MAINNET_LEDGER_CANISTER_ID;
 use crate::lib::nns_types::account_identifier::AccountIdentifier;
-use crate::lib::nns_types::icpts::ICPTs;
 
-use anyhow::{anyhow, Context};
-use candid::{Decode, Encode};
+use anyhow::anyhow;
 use clap::Parser;
 use ic_types::Principal;
 use std::str::FromStr;
 
-const ACCOUNT_BALANCE_METHOD: &str = "account_balance_dfx";
-
 /// Prints the account balance of the user
 #[derive(Parser)]
 pub struct BalanceOpts {
     /// Specifies an AccountIdentifier to get the balance of
     of: Option<String>,
 
+    /// Gets the balance of a subaccount of the selected identity, given as a name from
+    /// `dfx ledger subaccounts` or in hex.
+    #[clap(long, conflicts_with("of"))]
+    subaccount: Option<String>,
+
     /// Canister ID of the ledger canister.
     #[clap(long)]
     ledger_canister_id: Option<Principal>,
@@ -27,10 +30,14 @@ pub async fn exec(env: &dyn Environment, opts: BalanceOpts) -> DfxResult {
     let sender = env
         .get_selected_identity_principal()
         .expect("Selected identity not instantiated.");
+    let subaccount = match &opts.subaccount {
+        Some(subaccount) => Some(SubaccountStore::for_env(env)?.resolve(subaccount)?),
+        None => None,
+    };
     let acc_id = opts
         .of
         .map_or_else(
-            || Ok(AccountIdentifier::new(sender, None)),
+            || Ok(AccountIdentifier::new(sender, subaccount)),
             |v| AccountIdentifier::from_str(&v),
         )
         .map_err(|err| anyhow!(err))?;
@@ -42,24 +49,7 @@ pub async fn exec(env: &dyn Environment, opts: BalanceOpts) -> DfxResult {
         .ledger_canister_id
         .unwrap_or(MAINNET_LEDGER_CANISTER_ID);
 
-    let result = agent
-        .query(&canister_id, ACCOUNT_BALANCE_METHOD)
-        .with_arg(
-            Encode!(&AccountBalanceArgs {
-                account: acc_id.to_string()
-            })
-            .context("Failed to encode arguments.")?,
-        )
-        .call()
-        .await
-        .with_context(|| {
-            format!(
-                "Failed query call to {} for method {}.",
-                canister_id, ACCOUNT_BALANCE_METHOD
-            )
-        })?;
-
-    let balance = Decode!(&result, ICPTs).context("Failed to decode response.")?;
+    let balance = account_balance(agent, &canister_id, &acc_id).await?;
 
     println!("{}", balance);
 
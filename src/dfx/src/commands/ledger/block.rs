@@ -0,0 +1,66 @@
+use crate::commands::ledger::{format_e8s, get_blocks, BlockRow};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::ledger_types::{BlockHeight, MAINNET_LEDGER_CANISTER_ID};
+use crate::lib::root_key::fetch_root_key_if_needed;
+
+use anyhow::{anyhow, bail};
+use clap::Parser;
+use ic_types::Principal;
+
+/// Prints the transaction recorded in a block of the ledger.
+#[derive(Parser)]
+pub struct BlockOpts {
+    /// The height of the block, as printed by `dfx ledger transfer`.
+    height: BlockHeight,
+
+    /// Specifies the format of the output.
+    #[clap(long, default_value("text"), possible_values(&["text", "json"]))]
+    output: String,
+
+    /// Canister ID of the ledger canister.
+    #[clap(long)]
+    ledger_canister_id: Option<Principal>,
+}
+
+pub async fn exec(env: &dyn Environment, opts: BlockOpts) -> DfxResult {
+    let agent = env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    fetch_root_key_if_needed(env).await?;
+
+    let canister_id = opts
+        .ledger_canister_id
+        .unwrap_or(MAINNET_LEDGER_CANISTER_ID);
+
+    let (chain_length, blocks) = get_blocks(agent, &canister_id, opts.height, 1).await?;
+    let block = match blocks
+        .into_iter()
+        .find(|(height, _)| *height == opts.height)
+    {
+        Some((_, block)) => block,
+        None if opts.height >= chain_length => bail!(
+            "Block {} does not exist yet, the ledger has {} blocks.",
+            opts.height,
+            chain_length
+        ),
+        None => bail!("The ledger did not return block {}.", opts.height),
+    };
+    let row = BlockRow::new(opts.height, &block)?;
+
+    if opts.output == "json" {
+        println!("{}", serde_json::to_string_pretty(&row)?);
+    } else {
+        println!("Height: {}", row.height);
+        println!("Timestamp: {}", row.timestamp);
+        println!("Operation: {}", row.operation);
+        println!("From: {}", row.from.as_deref().unwrap_or("-"));
+        println!("To: {}", row.to.as_deref().unwrap_or("-"));
+        println!("Amount: {}", format_e8s(row.amount_e8s));
+        println!("Fee: {}", format_e8s(row.fee_e8s));
+        println!("Memo: {}", row.memo);
+    }
+
+    Ok(())
+}
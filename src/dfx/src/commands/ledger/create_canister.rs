@@ -1,6 +1,7 @@
 use crate::commands::ledger::{get_icpts_from_args, transfer_and_notify};
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
+use crate::lib::ledger_journal::JournalOperation;
 use crate::lib::ledger_types::{CyclesResponse, Memo};
 use crate::lib::nns_types::account_identifier::Subaccount;
 use crate::lib::nns_types::icpts::{ICPTs, TRANSACTION_FEE};
@@ -55,16 +56,20 @@ pub async fn exec(env: &dyn Environment, opts: CreateCanisterOpts) -> DfxResult
 
     let memo = Memo(MEMO_CREATE_CANISTER);
 
-    let to_subaccount = Some(Subaccount::from(
-        &Principal::from_text(opts.controller).context("Failed to parse controller principal.")?,
-    ));
+    let controller =
+        Principal::from_text(opts.controller).context("Failed to parse controller principal.")?;
+    let to_subaccount = Some(Subaccount::from(&controller));
 
     let max_fee = opts
         .max_fee
         .map_or(Ok(TRANSACTION_FEE), |v| ICPTs::from_str(&v))
         .map_err(|err| anyhow!(err))?;
 
-    let result = transfer_and_notify(env, memo, amount, fee, to_subaccount, max_fee).await?;
+    let operation = JournalOperation::CreateCanister {
+        controller: controller.to_text(),
+    };
+    let result =
+        transfer_and_notify(env, operation, memo, amount, fee, to_subaccount, max_fee).await?;
 
     match result {
         CyclesResponse::CanisterCreated(v) => {
@@ -0,0 +1,102 @@
+use crate::commands::ledger::{get_blocks, BlockRow};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::ledger_types::MAINNET_LEDGER_CANISTER_ID;
+use crate::lib::nns_types::account_identifier::AccountIdentifier;
+use crate::lib::root_key::fetch_root_key_if_needed;
+
+use anyhow::anyhow;
+use clap::Parser;
+use ic_types::Principal;
+use std::str::FromStr;
+
+/// How many blocks are fetched per query while scanning the chain.
+const BATCH_SIZE: u64 = 1000;
+
+/// Lists the latest transactions that sent ICP to or from an account, newest first.
+/// The ledger has no index by account, so this scans the chain backwards from its tip,
+/// or from --start, through at most --max-blocks blocks.
+#[derive(Parser)]
+pub struct HistoryOpts {
+    /// The AccountIdentifier to list the transactions of. Defaults to the account of the selected identity.
+    #[clap(long)]
+    account: Option<String>,
+
+    /// The maximum number of transactions to list.
+    #[clap(long, default_value("10"))]
+    limit: usize,
+
+    /// The height of the newest block to scan. Defaults to the tip of the chain.
+    #[clap(long)]
+    start: Option<u64>,
+
+    /// The maximum number of blocks to scan.
+    #[clap(long, default_value("10000"))]
+    max_blocks: u64,
+
+    /// Specifies the format of the output.
+    #[clap(long, default_value("text"), possible_values(&["text", "json"]))]
+    output: String,
+
+    /// Canister ID of the ledger canister.
+    #[clap(long)]
+    ledger_canister_id: Option<Principal>,
+}
+
+pub async fn exec(env: &dyn Environment, opts: HistoryOpts) -> DfxResult {
+    let account = match &opts.account {
+        Some(account) => AccountIdentifier::from_str(account).map_err(|e| anyhow!(e))?,
+        None => AccountIdentifier::new(
+            env.get_selected_identity_principal()
+                .expect("Selected identity not instantiated."),
+            None,
+        ),
+    };
+    let agent = env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    fetch_root_key_if_needed(env).await?;
+
+    let canister_id = opts
+        .ledger_canister_id
+        .unwrap_or(MAINNET_LEDGER_CANISTER_ID);
+
+    let (tip, _) = get_blocks(agent, &canister_id, 0, 0).await?;
+    let mut end = match opts.start {
+        Some(start) => tip.min(start.saturating_add(1)),
+        None => tip,
+    };
+    let oldest = end.saturating_sub(opts.max_blocks);
+    let mut rows = vec![];
+    while end > oldest && rows.len() < opts.limit {
+        let start = end.saturating_sub(BATCH_SIZE).max(oldest);
+        let (_, blocks) = get_blocks(agent, &canister_id, start, end - start).await?;
+        for (height, block) in blocks.iter().rev() {
+            let row = BlockRow::new(*height, block)?;
+            if row.involves(&account) && rows.len() < opts.limit {
+                rows.push(row);
+            }
+        }
+        end = start;
+    }
+    if end > 0 && end == oldest && rows.len() < opts.limit {
+        eprintln!(
+            "Stopped after scanning {} blocks. Pass --start {} to scan older blocks, or raise --max-blocks.",
+            opts.max_blocks,
+            end - 1
+        );
+    }
+
+    if opts.output == "json" {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else if rows.is_empty() {
+        eprintln!("No transactions found for {}.", account);
+    } else {
+        for row in rows {
+            println!("{}", row.to_line());
+        }
+    }
+
+    Ok(())
+}
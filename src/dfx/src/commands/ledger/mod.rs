@@ -1,8 +1,11 @@
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
+use crate::lib::ledger_journal::{Journal, JournalEntry, JournalOperation};
 use crate::lib::ledger_types::{
-    AccountIdBlob, BlockHeight, CyclesResponse, Memo, NotifyCanisterArgs, TimeStamp, TransferArgs,
-    TransferError, TransferResult, MAINNET_CYCLE_MINTER_CANISTER_ID, MAINNET_LEDGER_CANISTER_ID,
+    AccountBalanceArgs, Block, BlockHeight, CyclesResponse, GetBlocksArgs, Memo,
+    NotifyCanisterArgs, Operation, QueryArchiveResult, QueryBlocksResponse, TimeStamp,
+    TransferArgs, TransferError, TransferResult, MAINNET_CYCLE_MINTER_CANISTER_ID,
+    MAINNET_LEDGER_CANISTER_ID,
 };
 use crate::lib::nns_types::account_identifier::{AccountIdentifier, Subaccount};
 use crate::lib::nns_types::icpts::ICPTs;
@@ -13,24 +16,33 @@ use crate::util::expiry_duration;
 
 use anyhow::{anyhow, bail, Context};
 use candid::{Decode, Encode};
+use chrono::{TimeZone, Utc};
 use clap::Parser;
 use fn_error_context::context;
 use garcon::{Delay, Waiter};
 use ic_agent::agent_error::HttpErrorPayload;
 use ic_agent::{Agent, AgentError};
 use ic_types::Principal;
+use serde::Serialize;
+use std::convert::TryFrom;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::runtime::Runtime;
 
 const TRANSFER_METHOD: &str = "transfer";
 const NOTIFY_METHOD: &str = "notify_dfx";
+const QUERY_BLOCKS_METHOD: &str = "query_blocks";
+const ACCOUNT_BALANCE_METHOD: &str = "account_balance_dfx";
 
 mod account_id;
 mod balance;
+mod block;
 mod create_canister;
 mod fabricate_cycles;
+mod history;
 mod notify;
+mod pending;
+mod subaccounts;
 mod top_up;
 mod transfer;
 
@@ -50,9 +62,13 @@ pub struct LedgerOpts {
 enum SubCommand {
     AccountId(account_id::AccountIdOpts),
     Balance(balance::BalanceOpts),
+    Block(block::BlockOpts),
     CreateCanister(create_canister::CreateCanisterOpts),
     FabricateCycles(fabricate_cycles::FabricateCyclesOpts),
+    History(history::HistoryOpts),
     Notify(notify::NotifyOpts),
+    Pending(pending::PendingOpts),
+    Subaccounts(subaccounts::SubaccountsOpts),
     TopUp(top_up::TopUpOpts),
     Transfer(transfer::TransferOpts),
 }
@@ -64,9 +80,13 @@ pub fn exec(env: &dyn Environment, opts: LedgerOpts) -> DfxResult {
         match opts.subcmd {
             SubCommand::AccountId(v) => account_id::exec(&agent_env, v).await,
             SubCommand::Balance(v) => balance::exec(&agent_env, v).await,
+            SubCommand::Block(v) => block::exec(&agent_env, v).await,
             SubCommand::CreateCanister(v) => create_canister::exec(&agent_env, v).await,
             SubCommand::FabricateCycles(v) => fabricate_cycles::exec(&agent_env, v).await,
+            SubCommand::History(v) => history::exec(&agent_env, v).await,
             SubCommand::Notify(v) => notify::exec(&agent_env, v).await,
+            SubCommand::Pending(v) => pending::exec(&agent_env, v).await,
+            SubCommand::Subaccounts(v) => subaccounts::exec(&agent_env, v).await,
             SubCommand::TopUp(v) => top_up::exec(&agent_env, v).await,
             SubCommand::Transfer(v) => transfer::exec(&agent_env, v).await,
         }
@@ -105,20 +125,38 @@ fn get_icpts_from_args(
     }
 }
 
+#[context("Failed to get the balance of account {}.", account)]
+async fn account_balance(
+    agent: &Agent,
+    canister_id: &Principal,
+    account: &AccountIdentifier,
+) -> DfxResult<ICPTs> {
+    let result = agent
+        .query(canister_id, ACCOUNT_BALANCE_METHOD)
+        .with_arg(
+            Encode!(&AccountBalanceArgs {
+                account: account.to_string()
+            })
+            .context("Failed to encode arguments.")?,
+        )
+        .call()
+        .await
+        .with_context(|| {
+            format!(
+                "Failed query call to {} for method {}.",
+                canister_id, ACCOUNT_BALANCE_METHOD
+            )
+        })?;
+
+    Decode!(&result, ICPTs).context("Failed to decode response.")
+}
+
 #[context("Failed to transfer funds.")]
 pub async fn transfer(
     agent: &Agent,
     canister_id: &Principal,
-    memo: Memo,
-    amount: ICPTs,
-    fee: ICPTs,
-    to: AccountIdBlob,
+    args: &TransferArgs,
 ) -> DfxResult<BlockHeight> {
-    let timestamp_nanos = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos() as u64;
-
     let mut waiter = Delay::builder()
         .with(Delay::count_timeout(30))
         .exponential_backoff_capped(
@@ -132,17 +170,7 @@ pub async fn transfer(
     let block_height: BlockHeight = loop {
         match agent
             .update(canister_id, TRANSFER_METHOD)
-            .with_arg(
-                Encode!(&TransferArgs {
-                    memo,
-                    amount,
-                    fee,
-                    from_subaccount: None,
-                    to,
-                    created_at_time: Some(TimeStamp { timestamp_nanos }),
-                })
-                .context("Failed to encode arguments.")?,
-            )
+            .with_arg(Encode!(args).context("Failed to encode arguments.")?)
             .call_and_wait(waiter_with_timeout(expiry_duration()))
             .await
         {
@@ -170,27 +198,105 @@ pub async fn transfer(
     Ok(block_height)
 }
 
-#[context("Failed to perform transfer_and_notify.")]
-async fn transfer_and_notify(
+/// Creates the journal entry for a transfer that is about to be sent.
+fn new_journal_entry(
     env: &dyn Environment,
+    ledger: &Principal,
+    operation: JournalOperation,
     memo: Memo,
     amount: ICPTs,
     fee: ICPTs,
-    to_subaccount: Option<Subaccount>,
-    max_fee: ICPTs,
-) -> DfxResult<CyclesResponse> {
-    let agent = env
-        .get_agent()
-        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
-
-    fetch_root_key_if_needed(env).await?;
-
-    let to = AccountIdentifier::new(MAINNET_CYCLE_MINTER_CANISTER_ID, to_subaccount).to_address();
+    to: &AccountIdentifier,
+) -> DfxResult<JournalEntry> {
+    let network = env
+        .get_network_descriptor()
+        .ok_or_else(|| anyhow!("Cannot get network descriptor from environment."))?;
+    Ok(JournalEntry {
+        network: network.name.clone(),
+        ledger: ledger.to_text(),
+        operation,
+        memo: memo.0,
+        created_at_time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64,
+        amount_e8s: amount.get_e8s(),
+        fee_e8s: fee.get_e8s(),
+        to: to.to_hex(),
+        from_subaccount: None,
+        max_fee_e8s: None,
+        block_height: None,
+    })
+}
 
-    let block_height = transfer(agent, &MAINNET_LEDGER_CANISTER_ID, memo, amount, fee, to).await?;
+/// Sends the transfer of a journal entry, and records its block height in the journal.
+/// Sending the same entry again is deduplicated by the ledger as long as its
+/// created_at_time is within the ledger's transaction window.
+/// If the ledger rejects the transfer, the entry is removed from the journal, except when it
+/// is too old: the ledger then no longer tells whether an earlier send was recorded.
+#[context("Failed to send the transfer of journal entry {}.", entry.created_at_time)]
+async fn send_journaled_transfer(
+    agent: &Agent,
+    journal: &mut Journal,
+    entry: &JournalEntry,
+) -> DfxResult<BlockHeight> {
+    let ledger = Principal::from_text(&entry.ledger)?;
+    let to = AccountIdentifier::from_hex(&entry.to)
+        .map_err(|e| anyhow!(e))?
+        .to_address();
+    let from_subaccount = match &entry.from_subaccount {
+        Some(hex) => Some(
+            Subaccount::try_from(&hex::decode(hex)?[..])
+                .context("The journal has an invalid subaccount.")?,
+        ),
+        None => None,
+    };
+    let args = TransferArgs {
+        memo: Memo(entry.memo),
+        amount: ICPTs::from_e8s(entry.amount_e8s),
+        fee: ICPTs::from_e8s(entry.fee_e8s),
+        from_subaccount,
+        to,
+        created_at_time: Some(TimeStamp {
+            timestamp_nanos: entry.created_at_time,
+        }),
+    };
+    let block_height = match transfer(agent, &ledger, &args).await {
+        Ok(block_height) => block_height,
+        Err(err) => {
+            match err.downcast_ref::<TransferError>() {
+                Some(TransferError::TxTooOld { .. }) | None => {}
+                Some(_) => journal.remove(entry.created_at_time)?,
+            }
+            return Err(err);
+        }
+    };
+    journal.set_block_height(entry.created_at_time, block_height)?;
+    Ok(block_height)
+}
 
-    println!("Transfer sent at BlockHeight: {}", block_height);
+/// Writes the entry to the journal of the selected identity, then sends its transfer.
+async fn journaled_transfer(
+    env: &dyn Environment,
+    agent: &Agent,
+    entry: JournalEntry,
+) -> DfxResult<(Journal, BlockHeight)> {
+    let mut journal = Journal::for_env(env)?;
+    journal.add(entry.clone())?;
+    let block_height = send_journaled_transfer(agent, &mut journal, &entry).await?;
+    Ok((journal, block_height))
+}
 
+#[context(
+    "Failed to notify the cycles minting canister of block {}.",
+    block_height
+)]
+async fn notify(
+    agent: &Agent,
+    block_height: BlockHeight,
+    max_fee: ICPTs,
+    to_subaccount: Option<Subaccount>,
+) -> DfxResult<CyclesResponse> {
     let result = agent
         .update(&MAINNET_LEDGER_CANISTER_ID, NOTIFY_METHOD)
         .with_arg(
@@ -211,7 +317,177 @@ async fn transfer_and_notify(
     Ok(result)
 }
 
-fn retryable(agent_error: &AgentError) -> bool {
+#[context("Failed to perform transfer_and_notify.")]
+async fn transfer_and_notify(
+    env: &dyn Environment,
+    operation: JournalOperation,
+    memo: Memo,
+    amount: ICPTs,
+    fee: ICPTs,
+    to_subaccount: Option<Subaccount>,
+    max_fee: ICPTs,
+) -> DfxResult<CyclesResponse> {
+    let agent = env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    fetch_root_key_if_needed(env).await?;
+
+    let to = AccountIdentifier::new(MAINNET_CYCLE_MINTER_CANISTER_ID, to_subaccount);
+    let entry = JournalEntry {
+        max_fee_e8s: Some(max_fee.get_e8s()),
+        ..new_journal_entry(
+            env,
+            &MAINNET_LEDGER_CANISTER_ID,
+            operation,
+            memo,
+            amount,
+            fee,
+            &to,
+        )?
+    };
+
+    let (mut journal, block_height) = journaled_transfer(env, agent, entry.clone()).await?;
+
+    println!("Transfer sent at BlockHeight: {}", block_height);
+
+    let result = notify(agent, block_height, max_fee, to_subaccount).await?;
+    journal.remove(entry.created_at_time)?;
+    Ok(result)
+}
+
+/// Returns the length of the chain and the blocks in `[start, start + length)` that
+/// exist, fetching the archived ones from the archive canisters.
+#[context("Failed to get blocks {} to {} from the ledger.", start, start + length)]
+async fn get_blocks(
+    agent: &Agent,
+    canister_id: &Principal,
+    start: BlockHeight,
+    length: u64,
+) -> DfxResult<(u64, Vec<(BlockHeight, Block)>)> {
+    let result = agent
+        .query(canister_id, QUERY_BLOCKS_METHOD)
+        .with_arg(Encode!(&GetBlocksArgs { start, length }).context("Failed to encode arguments.")?)
+        .call()
+        .await
+        .with_context(|| {
+            format!(
+                "Failed query call to {} for method {}.",
+                canister_id, QUERY_BLOCKS_METHOD
+            )
+        })?;
+    let response = Decode!(&result, QueryBlocksResponse).context("Failed to decode response.")?;
+
+    let mut blocks = vec![];
+    for range in response.archived_blocks {
+        let result = agent
+            .query(&range.callback.principal, &range.callback.method)
+            .with_arg(
+                Encode!(&GetBlocksArgs {
+                    start: range.start,
+                    length: range.length,
+                })
+                .context("Failed to encode arguments.")?,
+            )
+            .call()
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed query call to archive {} for method {}.",
+                    range.callback.principal, range.callback.method
+                )
+            })?;
+        let archived = Decode!(&result, QueryArchiveResult)
+            .context("Failed to decode archive response.")?
+            .map_err(|e| anyhow!("The archive {} failed: {}", range.callback.principal, e))?;
+        blocks.extend((range.start..).zip(archived.blocks));
+    }
+    blocks.extend((response.first_block_index..).zip(response.blocks));
+    blocks.sort_by_key(|(height, _)| *height);
+    Ok((response.chain_length, blocks))
+}
+
+/// A block decoded into the fields an account statement needs. Amounts are in e8s.
+#[derive(Serialize)]
+struct BlockRow {
+    height: BlockHeight,
+    timestamp: String,
+    operation: &'static str,
+    from: Option<String>,
+    to: Option<String>,
+    amount_e8s: Option<u64>,
+    fee_e8s: Option<u64>,
+    memo: u64,
+}
+
+impl BlockRow {
+    fn new(height: BlockHeight, block: &Block) -> DfxResult<Self> {
+        let account = |bytes: &[u8]| {
+            AccountIdentifier::from_slice(bytes)
+                .map(|id| id.to_hex())
+                .map_err(|e| anyhow!("Block {} has an invalid account: {}", height, e))
+        };
+        let (operation, from, to, amount, fee) = match &block.transaction.operation {
+            None => ("none", None, None, None, None),
+            Some(Operation::Mint { to, amount }) => {
+                ("mint", None, Some(account(to)?), Some(*amount), None)
+            }
+            Some(Operation::Burn { from, amount }) => {
+                ("burn", Some(account(from)?), None, Some(*amount), None)
+            }
+            Some(Operation::Transfer {
+                from,
+                to,
+                amount,
+                fee,
+            }) => (
+                "transfer",
+                Some(account(from)?),
+                Some(account(to)?),
+                Some(*amount),
+                Some(*fee),
+            ),
+        };
+        Ok(BlockRow {
+            height,
+            timestamp: Utc
+                .timestamp_nanos(block.timestamp.timestamp_nanos as i64)
+                .to_rfc3339(),
+            operation,
+            from,
+            to,
+            amount_e8s: amount.map(ICPTs::get_e8s),
+            fee_e8s: fee.map(ICPTs::get_e8s),
+            memo: block.transaction.memo.0,
+        })
+    }
+
+    fn involves(&self, account: &AccountIdentifier) -> bool {
+        let account = account.to_hex();
+        self.from.as_ref() == Some(&account) || self.to.as_ref() == Some(&account)
+    }
+
+    /// The row on one line: height, time, operation, accounts, amount and fee.
+    fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {} -> {} {} (fee {}) memo {}",
+            self.height,
+            self.timestamp,
+            self.operation,
+            self.from.as_deref().unwrap_or("-"),
+            self.to.as_deref().unwrap_or("-"),
+            format_e8s(self.amount_e8s),
+            format_e8s(self.fee_e8s),
+            self.memo
+        )
+    }
+}
+
+fn format_e8s(e8s: Option<u64>) -> String {
+    e8s.map_or_else(|| "-".to_string(), |e8s| ICPTs::from_e8s(e8s).to_string())
+}
+
+pub fn retryable(agent_error: &AgentError) -> bool {
     match agent_error {
         AgentError::ReplicaError {
             reject_code,
@@ -0,0 +1,160 @@
+use crate::commands::ledger::{notify, send_journaled_transfer};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::ledger_journal::{Journal, JournalEntry, JournalOperation};
+use crate::lib::ledger_types::{BlockHeight, CyclesResponse};
+use crate::lib::nns_types::account_identifier::Subaccount;
+use crate::lib::nns_types::icpts::{ICPTs, TRANSACTION_FEE};
+use crate::lib::root_key::fetch_root_key_if_needed;
+
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use ic_agent::Agent;
+use ic_types::Principal;
+
+/// Lists the ICP transfers, top-ups and canister creations of the selected identity
+/// that did not complete on this network, and resumes them.
+#[derive(Parser)]
+pub struct PendingOpts {
+    /// Resumes the pending operations: transfers that may not have been recorded are
+    /// sent again, which the ledger deduplicates, and recorded ones are notified.
+    #[clap(long, conflicts_with("discard"))]
+    resume: bool,
+
+    /// Removes the operation with this id from the journal without resuming it.
+    #[clap(long, value_name = "ID")]
+    discard: Option<u64>,
+}
+
+pub async fn exec(env: &dyn Environment, opts: PendingOpts) -> DfxResult {
+    let mut journal = Journal::for_env(env)?;
+    let network = env
+        .get_network_descriptor()
+        .ok_or_else(|| anyhow!("Cannot get network descriptor from environment."))?
+        .name
+        .clone();
+    let entries: Vec<JournalEntry> = journal
+        .entries()
+        .iter()
+        .filter(|entry| entry.network == network)
+        .cloned()
+        .collect();
+
+    if let Some(id) = opts.discard {
+        if !entries.iter().any(|entry| entry.created_at_time == id) {
+            bail!(
+                "There is no pending operation {} on network {}.",
+                id,
+                network
+            );
+        }
+        journal.remove(id)?;
+        println!("Discarded pending operation {}.", id);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No pending ledger operations.");
+        return Ok(());
+    }
+    for entry in &entries {
+        println!("{}", describe(entry));
+    }
+    if !opts.resume {
+        return Ok(());
+    }
+
+    let agent = env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    fetch_root_key_if_needed(env).await?;
+
+    let mut failed = 0;
+    for entry in &entries {
+        if let Err(err) = resume(agent, &mut journal, entry).await {
+            eprintln!("{:?}", err);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!(
+            "{} of {} pending operations could not be completed.",
+            failed,
+            entries.len()
+        );
+    }
+    Ok(())
+}
+
+fn describe(entry: &JournalEntry) -> String {
+    let operation = match &entry.operation {
+        JournalOperation::Transfer => format!("transfer to {}", entry.to),
+        JournalOperation::TopUp { canister } => format!("top-up of {}", canister),
+        JournalOperation::CreateCanister { controller } => {
+            format!("canister creation for {}", controller)
+        }
+    };
+    let status = match entry.block_height {
+        Some(height) => format!("recorded at BlockHeight {}", height),
+        None => "not known to be recorded".to_string(),
+    };
+    format!(
+        "{} {} of {}, {}",
+        entry.created_at_time,
+        operation,
+        ICPTs::from_e8s(entry.amount_e8s),
+        status
+    )
+}
+
+async fn resume(agent: &Agent, journal: &mut Journal, entry: &JournalEntry) -> DfxResult {
+    let block_height: BlockHeight = match entry.block_height {
+        Some(height) => height,
+        None => {
+            let height = send_journaled_transfer(agent, journal, entry)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Operation {} could not be resumed. If the ledger reports that it is too old, \
+                         check `dfx ledger history` and remove it with `dfx ledger pending --discard {}`.",
+                        entry.created_at_time, entry.created_at_time
+                    )
+                })?;
+            println!(
+                "Operation {}: transfer recorded at BlockHeight: {}",
+                entry.created_at_time, height
+            );
+            height
+        }
+    };
+
+    let to_principal = match &entry.operation {
+        JournalOperation::Transfer => None,
+        JournalOperation::TopUp { canister } => Some(canister),
+        JournalOperation::CreateCanister { controller } => Some(controller),
+    };
+    if let Some(to_principal) = to_principal {
+        let to_subaccount = Some(Subaccount::from(&Principal::from_text(to_principal)?));
+        let max_fee = entry.max_fee_e8s.map_or(TRANSACTION_FEE, ICPTs::from_e8s);
+        match notify(agent, block_height, max_fee, to_subaccount).await? {
+            CyclesResponse::ToppedUp(()) => {
+                println!(
+                    "Operation {}: canister was topped up!",
+                    entry.created_at_time
+                )
+            }
+            CyclesResponse::CanisterCreated(v) => println!(
+                "Operation {}: canister created with id: {:?}",
+                entry.created_at_time,
+                v.to_text()
+            ),
+            CyclesResponse::Refunded(msg, _) => println!(
+                "Operation {}: refunded with message: {}",
+                entry.created_at_time, msg
+            ),
+        }
+    }
+
+    journal.remove(entry.created_at_time)
+}
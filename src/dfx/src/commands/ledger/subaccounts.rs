@@ -0,0 +1,98 @@
+use crate::commands::ledger::account_balance;
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::identity::subaccounts::{self, SubaccountStore};
+use crate::lib::ledger_types::MAINNET_LEDGER_CANISTER_ID;
+use crate::lib::nns_types::account_identifier::AccountIdentifier;
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use ic_types::Principal;
+
+/// Lists the named subaccounts of the selected identity with their balances.
+#[derive(Parser)]
+pub struct SubaccountsOpts {
+    /// Canister ID of the ledger canister.
+    #[clap(long)]
+    ledger_canister_id: Option<Principal>,
+
+    #[clap(subcommand)]
+    subcmd: Option<SubCommand>,
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    Add(AddOpts),
+    Remove(RemoveOpts),
+}
+
+/// Names a subaccount of the selected identity.
+#[derive(Parser)]
+struct AddOpts {
+    /// The name of the subaccount, e.g. treasury.
+    name: String,
+
+    /// The subaccount, as a 64 character long hex string. By default, the lowest
+    /// number that is not named yet is used, written as 32 big-endian bytes.
+    #[clap(long)]
+    subaccount: Option<String>,
+}
+
+/// Forgets the name of a subaccount. The funds in it are not affected.
+#[derive(Parser)]
+struct RemoveOpts {
+    /// The name of the subaccount.
+    name: String,
+}
+
+pub async fn exec(env: &dyn Environment, opts: SubaccountsOpts) -> DfxResult {
+    let mut store = SubaccountStore::for_env(env)?;
+    let principal = env
+        .get_selected_identity_principal()
+        .context("No identity is selected")?;
+
+    match opts.subcmd {
+        Some(SubCommand::Add(add)) => {
+            let subaccount = match &add.subaccount {
+                Some(hex) => Some(subaccounts::parse_hex(hex)?),
+                None => None,
+            };
+            let subaccount = store.add(&add.name, subaccount)?;
+            println!(
+                "Added subaccount '{}': {}",
+                add.name,
+                AccountIdentifier::new(principal, Some(subaccount))
+            );
+        }
+        Some(SubCommand::Remove(remove)) => {
+            store.remove(&remove.name)?;
+            println!("Removed subaccount '{}'.", remove.name);
+        }
+        None => {
+            let subaccounts = store.list()?;
+            if subaccounts.is_empty() {
+                println!("No named subaccounts. Add one with `dfx ledger subaccounts add <name>`.");
+                return Ok(());
+            }
+            let agent = env
+                .get_agent()
+                .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+            let canister_id = opts
+                .ledger_canister_id
+                .unwrap_or(MAINNET_LEDGER_CANISTER_ID);
+            for (name, subaccount) in subaccounts {
+                let account = AccountIdentifier::new(principal, Some(subaccount));
+                let balance = account_balance(agent, &canister_id, &account).await?;
+                println!(
+                    "{} {} {} {}",
+                    name,
+                    hex::encode(subaccount.0),
+                    account,
+                    balance
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,6 +1,8 @@
-use crate::commands::ledger::{get_icpts_from_args, transfer};
+use crate::commands::ledger::{get_icpts_from_args, journaled_transfer, new_journal_entry};
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
+use crate::lib::identity::subaccounts::SubaccountStore;
+use crate::lib::ledger_journal::{JournalEntry, JournalOperation};
 use crate::lib::ledger_types::{Memo, MAINNET_LEDGER_CANISTER_ID};
 use crate::lib::nns_types::account_identifier::AccountIdentifier;
 use crate::lib::nns_types::icpts::{ICPTs, TRANSACTION_FEE};
@@ -40,6 +42,11 @@ pub struct TransferOpts {
     #[clap(long, validator(icpts_amount_validator))]
     fee: Option<String>,
 
+    /// The subaccount to transfer from, as a name from `dfx ledger subaccounts` or in hex.
+    /// By default, the transfer comes from the default subaccount.
+    #[clap(long)]
+    from_subaccount: Option<String>,
+
     #[clap(long)]
     /// Canister ID of the ledger canister.
     ledger_canister_id: Option<Principal>,
@@ -65,8 +72,12 @@ pub async fn exec(env: &dyn Environment, opts: TransferOpts) -> DfxResult {
                 "Failed to parse transfer destination from string '{}'.",
                 &opts.to
             )
-        })?
-        .to_address();
+        })?;
+
+    let from_subaccount = match &opts.from_subaccount {
+        Some(from) => Some(SubaccountStore::for_env(env)?.resolve(from)?),
+        None => None,
+    };
 
     let agent = env
         .get_agent()
@@ -78,7 +89,20 @@ pub async fn exec(env: &dyn Environment, opts: TransferOpts) -> DfxResult {
         .ledger_canister_id
         .unwrap_or(MAINNET_LEDGER_CANISTER_ID);
 
-    let block_height = transfer(agent, &canister_id, memo, amount, fee, to).await?;
+    let entry = JournalEntry {
+        from_subaccount: from_subaccount.map(|s| hex::encode(s.0)),
+        ..new_journal_entry(
+            env,
+            &canister_id,
+            JournalOperation::Transfer,
+            memo,
+            amount,
+            fee,
+            &to,
+        )?
+    };
+    let (mut journal, block_height) = journaled_transfer(env, agent, entry.clone()).await?;
+    journal.remove(entry.created_at_time)?;
 
     println!("Transfer sent at BlockHeight: {}", block_height);
 
@@ -16,11 +16,14 @@ mod identity;
 mod language_service;
 mod ledger;
 mod new;
+mod nns;
 mod ping;
 mod remote;
+mod replay;
 mod replica;
 mod start;
 mod stop;
+mod token;
 mod toolchain;
 mod upgrade;
 mod wallet;
@@ -41,11 +44,14 @@ pub enum Command {
     LanguageServices(language_service::LanguageServiceOpts),
     Ledger(ledger::LedgerOpts),
     New(new::NewOpts),
+    Nns(nns::NnsOpts),
     Ping(ping::PingOpts),
     Remote(remote::RemoteOpts),
+    Replay(replay::ReplayOpts),
     Replica(replica::ReplicaOpts),
     Start(start::StartOpts),
     Stop(stop::StopOpts),
+    Token(token::TokenOpts),
     Toolchain(toolchain::ToolchainOpts),
     Upgrade(upgrade::UpgradeOpts),
     Wallet(wallet::WalletOpts),
@@ -66,11 +72,14 @@ pub fn exec(env: &dyn Environment, cmd: Command) -> DfxResult {
         Command::LanguageServices(v) => language_service::exec(env, v),
         Command::Ledger(v) => ledger::exec(env, v),
         Command::New(v) => new::exec(env, v),
+        Command::Nns(v) => nns::exec(env, v),
         Command::Ping(v) => ping::exec(env, v),
         Command::Remote(v) => remote::exec(env, v),
+        Command::Replay(v) => replay::exec(env, v),
         Command::Replica(v) => replica::exec(env, v),
         Command::Start(v) => start::exec(env, v),
         Command::Stop(v) => stop::exec(env, v),
+        Command::Token(v) => token::exec(env, v),
         Command::Toolchain(v) => toolchain::exec(env, v),
         Command::Upgrade(v) => upgrade::exec(env, v),
         Command::Wallet(v) => wallet::exec(env, v),
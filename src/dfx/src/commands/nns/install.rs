@@ -0,0 +1,64 @@
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::nns::{
+    install_nns, is_nns_subnet, wasm_path, CYCLES_MINTING_CANISTER_WASM, LEDGER_WASM,
+};
+use crate::lib::provider::create_agent_environment;
+use crate::lib::root_key::fetch_root_key_if_needed;
+
+use anyhow::{anyhow, bail};
+use clap::Parser;
+use slog::warn;
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+/// Installs a ledger and a cycles minting canister on the local replica, at the canister ids
+/// they have on mainnet, so that `dfx ledger` commands work locally.
+/// The replica must not have any canisters yet.
+#[derive(Parser)]
+#[clap(name("install"))]
+pub struct NnsInstallOpts {
+    /// The wasm of the ledger. Defaults to ledger.wasm in the dfx cache, which is
+    /// downloaded the first time it is needed.
+    #[clap(long)]
+    ledger_wasm: Option<PathBuf>,
+
+    /// The wasm of the cycles minting canister. Defaults to cycles-minting-canister.wasm
+    /// in the dfx cache.
+    #[clap(long)]
+    cycles_minting_wasm: Option<PathBuf>,
+}
+
+pub fn exec(env: &dyn Environment, opts: NnsInstallOpts) -> DfxResult {
+    let ledger_wasm = wasm_path(env, opts.ledger_wasm, LEDGER_WASM)?;
+    let cycles_minting_wasm =
+        wasm_path(env, opts.cycles_minting_wasm, CYCLES_MINTING_CANISTER_WASM)?;
+
+    let agent_env = create_agent_environment(env, None)?;
+    let network_descriptor = agent_env
+        .get_network_descriptor()
+        .ok_or_else(|| anyhow!("Cannot get network descriptor from environment."))?;
+    if network_descriptor.is_ic {
+        bail!("The NNS canisters can only be installed on a local replica.");
+    }
+    let url = network_descriptor
+        .providers
+        .first()
+        .ok_or_else(|| anyhow!("The local network does not specify any network providers."))?
+        .clone();
+    if !is_nns_subnet(&env.get_state_dir()) {
+        warn!(
+            env.get_logger(),
+            "The local replica is not a system subnet, so the cycles minting canister cannot mint cycles. Start it with `dfx start --clean --with-ledger` instead."
+        );
+    }
+    let agent = agent_env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    let runtime = Runtime::new().expect("Unable to create a runtime");
+    runtime.block_on(async {
+        fetch_root_key_if_needed(&agent_env).await?;
+        install_nns(&agent_env, agent, &url, &ledger_wasm, &cycles_minting_wasm).await
+    })
+}
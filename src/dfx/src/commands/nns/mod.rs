@@ -0,0 +1,25 @@
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+
+use clap::Parser;
+
+mod install;
+
+/// Manages the NNS canisters on the local replica.
+#[derive(Parser)]
+#[clap(name("nns"))]
+pub struct NnsOpts {
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    Install(install::NnsInstallOpts),
+}
+
+pub fn exec(env: &dyn Environment, opts: NnsOpts) -> DfxResult {
+    match opts.subcmd {
+        SubCommand::Install(v) => install::exec(env, v),
+    }
+}
@@ -44,6 +44,7 @@ pub fn exec(env: &dyn Environment, opts: PingOpts) -> DfxResult {
                 providers: vec![url],
                 r#type: NetworkType::Ephemeral,
                 is_ic,
+                subnet: None,
             };
             Ok(network_descriptor)
         })?;
@@ -0,0 +1,264 @@
+use crate::lib::certificate::decode_certificate;
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::provider::create_agent_environment;
+use crate::lib::recording::{call_outcome, load_recording, ExchangeKind, RecordedExchange};
+use crate::lib::root_key::fetch_root_key_if_needed;
+use crate::lib::waiter::waiter_with_timeout;
+use crate::util::expiry_duration;
+
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use ic_agent::{Agent, AgentError};
+use ic_types::Principal;
+use serde_cbor::Value;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+/// Sends the update and query calls recorded with `dfx --record <dir>` again, signed
+/// by the selected identity, and compares the responses with the recorded ones.
+#[derive(Parser)]
+pub struct ReplayOpts {
+    /// The directory the calls were recorded in.
+    dir: PathBuf,
+
+    /// The network to send the calls to. By default, the local network is used.
+    /// A valid URL (starting with `http:` or `https:`) can be used here, and a special
+    /// ephemeral network will be created specifically for this request. E.g.
+    /// "http://localhost:12345/" is a valid network name.
+    #[clap(long)]
+    network: Option<String>,
+}
+
+pub fn exec(env: &dyn Environment, opts: ReplayOpts) -> DfxResult {
+    let exchanges = load_recording(&opts.dir)?;
+    let agent_env = create_agent_environment(env, opts.network)?;
+    let agent = agent_env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+
+    let runtime = Runtime::new().expect("Unable to create a runtime");
+    runtime.block_on(fetch_root_key_if_needed(&agent_env))?;
+    let mut replayed = 0;
+    let mut different = 0;
+    for exchange in &exchanges {
+        let (recorded, replay) = match exchange.kind {
+            ExchangeKind::Query => (
+                recorded_query_outcome(exchange),
+                runtime.block_on(replay_query(agent, exchange))?,
+            ),
+            ExchangeKind::Call => (
+                recorded_call_outcome(&exchanges, exchange),
+                runtime.block_on(replay_call(agent, exchange))?,
+            ),
+            ExchangeKind::ReadState | ExchangeKind::Status => continue,
+        };
+        replayed += 1;
+
+        let method = exchange
+            .decoded_envelope
+            .pointer("/content/method_name")
+            .and_then(|m| m.as_str())
+            .unwrap_or("?");
+        let canister = exchange.effective_canister_id.as_deref().unwrap_or("?");
+        if recorded == replay {
+            println!(
+                "{:05} {} {} {}: same",
+                exchange.sequence,
+                exchange.kind.as_str(),
+                canister,
+                method
+            );
+        } else {
+            different += 1;
+            println!(
+                "{:05} {} {} {}: different",
+                exchange.sequence,
+                exchange.kind.as_str(),
+                canister,
+                method
+            );
+            print_diff(&recorded, &replay);
+        }
+    }
+
+    if different > 0 {
+        bail!(
+            "{} of {} replayed calls returned a different response.",
+            different,
+            replayed
+        );
+    }
+    Ok(())
+}
+
+fn error_json(error: &str) -> serde_json::Value {
+    serde_json::json!({ "error": error })
+}
+
+/// The outcome of a recorded query, in the form `call_outcome` reports the outcome of a call.
+fn recorded_query_outcome(exchange: &RecordedExchange) -> serde_json::Value {
+    let response = match (&exchange.decoded_response, &exchange.error) {
+        (Some(response), _) => response,
+        (None, Some(error)) => return error_json(error),
+        (None, None) => return serde_json::Value::Null,
+    };
+    let mut outcome = serde_json::json!({ "status": response["status"] });
+    if let Some(reply) = response.pointer("/reply/arg") {
+        outcome["reply"] = reply.clone();
+    }
+    for field in ["reject_code", "reject_message"] {
+        if let Some(value) = response.get(field) {
+            outcome[field] = value.clone();
+        }
+    }
+    outcome
+}
+
+/// The outcome of a replayed query or call, in the form `call_outcome` reports it.
+fn replayed_outcome(result: Result<Vec<u8>, AgentError>) -> serde_json::Value {
+    match result {
+        Ok(reply) => serde_json::json!({ "status": "replied", "reply": hex::encode(reply) }),
+        Err(AgentError::ReplicaError {
+            reject_code,
+            reject_message,
+        }) => serde_json::json!({
+            "status": "rejected",
+            "reject_code": reject_code,
+            "reject_message": reject_message,
+        }),
+        Err(e) => error_json(&e.to_string()),
+    }
+}
+
+/// The canister, method and argument of a recorded request, to send them again with a
+/// fresh envelope.
+struct RecordedRequest {
+    effective_canister_id: Principal,
+    canister_id: Principal,
+    method_name: String,
+    arg: Vec<u8>,
+}
+
+fn parse_exchange(exchange: &RecordedExchange) -> DfxResult<RecordedRequest> {
+    let effective_canister_id = exchange
+        .effective_canister_id
+        .as_deref()
+        .ok_or_else(|| anyhow!("Request {} has no canister id.", exchange.sequence))?;
+    let effective_canister_id = Principal::from_text(effective_canister_id)
+        .with_context(|| format!("Failed to parse canister id {}.", effective_canister_id))?;
+    let content_field = |name: &str| {
+        exchange
+            .decoded_envelope
+            .pointer(&format!("/content/{}", name))
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| {
+                anyhow!(
+                    "The envelope of request {} has no {}.",
+                    exchange.sequence,
+                    name
+                )
+            })
+    };
+    let canister_id = hex::decode(content_field("canister_id")?)
+        .ok()
+        .and_then(|bytes| Principal::try_from(&bytes).ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "Failed to decode canister id of request {}.",
+                exchange.sequence
+            )
+        })?;
+    let arg = hex::decode(content_field("arg")?).with_context(|| {
+        format!(
+            "Failed to decode argument of request {}.",
+            exchange.sequence
+        )
+    })?;
+    Ok(RecordedRequest {
+        effective_canister_id,
+        canister_id,
+        method_name: content_field("method_name")?.to_string(),
+        arg,
+    })
+}
+
+async fn replay_query(agent: &Agent, exchange: &RecordedExchange) -> DfxResult<serde_json::Value> {
+    let request = parse_exchange(exchange)?;
+    let result = agent
+        .query(&request.canister_id, &request.method_name)
+        .with_effective_canister_id(request.effective_canister_id)
+        .with_arg(request.arg)
+        .call()
+        .await;
+    Ok(replayed_outcome(result))
+}
+
+/// The last recorded read_state request that asked for the status of this call.
+fn status_request<'a>(
+    exchanges: &'a [RecordedExchange],
+    call: &RecordedExchange,
+) -> Option<&'a RecordedExchange> {
+    exchanges
+        .iter()
+        .filter(|e| e.kind == ExchangeKind::ReadState && e.request_id == call.request_id)
+        .last()
+}
+
+fn certificate_outcome(response: &[u8], request_id: &[u8]) -> Option<serde_json::Value> {
+    let response = serde_cbor::from_slice::<Value>(response).ok()?;
+    call_outcome(&decode_certificate(&response)?, request_id)
+}
+
+fn recorded_call_outcome(
+    exchanges: &[RecordedExchange],
+    call: &RecordedExchange,
+) -> serde_json::Value {
+    if let Some(error) = &call.error {
+        return error_json(error);
+    }
+    let request_id = call
+        .request_id
+        .as_deref()
+        .and_then(|id| hex::decode(id).ok())
+        .unwrap_or_default();
+    status_request(exchanges, call)
+        .and_then(|status| status.response.as_deref())
+        .and_then(|response| hex::decode(response).ok())
+        .and_then(|response| certificate_outcome(&response, &request_id))
+        .unwrap_or_else(|| error_json("The status of this call was not recorded."))
+}
+
+/// Sends the call again with a new envelope, signed by the selected identity with a fresh
+/// ingress expiry, so that the replica neither rejects it as expired nor as a duplicate.
+async fn replay_call(agent: &Agent, call: &RecordedExchange) -> DfxResult<serde_json::Value> {
+    let request = parse_exchange(call)?;
+    let result = agent
+        .update(&request.canister_id, &request.method_name)
+        .with_effective_canister_id(request.effective_canister_id)
+        .with_arg(request.arg)
+        .call_and_wait(waiter_with_timeout(expiry_duration()))
+        .await;
+    Ok(replayed_outcome(result))
+}
+
+fn print_diff(recorded: &serde_json::Value, replayed: &serde_json::Value) {
+    let recorded = serde_json::to_string_pretty(recorded).unwrap_or_default();
+    let replayed = serde_json::to_string_pretty(replayed).unwrap_or_default();
+    let recorded: Vec<&str> = recorded.lines().collect();
+    let replayed: Vec<&str> = replayed.lines().collect();
+    for i in 0..recorded.len().max(replayed.len()) {
+        match (recorded.get(i), replayed.get(i)) {
+            (Some(a), Some(b)) if a == b => println!("    {}", a),
+            (a, b) => {
+                if let Some(a) = a {
+                    println!("  - {}", a);
+                }
+                if let Some(b) = b {
+                    println!("  + {}", b);
+                }
+            }
+        }
+    }
+}
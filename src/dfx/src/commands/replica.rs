@@ -6,12 +6,13 @@ use crate::config::dfinity::ConfigDefaultsReplica;
 use crate::error_invalid_argument;
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
-use crate::lib::replica_config::{HttpHandlerConfig, ReplicaConfig};
+use crate::lib::replica_config::{get_ic_starter_args, HttpHandlerConfig, ReplicaConfig};
 
 use crate::commands::start::{
     configure_btc_adapter_if_enabled, configure_canister_http_adapter_if_enabled,
     empty_writable_path,
 };
+use anyhow::bail;
 use clap::Parser;
 use fn_error_context::context;
 use std::default::Default;
@@ -57,8 +58,10 @@ fn get_config(env: &dyn Environment, opts: ReplicaOpts) -> DfxResult<ReplicaConf
         http_handler.port = Some(port);
     };
 
+    let ic_starter_args = get_ic_starter_args(config.config.as_ref(), &[])?;
     let mut replica_config =
-        ReplicaConfig::new(&env.get_state_dir(), config.subnet_type.unwrap_or_default());
+        ReplicaConfig::new(&env.get_state_dir(), config.subnet_type.unwrap_or_default())
+            .with_ic_starter_args(ic_starter_args);
     replica_config.http_handler = http_handler;
 
     Ok(replica_config)
@@ -119,6 +122,7 @@ pub fn exec(env: &dyn Environment, opts: ReplicaOpts) -> DfxResult {
         .as_ref()
         .and_then(|cfg| cfg.get_socket_path());
 
+    let (crash_limit_sender, crash_limit_receiver) = crossbeam::channel::unbounded();
     system.block_on(async move {
         let shutdown_controller = start_shutdown_controller(env)?;
         if opts.emulator {
@@ -175,6 +179,7 @@ pub fn exec(env: &dyn Environment, opts: ReplicaOpts) -> DfxResult {
                 shutdown_controller,
                 btc_adapter_ready_subscribe,
                 canister_http_adapter_ready_subscribe,
+                Some(crash_limit_sender),
             )?;
         }
         DfxResult::Ok(())
@@ -188,5 +193,9 @@ pub fn exec(env: &dyn Environment, opts: ReplicaOpts) -> DfxResult {
         let _ = std::fs::remove_file(&canister_http_socket_path);
     }
 
+    if let Ok(summary) = crash_limit_receiver.try_recv() {
+        bail!(summary);
+    }
+
     Ok(())
 }
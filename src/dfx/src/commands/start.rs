@@ -3,22 +3,27 @@ use crate::actors::{
     start_btc_adapter_actor, start_canister_http_adapter_actor, start_emulator_actor,
     start_icx_proxy_actor, start_replica_actor, start_shutdown_controller,
 };
-use crate::config::dfinity::{Config, ConfigInterface};
+use crate::config::dfinity::{Config, ConfigInterface, ReplicaSubnetType};
 use crate::lib::environment::Environment;
 use crate::lib::error::{DfxError, DfxResult};
-use crate::lib::replica_config::ReplicaConfig;
-use crate::lib::{bitcoin, canister_http};
+use crate::lib::identity::identity_manager::IdentityManager;
+use crate::lib::metrics::command_timings_path;
+use crate::lib::nns::{is_nns_subnet, mark_nns_subnet};
+use crate::lib::replica_bootstrap::bootstrap_replica;
+use crate::lib::replica_config::{get_ic_starter_args, without_ic_starter_flag, ReplicaConfig};
+use crate::lib::{bitcoin, canister_http, subnets};
 use crate::util::get_reusable_socket_addr;
 
 use crate::actors::icx_proxy::IcxProxyConfig;
 use crate::lib::provider::get_network_descriptor;
-use crate::lib::webserver::run_webserver;
+use crate::lib::webserver::{run_webserver, ReplicaProxyConfig, SharedIdentity};
 use actix::Recipient;
 use anyhow::{anyhow, bail, Context, Error};
 use clap::Parser;
 use fn_error_context::context;
 use garcon::{Delay, Waiter};
 use ic_agent::Agent;
+use slog::Logger;
 use std::fs;
 use std::io::Read;
 use std::net::SocketAddr;
@@ -57,6 +62,25 @@ pub struct StartOpts {
     /// enable canister http requests
     #[clap(long, conflicts_with("emulator"))]
     enable_canister_http: bool,
+
+    /// Passes additional arguments to the replica for this run, e.g. --replica-arg "--log-level debug".
+    /// Only flags that dfx knows about and does not set itself are accepted.
+    #[clap(
+        long,
+        conflicts_with("emulator"),
+        multiple_occurrences(true),
+        allow_hyphen_values(true)
+    )]
+    replica_arg: Vec<String>,
+
+    /// Installs a ledger and a cycles minting canister once the replica is up, as
+    /// `dfx nns install` does. The replica runs as a system subnet from then on.
+    #[clap(long, conflicts_with("emulator"))]
+    with_ledger: bool,
+
+    /// Skips creating wallets for the identities in defaults.replica.bootstrap_identities.
+    #[clap(long)]
+    no_bootstrap: bool,
 }
 
 fn ping_and_wait(frontend_url: &str) -> DfxResult {
@@ -107,8 +131,8 @@ fn ping_and_wait(frontend_url: &str) -> DfxResult {
 // needs to wait and verify it's up before exiting.
 // Because the user may have specified to start on port 0, here we wait for
 // webserver_port_path to get written to and modify the frontend_url so we
-// ping the correct address.
-fn fg_ping_and_wait(webserver_port_path: PathBuf, frontend_url: String) -> DfxResult {
+// ping the correct address. Returns the modified frontend_url.
+fn fg_ping_and_wait(webserver_port_path: PathBuf, frontend_url: String) -> DfxResult<String> {
     let mut waiter = Delay::builder()
         .timeout(std::time::Duration::from_secs(30))
         .throttle(std::time::Duration::from_secs(1))
@@ -142,7 +166,8 @@ fn fg_ping_and_wait(webserver_port_path: PathBuf, frontend_url: String) -> DfxRe
         .rfind(':')
         .ok_or_else(|| anyhow!("Malformed frontend url: {}", frontend_url))?;
     frontend_url_mod.replace_range((port_offset + 1).., port.as_str());
-    ping_and_wait(&frontend_url_mod)
+    ping_and_wait(&frontend_url_mod)?;
+    Ok(frontend_url_mod)
 }
 
 /// Start the Internet Computer locally. Spawns a proxy to forward and
@@ -158,9 +183,27 @@ pub fn exec(
         bitcoin_node,
         enable_bitcoin,
         enable_canister_http,
+        replica_arg,
+        with_ledger,
+        no_bootstrap,
     }: StartOpts,
 ) -> DfxResult {
     let config = env.get_config_or_anyhow()?;
+    let replica_args = replica_arg
+        .iter()
+        .map(|arg| shell_words::split(arg))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to parse --replica-arg.")?
+        .concat();
+    let ic_starter_args = get_ic_starter_args(
+        config
+            .get_config()
+            .get_defaults()
+            .get_replica()
+            .config
+            .as_ref(),
+        &replica_args,
+    )?;
     let network_descriptor = get_network_descriptor(env, None)?;
     let temp_dir = env.get_temp_dir();
     let build_output_root = temp_dir.join(&network_descriptor.name).join("canisters");
@@ -175,6 +218,12 @@ pub fn exec(
         clean_state(temp_dir, &state_root)?;
     }
 
+    // The cycles minting canister can only mint cycles on a system subnet.
+    if with_ledger {
+        mark_nns_subnet(&state_root)?;
+    }
+    let nns_subnet = is_nns_subnet(&state_root);
+
     let pid_file_path = empty_writable_path(pid_file_path)?;
     let btc_adapter_pid_file_path = empty_writable_path(temp_dir.join("ic-btc-adapter-pid"))?;
     let btc_adapter_config_path = empty_writable_path(temp_dir.join("ic-btc-adapter-config.json"))?;
@@ -187,9 +236,36 @@ pub fn exec(
 
     let (frontend_url, address_and_port) = frontend_address(host, &config, background)?;
 
+    let bootstrap_identities = if no_bootstrap {
+        vec![]
+    } else {
+        config
+            .get_config()
+            .get_defaults()
+            .get_replica()
+            .bootstrap_identities
+            .clone()
+            .unwrap_or_default()
+    };
+    let logger = env.get_logger().clone();
+
     if background {
         send_background()?;
-        return fg_ping_and_wait(webserver_port_path, frontend_url);
+        let frontend_url = fg_ping_and_wait(webserver_port_path, frontend_url)?;
+        return setup_replica(with_ledger, logger, &frontend_url, &bootstrap_identities);
+    }
+    if with_ledger || !bootstrap_identities.is_empty() {
+        let webserver_port_path = webserver_port_path.clone();
+        let frontend_url = frontend_url.clone();
+        std::thread::spawn(move || {
+            if let Err(e) =
+                fg_ping_and_wait(webserver_port_path, frontend_url).and_then(|frontend_url| {
+                    setup_replica(with_ledger, logger, &frontend_url, &bootstrap_identities)
+                })
+            {
+                eprintln!("{:?}", e);
+            }
+        });
     }
 
     write_pid(&pid_file_path);
@@ -221,6 +297,41 @@ pub fn exec(
         .as_ref()
         .and_then(|cfg| cfg.get_socket_path());
 
+    let replica_port_path = if emulator {
+        temp_dir.join("ic-ref.port")
+    } else {
+        temp_dir
+            .join("replica-configuration")
+            .join("replica-1.port")
+    };
+    // Each named subnet runs in a replica of its own, without the adapters of the main replica.
+    // It gets the same replica settings, except for the metrics port, which only one
+    // replica can listen on.
+    let subnet_ic_starter_args = without_ic_starter_flag(&ic_starter_args, "--metrics-port");
+    let subnet_replica_configs = config
+        .get_config()
+        .get_defaults()
+        .get_replica()
+        .get_subnets()?
+        .into_iter()
+        .map(|(name, subnet)| {
+            ReplicaConfig::new(&subnets::state_root(&state_root, &name), subnet.r#type)
+                .with_random_port(&subnets::port_path(temp_dir, &name))
+                .with_subnet_name(&name)
+                .with_ic_starter_args(subnet_ic_starter_args.clone())
+        })
+        .collect::<Vec<_>>();
+    if emulator && !subnet_replica_configs.is_empty() {
+        bail!("The emulator cannot run the subnets in defaults.replica.subnets. Remove them or start the replica without --emulator.");
+    }
+
+    let replica_proxy = ReplicaProxyConfig {
+        replica_port_path: replica_port_path.clone(),
+        command_timings_path: command_timings_path(temp_dir, &network_descriptor.name),
+        canister_status_identity: canister_status_identity(env),
+    };
+
+    let (crash_limit_sender, crash_limit_receiver) = crossbeam::channel::unbounded();
     let system = actix::System::new();
     let _proxy = system.block_on(async move {
         let shutdown_controller = start_shutdown_controller(env)?;
@@ -260,19 +371,19 @@ pub fn exec(
                     (None, None)
                 };
 
-            let replica_port_path = env
-                .get_temp_dir()
-                .join("replica-configuration")
-                .join("replica-1.port");
-
-            let subnet_type = config
-                .get_config()
-                .get_defaults()
-                .get_replica()
-                .subnet_type
-                .unwrap_or_default();
+            let subnet_type = if nns_subnet {
+                ReplicaSubnetType::System
+            } else {
+                config
+                    .get_config()
+                    .get_defaults()
+                    .get_replica()
+                    .subnet_type
+                    .unwrap_or_default()
+            };
             let mut replica_config = ReplicaConfig::new(&env.get_state_dir(), subnet_type)
-                .with_random_port(&replica_port_path);
+                .with_random_port(&replica_port_path)
+                .with_ic_starter_args(ic_starter_args);
             if btc_adapter_config.is_some() {
                 replica_config = replica_config.with_btc_adapter_enabled();
                 if let Some(btc_adapter_socket) = btc_adapter_socket_path {
@@ -292,7 +403,18 @@ pub fn exec(
                 shutdown_controller.clone(),
                 btc_adapter_ready_subscribe,
                 canister_http_adapter_ready_subscribe,
+                Some(crash_limit_sender.clone()),
             )?;
+            for subnet_replica_config in subnet_replica_configs {
+                start_replica_actor(
+                    env,
+                    subnet_replica_config,
+                    shutdown_controller.clone(),
+                    None,
+                    None,
+                    Some(crash_limit_sender.clone()),
+                )?;
+            }
             replica.recipient()
         };
 
@@ -302,6 +424,7 @@ pub fn exec(
             proxy_port: webserver_bind.port(),
             providers: vec![],
             fetch_root_key: !network_descriptor.is_ic,
+            replica_via_webserver: true,
         };
 
         run_webserver(
@@ -309,6 +432,7 @@ pub fn exec(
             build_output_root,
             network_descriptor,
             webserver_bind,
+            Some(replica_proxy),
         )?;
 
         let proxy = start_icx_proxy_actor(
@@ -329,9 +453,28 @@ pub fn exec(
         let _ = std::fs::remove_file(&canister_http_socket_path);
     }
 
+    if let Ok(summary) = crash_limit_receiver.try_recv() {
+        bail!(summary);
+    }
+
     Ok(())
 }
 
+/// The selected identity, to poll the status of canisters for `/_/metrics`.
+/// Identities that would prompt for a password or a PIN are skipped.
+fn canister_status_identity(env: &dyn Environment) -> Option<SharedIdentity> {
+    let mut identity_manager = IdentityManager::new(env).ok()?;
+    let name = identity_manager.get_selected_identity_name().clone();
+    let identity_config = identity_manager
+        .get_identity_config_or_default(&name)
+        .ok()?;
+    if identity_config.encryption.is_some() || identity_config.hsm.is_some() {
+        return None;
+    }
+    let identity = identity_manager.instantiate_selected_identity().ok()?;
+    Some(SharedIdentity::new(identity))
+}
+
 #[context("Failed to clean existing replica state.")]
 fn clean_state(temp_dir: &Path, state_root: &Path) -> DfxResult {
     // Clean the contents of the provided directory including the
@@ -349,14 +492,55 @@ fn clean_state(temp_dir: &Path, state_root: &Path) -> DfxResult {
     Ok(())
 }
 
+/// Sets up the replica once it is up: installs the ledger if asked to, and
+/// bootstraps the wallets of the identities in defaults.replica.bootstrap_identities.
+fn setup_replica(
+    with_ledger: bool,
+    logger: Logger,
+    frontend_url: &str,
+    bootstrap_identities: &[String],
+) -> DfxResult {
+    if with_ledger {
+        install_ledger()?;
+    }
+    if !bootstrap_identities.is_empty() {
+        bootstrap_replica(logger, frontend_url, bootstrap_identities)?;
+    }
+    Ok(())
+}
+
+/// Installs the ledger and the cycles minting canister with `dfx nns install`.
+#[context("Failed to install the ledger.")]
+fn install_ledger() -> DfxResult {
+    let exe = std::env::current_exe().context("Failed to get current executable.")?;
+    let status = Command::new(exe)
+        .args(&["nns", "install"])
+        .status()
+        .context("Failed to run dfx nns install.")?;
+    if !status.success() {
+        bail!("dfx nns install failed.");
+    }
+    Ok(())
+}
+
 #[context("Failed to spawn background dfx.")]
 fn send_background() -> DfxResult<()> {
     // Background strategy is different; we spawn `dfx` with the same arguments
-    // (minus --background), ping and exit.
+    // (minus --background), ping and exit. The ledger is installed and the identities
+    // are bootstrapped by this process once the replica is up, so --with-ledger is not
+    // passed on either, and the child is told not to bootstrap. The state was already
+    // cleaned by this process, and the child must keep the NNS mark, so --clean is
+    // dropped too.
     let exe = std::env::current_exe().context("Failed to get current executable.")?;
     let mut cmd = Command::new(exe);
     // Skip 1 because arg0 is this executable's path.
-    cmd.args(std::env::args().skip(1).filter(|a| !a.eq("--background")));
+    cmd.args(
+        std::env::args()
+            .skip(1)
+            .filter(|a| !a.eq("--background") && !a.eq("--with-ledger") && !a.eq("--clean"))
+            .filter(|a| !a.eq("--no-bootstrap")),
+    );
+    cmd.arg("--no-bootstrap");
 
     cmd.spawn().context("Failed to spawn child process.")?;
     Ok(())
@@ -0,0 +1,50 @@
+use crate::commands::token::{get_agent, get_token_format};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::icrc1::Account;
+
+use anyhow::Context;
+use candid::{Decode, Encode, Nat};
+use clap::Parser;
+use ic_types::Principal;
+use std::str::FromStr;
+
+const BALANCE_METHOD: &str = "icrc1_balance_of";
+
+/// Prints the token balance of an account.
+#[derive(Parser)]
+pub struct BalanceOpts {
+    /// The account to get the balance of, in the ICRC-1 textual encoding.
+    /// Defaults to the default subaccount of the selected identity.
+    of: Option<String>,
+}
+
+pub async fn exec(env: &dyn Environment, opts: BalanceOpts, ledger: &Principal) -> DfxResult {
+    let account = match &opts.of {
+        Some(of) => Account::from_str(of)?,
+        None => Account::new(
+            env.get_selected_identity_principal()
+                .expect("Selected identity not instantiated."),
+            None,
+        ),
+    };
+    let agent = get_agent(env)?;
+    let token = get_token_format(agent, ledger).await?;
+
+    let result = agent
+        .query(ledger, BALANCE_METHOD)
+        .with_arg(Encode!(&account).context("Failed to encode arguments.")?)
+        .call()
+        .await
+        .with_context(|| {
+            format!(
+                "Failed query call to {} for method {}.",
+                ledger, BALANCE_METHOD
+            )
+        })?;
+    let balance = Decode!(&result, Nat).context("Failed to decode response.")?;
+
+    println!("{}", token.format(&balance));
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use crate::commands::token::{get_agent, get_token_format, query};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+
+use candid::Nat;
+use clap::Parser;
+use ic_types::Principal;
+
+/// Prints the fee the ledger charges for a transfer.
+#[derive(Parser)]
+pub struct FeeOpts {}
+
+pub async fn exec(env: &dyn Environment, _opts: FeeOpts, ledger: &Principal) -> DfxResult {
+    let agent = get_agent(env)?;
+    let token = get_token_format(agent, ledger).await?;
+    let fee: Nat = query(agent, ledger, "icrc1_fee").await?;
+    println!("{}", token.format(&fee));
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use crate::commands::token::{get_agent, query};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::icrc1::Value;
+
+use clap::Parser;
+use ic_types::Principal;
+
+/// Prints the metadata the ledger reports about the token, one `key: value` per line.
+#[derive(Parser)]
+pub struct MetadataOpts {}
+
+pub async fn exec(env: &dyn Environment, _opts: MetadataOpts, ledger: &Principal) -> DfxResult {
+    let agent = get_agent(env)?;
+    let metadata: Vec<(String, Value)> = query(agent, ledger, "icrc1_metadata").await?;
+    for (key, value) in metadata {
+        println!("{}: {}", key, value);
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::icrc1::TokenFormat;
+use crate::lib::models::canister_id_store::CanisterIdStore;
+use crate::lib::provider::create_agent_environment;
+use crate::lib::root_key::fetch_root_key_if_needed;
+
+use anyhow::{anyhow, Context};
+use candid::{CandidType, Decode, Encode};
+use clap::Parser;
+use fn_error_context::context;
+use ic_agent::Agent;
+use ic_types::Principal;
+use serde::de::DeserializeOwned;
+use tokio::runtime::Runtime;
+
+mod balance;
+mod fee;
+mod metadata;
+mod supply;
+mod transfer;
+
+/// Interacts with a ledger that implements the ICRC-1 token standard.
+#[derive(Parser)]
+#[clap(name("token"))]
+pub struct TokenOpts {
+    /// The ledger canister, as a canister name from dfx.json or a canister id.
+    #[clap(long)]
+    ledger: String,
+
+    /// Override the compute network to connect to. By default, the local network is used.
+    #[clap(long)]
+    network: Option<String>,
+
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    Balance(balance::BalanceOpts),
+    Fee(fee::FeeOpts),
+    Metadata(metadata::MetadataOpts),
+    Supply(supply::SupplyOpts),
+    Transfer(transfer::TransferOpts),
+}
+
+pub fn exec(env: &dyn Environment, opts: TokenOpts) -> DfxResult {
+    let agent_env = create_agent_environment(env, opts.network.clone())?;
+    let canister_id_store = CanisterIdStore::for_env(&agent_env)?;
+    let ledger =
+        Principal::from_text(&opts.ledger).or_else(|_| canister_id_store.get(&opts.ledger))?;
+    let runtime = Runtime::new().expect("Unable to create a runtime");
+    runtime.block_on(async {
+        fetch_root_key_if_needed(&agent_env).await?;
+        match opts.subcmd {
+            SubCommand::Balance(v) => balance::exec(&agent_env, v, &ledger).await,
+            SubCommand::Fee(v) => fee::exec(&agent_env, v, &ledger).await,
+            SubCommand::Metadata(v) => metadata::exec(&agent_env, v, &ledger).await,
+            SubCommand::Supply(v) => supply::exec(&agent_env, v, &ledger).await,
+            SubCommand::Transfer(v) => transfer::exec(&agent_env, v, &ledger).await,
+        }
+    })
+}
+
+fn get_agent(env: &dyn Environment) -> DfxResult<&Agent> {
+    env.get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))
+}
+
+/// Calls a query method of the ledger that takes no arguments.
+async fn query<T: CandidType + DeserializeOwned>(
+    agent: &Agent,
+    ledger: &Principal,
+    method: &str,
+) -> DfxResult<T> {
+    let result = agent
+        .query(ledger, method)
+        .with_arg(Encode!().context("Failed to encode arguments.")?)
+        .call()
+        .await
+        .with_context(|| format!("Failed query call to {} for method {}.", ledger, method))?;
+    Decode!(&result, T).with_context(|| format!("Failed to decode {} response.", method))
+}
+
+#[context("Failed to read the decimals and symbol of ledger {}.", ledger)]
+async fn get_token_format(agent: &Agent, ledger: &Principal) -> DfxResult<TokenFormat> {
+    Ok(TokenFormat {
+        decimals: query(agent, ledger, "icrc1_decimals").await?,
+        symbol: query(agent, ledger, "icrc1_symbol").await?,
+    })
+}
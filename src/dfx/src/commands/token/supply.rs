@@ -0,0 +1,19 @@
+use crate::commands::token::{get_agent, get_token_format, query};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+
+use candid::Nat;
+use clap::Parser;
+use ic_types::Principal;
+
+/// Prints the total supply of the token.
+#[derive(Parser)]
+pub struct SupplyOpts {}
+
+pub async fn exec(env: &dyn Environment, _opts: SupplyOpts, ledger: &Principal) -> DfxResult {
+    let agent = get_agent(env)?;
+    let token = get_token_format(agent, ledger).await?;
+    let supply: Nat = query(agent, ledger, "icrc1_total_supply").await?;
+    println!("{}", token.format(&supply));
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use crate::commands::ledger::retryable;
+use crate::commands::token::{get_agent, get_token_format, query};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::icrc1::{parse_subaccount, Account, TransferArg, TransferError, TransferResult};
+use crate::lib::waiter::waiter_with_timeout;
+use crate::util::clap::validators::memo_validator;
+use crate::util::expiry_duration;
+
+use anyhow::{anyhow, bail, Context};
+use candid::{Decode, Encode, Nat};
+use clap::Parser;
+use garcon::{Delay, Waiter};
+use ic_types::Principal;
+use serde_bytes::ByteBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TRANSFER_METHOD: &str = "icrc1_transfer";
+
+/// Transfers tokens from the selected identity to an account.
+#[derive(Parser)]
+pub struct TransferOpts {
+    /// The destination account, in the ICRC-1 textual encoding.
+    to: String,
+
+    /// The amount to transfer, as a decimal number in the token's units, e.g. 1.25.
+    #[clap(long)]
+    amount: String,
+
+    /// The transfer fee, in the token's units. By default, the ledger's current fee is used.
+    #[clap(long)]
+    fee: Option<String>,
+
+    /// A numeric memo for this transfer. It is sent as 8 big-endian bytes.
+    #[clap(long, validator(memo_validator))]
+    memo: Option<String>,
+
+    /// The subaccount of the selected identity to transfer from, in hex.
+    #[clap(long)]
+    from_subaccount: Option<String>,
+
+    /// The creation time of the transfer, in nanoseconds since the epoch.
+    /// The ledger rejects a second transfer with the same arguments and creation time,
+    /// so passing the time printed by a failed attempt retries it without sending twice.
+    #[clap(long)]
+    created_at_time: Option<u64>,
+}
+
+pub async fn exec(env: &dyn Environment, opts: TransferOpts, ledger: &Principal) -> DfxResult {
+    let to = Account::from_str(&opts.to)
+        .with_context(|| format!("Failed to parse transfer destination '{}'.", opts.to))?;
+    let from_subaccount = opts
+        .from_subaccount
+        .as_deref()
+        .map(parse_subaccount)
+        .transpose()
+        .context("Failed to parse --from-subaccount.")?;
+    let memo = opts.memo.as_deref().map(|memo| {
+        let memo = memo
+            .parse::<u64>()
+            .expect("bug: memo_validator did not validate the memo");
+        ByteBuf::from(memo.to_be_bytes().to_vec())
+    });
+    let created_at_time = opts.created_at_time.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    });
+
+    let agent = get_agent(env)?;
+    let token = get_token_format(agent, ledger).await?;
+    let amount = token.parse(&opts.amount)?;
+    let fee = match &opts.fee {
+        Some(fee) => token.parse(fee)?,
+        None => query::<Nat>(agent, ledger, "icrc1_fee").await?,
+    };
+
+    let arg = Encode!(&TransferArg {
+        from_subaccount: from_subaccount.map(|s| ByteBuf::from(s.to_vec())),
+        to,
+        amount,
+        fee: Some(fee),
+        memo,
+        created_at_time: Some(created_at_time),
+    })
+    .context("Failed to encode arguments.")?;
+
+    let mut waiter = Delay::builder()
+        .with(Delay::count_timeout(30))
+        .exponential_backoff_capped(
+            std::time::Duration::from_secs(1),
+            2.0,
+            std::time::Duration::from_secs(16),
+        )
+        .build();
+    waiter.start();
+
+    let retry_hint = format!(
+        "To retry this transfer without sending it twice, pass --created-at-time {}.",
+        created_at_time
+    );
+    let block_index = loop {
+        match agent
+            .update(ledger, TRANSFER_METHOD)
+            .with_arg(arg.clone())
+            .call_and_wait(waiter_with_timeout(expiry_duration()))
+            .await
+        {
+            Ok(data) => {
+                let result = Decode!(&data, TransferResult)
+                    .context("Failed to decode transfer response.")?;
+                match result {
+                    Ok(block_index) => break block_index,
+                    Err(TransferError::Duplicate { duplicate_of }) => {
+                        eprintln!(
+                            "The same transfer was already recorded in block {}.",
+                            duplicate_of.0.to_str_radix(10)
+                        );
+                        break duplicate_of;
+                    }
+                    Err(TransferError::TemporarilyUnavailable) => {
+                        eprintln!("The ledger is temporarily unavailable. Waiting to retry.");
+                        if waiter.async_wait().await.is_err() {
+                            bail!(
+                                "{} {}",
+                                TransferError::TemporarilyUnavailable.describe(&token),
+                                retry_hint
+                            );
+                        }
+                    }
+                    Err(transfer_err) => bail!(transfer_err.describe(&token)),
+                }
+            }
+            Err(agent_err) if !retryable(&agent_err) => {
+                return Err(anyhow!(agent_err).context(retry_hint));
+            }
+            Err(agent_err) => {
+                eprintln!("Waiting to retry after error: {:?}", &agent_err);
+                if let Err(_waiter_err) = waiter.async_wait().await {
+                    return Err(anyhow!(agent_err).context(retry_hint));
+                }
+            }
+        }
+    };
+
+    println!(
+        "Transfer sent at block index: {}",
+        block_index.0.to_str_radix(10)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use crate::lib::dist;
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+
+use anyhow::Context;
+use clap::Parser;
+use semver::Version;
+use std::path::PathBuf;
+
+/// Package the release of an SDK version into a file for machines without network access
+#[derive(Parser)]
+#[clap(name("bundle"))]
+pub struct ToolchainBundle {
+    /// SDK version, such as '0.10.0'
+    version: String,
+
+    /// The file to write the bundle to. Defaults to dfx-<version>-bundle.tar.gz
+    #[clap(long, short('o'))]
+    output: Option<PathBuf>,
+
+    #[clap(long, default_value = "https://sdk.dfinity.org", hide(true))]
+    release_root: String,
+
+    /// Bundles without verifying the signature of the release manifest and the
+    /// checksum of the download.
+    #[clap(long)]
+    insecure: bool,
+}
+
+pub fn exec(_env: &dyn Environment, opts: ToolchainBundle) -> DfxResult {
+    let version = Version::parse(&opts.version)
+        .with_context(|| format!("Failed to parse version from '{}'.", opts.version))?;
+    let output = opts
+        .output
+        .unwrap_or_else(|| PathBuf::from(format!("dfx-{}-bundle.tar.gz", version)));
+    dist::create_bundle(&version, &output, &opts.release_root, opts.insecure)?;
+    eprintln!(
+        "SDK version {} bundled into {}. Install it with `dfx toolchain install --from-bundle {}`.",
+        version,
+        output.to_string_lossy(),
+        output.to_string_lossy()
+    );
+    Ok(())
+}
@@ -12,6 +12,11 @@ use clap::Parser;
 pub struct ToolchainDefault {
     /// Toolchain name, such as '0.6.22', '0.6', 'latest'
     toolchain: Option<String>,
+
+    /// Installs without verifying the signature of the release manifest and the
+    /// checksum of the download.
+    #[clap(long)]
+    insecure: bool,
 }
 
 pub fn exec(_env: &dyn Environment, opts: ToolchainDefault) -> DfxResult {
@@ -20,7 +25,7 @@ pub fn exec(_env: &dyn Environment, opts: ToolchainDefault) -> DfxResult {
             let toolchain = name
                 .parse::<Toolchain>()
                 .context("Failed to parse toolchain name.")?;
-            toolchain.set_default()?;
+            toolchain.set_default(opts.insecure)?;
         }
         None => {
             let toolchain = toolchain::get_default_toolchain()?;
@@ -1,25 +1,75 @@
+use crate::lib::dist;
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
 use crate::lib::toolchain::Toolchain;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use clap::Parser;
+use std::path::PathBuf;
 
 /// Install or update given toolchain(s)
 #[derive(Parser)]
 #[clap(name("install"))]
 pub struct ToolchainInstall {
     /// Toolchain name, such as '0.6.22', '0.6', 'latest'
-    #[clap(required = true, min_values = 1)]
+    #[clap(required_unless_present("from-bundle"))]
     toolchains: Vec<String>,
+
+    /// Installs the SDK version in a bundle created with `dfx toolchain bundle`
+    /// instead of downloading it. The toolchains default to the version of the bundle.
+    #[clap(long)]
+    from_bundle: Option<PathBuf>,
+
+    /// Installs without verifying the signature of the release manifest and the
+    /// checksum of the download or bundle.
+    #[clap(long)]
+    insecure: bool,
 }
 
 pub fn exec(_env: &dyn Environment, opts: ToolchainInstall) -> DfxResult {
-    for s in opts.toolchains {
-        let toolchain = s
-            .parse::<Toolchain>()
-            .with_context(|| format!("Failed to parse toolchain {}.", s))?;
-        toolchain.update()?;
+    let toolchains = opts
+        .toolchains
+        .iter()
+        .map(|s| {
+            s.parse::<Toolchain>()
+                .with_context(|| format!("Failed to parse toolchain {}.", s))
+        })
+        .collect::<DfxResult<Vec<_>>>()?;
+
+    match opts.from_bundle {
+        Some(bundle) => {
+            if opts.insecure {
+                eprintln!("Warning: The bundle will not be verified.");
+            }
+            let version = dist::install_bundle(&bundle, opts.insecure)?;
+            let toolchains = if toolchains.is_empty() {
+                vec![Toolchain::CompleteVersion(version.clone())]
+            } else {
+                toolchains
+            };
+            for toolchain in toolchains {
+                if !toolchain.accepts(&version) {
+                    bail!(
+                        "Toolchain {} cannot be installed from a bundle of SDK version {}.",
+                        toolchain,
+                        version
+                    );
+                }
+                toolchain.link(&version)?;
+                eprintln!(
+                    "Toolchain {0} installed - SDK version {1}",
+                    toolchain, version
+                );
+            }
+        }
+        None => {
+            if opts.insecure {
+                eprintln!("Warning: The downloaded SDK versions will not be verified.");
+            }
+            for toolchain in toolchains {
+                toolchain.update(opts.insecure)?;
+            }
+        }
     }
     Ok(())
 }
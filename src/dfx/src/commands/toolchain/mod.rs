@@ -3,10 +3,12 @@ use crate::lib::error::DfxResult;
 
 use clap::Parser;
 
+mod bundle;
 mod default;
 mod install;
 mod list;
 mod uninstall;
+mod which;
 
 /// Manage the dfx toolchains
 #[derive(Parser)]
@@ -18,17 +20,21 @@ pub struct ToolchainOpts {
 
 #[derive(Parser)]
 pub enum SubCommand {
+    Bundle(bundle::ToolchainBundle),
     Install(install::ToolchainInstall),
     Uninstall(uninstall::ToolchainUninstall),
     List(list::ToolchainList),
     Default(default::ToolchainDefault),
+    Which(which::ToolchainWhich),
 }
 
 pub fn exec(env: &dyn Environment, opts: ToolchainOpts) -> DfxResult {
     match opts.subcmd {
+        SubCommand::Bundle(v) => bundle::exec(env, v),
         SubCommand::Install(v) => install::exec(env, v),
         SubCommand::Uninstall(v) => uninstall::exec(env, v),
         SubCommand::List(v) => list::exec(env, v),
         SubCommand::Default(v) => default::exec(env, v),
+        SubCommand::Which(v) => which::exec(env, v),
     }
 }
@@ -0,0 +1,47 @@
+use crate::config::{cache, dfx_version};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::toolchain;
+
+use clap::Parser;
+
+/// Show which version of dfx runs in the current directory, and why
+#[derive(Parser)]
+#[clap(name("which"))]
+pub struct ToolchainWhich {}
+
+pub fn exec(env: &dyn Environment, _opts: ToolchainWhich) -> DfxResult {
+    let config = env.get_config();
+    let selection = toolchain::select_version(
+        config
+            .as_ref()
+            .and_then(|c| c.get_config().get_dfx())
+            .as_deref(),
+    )?;
+    match &selection.version {
+        Some(version) => {
+            println!("dfx {}", version);
+            if version == dfx_version() {
+                let path = std::env::current_exe()?;
+                println!("  path: {} (running)", path.to_string_lossy());
+            } else if cache::is_version_installed(&version.to_string())? {
+                let path = cache::get_binary_path_from_version(&version.to_string(), "dfx")?;
+                println!("  path: {}", path.to_string_lossy());
+            } else {
+                println!("  not installed, dfx asks to install it the next time it runs here (or installs it without asking with --install-dfx)");
+            }
+        }
+        None => {
+            println!(
+                "dfx {}",
+                selection.project_requirement.as_deref().unwrap_or_default()
+            );
+            println!("  not installed, dfx asks to install the highest version that matches the next time it runs here (or installs it without asking with --install-dfx)");
+        }
+    }
+    if let Some(config) = &config {
+        println!("  project: {}", config.get_path().to_string_lossy());
+    }
+    println!("  reason: {}", selection.reason);
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
-use crate::lib::manifest::{get_latest_release, get_latest_version};
+use crate::lib::manifest::{fetch_manifest, get_latest_release};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use clap::Parser;
 use semver::Version;
 
@@ -15,6 +15,11 @@ pub struct UpgradeOpts {
 
     #[clap(long, default_value = "https://sdk.dfinity.org", hide(true))]
     release_root: String,
+
+    /// Installs the new version without verifying the signature of the release
+    /// manifest and the checksum of the download.
+    #[clap(long)]
+    insecure: bool,
 }
 
 pub fn exec(env: &dyn Environment, opts: UpgradeOpts) -> DfxResult {
@@ -33,11 +38,19 @@ pub fn exec(env: &dyn Environment, opts: UpgradeOpts) -> DfxResult {
 
     println!("Current version: {}", current_version);
     let release_root = opts.release_root.as_str();
-    let latest_version = get_latest_version(release_root, None)?;
+    if opts.insecure {
+        eprintln!("Warning: The release will not be verified before it is installed.");
+    }
+    let manifest = fetch_manifest(release_root, None, opts.insecure)?;
+    let latest_version = manifest
+        .get_tag_version("latest")
+        .ok_or_else(|| anyhow!("The manifest has no 'latest' tag."))?
+        .clone();
 
     if latest_version > current_version {
         println!("New version available: {}", latest_version);
-        get_latest_release(release_root, &latest_version, os_arch)?;
+        let manifest = if opts.insecure { None } else { Some(&manifest) };
+        get_latest_release(release_root, &latest_version, os_arch, manifest)?;
     } else {
         println!("Already up to date");
     }
@@ -0,0 +1,205 @@
+use crate::commands::wallet::events::{describe_principal, get_events, Event, EventKind};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::models::canister_id_store::CanisterIdStore;
+
+use chrono::{Datelike, Duration, NaiveDate, TimeZone, Utc};
+use clap::Parser;
+use ic_types::Principal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const BAR_WIDTH: u128 = 40;
+
+/// Charts the cycles the selected identity's wallet spent on each canister over time:
+/// cycles sent to it and not refunded, cycles it was created with, and cycles
+/// attached to calls to it.
+#[derive(Parser)]
+pub struct ChartOpts {
+    /// The length of the periods spending is summed over.
+    #[clap(long, default_value("day"), possible_values(&["day", "week", "month"]))]
+    period: String,
+
+    /// The id of the first wallet event to include. By default, all events are included.
+    #[clap(long)]
+    from: Option<u32>,
+
+    /// The id of the last wallet event to include.
+    #[clap(long)]
+    to: Option<u32>,
+
+    /// Specifies the format of the output.
+    #[clap(long, default_value("text"), possible_values(&["text", "json"]))]
+    output: String,
+}
+
+#[derive(Serialize)]
+struct SpendingRow {
+    period: String,
+    canister: String,
+    cycles: String,
+}
+
+/// Returns the first day of the period that the timestamp, in nanoseconds since
+/// the UNIX epoch, falls into.
+fn period_start(timestamp: u64, period: &str) -> NaiveDate {
+    let date = Utc.timestamp_nanos(timestamp as i64).date().naive_utc();
+    match period {
+        "week" => date - Duration::days(date.weekday().num_days_from_monday().into()),
+        "month" => date.with_day(1).unwrap(),
+        _ => date,
+    }
+}
+
+/// Sums the cycles spent on each canister per period.
+fn aggregate_spending(
+    events: &[Event<u128>],
+    period: &str,
+) -> BTreeMap<NaiveDate, BTreeMap<Principal, u128>> {
+    let mut spending: BTreeMap<NaiveDate, BTreeMap<Principal, u128>> = BTreeMap::new();
+    for event in events {
+        let (canister, cycles) = match &event.kind {
+            EventKind::CyclesSent { to, amount, refund } => (to, amount.saturating_sub(*refund)),
+            EventKind::CanisterCreated { canister, cycles } => (canister, *cycles),
+            EventKind::CanisterCalled {
+                canister, cycles, ..
+            } => (canister, *cycles),
+            _ => continue,
+        };
+        if cycles == 0 {
+            continue;
+        }
+        let spent = spending
+            .entry(period_start(event.timestamp, period))
+            .or_default()
+            .entry(*canister)
+            .or_default();
+        *spent = spent.saturating_add(cycles);
+    }
+    spending
+}
+
+pub async fn exec(env: &dyn Environment, opts: ChartOpts) -> DfxResult {
+    let events = get_events(env, Some(opts.from.unwrap_or(0)), opts.to).await?;
+    let spending = aggregate_spending(&events, &opts.period);
+    let canister_id_store = CanisterIdStore::for_env(env)?;
+    let rows: Vec<SpendingRow> = spending
+        .iter()
+        .flat_map(|(start, canisters)| {
+            canisters
+                .iter()
+                .map(move |(canister, cycles)| (start, canister, cycles))
+        })
+        .map(|(start, canister, cycles)| SpendingRow {
+            period: start.to_string(),
+            canister: describe_principal(&canister_id_store, canister),
+            cycles: cycles.to_string(),
+        })
+        .collect();
+
+    if opts.output == "json" {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    if rows.is_empty() {
+        eprintln!("The wallet has not spent any cycles.");
+        return Ok(());
+    }
+
+    let max = spending
+        .values()
+        .flat_map(|canisters| canisters.values())
+        .copied()
+        .max()
+        .unwrap_or(1);
+    let width = rows.iter().map(|row| row.canister.len()).max().unwrap_or(0);
+    let mut rows = rows.iter();
+    for (start, canisters) in &spending {
+        println!("{}", start);
+        for (cycles, row) in canisters.values().zip(rows.by_ref()) {
+            let bar = "#".repeat(((cycles * BAR_WIDTH + max - 1) / max) as usize);
+            println!(
+                "  {:<width$}  {:<bar_width$}  {} cycles",
+                row.canister,
+                bar,
+                row.cycles,
+                width = width,
+                bar_width = BAR_WIDTH as usize
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+    fn event(id: u32, timestamp: u64, kind: EventKind<u128>) -> Event<u128> {
+        Event {
+            id,
+            timestamp,
+            kind,
+        }
+    }
+
+    #[test]
+    fn aggregates_spending_per_canister_and_period() {
+        let a = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let b = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        // 1970-01-01 was a Thursday, so days 0 and 4 are in different weeks.
+        let events = vec![
+            event(
+                0,
+                0,
+                EventKind::CanisterCreated {
+                    canister: a,
+                    cycles: 100,
+                },
+            ),
+            event(
+                1,
+                DAY,
+                EventKind::CyclesSent {
+                    to: a,
+                    amount: 50,
+                    refund: 20,
+                },
+            ),
+            event(
+                2,
+                4 * DAY,
+                EventKind::CanisterCalled {
+                    canister: b,
+                    method_name: "greet".to_string(),
+                    cycles: 7,
+                },
+            ),
+            event(
+                3,
+                4 * DAY,
+                EventKind::CyclesReceived {
+                    from: b,
+                    amount: 1000,
+                    memo: None,
+                },
+            ),
+        ];
+
+        let daily = aggregate_spending(&events, "day");
+        assert_eq!(daily.len(), 3);
+
+        let weekly = aggregate_spending(&events, "week");
+        let weeks: Vec<_> = weekly.keys().map(|d| d.to_string()).collect();
+        assert_eq!(weeks, vec!["1969-12-29", "1970-01-05"]);
+        assert_eq!(weekly.values().next().unwrap()[&a], 130);
+        assert_eq!(weekly.values().nth(1).unwrap()[&b], 7);
+
+        let monthly = aggregate_spending(&events, "month");
+        assert_eq!(monthly.len(), 1);
+        assert_eq!(monthly.values().next().unwrap().len(), 2);
+    }
+}
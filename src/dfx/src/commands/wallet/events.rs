@@ -0,0 +1,272 @@
+use crate::commands::wallet::get_wallet;
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::models::canister_id_store::CanisterIdStore;
+
+use anyhow::Context;
+use candid::{CandidType, Deserialize};
+use chrono::{TimeZone, Utc};
+use clap::Parser;
+use fn_error_context::context;
+use ic_types::Principal;
+use ic_utils::call::SyncCall;
+use serde::Serialize;
+
+/// Lists the events in the selected identity's cycles wallet: cycles it received
+/// and sent, canisters it created and called, and changes to its custodians and
+/// controllers.
+#[derive(Parser)]
+pub struct EventsOpts {
+    /// The id of the first event to list. By default, the last 20 events are listed.
+    #[clap(long)]
+    from: Option<u32>,
+
+    /// The id of the last event to list.
+    #[clap(long)]
+    to: Option<u32>,
+
+    /// Specifies the format of the output.
+    #[clap(long, default_value("text"), possible_values(&["text", "json"]))]
+    output: String,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy)]
+pub enum Role {
+    Contact,
+    Custodian,
+    Controller,
+}
+
+/// The kind of a wallet event. Wallets that support 128-bit cycles report amounts
+/// as `nat`, older wallets as `nat64`.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum EventKind<TCycles> {
+    CyclesSent {
+        to: Principal,
+        amount: TCycles,
+        refund: TCycles,
+    },
+    CyclesReceived {
+        from: Principal,
+        amount: TCycles,
+        memo: Option<String>,
+    },
+    AddressAdded {
+        id: Principal,
+        name: Option<String>,
+        role: Role,
+    },
+    AddressRemoved {
+        id: Principal,
+    },
+    CanisterCreated {
+        canister: Principal,
+        cycles: TCycles,
+    },
+    CanisterCalled {
+        canister: Principal,
+        method_name: String,
+        cycles: TCycles,
+    },
+    WalletDeployed {
+        canister: Principal,
+    },
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct Event<TCycles> {
+    pub id: u32,
+    /// Nanoseconds since the UNIX epoch.
+    pub timestamp: u64,
+    pub kind: EventKind<TCycles>,
+}
+
+impl From<Event<u64>> for Event<u128> {
+    fn from(event: Event<u64>) -> Self {
+        let kind = match event.kind {
+            EventKind::CyclesSent { to, amount, refund } => EventKind::CyclesSent {
+                to,
+                amount: amount.into(),
+                refund: refund.into(),
+            },
+            EventKind::CyclesReceived { from, amount, memo } => EventKind::CyclesReceived {
+                from,
+                amount: amount.into(),
+                memo,
+            },
+            EventKind::AddressAdded { id, name, role } => {
+                EventKind::AddressAdded { id, name, role }
+            }
+            EventKind::AddressRemoved { id } => EventKind::AddressRemoved { id },
+            EventKind::CanisterCreated { canister, cycles } => EventKind::CanisterCreated {
+                canister,
+                cycles: cycles.into(),
+            },
+            EventKind::CanisterCalled {
+                canister,
+                method_name,
+                cycles,
+            } => EventKind::CanisterCalled {
+                canister,
+                method_name,
+                cycles: cycles.into(),
+            },
+            EventKind::WalletDeployed { canister } => EventKind::WalletDeployed { canister },
+        };
+        Event {
+            id: event.id,
+            timestamp: event.timestamp,
+            kind,
+        }
+    }
+}
+
+#[derive(CandidType)]
+struct GetEventsArgs {
+    from: Option<u32>,
+    to: Option<u32>,
+}
+
+/// Fetches the events of the selected identity's wallet, with `get_events128` if
+/// the wallet supports 128-bit cycles and `get_events` otherwise.
+#[context("Failed to fetch the wallet's events.")]
+pub async fn get_events(
+    env: &dyn Environment,
+    from: Option<u32>,
+    to: Option<u32>,
+) -> DfxResult<Vec<Event<u128>>> {
+    let wallet = get_wallet(env).await?;
+    let args = Some(GetEventsArgs { from, to });
+    if wallet.version_supports_u128_cycles() {
+        let (events,): (Vec<Event<u128>>,) = wallet
+            .query_("get_events128")
+            .with_arg(args)
+            .build()
+            .call()
+            .await
+            .context("Query to wallet failed.")?;
+        Ok(events)
+    } else {
+        let (events,): (Vec<Event<u64>>,) = wallet
+            .query_("get_events")
+            .with_arg(args)
+            .build()
+            .call()
+            .await
+            .context("Query to wallet failed.")?;
+        Ok(events.into_iter().map(Event::from).collect())
+    }
+}
+
+/// Names a canister of the project, or shows its id.
+pub fn describe_principal(canister_id_store: &CanisterIdStore, principal: &Principal) -> String {
+    let text = principal.to_text();
+    match canister_id_store.get_name(&text) {
+        Some(name) => format!("{} ({})", name, text),
+        None => text,
+    }
+}
+
+#[derive(Serialize)]
+struct EventRow {
+    id: u32,
+    timestamp: String,
+    kind: String,
+    description: String,
+}
+
+impl EventRow {
+    fn new(event: &Event<u128>, canister_id_store: &CanisterIdStore) -> Self {
+        let describe = |principal: &Principal| describe_principal(canister_id_store, principal);
+        let (kind, description) = match &event.kind {
+            EventKind::CyclesSent { to, amount, refund } => (
+                "cycles-sent",
+                format!(
+                    "Sent {} cycles to {}, {} cycles refunded",
+                    amount,
+                    describe(to),
+                    refund
+                ),
+            ),
+            EventKind::CyclesReceived { from, amount, memo } => (
+                "cycles-received",
+                match memo {
+                    Some(memo) => format!(
+                        "Received {} cycles from {}, memo: {}",
+                        amount,
+                        describe(from),
+                        memo
+                    ),
+                    None => format!("Received {} cycles from {}", amount, describe(from)),
+                },
+            ),
+            EventKind::AddressAdded { id, name, role } => (
+                "address-added",
+                match name {
+                    Some(name) => format!("Added {:?} {} named {}", role, id, name),
+                    None => format!("Added {:?} {}", role, id),
+                },
+            ),
+            EventKind::AddressRemoved { id } => ("address-removed", format!("Removed {}", id)),
+            EventKind::CanisterCreated { canister, cycles } => (
+                "canister-created",
+                format!(
+                    "Created canister {} with {} cycles",
+                    describe(canister),
+                    cycles
+                ),
+            ),
+            EventKind::CanisterCalled {
+                canister,
+                method_name,
+                cycles,
+            } => (
+                "canister-called",
+                format!(
+                    "Called {} on {} with {} cycles",
+                    method_name,
+                    describe(canister),
+                    cycles
+                ),
+            ),
+            EventKind::WalletDeployed { canister } => (
+                "wallet-deployed",
+                format!("Deployed the wallet {}", canister),
+            ),
+        };
+        EventRow {
+            id: event.id,
+            timestamp: Utc.timestamp_nanos(event.timestamp as i64).to_rfc3339(),
+            kind: kind.to_string(),
+            description,
+        }
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{:>5}  {}  {:<16}  {}",
+            self.id, self.timestamp, self.kind, self.description
+        )
+    }
+}
+
+pub async fn exec(env: &dyn Environment, opts: EventsOpts) -> DfxResult {
+    let events = get_events(env, opts.from, opts.to).await?;
+    let canister_id_store = CanisterIdStore::for_env(env)?;
+    let rows: Vec<EventRow> = events
+        .iter()
+        .map(|event| EventRow::new(event, &canister_id_store))
+        .collect();
+
+    if opts.output == "json" {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else if rows.is_empty() {
+        eprintln!("No wallet events found.");
+    } else {
+        for row in rows {
+            println!("{}", row.to_line());
+        }
+    }
+
+    Ok(())
+}
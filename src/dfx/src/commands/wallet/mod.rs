@@ -18,9 +18,11 @@ use tokio::runtime::Runtime;
 mod add_controller;
 mod authorize;
 mod balance;
+mod chart;
 mod controllers;
 mod custodians;
 mod deauthorize;
+mod events;
 mod list_addresses;
 mod name;
 mod remove_controller;
@@ -49,9 +51,11 @@ enum SubCommand {
     AddController(add_controller::AddControllerOpts),
     Authorize(authorize::AuthorizeOpts),
     Balance(balance::WalletBalanceOpts),
+    Chart(chart::ChartOpts),
     Controllers(controllers::ControllersOpts),
     Custodians(custodians::CustodiansOpts),
     Deauthorize(deauthorize::DeauthorizeOpts),
+    Events(events::EventsOpts),
     Name(name::NameOpts),
     RemoveController(remove_controller::RemoveControllerOpts),
     Send(send::SendOpts),
@@ -68,9 +72,11 @@ pub fn exec(env: &dyn Environment, opts: WalletOpts) -> DfxResult {
             SubCommand::AddController(v) => add_controller::exec(&agent_env, v).await,
             SubCommand::Authorize(v) => authorize::exec(&agent_env, v).await,
             SubCommand::Balance(v) => balance::exec(&agent_env, v).await,
+            SubCommand::Chart(v) => chart::exec(&agent_env, v).await,
             SubCommand::Controllers(v) => controllers::exec(&agent_env, v).await,
             SubCommand::Custodians(v) => custodians::exec(&agent_env, v).await,
             SubCommand::Deauthorize(v) => deauthorize::exec(&agent_env, v).await,
+            SubCommand::Events(v) => events::exec(&agent_env, v).await,
             SubCommand::Name(v) => name::exec(&agent_env, v).await,
             SubCommand::RemoveController(v) => remove_controller::exec(&agent_env, v).await,
             SubCommand::Send(v) => send::exec(&agent_env, v).await,
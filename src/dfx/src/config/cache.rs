@@ -8,12 +8,13 @@ use indicatif::{ProgressBar, ProgressDrawTarget};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use semver::Version;
+use std::collections::BTreeMap;
 use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
 use std::process::ExitStatus;
 
 // POSIX permissions for files in the cache.
-const EXEC_READ_USER_ONLY_PERMISSION: u32 = 0o500;
+pub const EXEC_READ_USER_ONLY_PERMISSION: u32 = 0o500;
 
 pub trait Cache {
     fn version_str(&self) -> String;
@@ -127,6 +128,39 @@ pub fn delete_version(v: &str) -> DfxResult<bool> {
     Ok(true)
 }
 
+/// The disk usage of the cache of a version, in bytes, in total and by top level entry
+/// (the binaries, and directories such as the motoko base library).
+#[context("Failed to compute disk usage of cache for version '{}'.", v)]
+pub fn version_disk_usage(v: &str) -> DfxResult<(u64, BTreeMap<String, u64>)> {
+    let root = get_bin_cache(v)?;
+    let mut entries = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(&root).min_depth(1) {
+        let entry = entry
+            .with_context(|| format!("Failed to walk cache at {}.", root.to_string_lossy()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let size = entry
+            .metadata()
+            .with_context(|| {
+                format!(
+                    "Failed to read metadata of {}.",
+                    entry.path().to_string_lossy()
+                )
+            })?
+            .len();
+        let relative = entry
+            .path()
+            .strip_prefix(&root)
+            .unwrap_or_else(|_| entry.path());
+        if let Some(first) = relative.components().next() {
+            let name = first.as_os_str().to_string_lossy().to_string();
+            *entries.entry(name).or_insert(0) += size;
+        }
+    }
+    Ok((entries.values().sum(), entries))
+}
+
 #[context("Failed to install binary cache for version '{}'.", v)]
 pub fn install_version(v: &str, force: bool) -> DfxResult<PathBuf> {
     let p = get_bin_cache(v)?;
@@ -310,7 +344,7 @@ pub fn list_versions() -> DfxResult<Vec<Version>> {
     Ok(result)
 }
 
-pub fn call_cached_dfx(v: &Version) -> DfxResult<ExitStatus> {
+pub fn call_cached_dfx(v: &Version, args: &[String]) -> DfxResult<ExitStatus> {
     let v = format!("{}", v);
     let command_path = get_binary_path_from_version(&v, "dfx")?;
     if command_path
@@ -320,7 +354,7 @@ pub fn call_cached_dfx(v: &Version) -> DfxResult<ExitStatus> {
     }
 
     std::process::Command::new(command_path)
-        .args(std::env::args().skip(1))
+        .args(args)
         .status()
         .map_err(DfxError::from)
 }
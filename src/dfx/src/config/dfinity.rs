@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use crate::lib::cycles_ledger::CyclesSource;
 use crate::lib::error::{BuildError, DfxError, DfxResult};
 use crate::{error_invalid_argument, error_invalid_config, error_invalid_data};
 
@@ -19,6 +20,8 @@ const EMPTY_CONFIG_DEFAULTS: ConfigDefaults = ConfigDefaults {
     bootstrap: None,
     build: None,
     canister_http: None,
+    cycles: None,
+    ledger: None,
     replica: None,
 };
 
@@ -30,6 +33,16 @@ const EMPTY_CONFIG_DEFAULTS_BITCOIN: ConfigDefaultsBitcoin = ConfigDefaultsBitco
 const EMPTY_CONFIG_DEFAULTS_CANISTER_HTTP: ConfigDefaultsCanisterHttp =
     ConfigDefaultsCanisterHttp { enabled: false };
 
+const EMPTY_CONFIG_DEFAULTS_CYCLES: ConfigDefaultsCycles = ConfigDefaultsCycles {
+    source: None,
+    ledger: None,
+    max_cycles_per_deploy: None,
+};
+
+const EMPTY_CONFIG_DEFAULTS_LEDGER: ConfigDefaultsLedger = ConfigDefaultsLedger {
+    initial_balances: None,
+};
+
 const EMPTY_CONFIG_DEFAULTS_BOOTSTRAP: ConfigDefaultsBootstrap = ConfigDefaultsBootstrap {
     ip: None,
     port: None,
@@ -39,11 +52,16 @@ const EMPTY_CONFIG_DEFAULTS_BOOTSTRAP: ConfigDefaultsBootstrap = ConfigDefaultsB
 const EMPTY_CONFIG_DEFAULTS_BUILD: ConfigDefaultsBuild = ConfigDefaultsBuild {
     packtool: None,
     args: None,
+    packages: None,
 };
 
 const EMPTY_CONFIG_DEFAULTS_REPLICA: ConfigDefaultsReplica = ConfigDefaultsReplica {
     port: None,
     subnet_type: None,
+    subnets: None,
+    config: None,
+    restart: None,
+    bootstrap_identities: None,
 };
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -75,6 +93,16 @@ pub struct ConfigCanistersCanister {
     pub extras: BTreeMap<String, Value>,
 }
 
+/// Thresholds for `dfx canister watch-cycles`, in the `watch_cycles` field of a canister.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConfigCanistersCanisterWatchCycles {
+    /// The canister needs attention when its balance drops below this many cycles.
+    pub threshold: Option<u128>,
+
+    /// How many cycles to top the canister up with.
+    pub top_up: Option<u128>,
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct CanisterDeclarationsConfig {
     // Directory to place declarations for that canister
@@ -112,6 +140,48 @@ fn default_as_false() -> bool {
     false
 }
 
+/// Where canister creations and top-ups take their cycles from, by network name.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConfigDefaultsCycles {
+    /// The cycles source of each network. Networks that are not listed use the wallet.
+    pub source: Option<BTreeMap<String, CyclesSource>>,
+
+    /// The cycles ledger of each network, as a canister name or id.
+    /// The ic network defaults to the mainnet cycles ledger.
+    pub ledger: Option<BTreeMap<String, String>>,
+
+    /// The most cycles `dfx deploy` may spend on each network, by its own estimate.
+    pub max_cycles_per_deploy: Option<BTreeMap<String, u128>>,
+}
+
+impl ConfigDefaultsCycles {
+    pub fn get_source(&self, network: &str) -> Option<CyclesSource> {
+        self.source
+            .as_ref()
+            .and_then(|sources| sources.get(network).copied())
+    }
+
+    pub fn get_ledger(&self, network: &str) -> Option<String> {
+        self.ledger
+            .as_ref()
+            .and_then(|ledgers| ledgers.get(network).cloned())
+    }
+
+    pub fn get_max_cycles_per_deploy(&self, network: &str) -> Option<u128> {
+        self.max_cycles_per_deploy
+            .as_ref()
+            .and_then(|budgets| budgets.get(network).copied())
+    }
+}
+
+/// The ledger that `dfx nns install` deploys on the local replica.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConfigDefaultsLedger {
+    /// ICP given to identities when the ledger is installed, by identity name.
+    /// Defaults to 1000 ICP for the selected identity.
+    pub initial_balances: Option<BTreeMap<String, String>>,
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ConfigDefaultsBootstrap {
     pub ip: Option<IpAddr>,
@@ -123,12 +193,131 @@ pub struct ConfigDefaultsBootstrap {
 pub struct ConfigDefaultsBuild {
     pub packtool: Option<String>,
     pub args: Option<String>,
+
+    /// Motoko packages, resolved by dfx and passed to moc in addition to the packtool output.
+    pub packages: Option<Vec<ConfigMotokoPackage>>,
+}
+
+/// A Motoko package, either from a git repository (`repo` with `version` or `rev`)
+/// or from a local `path`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigMotokoPackage {
+    pub name: String,
+
+    /// Git repository URL. `file://` URLs are supported.
+    pub repo: Option<String>,
+
+    /// A tag or branch of the repository.
+    pub version: Option<String>,
+
+    /// A commit of the repository. Takes precedence over `version`.
+    pub rev: Option<String>,
+
+    /// A local directory, relative to dfx.json.
+    pub path: Option<PathBuf>,
+
+    /// The directory within the package containing its modules. Default is "src".
+    pub src: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ConfigDefaultsReplica {
     pub port: Option<u16>,
     pub subnet_type: Option<ReplicaSubnetType>,
+
+    /// Additional subnets, by name. `dfx start` runs a replica for each of them next to
+    /// the main replica.
+    pub subnets: Option<BTreeMap<String, ConfigReplicaSubnet>>,
+
+    /// Options passed on to ic-starter.
+    pub config: Option<ConfigReplicaSettings>,
+
+    pub restart: Option<ConfigDefaultsReplicaRestart>,
+
+    /// Identities that `dfx start` creates a cycles wallet for once the replica is up.
+    pub bootstrap_identities: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConfigReplicaSubnet {
+    pub r#type: ReplicaSubnetType,
+}
+
+/// Replica options, each passed to ic-starter as the flag of the same name.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigReplicaSettings {
+    pub log_level: Option<ReplicaLogLevel>,
+    pub metrics_port: Option<u16>,
+    pub max_ingress_bytes_per_message: Option<u64>,
+    pub max_ingress_messages_per_block: Option<u64>,
+    pub max_block_payload_size: Option<u64>,
+    pub consensus_pool_backend: Option<ConsensusPoolBackend>,
+    pub initial_notary_delay_millis: Option<u64>,
+    pub unit_delay_millis: Option<u64>,
+    pub dkg_interval_length: Option<u64>,
+
+    /// Additional ic-starter flags, checked against the flags dfx knows about.
+    pub extra_args: Option<Vec<String>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplicaLogLevel {
+    Critical,
+    Error,
+    Warning,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl ReplicaLogLevel {
+    pub fn as_ic_starter_string(&self) -> String {
+        match self {
+            ReplicaLogLevel::Critical => "critical".to_string(),
+            ReplicaLogLevel::Error => "error".to_string(),
+            ReplicaLogLevel::Warning => "warning".to_string(),
+            ReplicaLogLevel::Info => "info".to_string(),
+            ReplicaLogLevel::Debug => "debug".to_string(),
+            ReplicaLogLevel::Trace => "trace".to_string(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsensusPoolBackend {
+    Rocksdb,
+    Lmdb,
+}
+
+impl ConsensusPoolBackend {
+    pub fn as_ic_starter_string(&self) -> String {
+        match self {
+            ConsensusPoolBackend::Rocksdb => "rocksdb".to_string(),
+            ConsensusPoolBackend::Lmdb => "lmdb".to_string(),
+        }
+    }
+}
+
+/// How `dfx start` handles crashes of the replica process.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConfigDefaultsReplicaRestart {
+    /// Number of consecutive crashes after which dfx stops restarting the replica. Default is 5.
+    pub max_restarts: Option<u32>,
+
+    /// Delay before the first restart, doubled for every consecutive crash. Default is 1.
+    pub initial_backoff_secs: Option<u64>,
+
+    /// Upper bound of the delay between restarts. Default is 30.
+    pub max_backoff_secs: Option<u64>,
+
+    /// Uptime after which a crash no longer counts as consecutive to earlier crashes. Default is 60.
+    pub healthy_uptime_secs: Option<u64>,
+
+    /// Number of lines of the replica's stderr kept in crash reports. Default is 100.
+    pub crash_report_lines: Option<usize>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -220,6 +409,8 @@ pub struct ConfigDefaults {
     pub bootstrap: Option<ConfigDefaultsBootstrap>,
     pub build: Option<ConfigDefaultsBuild>,
     pub canister_http: Option<ConfigDefaultsCanisterHttp>,
+    pub cycles: Option<ConfigDefaultsCycles>,
+    pub ledger: Option<ConfigDefaultsLedger>,
     pub replica: Option<ConfigDefaultsReplica>,
 }
 
@@ -248,6 +439,27 @@ pub fn to_socket_addr(s: &str) -> DfxResult<SocketAddr> {
     }
 }
 
+impl ConfigDefaultsReplica {
+    /// The additional subnets of the local replica, by name.
+    #[context("Failed to read defaults.replica.subnets.")]
+    pub fn get_subnets(&self) -> DfxResult<BTreeMap<String, ConfigReplicaSubnet>> {
+        let subnets = self.subnets.clone().unwrap_or_default();
+        for name in subnets.keys() {
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(error_invalid_config!(
+                    "Subnet name '{}' may only contain letters, digits, '-' and '_'.",
+                    name
+                ));
+            }
+        }
+        Ok(subnets)
+    }
+}
+
 impl ConfigDefaultsBuild {
     pub fn get_packtool(&self) -> Option<String> {
         match &self.packtool {
@@ -261,6 +473,9 @@ impl ConfigDefaultsBuild {
             _ => None,
         }
     }
+    pub fn get_packages(&self) -> Vec<ConfigMotokoPackage> {
+        self.packages.clone().unwrap_or_default()
+    }
 }
 
 impl ConfigDefaults {
@@ -288,6 +503,18 @@ impl ConfigDefaults {
             None => &EMPTY_CONFIG_DEFAULTS_CANISTER_HTTP,
         }
     }
+    pub fn get_cycles(&self) -> &ConfigDefaultsCycles {
+        match &self.cycles {
+            Some(x) => x,
+            None => &EMPTY_CONFIG_DEFAULTS_CYCLES,
+        }
+    }
+    pub fn get_ledger(&self) -> &ConfigDefaultsLedger {
+        match &self.ledger {
+            Some(x) => x,
+            None => &EMPTY_CONFIG_DEFAULTS_LEDGER,
+        }
+    }
     pub fn get_replica(&self) -> &ConfigDefaultsReplica {
         match &self.replica {
             Some(x) => x,
@@ -412,6 +639,23 @@ impl ConfigInterface {
         self.get_initialization_value(canister_name, "freezing_threshold")
     }
 
+    #[context("Failed to get the watch_cycles settings for '{}'.", canister_name)]
+    pub fn get_watch_cycles(
+        &self,
+        canister_name: &str,
+    ) -> DfxResult<ConfigCanistersCanisterWatchCycles> {
+        let watch_cycles = self
+            .canisters
+            .as_ref()
+            .and_then(|canisters| canisters.get(canister_name))
+            .and_then(|canister| canister.extras.get("watch_cycles"));
+        match watch_cycles {
+            Some(value) => ConfigCanistersCanisterWatchCycles::deserialize(value)
+                .map_err(|e| error_invalid_config!("Field watch_cycles is invalid: {}", e)),
+            None => Ok(ConfigCanistersCanisterWatchCycles::default()),
+        }
+    }
+
     fn get_initialization_value(
         &self,
         canister_name: &str,
@@ -837,4 +1081,49 @@ mod tests {
         assert_eq!(None, compute_allocation);
         assert_eq!(None, memory_allocation);
     }
+
+    #[test]
+    fn subnet_names_must_be_path_safe() {
+        let config = Config::from_str(
+            r#"{
+              "defaults": {
+                "replica": {
+                  "subnets": {
+                    "sys": { "type": "system" },
+                    "verified-app": { "type": "verifiedapplication" }
+                  }
+                }
+              }
+        }"#,
+        )
+        .unwrap();
+        let subnets = config
+            .get_config()
+            .get_defaults()
+            .get_replica()
+            .get_subnets()
+            .unwrap();
+        assert_eq!(subnets["sys"].r#type, ReplicaSubnetType::System);
+        assert_eq!(
+            subnets["verified-app"].r#type,
+            ReplicaSubnetType::VerifiedApplication
+        );
+
+        let config = Config::from_str(
+            r#"{
+              "defaults": {
+                "replica": {
+                  "subnets": { "../sys": { "type": "system" } }
+                }
+              }
+        }"#,
+        )
+        .unwrap();
+        assert!(config
+            .get_config()
+            .get_defaults()
+            .get_replica()
+            .get_subnets()
+            .is_err());
+    }
 }
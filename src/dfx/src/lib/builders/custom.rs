@@ -50,7 +50,7 @@ impl CustomBuilderExtra {
             .collect::<DfxResult<Vec<CanisterId>>>().with_context( || format!("Failed to collect dependencies (canister ids) of canister {}.", info.get_name()))?;
 
         let wasm = info
-            .get_output_wasm_path()
+            .get_output_wasm_path()?
             .expect("Missing wasm key in JSON.");
         let candid = info
             .get_output_idl_path()
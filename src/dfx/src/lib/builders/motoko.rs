@@ -134,8 +134,12 @@ impl CanisterBuilder for MotokoBuilder {
         std::fs::create_dir_all(&idl_dir_path)
             .with_context(|| format!("Failed to create {}.", idl_dir_path.to_string_lossy()))?;
 
-        let package_arguments =
-            package_arguments::load(cache.as_ref(), motoko_info.get_packtool())?;
+        let package_arguments = package_arguments::load(
+            cache.as_ref(),
+            motoko_info.get_packtool(),
+            motoko_info.get_packages(),
+            canister_info.get_workspace_root(),
+        )?;
 
         let moc_arguments = match motoko_info.get_args() {
             Some(args) => [
@@ -1,6 +1,7 @@
 use crate::lib::builders::{
     BuildConfig, BuildOutput, CanisterBuilder, IdlBuildOutput, WasmBuildOutput,
 };
+use crate::lib::candid_extraction::{extract_candid, normalize_candid};
 use crate::lib::canister_info::rust::RustCanisterInfo;
 use crate::lib::canister_info::CanisterInfo;
 use crate::lib::environment::Environment;
@@ -12,7 +13,7 @@ use fn_error_context::context;
 use ic_types::principal::Principal as CanisterId;
 use serde::Deserialize;
 use slog::{info, o, warn};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 
 pub struct RustBuilder {
@@ -30,6 +31,43 @@ impl RustBuilder {
     }
 }
 
+impl RustBuilder {
+    /// Writes the service definition found in the compiled wasm to a did file under .dfx,
+    /// and fails if the did file configured in dfx.json does not match it.
+    #[context("Failed to extract the candid interface of package '{}'.", rust_info.get_package())]
+    fn extract_candid(&self, rust_info: &RustCanisterInfo, wasm_path: &Path) -> DfxResult {
+        let wasm = std::fs::read(wasm_path)
+            .with_context(|| format!("Failed to read {}.", wasm_path.to_string_lossy()))?;
+        let candid = extract_candid(&wasm)?;
+
+        let output_idl_path = rust_info.get_extracted_idl_path();
+        info!(
+            self.logger,
+            "Writing extracted candid interface to {}",
+            output_idl_path.to_string_lossy()
+        );
+        if let Some(parent) = output_idl_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}.", parent.to_string_lossy()))?;
+        }
+        std::fs::write(output_idl_path, &candid)
+            .with_context(|| format!("Failed to write {}.", output_idl_path.to_string_lossy()))?;
+
+        if let Some(candid_path) = rust_info.get_candid_path() {
+            let checked_in = std::fs::read_to_string(candid_path)
+                .with_context(|| format!("Failed to read {}.", candid_path.to_string_lossy()))?;
+            if normalize_candid(&checked_in)? != normalize_candid(&candid)? {
+                bail!(
+                    "The candid interface in {} does not match the one extracted from the canister. The extracted interface was written to {}.",
+                    candid_path.to_string_lossy(),
+                    output_idl_path.to_string_lossy()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
 impl CanisterBuilder for RustBuilder {
     #[context("Failed to get dependencies for canister '{}'.", info.get_name())]
     fn get_dependencies(
@@ -71,17 +109,45 @@ impl CanisterBuilder for RustBuilder {
         let package = rust_info.get_package();
 
         let canister_id = canister_info.get_canister_id().unwrap();
+        let wasm_path = rust_info.get_output_wasm_path()?;
+
+        let mut args = vec![
+            "build".to_string(),
+            "--target".to_string(),
+            "wasm32-unknown-unknown".to_string(),
+        ];
+        match rust_info.get_profile() {
+            "release" => args.push("--release".to_string()),
+            "dev" => {}
+            profile => {
+                args.push("--profile".to_string());
+                args.push(profile.to_string());
+            }
+        }
+        args.push("-p".to_string());
+        args.push(package.to_string());
+        if let Some(manifest_path) = rust_info.get_manifest_path() {
+            args.push("--manifest-path".to_string());
+            args.push(manifest_path.to_string_lossy().to_string());
+        }
+        if let Some(target_dir) = rust_info.get_target_dir() {
+            args.push("--target-dir".to_string());
+            args.push(target_dir.to_string_lossy().to_string());
+        }
+        if !rust_info.get_features().is_empty() {
+            args.push("--features".to_string());
+            args.push(rust_info.get_features().join(","));
+        }
+        if rust_info.get_no_default_features() {
+            args.push("--no-default-features".to_string());
+        }
 
         let mut cargo = std::process::Command::new("cargo");
         cargo
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
-            .arg("build")
-            .arg("--target")
-            .arg("wasm32-unknown-unknown")
-            .arg("--release")
-            .arg("-p")
-            .arg(package);
+            .current_dir(canister_info.get_workspace_root())
+            .args(&args);
 
         let dependencies = self
             .get_dependencies(pool, canister_info)
@@ -91,20 +157,24 @@ impl CanisterBuilder for RustBuilder {
         for (key, val) in vars {
             cargo.env(key.as_ref(), val);
         }
+        if let Some(rustflags) = rust_info.get_rustflags() {
+            cargo.env("RUSTFLAGS", rustflags);
+        }
 
-        info!(
-            self.logger,
-            "Executing: cargo build --target wasm32-unknown-unknown --release -p {}", package
-        );
+        info!(self.logger, "Executing: cargo {}", args.join(" "));
         let output = cargo.output().context("Failed to run 'cargo build'.")?;
 
+        // Extract before optimizing, since the optimizer may strip custom sections.
+        if output.status.success() && rust_info.get_extract_candid() {
+            self.extract_candid(&rust_info, &wasm_path)?;
+        }
+
         if std::process::Command::new("ic-cdk-optimizer")
             .arg("--version")
             .output()
             .is_ok()
         {
             let mut optimizer = std::process::Command::new("ic-cdk-optimizer");
-            let wasm_path = format!("target/wasm32-unknown-unknown/release/{}.wasm", package);
             optimizer
                 .stdout(Stdio::inherit())
                 .stderr(Stdio::inherit())
@@ -116,7 +186,8 @@ impl CanisterBuilder for RustBuilder {
             // no matter optimized or not.
             info!(
                 self.logger,
-                "Executing: ic-cdk-optimizer -o {0} {0}", &wasm_path
+                "Executing: ic-cdk-optimizer -o {0} {0}",
+                wasm_path.to_string_lossy()
             );
             if !matches!(optimizer.status(), Ok(status) if status.success()) {
                 warn!(self.logger, "Failed to run ic-cdk-optimizer.");
@@ -133,7 +204,7 @@ Run `cargo install ic-cdk-optimizer` to install it.
         if output.status.success() {
             Ok(BuildOutput {
                 canister_id,
-                wasm: WasmBuildOutput::File(rust_info.get_output_wasm_path().to_path_buf()),
+                wasm: WasmBuildOutput::File(wasm_path),
                 idl: IdlBuildOutput::File(rust_info.get_output_idl_path().to_path_buf()),
             })
         } else {
@@ -0,0 +1,309 @@
+use crate::lib::error::DfxResult;
+use crate::util::check_candid_str;
+
+use anyhow::{anyhow, bail, Context};
+use candid::Decode;
+use fn_error_context::context;
+use std::fmt;
+use std::sync::mpsc;
+use std::time::Duration;
+use wasmi::{
+    Externals, FuncInstance, FuncRef, HostError, ImportsBuilder, MemoryRef, ModuleImportResolver,
+    ModuleInstance, RuntimeArgs, RuntimeValue, Signature, Trap, TrapKind,
+};
+use wasmparser::{Parser, Payload};
+
+/// Custom section names under which a canister may carry its service definition.
+const CANDID_SECTIONS: &[&str] = &[
+    "icp:public candid:service",
+    "icp:private candid:service",
+    "candid:service",
+];
+
+/// The query method exported by `candid::export_service!()` in Rust canisters.
+const CANDID_EXPORT: &str = "canister_query __get_candid_interface_tmp_hack";
+
+/// How long the candid export may run in the interpreter. Generating the service
+/// definition takes milliseconds, so a canister still running after this is stuck.
+const CANDID_EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Extracts the Candid service definition of a compiled canister.
+/// The definition is read from a `candid:service` custom section if there is one,
+/// otherwise the `__get_candid_interface_tmp_hack` query is run in a wasm interpreter.
+#[context("Failed to extract the candid service definition from the wasm module.")]
+pub fn extract_candid(wasm: &[u8]) -> DfxResult<String> {
+    let candid = match candid_from_custom_section(wasm)? {
+        Some(candid) => candid,
+        None => candid_from_export(wasm, CANDID_EXPORT_TIMEOUT)?,
+    };
+    check_candid_str(&candid)?;
+    Ok(candid)
+}
+
+fn candid_from_custom_section(wasm: &[u8]) -> DfxResult<Option<String>> {
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::CustomSection { name, data, .. } =
+            payload.context("Failed to parse wasm module.")?
+        {
+            if CANDID_SECTIONS.contains(&name) {
+                return Ok(Some(String::from_utf8(data.to_vec()).with_context(
+                    || format!("Custom section '{}' is not utf-8.", name),
+                )?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug)]
+struct Ic0Trap(String);
+
+impl fmt::Display for Ic0Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl HostError for Ic0Trap {}
+
+fn trap(message: String) -> Trap {
+    Trap::new(TrapKind::Host(Box::new(Ic0Trap(message))))
+}
+
+/// The system API functions needed to run a query that takes no arguments and replies.
+/// Every other `ic0` import traps when called.
+const IC0_FUNCTIONS: &[&str] = &[
+    "msg_arg_data_size",
+    "msg_arg_data_copy",
+    "msg_reply_data_append",
+    "msg_reply",
+    "trap",
+    "debug_print",
+];
+
+struct Ic0Resolver;
+
+impl ModuleImportResolver for Ic0Resolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        signature: &Signature,
+    ) -> Result<FuncRef, wasmi::Error> {
+        let index = IC0_FUNCTIONS
+            .iter()
+            .position(|f| *f == field_name)
+            .unwrap_or(IC0_FUNCTIONS.len());
+        Ok(FuncInstance::alloc_host(signature.clone(), index))
+    }
+}
+
+/// A minimal stand-in for the canister system API: the argument is the empty Candid
+/// message and the reply is collected in `reply`.
+struct Ic0Externals {
+    memory: Option<MemoryRef>,
+    arg: Vec<u8>,
+    reply: Vec<u8>,
+    replied: bool,
+}
+
+impl Ic0Externals {
+    fn memory(&self) -> Result<&MemoryRef, Trap> {
+        self.memory
+            .as_ref()
+            .ok_or_else(|| trap("The canister does not export its memory.".to_string()))
+    }
+
+    fn read(&self, offset: u32, size: u32) -> Result<Vec<u8>, Trap> {
+        self.memory()?
+            .get(offset, size as usize)
+            .map_err(|e| trap(e.to_string()))
+    }
+}
+
+impl Externals for Ic0Externals {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        match IC0_FUNCTIONS.get(index).copied() {
+            Some("msg_arg_data_size") => Ok(Some(RuntimeValue::I32(self.arg.len() as i32))),
+            Some("msg_arg_data_copy") => {
+                let dst: u32 = args.nth_checked(0)?;
+                let offset: u32 = args.nth_checked(1)?;
+                let size: u32 = args.nth_checked(2)?;
+                let data = self
+                    .arg
+                    .get(offset as usize..offset as usize + size as usize)
+                    .ok_or_else(|| trap("msg_arg_data_copy out of bounds.".to_string()))?
+                    .to_vec();
+                self.memory()?
+                    .set(dst, &data)
+                    .map_err(|e| trap(e.to_string()))?;
+                Ok(None)
+            }
+            Some("msg_reply_data_append") => {
+                let src: u32 = args.nth_checked(0)?;
+                let size: u32 = args.nth_checked(1)?;
+                let data = self.read(src, size)?;
+                self.reply.extend(data);
+                Ok(None)
+            }
+            Some("msg_reply") => {
+                self.replied = true;
+                Ok(None)
+            }
+            Some("trap") => {
+                let src: u32 = args.nth_checked(0)?;
+                let size: u32 = args.nth_checked(1)?;
+                let message = self.read(src, size)?;
+                Err(trap(format!(
+                    "Canister trapped: {}",
+                    String::from_utf8_lossy(&message)
+                )))
+            }
+            Some("debug_print") => Ok(None),
+            _ => Err(trap(
+                "The candid export called an unsupported system API function.".to_string(),
+            )),
+        }
+    }
+}
+
+/// Runs the candid export on its own thread and gives up after `timeout`.
+/// wasmi cannot interrupt a running module, so a canister that loops forever is left
+/// running on the detached thread until dfx exits.
+fn candid_from_export(wasm: &[u8], timeout: Duration) -> DfxResult<String> {
+    let wasm = wasm.to_vec();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("candid-extraction".to_string())
+        .spawn(move || {
+            // The receiver is gone if extraction timed out; nobody needs the result then.
+            let _ = sender.send(run_candid_export(&wasm));
+        })
+        .context("Failed to start the wasm interpreter thread.")?;
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            bail!("'{}' did not finish within {:?}.", CANDID_EXPORT, timeout)
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            bail!("The wasm interpreter running '{}' panicked.", CANDID_EXPORT)
+        }
+    }
+}
+
+fn run_candid_export(wasm: &[u8]) -> DfxResult<String> {
+    let module = wasmi::Module::from_buffer(wasm)
+        .map_err(|e| anyhow!("Failed to load wasm module: {}", e))?;
+    let imports = ImportsBuilder::new().with_resolver("ic0", &Ic0Resolver);
+    let mut externals = Ic0Externals {
+        memory: None,
+        arg: candid::Encode!().context("Failed to encode empty argument.")?,
+        reply: vec![],
+        replied: false,
+    };
+    let instance = ModuleInstance::new(&module, &imports)
+        .map_err(|e| anyhow!("Failed to instantiate wasm module: {}", e))?
+        .run_start(&mut externals)
+        .map_err(|e| anyhow!("Failed to run the start function: {}", e))?;
+    if instance.export_by_name(CANDID_EXPORT).is_none() {
+        bail!(
+            "The wasm module has neither a candid:service custom section nor a '{}' export. Use `candid::export_service!()` in the canister to export its interface.",
+            CANDID_EXPORT
+        );
+    }
+    externals.memory = instance
+        .export_by_name("memory")
+        .and_then(|e| e.as_memory().cloned());
+
+    instance
+        .invoke_export(CANDID_EXPORT, &[], &mut externals)
+        .map_err(|e| anyhow!("Failed to run '{}': {}", CANDID_EXPORT, e))?;
+    if !externals.replied {
+        bail!("'{}' did not reply.", CANDID_EXPORT);
+    }
+    Decode!(&externals.reply, String).context("Failed to decode the candid interface.")
+}
+
+/// Returns the canonical text of a service definition, so that did files which differ
+/// only in formatting or comments compare equal.
+#[context("Failed to normalize candid service definition.")]
+pub fn normalize_candid(candid: &str) -> DfxResult<String> {
+    let (env, ty) = check_candid_str(candid)?;
+    Ok(candid::bindings::candid::compile(&env, &ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module_with_custom_section(name: &str, data: &str) -> Vec<u8> {
+        let mut section = vec![name.len() as u8];
+        section.extend(name.as_bytes());
+        section.extend(data.as_bytes());
+
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.push(0);
+        wasm.push(section.len() as u8);
+        wasm.extend(section);
+        wasm
+    }
+
+    #[test]
+    fn extracts_candid_from_custom_section() {
+        let candid = "service : { greet : (text) -> (text) query }";
+        let wasm = module_with_custom_section("icp:public candid:service", candid);
+        assert_eq!(extract_candid(&wasm).unwrap(), candid);
+    }
+
+    #[test]
+    fn rejects_invalid_candid_in_custom_section() {
+        let wasm = module_with_custom_section("candid:service", "service : {");
+        assert!(extract_candid(&wasm).is_err());
+    }
+
+    #[test]
+    fn fails_without_candid() {
+        let wasm = b"\0asm\x01\0\0\0".to_vec();
+        assert!(extract_candid(&wasm).is_err());
+    }
+
+    #[test]
+    fn times_out_on_a_looping_export() {
+        // (module (memory (export "memory") 1)
+        //   (func (export "canister_query __get_candid_interface_tmp_hack") (loop (br 0))))
+        let name = CANDID_EXPORT.as_bytes();
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // type section: one type () -> ()
+        wasm.extend(&[1, 4, 1, 0x60, 0, 0]);
+        // function section: one function of type 0
+        wasm.extend(&[3, 2, 1, 0]);
+        // memory section: one memory with a minimum of one page
+        wasm.extend(&[5, 3, 1, 0, 1]);
+        // export section: the memory and the function
+        let mut exports = vec![2, 6];
+        exports.extend(b"memory");
+        exports.extend(&[2, 0, name.len() as u8]);
+        exports.extend(name);
+        exports.extend(&[0, 0]);
+        wasm.push(7);
+        wasm.push(exports.len() as u8);
+        wasm.extend(exports);
+        // code section: loop (br 0) end end
+        wasm.extend(&[10, 9, 1, 7, 0, 0x03, 0x40, 0x0c, 0, 0x0b, 0x0b]);
+
+        let error = candid_from_export(&wasm, Duration::from_millis(100)).unwrap_err();
+        assert!(format!("{:#}", error).contains("did not finish"));
+    }
+
+    #[test]
+    fn normalization_ignores_formatting_and_comments() {
+        let a = "service : { greet : (text) -> (text) query }";
+        let b = "// the greeter\nservice : {\n  greet: (text) -> (text) query;\n}\n";
+        assert_eq!(normalize_candid(a).unwrap(), normalize_candid(b).unwrap());
+        let c = "service : { greet : (text) -> (text) }";
+        assert_ne!(normalize_candid(a).unwrap(), normalize_candid(c).unwrap());
+    }
+}
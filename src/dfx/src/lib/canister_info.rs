@@ -1,5 +1,5 @@
 #![allow(dead_code)]
-use crate::config::dfinity::{CanisterDeclarationsConfig, Config};
+use crate::config::dfinity::{CanisterDeclarationsConfig, Config, ConfigMotokoPackage};
 use crate::lib::canister_info::assets::AssetsCanisterInfo;
 use crate::lib::canister_info::custom::CustomCanisterInfo;
 use crate::lib::canister_info::motoko::MotokoCanisterInfo;
@@ -49,6 +49,7 @@ pub struct CanisterInfo {
 
     packtool: Option<String>,
     args: Option<String>,
+    packages: Vec<ConfigMotokoPackage>,
 
     extras: BTreeMap<String, serde_json::Value>,
 }
@@ -129,6 +130,7 @@ impl CanisterInfo {
 
             packtool: build_defaults.get_packtool(),
             args: build_defaults.get_args(),
+            packages: build_defaults.get_packages(),
             extras,
         };
 
@@ -237,6 +239,10 @@ impl CanisterInfo {
         &self.args
     }
 
+    pub fn get_packages(&self) -> &[ConfigMotokoPackage] {
+        &self.packages
+    }
+
     pub fn get_build_wasm_path(&self) -> PathBuf {
         self.build_root
             .join(PathBuf::from(&self.name))
@@ -258,18 +264,18 @@ impl CanisterInfo {
             .with_extension("js")
     }
 
-    pub fn get_output_wasm_path(&self) -> Option<PathBuf> {
-        if let Ok(info) = self.as_info::<MotokoCanisterInfo>() {
+    pub fn get_output_wasm_path(&self) -> DfxResult<Option<PathBuf>> {
+        Ok(if let Ok(info) = self.as_info::<MotokoCanisterInfo>() {
             Some(info.get_output_wasm_path().to_path_buf())
         } else if let Ok(info) = self.as_info::<CustomCanisterInfo>() {
             Some(info.get_output_wasm_path().to_path_buf())
         } else if let Ok(info) = self.as_info::<AssetsCanisterInfo>() {
             Some(info.get_output_wasm_path().to_path_buf())
         } else if let Ok(info) = self.as_info::<RustCanisterInfo>() {
-            Some(info.get_output_wasm_path().to_path_buf())
+            Some(info.get_output_wasm_path()?)
         } else {
             None
-        }
+        })
     }
 
     pub fn get_output_idl_path(&self) -> Option<PathBuf> {
@@ -1,3 +1,4 @@
+use crate::config::dfinity::ConfigMotokoPackage;
 use crate::lib::canister_info::{CanisterInfo, CanisterInfoFactory};
 use crate::lib::error::DfxResult;
 use std::path::{Path, PathBuf};
@@ -16,6 +17,7 @@ pub struct MotokoCanisterInfo {
 
     packtool: Option<String>,
     moc_args: Option<String>,
+    packages: Vec<ConfigMotokoPackage>,
 }
 
 impl MotokoCanisterInfo {
@@ -49,6 +51,9 @@ impl MotokoCanisterInfo {
     pub fn get_args(&self) -> &Option<String> {
         &self.moc_args
     }
+    pub fn get_packages(&self) -> &[ConfigMotokoPackage] {
+        &self.packages
+    }
 }
 
 impl CanisterInfoFactory for MotokoCanisterInfo {
@@ -89,6 +94,7 @@ impl CanisterInfoFactory for MotokoCanisterInfo {
             output_assets_root,
             packtool: info.get_packtool().clone(),
             moc_args: info.get_args().clone(),
+            packages: info.get_packages().to_vec(),
         })
     }
 }
@@ -1,10 +1,34 @@
 use crate::lib::canister_info::{CanisterInfo, CanisterInfoFactory};
 use crate::lib::error::DfxResult;
+
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    /// `cargo metadata` output by workspace root and manifest path, so that cargo runs
+    /// at most once per manifest in a dfx invocation.
+    static ref CARGO_METADATA: Mutex<HashMap<(PathBuf, Option<PathBuf>), Arc<CargoMetadata>>> =
+        Mutex::new(HashMap::new());
+}
 
 pub struct RustCanisterInfo {
     package: String,
-    output_wasm_path: PathBuf,
+    features: Vec<String>,
+    no_default_features: bool,
+    profile: String,
+    manifest_path: Option<PathBuf>,
+    target_dir: Option<PathBuf>,
+    rustflags: Option<String>,
+    extract_candid: bool,
+    candid_path: Option<PathBuf>,
+    workspace_root: PathBuf,
+
+    extracted_idl_path: PathBuf,
     output_idl_path: PathBuf,
 }
 
@@ -13,8 +37,75 @@ impl RustCanisterInfo {
         &self.package
     }
 
-    pub fn get_output_wasm_path(&self) -> &Path {
-        self.output_wasm_path.as_path()
+    pub fn get_features(&self) -> &[String] {
+        &self.features
+    }
+
+    pub fn get_no_default_features(&self) -> bool {
+        self.no_default_features
+    }
+
+    pub fn get_profile(&self) -> &str {
+        &self.profile
+    }
+
+    pub fn get_manifest_path(&self) -> Option<&Path> {
+        self.manifest_path.as_deref()
+    }
+
+    pub fn get_target_dir(&self) -> Option<&Path> {
+        self.target_dir.as_deref()
+    }
+
+    pub fn get_rustflags(&self) -> Option<&str> {
+        self.rustflags.as_deref()
+    }
+
+    /// Whether the service definition is extracted from the compiled wasm.
+    pub fn get_extract_candid(&self) -> bool {
+        self.extract_candid
+    }
+
+    /// The did file configured in dfx.json. When extracting the service definition,
+    /// this is optional and checked against the extracted one.
+    pub fn get_candid_path(&self) -> Option<&Path> {
+        self.candid_path.as_deref()
+    }
+
+    /// The wasm cargo builds for the package. Locating it runs `cargo metadata`,
+    /// so this is only called when building or installing the canister.
+    pub fn get_output_wasm_path(&self) -> DfxResult<PathBuf> {
+        let metadata = match cargo_metadata(&self.workspace_root, self.manifest_path.as_deref()) {
+            Ok(metadata) => Some(metadata),
+            // If cargo is not installed (e.g. when only installing a prebuilt canister),
+            // assume the default layout of a workspace at the project root.
+            Err(CargoMetadataError::NotInstalled) if self.manifest_path.is_none() => None,
+            Err(CargoMetadataError::NotInstalled) => {
+                bail!("Cannot locate the wasm of a package with a manifest_path: cargo is not installed.")
+            }
+            Err(CargoMetadataError::Failed(e)) => {
+                return Err(e.context(format!(
+                    "Failed to locate the wasm of package '{}'.",
+                    self.package
+                )))
+            }
+        };
+        let target_directory = match (&self.target_dir, &metadata) {
+            (Some(target_dir), _) => target_dir.clone(),
+            (None, Some(metadata)) => metadata.target_directory.clone(),
+            (None, None) => self.workspace_root.join("target"),
+        };
+        Ok(target_directory
+            .join("wasm32-unknown-unknown")
+            .join(profile_dir(&self.profile))
+            .join(wasm_name(metadata.as_deref(), &self.package))
+            .with_extension("wasm"))
+    }
+
+    /// Where the service definition extracted from the wasm is written, under .dfx,
+    /// so that the did file configured in dfx.json is never overwritten.
+    pub fn get_extracted_idl_path(&self) -> &Path {
+        self.extracted_idl_path.as_path()
     }
 
     pub fn get_output_idl_path(&self) -> &Path {
@@ -22,6 +113,106 @@ impl RustCanisterInfo {
     }
 }
 
+/// The parts of `cargo metadata --format-version 1` output dfx cares about.
+#[derive(Deserialize)]
+struct CargoMetadata {
+    target_directory: PathBuf,
+    packages: Vec<CargoPackage>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    name: String,
+    targets: Vec<CargoTarget>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+    name: String,
+    crate_types: Vec<String>,
+}
+
+enum CargoMetadataError {
+    /// cargo could not be found on the PATH.
+    NotInstalled,
+    /// cargo ran but failed, e.g. because of a broken Cargo.toml or manifest_path.
+    Failed(anyhow::Error),
+}
+
+fn cargo_metadata(
+    workspace_root: &Path,
+    manifest_path: Option<&Path>,
+) -> Result<Arc<CargoMetadata>, CargoMetadataError> {
+    let key = (
+        workspace_root.to_path_buf(),
+        manifest_path.map(Path::to_path_buf),
+    );
+    if let Some(metadata) = CARGO_METADATA.lock().unwrap().get(&key) {
+        return Ok(metadata.clone());
+    }
+    let metadata = Arc::new(run_cargo_metadata(workspace_root, manifest_path)?);
+    CARGO_METADATA.lock().unwrap().insert(key, metadata.clone());
+    Ok(metadata)
+}
+
+fn run_cargo_metadata(
+    workspace_root: &Path,
+    manifest_path: Option<&Path>,
+) -> Result<CargoMetadata, CargoMetadataError> {
+    let mut cmd = std::process::Command::new("cargo");
+    cmd.current_dir(workspace_root)
+        .arg("metadata")
+        .arg("--format-version")
+        .arg("1")
+        .arg("--no-deps");
+    if let Some(manifest_path) = manifest_path {
+        cmd.arg("--manifest-path").arg(manifest_path);
+    }
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Err(CargoMetadataError::NotInstalled),
+        Err(e) => {
+            return Err(CargoMetadataError::Failed(
+                anyhow::Error::new(e).context("Failed to run 'cargo metadata'."),
+            ))
+        }
+    };
+    if !output.status.success() {
+        return Err(CargoMetadataError::Failed(anyhow::anyhow!(
+            "'cargo metadata' failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    serde_json::from_slice(&output.stdout)
+        .context("Failed to parse 'cargo metadata' output.")
+        .map_err(CargoMetadataError::Failed)
+}
+
+/// The file name cargo gives the wasm of the package: the name of its cdylib target,
+/// or the package name, with dashes replaced by underscores.
+fn wasm_name(metadata: Option<&CargoMetadata>, package: &str) -> String {
+    metadata
+        .and_then(|metadata| metadata.packages.iter().find(|p| p.name == package))
+        .and_then(|package| {
+            package
+                .targets
+                .iter()
+                .find(|t| t.crate_types.iter().any(|c| c == "cdylib"))
+        })
+        .map_or(package, |target| &target.name)
+        .replace('-', "_")
+}
+
+/// Cargo places the artifacts of the `dev` and `test` profiles in `debug`, of the `release`
+/// and `bench` profiles in `release`, and of custom profiles in a directory of the same name.
+fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
+    }
+}
+
 impl CanisterInfoFactory for RustCanisterInfo {
     fn supports(info: &CanisterInfo) -> bool {
         info.get_type() == "rust"
@@ -29,23 +220,84 @@ impl CanisterInfoFactory for RustCanisterInfo {
 
     fn create(info: &CanisterInfo) -> DfxResult<Self> {
         let package = info.get_extra::<String>("package")?;
+        let features = info
+            .get_extra_optional::<Vec<String>>("features")?
+            .unwrap_or_default();
+        let no_default_features = info
+            .get_extra_optional::<bool>("no_default_features")?
+            .unwrap_or(false);
+        let profile = info
+            .get_extra_optional::<String>("profile")?
+            .unwrap_or_else(|| "release".to_string());
+        let rustflags = info.get_extra_optional::<String>("rustflags")?;
 
         let workspace_root = info.get_workspace_root();
-        let output_wasm_path = workspace_root.join(format!(
-            "target/wasm32-unknown-unknown/release/{}.wasm",
-            package
-        ));
-        let candid = if let Some(remote_candid) = info.get_remote_candid_if_remote() {
-            PathBuf::from(remote_candid)
+        let manifest_path = info
+            .get_extra_optional::<PathBuf>("manifest_path")?
+            .map(|p| workspace_root.join(p));
+        let target_dir = info
+            .get_extra_optional::<PathBuf>("target_dir")?
+            .map(|p| workspace_root.join(p));
+
+        let extract_candid = info
+            .get_extra_optional::<bool>("extract_candid")?
+            .unwrap_or(false);
+        let candid_path = if let Some(remote_candid) = info.get_remote_candid_if_remote() {
+            Some(workspace_root.join(remote_candid))
+        } else if extract_candid {
+            info.get_extra_optional::<PathBuf>("candid")?
+                .map(|candid| workspace_root.join(candid))
         } else {
-            info.get_extra::<PathBuf>("candid")?
+            Some(workspace_root.join(info.get_extra::<PathBuf>("candid")?))
+        };
+        let extracted_idl_path = info.get_build_idl_path();
+        let output_idl_path = match &candid_path {
+            Some(candid_path) if !extract_candid || info.get_remote_id().is_some() => {
+                candid_path.clone()
+            }
+            _ => extracted_idl_path.clone(),
         };
-        let output_idl_path = workspace_root.join(candid);
 
         Ok(Self {
             package,
-            output_wasm_path,
+            features,
+            no_default_features,
+            profile,
+            manifest_path,
+            target_dir,
+            rustflags,
+            extract_candid,
+            candid_path,
+            extracted_idl_path,
+            workspace_root: workspace_root.to_path_buf(),
             output_idl_path,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wasm_name_replaces_dashes() {
+        assert_eq!(wasm_name(None, "my-canister"), "my_canister");
+        let metadata = CargoMetadata {
+            target_directory: PathBuf::from("target"),
+            packages: vec![CargoPackage {
+                name: "my-canister".to_string(),
+                targets: vec![
+                    CargoTarget {
+                        name: "my-tool".to_string(),
+                        crate_types: vec!["bin".to_string()],
+                    },
+                    CargoTarget {
+                        name: "my-lib".to_string(),
+                        crate_types: vec!["cdylib".to_string()],
+                    },
+                ],
+            }],
+        };
+        assert_eq!(wasm_name(Some(&metadata), "my-canister"), "my_lib");
+    }
+}
@@ -0,0 +1,135 @@
+//! Reading the hash tree of a certificate returned by `read_state`, decoded from CBOR.
+use serde_cbor::Value;
+
+/// The value inside any CBOR tags.
+pub fn strip_tags(value: &Value) -> &Value {
+    match value {
+        Value::Tag(_, inner) => strip_tags(inner),
+        value => value,
+    }
+}
+
+/// The value of a field of a CBOR map.
+pub fn field<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    match strip_tags(value) {
+        Value::Map(map) => map.get(&Value::Text(name.to_string())).map(strip_tags),
+        _ => None,
+    }
+}
+
+/// Decodes the certificate in the body of a read_state response.
+pub fn decode_certificate(response: &Value) -> Option<Value> {
+    match field(response, "certificate")? {
+        Value::Bytes(certificate) => serde_cbor::from_slice(certificate).ok(),
+        _ => None,
+    }
+}
+
+fn find_label<'a>(tree: &'a Value, label: &[u8]) -> Option<&'a Value> {
+    match strip_tags(tree) {
+        Value::Array(node) => match node.as_slice() {
+            [Value::Integer(1), left, right] => {
+                find_label(left, label).or_else(|| find_label(right, label))
+            }
+            [Value::Integer(2), Value::Bytes(l), subtree] if l.as_slice() == label => Some(subtree),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Looks up the leaf at `path` in the hash tree of a certificate.
+pub fn lookup_path<'a>(tree: &'a Value, path: &[&[u8]]) -> Option<&'a [u8]> {
+    match path.split_first() {
+        None => match strip_tags(tree) {
+            Value::Array(node) => match node.as_slice() {
+                [Value::Integer(3), Value::Bytes(leaf)] => Some(leaf.as_slice()),
+                _ => None,
+            },
+            _ => None,
+        },
+        Some((label, rest)) => {
+            find_label(tree, label).and_then(|subtree| lookup_path(subtree, rest))
+        }
+    }
+}
+
+fn collect_labels(tree: &Value, labels: &mut Vec<Vec<u8>>) {
+    if let Value::Array(node) = strip_tags(tree) {
+        match node.as_slice() {
+            [Value::Integer(1), left, right] => {
+                collect_labels(left, labels);
+                collect_labels(right, labels);
+            }
+            [Value::Integer(2), Value::Bytes(label), _] => labels.push(label.clone()),
+            _ => {}
+        }
+    }
+}
+
+/// The labels directly below `path` in the hash tree of a certificate, such as the ids
+/// of the subnets below `subnet`.
+pub fn certificate_labels(certificate: &Value, path: &[&[u8]]) -> Vec<Vec<u8>> {
+    let mut labels = vec![];
+    let mut tree = match field(certificate, "tree") {
+        Some(tree) => tree,
+        None => return labels,
+    };
+    for label in path {
+        match find_label(tree, label) {
+            Some(subtree) => tree = subtree,
+            None => return labels,
+        }
+    }
+    collect_labels(tree, &mut labels);
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn labeled(label: &str, subtree: Value) -> Value {
+        Value::Array(vec![
+            Value::Integer(2),
+            Value::Bytes(label.as_bytes().to_vec()),
+            subtree,
+        ])
+    }
+
+    fn leaf(data: &[u8]) -> Value {
+        Value::Array(vec![Value::Integer(3), Value::Bytes(data.to_vec())])
+    }
+
+    #[test]
+    fn lists_labels_below_path() {
+        let tree = Value::Array(vec![
+            Value::Integer(1),
+            labeled("time", leaf(&[1])),
+            labeled(
+                "subnet",
+                Value::Array(vec![
+                    Value::Integer(1),
+                    labeled("a", labeled("public_key", leaf(&[2]))),
+                    labeled("b", labeled("public_key", leaf(&[3]))),
+                ]),
+            ),
+        ]);
+        let mut certificate = BTreeMap::new();
+        certificate.insert(Value::Text("tree".to_string()), tree);
+        let certificate = Value::Map(certificate);
+        assert_eq!(
+            certificate_labels(&certificate, &[&b"subnet"[..]]),
+            vec![b"a".to_vec(), b"b".to_vec()]
+        );
+        assert!(certificate_labels(&certificate, &[&b"canister"[..]]).is_empty());
+    }
+
+    #[test]
+    fn looks_up_leaves() {
+        let tree = labeled("time", leaf(&[1]));
+        assert_eq!(lookup_path(&tree, &[&b"time"[..]]), Some(&[1u8][..]));
+        assert_eq!(lookup_path(&tree, &[&b"subnet"[..]]), None);
+    }
+}
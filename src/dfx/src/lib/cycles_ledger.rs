@@ -0,0 +1,337 @@
+//! Spending cycles that are held by the identity's principal on a cycles ledger,
+//! instead of by the identity's cycles wallet.
+//! The ledger follows the interface of https://github.com/dfinity/cycles-ledger:
+//! an ICRC-1 ledger of cycles that can also `withdraw` cycles into a canister and
+//! `create_canister` from the caller's balance.
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::ic_attributes::CanisterSettings;
+use crate::lib::models::canister_id_store::CanisterIdStore;
+use crate::lib::waiter::waiter_with_timeout;
+
+use anyhow::{anyhow, bail, Context};
+use candid::{CandidType, Decode, Encode, Nat};
+use fn_error_context::context;
+use ic_agent::Agent;
+use ic_types::Principal;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The cycles ledger on the Internet Computer.
+pub const MAINNET_CYCLES_LEDGER_CANISTER_ID: &str = "um5iw-rqaaa-aaaaq-qaaba-cai";
+
+/// The fee the cycles ledger charges for withdrawing cycles and creating canisters.
+pub const CYCLES_LEDGER_FEE: u128 = 100_000_000;
+
+const WITHDRAW_METHOD: &str = "withdraw";
+const CREATE_CANISTER_METHOD: &str = "create_canister";
+
+/// Where the cycles for creating and topping up canisters come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CyclesSource {
+    /// The balance of the selected identity's principal on the cycles ledger.
+    Identity,
+    /// The selected identity's cycles wallet.
+    Wallet,
+}
+
+impl FromStr for CyclesSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "identity" => Ok(CyclesSource::Identity),
+            "wallet" => Ok(CyclesSource::Wallet),
+            _ => bail!(
+                "Invalid cycles source '{}', expected 'identity' or 'wallet'.",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for CyclesSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CyclesSource::Identity => write!(f, "identity"),
+            CyclesSource::Wallet => write!(f, "wallet"),
+        }
+    }
+}
+
+/// Resolves the cycles source, given on the command line or configured for the
+/// network in defaults.cycles.source, to the cycles ledger to spend from.
+/// Returns None when cycles come from the wallet, which is the default.
+#[context("Failed to determine the source of cycles.")]
+pub fn get_cycles_ledger(
+    env: &dyn Environment,
+    cycles_source: Option<CyclesSource>,
+) -> DfxResult<Option<Principal>> {
+    let network = env
+        .get_network_descriptor()
+        .ok_or_else(|| anyhow!("Cannot get network descriptor from environment."))?;
+    let config = env.get_config();
+    let defaults = config
+        .as_ref()
+        .map(|config| config.get_config().get_defaults().get_cycles().clone())
+        .unwrap_or_default();
+
+    let source = cycles_source
+        .or_else(|| defaults.get_source(&network.name))
+        .unwrap_or(CyclesSource::Wallet);
+    if source == CyclesSource::Wallet {
+        return Ok(None);
+    }
+
+    let ledger = match defaults.get_ledger(&network.name) {
+        Some(ledger) => ledger,
+        None if network.is_ic => MAINNET_CYCLES_LEDGER_CANISTER_ID.to_string(),
+        None => bail!(
+            "No cycles ledger is configured for network '{}'. Set defaults.cycles.ledger.{} in dfx.json to its canister name or id.",
+            network.name,
+            network.name
+        ),
+    };
+    let ledger = Principal::from_text(&ledger)
+        .or_else(|_| CanisterIdStore::for_env(env)?.get(&ledger))
+        .with_context(|| format!("Failed to find the cycles ledger '{}'.", ledger))?;
+    Ok(Some(ledger))
+}
+
+/// Arguments for the `withdraw` call.
+#[derive(CandidType)]
+struct WithdrawArgs {
+    from_subaccount: Option<ByteBuf>,
+    to: Principal,
+    created_at_time: Option<u64>,
+    amount: Nat,
+}
+
+/// Error of the `withdraw` call. Fields dfx does not report are left out.
+#[derive(CandidType, Deserialize, Debug)]
+enum WithdrawError {
+    BadFee { expected_fee: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    TemporarilyUnavailable,
+    Duplicate { duplicate_of: Nat },
+    FailedToWithdraw { rejection_reason: String },
+    GenericError { error_code: Nat, message: String },
+    InvalidReceiver { receiver: Principal },
+}
+
+impl fmt::Display for WithdrawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadFee { expected_fee } => {
+                write!(f, "The fee is {} cycles.", expected_fee.0.to_str_radix(10))
+            }
+            Self::InsufficientFunds { balance } => write!(
+                f,
+                "Insufficient cycles. Current balance: {} cycles.",
+                balance.0.to_str_radix(10)
+            ),
+            Self::TooOld => write!(f, "The request is too old."),
+            Self::CreatedInFuture { ledger_time } => write!(
+                f,
+                "The request was created ahead of the ledger's time ({} ns). Check the system clock.",
+                ledger_time
+            ),
+            Self::TemporarilyUnavailable => {
+                write!(f, "The cycles ledger is temporarily unavailable.")
+            }
+            Self::Duplicate { duplicate_of } => write!(
+                f,
+                "The same request was already recorded in block {}.",
+                duplicate_of.0.to_str_radix(10)
+            ),
+            Self::FailedToWithdraw { rejection_reason } => {
+                write!(f, "Failed to deposit the cycles: {}", rejection_reason)
+            }
+            Self::GenericError {
+                error_code,
+                message,
+            } => write!(f, "Error {}: {}", error_code.0.to_str_radix(10), message),
+            Self::InvalidReceiver { receiver } => {
+                write!(f, "{} cannot receive cycles.", receiver)
+            }
+        }
+    }
+}
+
+/// Settings of the canister created by the `create_canister` call.
+#[derive(CandidType)]
+struct CmcCanisterSettings {
+    controllers: Option<Vec<Principal>>,
+    compute_allocation: Option<Nat>,
+    memory_allocation: Option<Nat>,
+    freezing_threshold: Option<Nat>,
+}
+
+#[derive(CandidType)]
+struct CmcCreateCanisterArgs {
+    settings: Option<CmcCanisterSettings>,
+}
+
+/// Arguments for the `create_canister` call.
+#[derive(CandidType)]
+struct CreateCanisterArgs {
+    from_subaccount: Option<ByteBuf>,
+    created_at_time: Option<u64>,
+    amount: Nat,
+    creation_args: Option<CmcCreateCanisterArgs>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct CreateCanisterSuccess {
+    block_id: Nat,
+    canister_id: Principal,
+}
+
+/// Error of the `create_canister` call. Fields dfx does not report are left out.
+#[derive(CandidType, Deserialize, Debug)]
+enum CreateCanisterError {
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    TemporarilyUnavailable,
+    Duplicate { canister_id: Option<Principal> },
+    FailedToCreate { error: String },
+    GenericError { error_code: Nat, message: String },
+}
+
+impl fmt::Display for CreateCanisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientFunds { balance } => write!(
+                f,
+                "Insufficient cycles. Current balance: {} cycles.",
+                balance.0.to_str_radix(10)
+            ),
+            Self::TooOld => write!(f, "The request is too old."),
+            Self::CreatedInFuture { ledger_time } => write!(
+                f,
+                "The request was created ahead of the ledger's time ({} ns). Check the system clock.",
+                ledger_time
+            ),
+            Self::TemporarilyUnavailable => {
+                write!(f, "The cycles ledger is temporarily unavailable.")
+            }
+            Self::Duplicate { canister_id } => match canister_id {
+                Some(canister_id) => write!(f, "The canister was already created: {}", canister_id),
+                None => write!(f, "The same request was already recorded."),
+            },
+            Self::FailedToCreate { error } => write!(f, "Failed to create the canister: {}", error),
+            Self::GenericError {
+                error_code,
+                message,
+            } => write!(f, "Error {}: {}", error_code.0.to_str_radix(10), message),
+        }
+    }
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards.")
+        .as_nanos() as u64
+}
+
+/// Moves cycles from the caller's balance on the cycles ledger into a canister.
+/// Returns the index of the ledger block that records it.
+#[context(
+    "Failed to withdraw {} cycles from cycles ledger {} into {}.",
+    amount,
+    ledger,
+    to
+)]
+pub async fn withdraw(
+    agent: &Agent,
+    ledger: &Principal,
+    to: Principal,
+    amount: u128,
+    timeout: Duration,
+) -> DfxResult<Nat> {
+    let args = WithdrawArgs {
+        from_subaccount: None,
+        to,
+        created_at_time: Some(now_nanos()),
+        amount: Nat::from(amount),
+    };
+    let result = agent
+        .update(ledger, WITHDRAW_METHOD)
+        .with_arg(Encode!(&args).context("Failed to encode arguments.")?)
+        .call_and_wait(waiter_with_timeout(timeout))
+        .await
+        .with_context(|| format!("Update call to {} failed.", WITHDRAW_METHOD))?;
+    match Decode!(&result, Result<Nat, WithdrawError>)
+        .context("Failed to decode withdraw response.")?
+    {
+        Ok(block_index) => Ok(block_index),
+        Err(err) => bail!("{}", err),
+    }
+}
+
+/// Creates a canister with `amount` cycles, paid from the caller's balance on
+/// the cycles ledger. The ledger's fee is charged on top of the amount.
+#[context("Failed to create a canister through cycles ledger {}.", ledger)]
+pub async fn create_canister(
+    agent: &Agent,
+    ledger: &Principal,
+    amount: u128,
+    settings: CanisterSettings,
+    timeout: Duration,
+) -> DfxResult<Principal> {
+    let args = CreateCanisterArgs {
+        from_subaccount: None,
+        created_at_time: Some(now_nanos()),
+        amount: Nat::from(amount),
+        creation_args: Some(CmcCreateCanisterArgs {
+            settings: Some(CmcCanisterSettings {
+                controllers: settings.controllers,
+                compute_allocation: settings.compute_allocation.map(u8::from).map(Nat::from),
+                memory_allocation: settings.memory_allocation.map(u64::from).map(Nat::from),
+                freezing_threshold: settings.freezing_threshold.map(u128::from).map(Nat::from),
+            }),
+        }),
+    };
+    let result = agent
+        .update(ledger, CREATE_CANISTER_METHOD)
+        .with_arg(Encode!(&args).context("Failed to encode arguments.")?)
+        .call_and_wait(waiter_with_timeout(timeout))
+        .await
+        .with_context(|| format!("Update call to {} failed.", CREATE_CANISTER_METHOD))?;
+    match Decode!(&result, Result<CreateCanisterSuccess, CreateCanisterError>)
+        .context("Failed to decode create_canister response.")?
+    {
+        Ok(success) => Ok(success.canister_id),
+        Err(err) => bail!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles_source() {
+        assert_eq!(
+            CyclesSource::from_str("identity").unwrap(),
+            CyclesSource::Identity
+        );
+        assert_eq!(
+            CyclesSource::from_str("wallet").unwrap(),
+            CyclesSource::Wallet
+        );
+        assert!(CyclesSource::from_str("ledger").is_err());
+        assert_eq!(
+            serde_json::from_str::<CyclesSource>("\"identity\"").unwrap(),
+            CyclesSource::Identity
+        );
+    }
+}
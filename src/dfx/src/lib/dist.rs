@@ -1,14 +1,20 @@
+use crate::config::cache::{self, EXEC_READ_USER_ONLY_PERMISSION};
 use crate::lib::error::{DfxError, DfxResult};
-use crate::lib::manifest::Manifest;
-use crate::{error_invalid_argument, error_invalid_data};
+use crate::lib::manifest::{self, Manifest};
 
-use anyhow::Context;
+use anyhow::{anyhow, bail, Context};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use fn_error_context::context;
 use indicatif::{ProgressBar, ProgressDrawTarget};
 use libflate::gzip::Decoder;
+use openssl::sha::Sha256;
 use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use tar::Archive;
 
@@ -16,48 +22,50 @@ pub static DEFAULT_RELEASE_ROOT: &str = "https://sdk.dfinity.org";
 pub static CACHE_ROOT: &str = ".cache/dfinity/versions/";
 pub static DOWNLOADS_DIR: &str = ".cache/dfinity/downloads/";
 
-#[context("Failed to get distribution manifest.")]
-pub fn get_manifest() -> DfxResult<Manifest> {
-    let url_string = format!("{}/manifest.json", DEFAULT_RELEASE_ROOT);
-    let url = reqwest::Url::parse(&url_string)
-        .map_err(|e| error_invalid_argument!("invalid manifest URL: {}", e))?;
-
-    let b = ProgressBar::new_spinner();
-    b.set_draw_target(ProgressDrawTarget::stderr());
+/// The file in a toolchain bundle that lists its version and platform.
+/// Next to it are the release tarball, the release manifest and its signature.
+const BUNDLE_MANIFEST: &str = "bundle.json";
+/// The directory the release tarball is unpacked into before it is moved into the cache.
+const BUNDLE_CACHE_DIR: &str = "cache";
 
-    b.set_message(format!("Fetching manifest {}", url));
-    b.enable_steady_tick(80);
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleManifest {
+    version: String,
+    platform: String,
+    /// The sha256 checksums of the other files of the bundle, by name.
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+}
 
-    let response = reqwest::blocking::get(url).map_err(DfxError::new)?;
-    let status_code = response.status();
-    b.finish_and_clear();
+fn sha256_hex(content: &[u8]) -> String {
+    let mut sha256 = Sha256::new();
+    sha256.update(content);
+    hex::encode(sha256.finish())
+}
 
-    if !status_code.is_success() {
-        return Err(error_invalid_data!(
-            "unable to fetch manifest: {}",
-            status_code.canonical_reason().unwrap_or("unknown error"),
-        ));
+fn platform() -> &'static str {
+    match std::env::consts::OS {
+        "linux" => "x86_64-linux",
+        "macos" => "x86_64-darwin",
+        _ => panic!("Not supported architecture"),
     }
+}
 
-    response
-        .json()
-        .map_err(|e| error_invalid_data!("invalid manifest: {}", e))
+/// Fetches the release manifest, verifying its signature unless `insecure` is set.
+#[context("Failed to get distribution manifest.")]
+pub fn get_manifest(insecure: bool) -> DfxResult<Manifest> {
+    manifest::fetch_manifest(DEFAULT_RELEASE_ROOT, None, insecure)
 }
 
 // Download a SDK version to cache
+// Unless `manifest` is None, the download is verified against the checksum in the manifest.
 #[context("Failed to download and install version '{}'.", version)]
-pub fn install_version(version: &Version) -> DfxResult<()> {
-    let arch_os = match std::env::consts::OS {
-        "linux" => "x86_64-linux",
-        "macos" => "x86_64-darwin",
-        _ => panic!("Not supported architecture"),
-    };
-    let url = reqwest::Url::parse(&format!(
-        "{0}/downloads/dfx/{1}/{2}/dfx-{1}.tar.gz",
-        DEFAULT_RELEASE_ROOT, version, arch_os
-    ))
-    .map_err(|e| error_invalid_argument!("invalid url: {}", e))?;
-
+pub fn install_version(
+    release_root: &str,
+    version: &Version,
+    manifest: Option<&Manifest>,
+) -> DfxResult<()> {
+    let arch_os = platform();
     let home = std::env::var("HOME").context("Failed to resolve env var HOME.")?;
     let home = Path::new(&home);
 
@@ -69,23 +77,28 @@ pub fn install_version(version: &Version) -> DfxResult<()> {
     let download_file = download_dir.join(&format!("dfx-{}.tar.gz", version));
     if download_file.exists() {
         println!("Found downloaded file {}", download_file.to_string_lossy());
+        let content = fs::read(&download_file)
+            .with_context(|| format!("Failed to read {}.", download_file.to_string_lossy()))?;
+        if let Some(manifest) = manifest {
+            if let Err(e) = manifest.verify_release(version, arch_os, &content) {
+                // Do not keep a corrupted or tampered download around to be found next time.
+                fs::remove_file(&download_file).with_context(|| {
+                    format!("Failed to remove {}.", download_file.to_string_lossy())
+                })?;
+                return Err(e);
+            }
+        }
     } else {
+        let content = manifest::download_release(release_root, version, arch_os, manifest)?;
         let mut dest = fs::File::create(&download_file).with_context(|| {
             format!("Failed to create file {}.", download_file.to_string_lossy())
         })?;
-        let b = ProgressBar::new_spinner();
-        b.set_draw_target(ProgressDrawTarget::stderr());
-        b.set_message(format!("Downloading {}", url));
-        b.enable_steady_tick(80);
-        let response = reqwest::blocking::get(url).map_err(DfxError::new)?;
-        let content = response.bytes().context("Failed to get response body.")?;
-        dest.write_all(&*content).with_context(|| {
+        dest.write_all(&content).with_context(|| {
             format!(
                 "Failed to write response content to {}.",
                 download_file.to_string_lossy()
             )
         })?;
-        b.finish_with_message("Download complete");
     }
 
     let mut cache_dir = home.join(CACHE_ROOT);
@@ -119,12 +132,191 @@ pub fn install_version(version: &Version) -> DfxResult<()> {
     })?;
     b.finish_with_message("Unpack complete");
 
-    // Install components
-    let dfx = cache_dir.join("dfx");
-    std::process::Command::new(dfx)
+    install_components(&cache_dir, version)
+}
+
+/// Runs `dfx cache install` of a version unpacked into `cache_dir`. If that fails, the version
+/// is removed from the cache again, so that it is not taken for installed.
+#[context("Failed to install the components of version '{}'.", version)]
+fn install_components(cache_dir: &Path, version: &Version) -> DfxResult {
+    let status = std::process::Command::new(cache_dir.join("dfx"))
         .args(&["cache", "install"])
         .status()
         .map_err(DfxError::from)?;
+    if !status.success() {
+        fs::remove_dir_all(cache_dir)
+            .with_context(|| format!("Failed to remove {}.", cache_dir.to_string_lossy()))?;
+        bail!(
+            "'dfx cache install' of version {} failed: {}.",
+            version,
+            status
+        );
+    }
+    Ok(())
+}
+
+fn release_tarball(version: &Version) -> String {
+    format!("dfx-{}.tar.gz", version)
+}
+
+#[context("Failed to add {} to the bundle.", name)]
+fn append_file<W: Write>(builder: &mut tar::Builder<W>, name: &str, content: &[u8]) -> DfxResult {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, content)?;
+    Ok(())
+}
+
+/// Packages the release tarball of a version, together with the release manifest and
+/// its signature, into a file that `install_bundle` installs on a machine without
+/// network access. The release is verified before it is bundled unless `insecure` is set.
+#[context("Failed to bundle version '{}'.", version)]
+pub fn create_bundle(
+    version: &Version,
+    output: &Path,
+    release_root: &str,
+    insecure: bool,
+) -> DfxResult {
+    let (content, signature) = manifest::fetch_manifest_files(release_root, None, true)?;
+    let manifest = manifest::verify_manifest(&content, signature.as_deref(), insecure)?;
+    let manifest = if insecure { None } else { Some(&manifest) };
+    let tarball = manifest::download_release(release_root, version, platform(), manifest)?;
 
+    let mut files = BTreeMap::new();
+    files.insert(manifest::MANIFEST_FILE.to_string(), sha256_hex(&content));
+    if let Some(signature) = &signature {
+        files.insert(manifest::SIGNATURE_FILE.to_string(), sha256_hex(signature));
+    }
+    files.insert(release_tarball(version), sha256_hex(&tarball));
+    let bundle_manifest = BundleManifest {
+        version: version.to_string(),
+        platform: platform().to_string(),
+        files,
+    };
+    let bundle_manifest = serde_json::to_vec_pretty(&bundle_manifest)
+        .context("Failed to serialize bundle manifest.")?;
+
+    let file = fs::File::create(output)
+        .with_context(|| format!("Failed to create {}.", output.to_string_lossy()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    append_file(&mut builder, BUNDLE_MANIFEST, &bundle_manifest)?;
+    append_file(&mut builder, manifest::MANIFEST_FILE, &content)?;
+    if let Some(signature) = &signature {
+        append_file(&mut builder, manifest::SIGNATURE_FILE, signature)?;
+    }
+    append_file(&mut builder, &release_tarball(version), &tarball)?;
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("Failed to write {}.", output.to_string_lossy()))?;
     Ok(())
 }
+
+/// Verifies the files of a bundle created by `create_bundle` against the checksums in
+/// its bundle.json and the release against the release manifest in the bundle, unless
+/// `insecure` is set, and installs it into the cache. Returns the version of the bundle.
+#[context("Failed to install bundle {}.", path.to_string_lossy())]
+pub fn install_bundle(path: &Path, insecure: bool) -> DfxResult<Version> {
+    let bin_cache_root = cache::get_bin_cache_root()?;
+    // Versions starting with '_' are skipped when listing the cache.
+    let unpacked = tempfile::Builder::new()
+        .prefix("_bundle_")
+        .tempdir_in(&bin_cache_root)
+        .context("Failed to create temporary directory.")?;
+
+    let tar_gz = fs::File::open(path)
+        .with_context(|| format!("Failed to open {}.", path.to_string_lossy()))?;
+    let tar = Decoder::new(tar_gz)
+        .with_context(|| format!("Failed to decode archive at {}.", path.to_string_lossy()))?;
+    Archive::new(tar)
+        .unpack(unpacked.path())
+        .with_context(|| format!("Failed to unpack archive at {}.", path.to_string_lossy()))?;
+
+    let read = |name: &str| {
+        fs::read(unpacked.path().join(name)).map_err(|_| anyhow!("The bundle has no {}.", name))
+    };
+    let bundle_manifest: BundleManifest = serde_json::from_slice(&read(BUNDLE_MANIFEST)?)
+        .with_context(|| format!("Failed to parse {}.", BUNDLE_MANIFEST))?;
+    let version = Version::parse(&bundle_manifest.version).with_context(|| {
+        format!(
+            "Failed to parse version from '{}'.",
+            bundle_manifest.version
+        )
+    })?;
+    if bundle_manifest.platform != platform() {
+        bail!(
+            "The bundle is for {}, but this machine is {}.",
+            bundle_manifest.platform,
+            platform()
+        );
+    }
+
+    let read_verified = |name: &str| -> DfxResult<Vec<u8>> {
+        let expected = bundle_manifest.files.get(name).ok_or_else(|| {
+            anyhow!(
+                "{} of the bundle has no checksum for {}.",
+                BUNDLE_MANIFEST,
+                name
+            )
+        })?;
+        let content = read(name)?;
+        let actual = sha256_hex(&content);
+        if actual != *expected {
+            bail!(
+                "Checksum mismatch for {} in the bundle: expected {}, found {}.",
+                name,
+                expected,
+                actual
+            );
+        }
+        Ok(content)
+    };
+
+    let tarball = read_verified(&release_tarball(&version))?;
+    if !insecure {
+        let content = read_verified(manifest::MANIFEST_FILE)?;
+        let signature = if bundle_manifest.files.contains_key(manifest::SIGNATURE_FILE) {
+            Some(read_verified(manifest::SIGNATURE_FILE)?)
+        } else {
+            None
+        };
+        let manifest = manifest::verify_manifest(&content, signature.as_deref(), false)?;
+        manifest.verify_release(&version, platform(), &tarball)?;
+    }
+
+    let p = cache::get_bin_cache(&bundle_manifest.version)?;
+    if p.exists() {
+        eprintln!("SDK version {} already installed", version);
+        return Ok(version);
+    }
+
+    let cache_dir = unpacked.path().join(BUNDLE_CACHE_DIR);
+    let tar = Decoder::new(tarball.as_slice())
+        .with_context(|| format!("Failed to decode {}.", release_tarball(&version)))?;
+    Archive::new(tar)
+        .unpack(&cache_dir)
+        .with_context(|| format!("Failed to unpack {}.", release_tarball(&version)))?;
+    let dfx = cache_dir.join("dfx");
+    let mut perms = fs::metadata(&dfx)
+        .map_err(|_| anyhow!("The release in the bundle has no dfx binary."))?
+        .permissions();
+    perms.set_mode(EXEC_READ_USER_ONLY_PERMISSION);
+    fs::set_permissions(&dfx, perms)
+        .with_context(|| format!("Failed to set permissions of {}.", dfx.to_string_lossy()))?;
+
+    // atomically install cache version into place
+    fs::rename(&cache_dir, &p).with_context(|| {
+        format!(
+            "Failed to move {} to {}.",
+            cache_dir.to_string_lossy(),
+            p.to_string_lossy()
+        )
+    })?;
+
+    install_components(&p, &version)?;
+    eprintln!("SDK version {} installed from bundle", version);
+
+    Ok(version)
+}
@@ -5,9 +5,11 @@ use crate::lib::error::DfxResult;
 use crate::lib::identity::identity_manager::IdentityManager;
 use crate::lib::network::network_descriptor::NetworkDescriptor;
 use crate::lib::progress_bar::ProgressBar;
+use crate::lib::recording::RecordingReplicaV2Transport;
 
 use anyhow::{anyhow, Context};
 use fn_error_context::context;
+use ic_agent::agent::http_transport::ReqwestHttpReplicaV2Transport;
 use ic_agent::{Agent, Identity};
 use ic_types::Principal;
 use semver::Version;
@@ -38,6 +40,9 @@ pub trait Environment {
     /// Notably, it is _not_ the name of the default identity or selected identity
     fn get_identity_override(&self) -> &Option<String>;
 
+    /// The directory requests to the replica are recorded in, if any.
+    fn get_record_dir(&self) -> &Option<PathBuf>;
+
     // Explicit lifetimes are actually needed for mockall to work properly.
     #[allow(clippy::needless_lifetimes)]
     fn get_agent<'a>(&'a self) -> Option<&'a Agent>;
@@ -72,6 +77,8 @@ pub struct EnvironmentImpl {
     progress: bool,
 
     identity_override: Option<String>,
+
+    record_dir: Option<PathBuf>,
 }
 
 impl EnvironmentImpl {
@@ -90,32 +97,9 @@ impl EnvironmentImpl {
             )
         })?;
 
-        // Figure out which version of DFX we should be running. This will use the following
-        // fallback sequence:
-        //   1. DFX_VERSION environment variable
-        //   2. dfx.json "dfx" field
-        //   3. this binary's version
-        // If any of those are empty string, we stop the fallback and use the current version.
-        // If any of those are a valid version, we try to use that directly as is.
-        // If any of those are an invalid version, we will show an error to the user.
-        let version = match std::env::var("DFX_VERSION") {
-            Err(_) => match &config {
-                None => dfx_version().clone(),
-                Some(c) => match &c.get_config().get_dfx() {
-                    None => dfx_version().clone(),
-                    Some(v) => Version::parse(v)
-                        .with_context(|| format!("Failed to parse version from '{}'.", v))?,
-                },
-            },
-            Ok(v) => {
-                if v.is_empty() {
-                    dfx_version().clone()
-                } else {
-                    Version::parse(&v)
-                        .with_context(|| format!("Failed to parse version from '{}'.", &v))?
-                }
-            }
-        };
+        // The version of dfx to run was selected before this one was allowed to run,
+        // so it is the running version.
+        let version = dfx_version().clone();
 
         Ok(EnvironmentImpl {
             cache: Arc::new(DiskBasedCache::with_version(&version)),
@@ -125,6 +109,7 @@ impl EnvironmentImpl {
             logger: None,
             progress: true,
             identity_override: None,
+            record_dir: None,
         })
     }
 
@@ -142,6 +127,11 @@ impl EnvironmentImpl {
         self.identity_override = identity;
         self
     }
+
+    pub fn with_record_dir(mut self, record_dir: Option<PathBuf>) -> Self {
+        self.record_dir = record_dir;
+        self
+    }
 }
 
 impl Environment for EnvironmentImpl {
@@ -179,6 +169,10 @@ impl Environment for EnvironmentImpl {
         &self.identity_override
     }
 
+    fn get_record_dir(&self) -> &Option<PathBuf> {
+        &self.record_dir
+    }
+
     fn get_agent(&self) -> Option<&Agent> {
         // create an AgentEnvironment explicitly, in order to specify network and agent.
         // See install, build for examples.
@@ -241,10 +235,25 @@ impl<'a> AgentEnvironment<'a> {
                 network_descriptor.name
             )
         })?;
+        let record_dir = backend.get_record_dir().as_deref();
+        if let Some(record_dir) = record_dir {
+            create_dir_all(record_dir).with_context(|| {
+                format!(
+                    "Failed to create recording directory {}.",
+                    record_dir.to_string_lossy()
+                )
+            })?;
+        }
         Ok(AgentEnvironment {
             backend,
-            agent: create_agent(backend.get_logger().clone(), agent_url, identity, timeout)
-                .expect("Failed to construct agent."),
+            agent: create_agent(
+                backend.get_logger().clone(),
+                agent_url,
+                identity,
+                timeout,
+                record_dir,
+            )
+            .expect("Failed to construct agent."),
             network_descriptor: network_descriptor.clone(),
             identity_manager,
         })
@@ -286,6 +295,10 @@ impl<'a> Environment for AgentEnvironment<'a> {
         self.backend.get_identity_override()
     }
 
+    fn get_record_dir(&self) -> &Option<PathBuf> {
+        self.backend.get_record_dir()
+    }
+
     fn get_agent(&self) -> Option<&Agent> {
         Some(&self.agent)
     }
@@ -466,21 +479,26 @@ impl ic_agent::agent::http_transport::PasswordManager for AgentClient {
     }
 }
 
-fn create_agent(
+pub fn create_agent(
     logger: Logger,
     url: &str,
     identity: Box<dyn Identity + Send + Sync>,
     timeout: Duration,
+    record_dir: Option<&Path>,
 ) -> Option<Agent> {
-    AgentClient::new(logger, url.to_string())
+    AgentClient::new(logger.clone(), url.to_string())
         .ok()
         .and_then(|executor| {
-            Agent::builder()
-                .with_transport(
-                    ic_agent::agent::http_transport::ReqwestHttpReplicaV2Transport::create(url)
-                        .unwrap()
-                        .with_password_manager(executor),
-                )
+            let transport = ReqwestHttpReplicaV2Transport::create(url)
+                .unwrap()
+                .with_password_manager(executor);
+            let builder = match record_dir {
+                Some(record_dir) => Agent::builder().with_transport(
+                    RecordingReplicaV2Transport::new(transport, record_dir, logger),
+                ),
+                None => Agent::builder().with_transport(transport),
+            };
+            builder
                 .with_boxed_identity(identity)
                 .with_ingress_expiry(Some(timeout))
                 .build()
@@ -0,0 +1,326 @@
+//! Types and helpers for ledgers that implement the ICRC-1 token standard.
+//! See https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-1
+use crate::lib::error::DfxResult;
+
+use anyhow::{anyhow, bail};
+use candid::{CandidType, Int, Nat};
+use ic_types::Principal;
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use std::fmt;
+use std::str::FromStr;
+
+pub const SUBACCOUNT_LENGTH: usize = 32;
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// An ICRC-1 account: a principal and an optional 32-byte subaccount.
+/// No subaccount is the same account as the all-zero subaccount.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<ByteBuf>,
+}
+
+impl Account {
+    pub fn new(owner: Principal, subaccount: Option<[u8; SUBACCOUNT_LENGTH]>) -> Self {
+        Account {
+            owner,
+            subaccount: subaccount.map(|s| ByteBuf::from(s.to_vec())),
+        }
+    }
+
+    /// The subaccount, with the default subaccount filled in.
+    pub fn effective_subaccount(&self) -> [u8; SUBACCOUNT_LENGTH] {
+        let mut subaccount = [0u8; SUBACCOUNT_LENGTH];
+        if let Some(s) = &self.subaccount {
+            if s.len() == SUBACCOUNT_LENGTH {
+                subaccount.copy_from_slice(s);
+            }
+        }
+        subaccount
+    }
+
+    fn checksum(&self) -> String {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(self.owner.as_slice());
+        hasher.update(&self.effective_subaccount());
+        base32_encode(&hasher.finalize().to_be_bytes())
+    }
+}
+
+/// Formats the account with the ICRC-1 textual encoding: the principal for the
+/// default subaccount, `<principal>-<checksum>.<subaccount in hex>` otherwise.
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subaccount = self.effective_subaccount();
+        if subaccount == [0u8; SUBACCOUNT_LENGTH] {
+            return write!(f, "{}", self.owner);
+        }
+        let hex = hex::encode(subaccount);
+        write!(
+            f,
+            "{}-{}.{}",
+            self.owner,
+            self.checksum(),
+            hex.trim_start_matches('0')
+        )
+    }
+}
+
+impl FromStr for Account {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (owner_and_checksum, subaccount) = match s.split_once('.') {
+            None => {
+                let owner = Principal::from_text(s)
+                    .map_err(|e| anyhow!("Invalid account '{}': {}", s, e))?;
+                return Ok(Account::new(owner, None));
+            }
+            Some(parts) => parts,
+        };
+        let (owner, checksum) = owner_and_checksum
+            .rsplit_once('-')
+            .ok_or_else(|| anyhow!("Invalid account '{}': missing checksum.", s))?;
+        let owner =
+            Principal::from_text(owner).map_err(|e| anyhow!("Invalid account '{}': {}", s, e))?;
+        let subaccount =
+            parse_subaccount(subaccount).map_err(|e| anyhow!("Invalid account '{}': {}", s, e))?;
+        if subaccount == [0u8; SUBACCOUNT_LENGTH] {
+            bail!(
+                "Invalid account '{}': the default subaccount must be written as the principal alone.",
+                s
+            );
+        }
+        let account = Account::new(owner, Some(subaccount));
+        if checksum != account.checksum() {
+            bail!("Invalid account '{}': the checksum does not match.", s);
+        }
+        Ok(account)
+    }
+}
+
+/// Parses a subaccount written in hex. Leading zeros may be left out.
+pub fn parse_subaccount(s: &str) -> DfxResult<[u8; SUBACCOUNT_LENGTH]> {
+    if s.is_empty() || s.len() > SUBACCOUNT_LENGTH * 2 {
+        bail!(
+            "a subaccount must be 1 to {} hex digits.",
+            SUBACCOUNT_LENGTH * 2
+        );
+    }
+    let padded = format!("{:0>64}", s);
+    let bytes = hex::decode(&padded).map_err(|e| anyhow!("invalid subaccount: {}", e))?;
+    let mut subaccount = [0u8; SUBACCOUNT_LENGTH];
+    subaccount.copy_from_slice(&bytes);
+    Ok(subaccount)
+}
+
+/// Lowercase RFC 4648 base32 without padding, as used by principals.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Arguments for the `icrc1_transfer` call.
+#[derive(CandidType)]
+pub struct TransferArg {
+    pub from_subaccount: Option<ByteBuf>,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<ByteBuf>,
+    pub created_at_time: Option<u64>,
+}
+
+/// Result of the `icrc1_transfer` call.
+pub type TransferResult = Result<Nat, TransferError>;
+
+/// Error of the `icrc1_transfer` call.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+impl TransferError {
+    /// Describes the error, with amounts in the token's units.
+    pub fn describe(&self, token: &TokenFormat) -> String {
+        match self {
+            Self::BadFee { expected_fee } => format!(
+                "The transfer fee is {}. Pass it with --fee or leave --fee out.",
+                token.format(expected_fee)
+            ),
+            Self::BadBurn { min_burn_amount } => format!(
+                "Transfers to the minting account must burn at least {}.",
+                token.format(min_burn_amount)
+            ),
+            Self::InsufficientFunds { balance } => format!(
+                "The account doesn't have enough funds for the amount plus the fee. Current balance: {}",
+                token.format(balance)
+            ),
+            Self::TooOld => {
+                "The transfer is too old for the ledger to accept it. Retry without --created-at-time."
+                    .to_string()
+            }
+            Self::CreatedInFuture { ledger_time } => format!(
+                "The transfer's created_at_time is ahead of the ledger's time ({} ns). Check the system clock.",
+                ledger_time
+            ),
+            Self::Duplicate { duplicate_of } => format!(
+                "The same transfer was already recorded in block {}.",
+                duplicate_of.0.to_str_radix(10)
+            ),
+            Self::TemporarilyUnavailable => "The ledger is temporarily unavailable.".to_string(),
+            Self::GenericError {
+                error_code,
+                message,
+            } => format!(
+                "The ledger rejected the transfer (error {}): {}",
+                error_code.0.to_str_radix(10),
+                message
+            ),
+        }
+    }
+}
+
+/// A value in the `icrc1_metadata` list.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Nat(Nat),
+    Int(Int),
+    Text(String),
+    Blob(ByteBuf),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nat(n) => write!(f, "{}", n.0.to_str_radix(10)),
+            Value::Int(i) => write!(f, "{}", i.0.to_str_radix(10)),
+            Value::Text(t) => write!(f, "{}", t),
+            Value::Blob(b) => write!(f, "0x{}", hex::encode(b)),
+        }
+    }
+}
+
+/// How amounts of a token are written: `decimals` digits after the point,
+/// followed by the token symbol.
+#[derive(Clone, Debug)]
+pub struct TokenFormat {
+    pub decimals: u8,
+    pub symbol: String,
+}
+
+impl TokenFormat {
+    /// Formats an amount in the ledger's smallest unit, e.g. `1.5 TKN`.
+    pub fn format(&self, amount: &Nat) -> String {
+        let digits = amount.0.to_str_radix(10);
+        let decimals = self.decimals as usize;
+        let padded = format!("{:0>width$}", digits, width = decimals + 1);
+        let (whole, fraction) = padded.split_at(padded.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            format!("{} {}", whole, self.symbol)
+        } else {
+            format!("{}.{} {}", whole, fraction, self.symbol)
+        }
+    }
+
+    /// Parses a decimal amount of the token into the ledger's smallest unit.
+    pub fn parse(&self, amount: &str) -> DfxResult<Nat> {
+        let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+        let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            bail!("Invalid amount '{}'.", amount);
+        }
+        if fraction.len() > self.decimals as usize {
+            bail!(
+                "Invalid amount '{}': {} has at most {} decimal places.",
+                amount,
+                self.symbol,
+                self.decimals
+            );
+        }
+        let units = format!(
+            "{}{:0<width$}",
+            whole,
+            fraction,
+            width = self.decimals as usize
+        );
+        let units = units.trim_start_matches('0');
+        Nat::from_str(if units.is_empty() { "0" } else { units })
+            .map_err(|e| anyhow!("Invalid amount '{}': {}", amount, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_text() {
+        let owner = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let account = Account::new(owner, None);
+        assert_eq!(account.to_string(), "ryjl3-tyaaa-aaaaa-aaaba-cai");
+        assert_eq!(Account::from_str(&account.to_string()).unwrap(), account);
+        assert_eq!(
+            Account::new(owner, Some([0; 32])).to_string(),
+            account.to_string()
+        );
+
+        let mut subaccount = [0u8; 32];
+        subaccount[31] = 1;
+        let account = Account::new(owner, Some(subaccount));
+        let text = account.to_string();
+        assert!(text.starts_with("ryjl3-tyaaa-aaaaa-aaaba-cai-"));
+        assert!(text.ends_with(".1"));
+        assert_eq!(Account::from_str(&text).unwrap(), account);
+
+        let bad_checksum = text.replacen("-cai-", "-cai-a", 1);
+        assert!(Account::from_str(&bad_checksum).is_err());
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32_encode(b""), "");
+        assert_eq!(base32_encode(b"f"), "my");
+        assert_eq!(base32_encode(b"foobar"), "mzxw6ytboi");
+    }
+
+    #[test]
+    fn test_token_format() {
+        let token = TokenFormat {
+            decimals: 8,
+            symbol: "TKN".to_string(),
+        };
+        assert_eq!(token.format(&Nat::from(150_000_000u64)), "1.5 TKN");
+        assert_eq!(token.format(&Nat::from(1u64)), "0.00000001 TKN");
+        assert_eq!(token.format(&Nat::from(0u64)), "0 TKN");
+        assert_eq!(token.parse("1.5").unwrap(), Nat::from(150_000_000u64));
+        assert_eq!(token.parse(".01").unwrap(), Nat::from(1_000_000u64));
+        assert_eq!(token.parse("0").unwrap(), Nat::from(0u64));
+        assert!(token.parse("0.000000001").is_err());
+        assert!(token.parse("1,5").is_err());
+        assert!(token.parse(".").is_err());
+    }
+}
@@ -29,6 +29,7 @@ use std::path::{Path, PathBuf};
 pub mod identity_manager;
 pub mod identity_utils;
 pub mod pem_encryption;
+pub mod subaccounts;
 use crate::util::assets::wallet_wasm;
 use crate::util::expiry_duration;
 pub use identity_manager::{
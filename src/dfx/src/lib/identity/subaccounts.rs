@@ -0,0 +1,164 @@
+//! Named ledger subaccounts of an identity, stored in `subaccounts.json` next to
+//! `wallets.json` in the identity's directory.
+use crate::lib::config::get_config_dfx_dir_path;
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::nns_types::account_identifier::Subaccount;
+
+use anyhow::{anyhow, bail, Context};
+use fn_error_context::context;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+const SUBACCOUNTS_FILENAME: &str = "subaccounts.json";
+
+pub struct SubaccountStore {
+    path: PathBuf,
+    /// Subaccounts by name, in hex.
+    subaccounts: BTreeMap<String, String>,
+}
+
+impl SubaccountStore {
+    /// The named subaccounts of the selected identity.
+    pub fn for_env(env: &dyn Environment) -> DfxResult<SubaccountStore> {
+        let identity = env
+            .get_selected_identity()
+            .ok_or_else(|| anyhow!("No identity is selected."))?;
+        SubaccountStore::for_identity(identity)
+    }
+
+    pub fn for_identity(identity: &str) -> DfxResult<SubaccountStore> {
+        let path = get_config_dfx_dir_path()?
+            .join("identity")
+            .join(identity)
+            .join(SUBACCOUNTS_FILENAME);
+        SubaccountStore::load(&path)
+    }
+
+    #[context("Failed to load named subaccounts from {}.", path.display())]
+    fn load(path: &Path) -> DfxResult<SubaccountStore> {
+        let subaccounts = if path.exists() {
+            serde_json::from_slice(&std::fs::read(path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(SubaccountStore {
+            path: path.to_path_buf(),
+            subaccounts,
+        })
+    }
+
+    #[context("Failed to save named subaccounts to {}.", self.path.display())]
+    fn save(&self) -> DfxResult {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write a temporary file and rename it, so an interrupted write cannot
+        // lose the names that are already there.
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.subaccounts)?)
+            .with_context(|| format!("Failed to write {}.", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to rename {}.", tmp.display()))?;
+        Ok(())
+    }
+
+    /// The named subaccounts, sorted by name.
+    pub fn list(&self) -> DfxResult<Vec<(String, Subaccount)>> {
+        self.subaccounts
+            .iter()
+            .map(|(name, hex)| Ok((name.clone(), parse_hex(hex)?)))
+            .collect()
+    }
+
+    /// Returns the subaccount with this name, or the subaccount written in hex.
+    pub fn resolve(&self, name_or_hex: &str) -> DfxResult<Subaccount> {
+        match self.subaccounts.get(name_or_hex) {
+            Some(hex) => parse_hex(hex),
+            None => parse_hex(name_or_hex).with_context(|| {
+                format!(
+                    "'{}' is neither a named subaccount nor a subaccount in hex. Run `dfx ledger subaccounts` to list the names.",
+                    name_or_hex
+                )
+            }),
+        }
+    }
+
+    /// Adds a named subaccount. Without an explicit value, the subaccount is the
+    /// lowest unused number, written as 32 big-endian bytes.
+    pub fn add(&mut self, name: &str, subaccount: Option<Subaccount>) -> DfxResult<Subaccount> {
+        if self.subaccounts.contains_key(name) {
+            bail!("A subaccount named '{}' already exists.", name);
+        }
+        if parse_hex(name).is_ok() {
+            bail!(
+                "'{}' cannot be a name, it reads as a subaccount in hex.",
+                name
+            );
+        }
+        let existing = self.list()?;
+        let subaccount = match subaccount {
+            Some(subaccount) => subaccount,
+            None => (1u64..)
+                .map(numbered_subaccount)
+                .find(|candidate| existing.iter().all(|(_, s)| s != candidate))
+                .unwrap(),
+        };
+        if let Some((other, _)) = existing.iter().find(|(_, s)| *s == subaccount) {
+            bail!("The subaccount is already named '{}'.", other);
+        }
+        self.subaccounts
+            .insert(name.to_string(), hex::encode(subaccount.0));
+        self.save()?;
+        Ok(subaccount)
+    }
+
+    pub fn remove(&mut self, name: &str) -> DfxResult {
+        if self.subaccounts.remove(name).is_none() {
+            bail!("There is no subaccount named '{}'.", name);
+        }
+        self.save()
+    }
+}
+
+/// Parses a subaccount written in hex, without looking up names.
+pub fn parse_hex(hex: &str) -> DfxResult<Subaccount> {
+    let bytes = hex::decode(hex)
+        .with_context(|| format!("Subaccount '{}' is not a valid hex string", hex))?;
+    Subaccount::try_from(&bytes[..])
+        .with_context(|| format!("Subaccount '{}' is not 64 characters long", hex))
+}
+
+fn numbered_subaccount(n: u64) -> Subaccount {
+    let mut subaccount = [0u8; 32];
+    subaccount[24..].copy_from_slice(&n.to_be_bytes());
+    Subaccount(subaccount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subaccount_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SUBACCOUNTS_FILENAME);
+        let mut store = SubaccountStore::load(&path).unwrap();
+        assert_eq!(store.add("treasury", None).unwrap(), numbered_subaccount(1));
+        assert_eq!(store.add("payroll", None).unwrap(), numbered_subaccount(2));
+        assert!(store.add("treasury", None).is_err());
+        assert!(store.add("other", Some(numbered_subaccount(2))).is_err());
+
+        let store = SubaccountStore::load(&path).unwrap();
+        assert_eq!(store.resolve("payroll").unwrap(), numbered_subaccount(2));
+        assert_eq!(
+            store
+                .resolve(&hex::encode(numbered_subaccount(3).0))
+                .unwrap(),
+            numbered_subaccount(3)
+        );
+        assert!(store.resolve("unknown").is_err());
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+}
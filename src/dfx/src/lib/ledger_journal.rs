@@ -0,0 +1,263 @@
+//! A journal of the ICP transfers dfx sends for an identity. An entry is written
+//! before the transfer is sent and removed once the operation it belongs to has
+//! completed, so `dfx ledger pending` can finish operations that were interrupted.
+use crate::lib::config::get_config_dfx_dir_path;
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+
+use anyhow::{anyhow, bail, Context};
+use fn_error_context::context;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const JOURNAL_FILE: &str = "ledger-journal.json";
+
+/// How long to wait for another dfx to finish changing the journal.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+/// The lock is only held while the journal is read, changed and written back, so an older
+/// lock was left behind by a dfx that was killed while holding it.
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// What the transfer pays for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum JournalOperation {
+    /// A plain `dfx ledger transfer`.
+    Transfer,
+    /// A transfer to the cycles minting canister that tops up `canister` once notified.
+    TopUp { canister: String },
+    /// A transfer to the cycles minting canister that creates a canister controlled
+    /// by `controller` once notified.
+    CreateCanister { controller: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// The name of the network the transfer was sent on.
+    pub network: String,
+    /// The canister id of the ledger.
+    pub ledger: String,
+    pub operation: JournalOperation,
+    pub memo: u64,
+    /// Sending the transfer again with the same created_at_time is deduplicated by
+    /// the ledger. It also identifies the entry.
+    pub created_at_time: u64,
+    pub amount_e8s: u64,
+    pub fee_e8s: u64,
+    /// The destination AccountIdentifier, in hex.
+    pub to: String,
+    /// The subaccount the transfer is sent from, in hex. None is the default subaccount.
+    #[serde(default)]
+    pub from_subaccount: Option<String>,
+    /// The maximum fee for the notify call, for operations that need one.
+    pub max_fee_e8s: Option<u64>,
+    /// Set once the ledger has recorded the transfer.
+    pub block_height: Option<u64>,
+}
+
+pub struct Journal {
+    path: PathBuf,
+    entries: Vec<JournalEntry>,
+}
+
+/// A lock file next to the journal, held while the journal is read, changed and written
+/// back, so that dfx processes running at the same time don't drop each other's entries.
+struct JournalLock {
+    path: PathBuf,
+}
+
+impl JournalLock {
+    #[context("Failed to lock the ledger journal at {}.", journal_path.display())]
+    fn acquire(journal_path: &Path) -> DfxResult<JournalLock> {
+        if let Some(parent) = journal_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let path = journal_path.with_extension("json.lock");
+        let started = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(JournalLock { path }),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    if JournalLock::is_stale(&path) {
+                        let _ = std::fs::remove_file(&path);
+                        continue;
+                    }
+                    if started.elapsed() > LOCK_TIMEOUT {
+                        bail!(
+                            "Another dfx is changing the journal. If none is running, remove {}.",
+                            path.display()
+                        );
+                    }
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("Failed to create {}.", path.display()))
+                }
+            }
+        }
+    }
+
+    fn is_stale(path: &Path) -> bool {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map_or(false, |age| age > LOCK_STALE_AFTER)
+    }
+}
+
+impl Drop for JournalLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn read_entries(path: &Path) -> DfxResult<Vec<JournalEntry>> {
+    if path.exists() {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    } else {
+        Ok(vec![])
+    }
+}
+
+impl Journal {
+    /// The journal of the selected identity.
+    pub fn for_env(env: &dyn Environment) -> DfxResult<Journal> {
+        let identity = env
+            .get_selected_identity()
+            .ok_or_else(|| anyhow!("No identity is selected."))?;
+        let path = get_config_dfx_dir_path()?
+            .join("identity")
+            .join(identity)
+            .join(JOURNAL_FILE);
+        Journal::load(&path)
+    }
+
+    #[context("Failed to load the ledger journal at {}.", path.display())]
+    pub fn load(path: &Path) -> DfxResult<Journal> {
+        Ok(Journal {
+            path: path.to_path_buf(),
+            entries: read_entries(path)?,
+        })
+    }
+
+    /// The entries as of the last time this process read or changed the journal.
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn add(&mut self, entry: JournalEntry) -> DfxResult {
+        self.update(|entries| entries.push(entry))
+    }
+
+    pub fn set_block_height(&mut self, created_at_time: u64, block_height: u64) -> DfxResult {
+        self.update(|entries| {
+            for entry in entries {
+                if entry.created_at_time == created_at_time {
+                    entry.block_height = Some(block_height);
+                }
+            }
+        })
+    }
+
+    pub fn remove(&mut self, created_at_time: u64) -> DfxResult {
+        self.update(|entries| entries.retain(|entry| entry.created_at_time != created_at_time))
+    }
+
+    /// Applies a change to the entries currently in the file, rather than to the ones
+    /// loaded earlier, which another dfx may have changed since.
+    #[context("Failed to update the ledger journal at {}.", self.path.display())]
+    fn update(&mut self, change: impl FnOnce(&mut Vec<JournalEntry>)) -> DfxResult {
+        let _lock = JournalLock::acquire(&self.path)?;
+        let mut entries = read_entries(&self.path)?;
+        change(&mut entries);
+        self.entries = entries;
+        self.save()
+    }
+
+    #[context("Failed to save the ledger journal at {}.", self.path.display())]
+    fn save(&self) -> DfxResult {
+        // Write a temporary file and rename it, so an interrupted write cannot
+        // lose the entries that are already there.
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.entries)?)
+            .with_context(|| format!("Failed to write {}.", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to rename {}.", tmp.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(JOURNAL_FILE);
+        let entry = JournalEntry {
+            network: "local".to_string(),
+            ledger: "ryjl3-tyaaa-aaaaa-aaaba-cai".to_string(),
+            operation: JournalOperation::TopUp {
+                canister: "rkp4c-7iaaa-aaaaa-aaaca-cai".to_string(),
+            },
+            memo: 1,
+            created_at_time: 42,
+            amount_e8s: 100_000_000,
+            fee_e8s: 10_000,
+            to: "00".repeat(32),
+            from_subaccount: None,
+            max_fee_e8s: Some(10_000),
+            block_height: None,
+        };
+
+        let mut journal = Journal::load(&path).unwrap();
+        journal.add(entry.clone()).unwrap();
+        journal.set_block_height(42, 7).unwrap();
+
+        let mut journal = Journal::load(&path).unwrap();
+        assert_eq!(journal.entries().len(), 1);
+        assert_eq!(journal.entries()[0].block_height, Some(7));
+        assert_eq!(journal.entries()[0].operation, entry.operation);
+
+        journal.remove(42).unwrap();
+        assert!(Journal::load(&path).unwrap().entries().is_empty());
+    }
+
+    #[test]
+    fn test_journal_keeps_entries_of_other_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(JOURNAL_FILE);
+        let entry = |created_at_time| JournalEntry {
+            network: "local".to_string(),
+            ledger: "ryjl3-tyaaa-aaaaa-aaaba-cai".to_string(),
+            operation: JournalOperation::Transfer,
+            memo: 1,
+            created_at_time,
+            amount_e8s: 100_000_000,
+            fee_e8s: 10_000,
+            to: "00".repeat(32),
+            from_subaccount: None,
+            max_fee_e8s: None,
+            block_height: None,
+        };
+
+        let mut first = Journal::load(&path).unwrap();
+        let mut second = Journal::load(&path).unwrap();
+        first.add(entry(1)).unwrap();
+        second.add(entry(2)).unwrap();
+        first.set_block_height(1, 7).unwrap();
+
+        let entries = Journal::load(&path).unwrap().entries().to_vec();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].block_height, Some(7));
+        assert_eq!(entries[1].created_at_time, 2);
+        assert!(!path.with_extension("json.lock").exists());
+    }
+}
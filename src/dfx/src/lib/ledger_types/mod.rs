@@ -6,9 +6,10 @@
 use crate::lib::nns_types::account_identifier::Subaccount;
 use crate::lib::nns_types::icpts::ICPTs;
 use crate::lib::nns_types::{account_identifier, icpts};
-use candid::CandidType;
+use candid::{CandidType, Func};
 use ic_types::principal::Principal;
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use std::fmt;
 
 /// Id of the ledger canister on the IC.
@@ -105,7 +106,7 @@ pub struct AccountBalanceArgs {
     pub account: String,
 }
 
-#[derive(CandidType)]
+#[derive(CandidType, Deserialize, Clone, Copy, Debug)]
 pub struct TimeStamp {
     pub timestamp_nanos: u64,
 }
@@ -119,6 +120,105 @@ pub struct NotifyCanisterArgs {
     pub to_subaccount: Option<account_identifier::Subaccount>,
 }
 
+/// Arguments for the `query_blocks` call of the ledger and the archive callbacks.
+#[derive(CandidType)]
+pub struct GetBlocksArgs {
+    pub start: BlockHeight,
+    pub length: u64,
+}
+
+/// Result of the `query_blocks` call.
+#[derive(CandidType, Deserialize)]
+pub struct QueryBlocksResponse {
+    pub chain_length: u64,
+    pub certificate: Option<ByteBuf>,
+    pub blocks: Vec<Block>,
+    pub first_block_index: BlockHeight,
+    pub archived_blocks: Vec<ArchivedBlocksRange>,
+}
+
+/// A range of blocks that has moved to an archive canister, and the query
+/// method of that archive that returns them.
+#[derive(CandidType, Deserialize)]
+pub struct ArchivedBlocksRange {
+    pub start: BlockHeight,
+    pub length: u64,
+    pub callback: Func,
+}
+
+/// Result of an archive callback.
+pub type QueryArchiveResult = Result<BlockRange, QueryArchiveError>;
+
+#[derive(CandidType, Deserialize)]
+pub struct BlockRange {
+    pub blocks: Vec<Block>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum QueryArchiveError {
+    BadFirstBlockIndex {
+        requested_index: BlockHeight,
+        first_valid_index: BlockHeight,
+    },
+    Other {
+        error_code: u64,
+        error_message: String,
+    },
+}
+
+impl fmt::Display for QueryArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadFirstBlockIndex {
+                requested_index,
+                first_valid_index,
+            } => write!(
+                f,
+                "block {} is not in the archive, its first block is {}",
+                requested_index, first_valid_index
+            ),
+            Self::Other {
+                error_code,
+                error_message,
+            } => write!(f, "error {}: {}", error_code, error_message),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Block {
+    pub parent_hash: Option<ByteBuf>,
+    pub transaction: Transaction,
+    pub timestamp: TimeStamp,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Transaction {
+    pub memo: Memo,
+    pub operation: Option<Operation>,
+    pub created_at_time: TimeStamp,
+}
+
+/// An operation recorded in a block. Account identifiers are in their 32-byte
+/// form, with the checksum.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Operation {
+    Mint {
+        to: ByteBuf,
+        amount: ICPTs,
+    },
+    Burn {
+        from: ByteBuf,
+        amount: ICPTs,
+    },
+    Transfer {
+        from: ByteBuf,
+        to: ByteBuf,
+        amount: ICPTs,
+        fee: ICPTs,
+    },
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
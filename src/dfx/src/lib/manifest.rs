@@ -1,10 +1,12 @@
 use crate::lib::error::{DfxError, DfxResult};
 use crate::{error_invalid_argument, error_invalid_data};
 
-use anyhow::Context;
+use anyhow::{anyhow, bail, Context};
 use fn_error_context::context;
 use indicatif::{ProgressBar, ProgressDrawTarget};
 use libflate::gzip::Decoder;
+use openssl::sha::Sha256;
+use ring::signature::{UnparsedPublicKey, ED25519};
 use semver::Version;
 use serde::{Deserialize, Deserializer};
 use std::collections::BTreeMap;
@@ -48,12 +50,28 @@ where
     Ok(result)
 }
 
+/// The ed25519 public key, base64 encoded, that verifies the signature of the release manifest,
+/// set at compile time with `DFX_RELEASE_PUBLIC_KEY`. The release server does not publish
+/// signed manifests yet, so release builds leave it unset and verification is opt-in until
+/// it does. Builds for the e2e tests set it to the key in `e2e/assets/release_key`.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("DFX_RELEASE_PUBLIC_KEY");
+
+/// The release manifest, and its detached signature, at the release root.
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const SIGNATURE_FILE: &str = "manifest.json.sig";
+
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 pub struct Manifest {
     #[serde(deserialize_with = "deserialize_tags")]
     tags: BTreeMap<String, Version>,
     #[serde(deserialize_with = "deserialize_versions")]
     versions: Vec<Version>,
+    /// SHA-256 of the release tarballs, by version and architecture.
+    #[serde(default)]
+    checksums: BTreeMap<String, BTreeMap<String, String>>,
+    /// Whether the signature of the manifest was verified against the release key.
+    #[serde(skip)]
+    verified: bool,
 }
 
 impl Manifest {
@@ -69,6 +87,41 @@ impl Manifest {
     pub fn get_tag_version(&self, tag: &str) -> Option<&Version> {
         self.tags.get(tag)
     }
+
+    pub fn get_checksum(&self, version: &Version, arch: &str) -> Option<&String> {
+        self.checksums
+            .get(&version.to_string())
+            .and_then(|checksums| checksums.get(arch))
+    }
+
+    /// Checks the tarball of a release against the checksum in the manifest. A manifest
+    /// verified against the release key must list the checksum; an unverified one is only
+    /// checked if it lists one.
+    #[context("Failed to verify the release of version {} for {}.", version, arch)]
+    pub fn verify_release(&self, version: &Version, arch: &str, tarball: &[u8]) -> DfxResult {
+        let expected = match self.get_checksum(version, arch) {
+            Some(expected) => expected,
+            None if self.verified => bail!(
+                "The manifest has no checksum for version {} on {}. Use --insecure to install it without verification.",
+                version,
+                arch
+            ),
+            None => return Ok(()),
+        };
+        let mut sha256 = Sha256::new();
+        sha256.update(tarball);
+        let actual = hex::encode(sha256.finish());
+        if actual != *expected {
+            bail!(
+                "Checksum mismatch for the release of version {} on {}: expected {}, found {}. Refusing to install it.",
+                version,
+                arch,
+                expected,
+                actual
+            );
+        }
+        Ok(())
+    }
 }
 
 pub fn is_upgrade_necessary(latest_version: Option<&Version>, current: &Version) -> bool {
@@ -78,15 +131,83 @@ pub fn is_upgrade_necessary(latest_version: Option<&Version>, current: &Version)
     }
 }
 
-#[context("Failed to fetch latest version.")]
-pub fn get_latest_version(
+#[context("Failed to fetch {}.", url)]
+fn fetch(client: &reqwest::blocking::Client, url: reqwest::Url) -> DfxResult<Vec<u8>> {
+    let response = client.get(url.clone()).send().map_err(DfxError::new)?;
+    let status_code = response.status();
+    if !status_code.is_success() {
+        return Err(error_invalid_data!(
+            "unable to fetch {}: {}",
+            url,
+            status_code.canonical_reason().unwrap_or("unknown error"),
+        ));
+    }
+    Ok(response
+        .bytes()
+        .context("Failed to get response body.")?
+        .to_vec())
+}
+
+/// Verifies the detached ed25519 signature of `content`. Both the signature and the
+/// public key are base64 encoded.
+#[context("Failed to verify signature.")]
+fn verify_signature(content: &[u8], signature: &[u8], public_key: &str) -> DfxResult {
+    let public_key = base64::decode(public_key.trim()).context("Invalid release public key.")?;
+    let signature = base64::decode(String::from_utf8_lossy(signature).trim())
+        .context("The signature is not base64 encoded.")?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(content, &signature)
+        .map_err(|_| anyhow!("The signature does not match the release key."))
+}
+
+/// The release key, unless `insecure` is set or dfx was built without one.
+fn release_public_key_unless_insecure(insecure: bool) -> Option<&'static str> {
+    if insecure {
+        None
+    } else {
+        if RELEASE_PUBLIC_KEY.is_none() {
+            eprintln!("Warning: This dfx was built without a release key, so the signature of the release manifest is not verified.");
+        }
+        RELEASE_PUBLIC_KEY
+    }
+}
+
+/// Fetches the release manifest. Unless `insecure` is set, the manifest is verified
+/// against its detached signature `manifest.json.sig` and the release key.
+#[context("Failed to fetch manifest.")]
+pub fn fetch_manifest(
     release_root: &str,
     timeout: Option<std::time::Duration>,
-) -> DfxResult<Version> {
+    insecure: bool,
+) -> DfxResult<Manifest> {
+    let public_key = release_public_key_unless_insecure(insecure);
+    fetch_manifest_with_key(release_root, timeout, public_key)
+}
+
+/// Parses a release manifest fetched earlier with `fetch_manifest_files`, verifying it
+/// like `fetch_manifest` does.
+#[context("Failed to verify manifest.")]
+pub fn verify_manifest(
+    content: &[u8],
+    signature: Option<&[u8]>,
+    insecure: bool,
+) -> DfxResult<Manifest> {
+    let public_key = release_public_key_unless_insecure(insecure);
+    parse_manifest(content, signature, public_key)
+}
+
+/// Fetches the release manifest as published and, if `with_signature` is set, its
+/// detached signature. A signature that cannot be fetched is left out.
+#[context("Failed to fetch manifest.")]
+pub fn fetch_manifest_files(
+    release_root: &str,
+    timeout: Option<std::time::Duration>,
+    with_signature: bool,
+) -> DfxResult<(Vec<u8>, Option<Vec<u8>>)> {
     let url = reqwest::Url::parse(release_root)
         .map_err(|e| error_invalid_argument!("invalid release root: {}", e))?;
     let manifest_url = url
-        .join("manifest.json")
+        .join(MANIFEST_FILE)
         .map_err(|e| error_invalid_argument!("invalid manifest URL: {}", e))?;
     println!("Fetching manifest {}", manifest_url);
 
@@ -102,20 +223,61 @@ pub fn get_latest_version(
     };
 
     let client = client.build().context("Failed to build client.")?;
-    let response = client.get(manifest_url).send().map_err(DfxError::new)?;
-    let status_code = response.status();
+    let content = fetch(&client, manifest_url);
+    let signature = if with_signature {
+        let signature_url = url
+            .join(SIGNATURE_FILE)
+            .map_err(|e| error_invalid_argument!("invalid signature URL: {}", e))?;
+        fetch(&client, signature_url).ok()
+    } else {
+        None
+    };
     b.finish_and_clear();
+    Ok((content?, signature))
+}
 
-    if !status_code.is_success() {
-        return Err(error_invalid_data!(
-            "unable to fetch manifest: {}",
-            status_code.canonical_reason().unwrap_or("unknown error"),
-        ));
+fn fetch_manifest_with_key(
+    release_root: &str,
+    timeout: Option<std::time::Duration>,
+    public_key: Option<&str>,
+) -> DfxResult<Manifest> {
+    let (content, signature) = fetch_manifest_files(release_root, timeout, public_key.is_some())?;
+    parse_manifest(&content, signature.as_deref(), public_key)
+}
+
+/// Parses the release manifest. With a public key, the signature must be present and valid.
+fn parse_manifest(
+    content: &[u8],
+    signature: Option<&[u8]>,
+    public_key: Option<&str>,
+) -> DfxResult<Manifest> {
+    let mut verified = false;
+    if let Some(public_key) = public_key {
+        let signature = signature.ok_or_else(|| {
+            anyhow!(
+                "The release manifest has no signature {}, so it cannot be verified.",
+                SIGNATURE_FILE
+            )
+        })?;
+        verify_signature(content, signature, public_key)?;
+        verified = true;
     }
 
-    let manifest: Manifest = response
-        .json()
+    let manifest: Manifest = serde_json::from_slice(content)
         .map_err(|e| error_invalid_data!("invalid manifest: {}", e))?;
+    Ok(Manifest {
+        verified,
+        ..manifest
+    })
+}
+
+/// The latest version of dfx. The manifest is not verified: the version is only a hint.
+#[context("Failed to fetch latest version.")]
+pub fn get_latest_version(
+    release_root: &str,
+    timeout: Option<std::time::Duration>,
+) -> DfxResult<Version> {
+    let manifest = fetch_manifest(release_root, timeout, true)?;
     manifest
         .tags
         .get("latest")
@@ -123,12 +285,14 @@ pub fn get_latest_version(
         .map(|v| v.clone())
 }
 
-#[context(
-    "Failed to get latest release for version {} and architecture {}.",
-    version,
-    arch
-)]
-pub fn get_latest_release(release_root: &str, version: &Version, arch: &str) -> DfxResult<()> {
+/// Downloads a release tarball, verifying it against the manifest unless it is `None`.
+#[context("Failed to download release of version {} for {}.", version, arch)]
+pub fn download_release(
+    release_root: &str,
+    version: &Version,
+    arch: &str,
+    manifest: Option<&Manifest>,
+) -> DfxResult<Vec<u8>> {
     let url = reqwest::Url::parse(&format!(
         "{0}/downloads/dfx/{1}/{2}/dfx-{1}.tar.gz",
         release_root, version, arch
@@ -140,8 +304,37 @@ pub fn get_latest_release(release_root: &str, version: &Version, arch: &str) ->
 
     b.set_message(format!("Downloading {}", url));
     b.enable_steady_tick(80);
-    let mut response = reqwest::blocking::get(url).map_err(DfxError::new)?;
-    let mut decoder = Decoder::new(&mut response)
+    let client = reqwest::blocking::Client::builder()
+        .build()
+        .context("Failed to build client.")?;
+    let tarball = fetch(&client, url);
+    b.finish_and_clear();
+    let tarball = tarball?;
+    if let Some(manifest) = manifest {
+        manifest.verify_release(version, arch, &tarball)?;
+    }
+    Ok(tarball)
+}
+
+/// Replaces the running dfx with the given release. Unless `manifest` is `None`,
+/// the release is verified against the checksum in the manifest first.
+#[context(
+    "Failed to get latest release for version {} and architecture {}.",
+    version,
+    arch
+)]
+pub fn get_latest_release(
+    release_root: &str,
+    version: &Version,
+    arch: &str,
+    manifest: Option<&Manifest>,
+) -> DfxResult<()> {
+    let tarball = download_release(release_root, version, arch, manifest)?;
+
+    let b = ProgressBar::new_spinner();
+    b.set_draw_target(ProgressDrawTarget::stderr());
+    b.enable_steady_tick(80);
+    let mut decoder = Decoder::new(tarball.as_slice())
         .map_err(|e| error_invalid_data!("unable to gunzip file: {}", e))?;
     let mut archive = Archive::new(&mut decoder);
     let current_exe_path = env::current_exe().map_err(DfxError::new)?;
@@ -200,6 +393,105 @@ mod tests {
         assert_eq!(manifest.versions, versions);
     }
 
+    fn sign(content: &[u8]) -> (String, String) {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        (
+            base64::encode(key_pair.sign(content).as_ref()),
+            base64::encode(key_pair.public_key().as_ref()),
+        )
+    }
+
+    #[test]
+    fn test_fetch_signed_manifest() {
+        let (signature, public_key) = sign(MANIFEST.as_bytes());
+        let _m = mockito::mock("GET", "/manifest.json")
+            .with_status(200)
+            .with_body(MANIFEST)
+            .create();
+        let _s = mockito::mock("GET", "/manifest.json.sig")
+            .with_status(200)
+            .with_body(&signature)
+            .create();
+        let manifest =
+            fetch_manifest_with_key(&mockito::server_url(), None, Some(&public_key)).unwrap();
+        assert_eq!(
+            manifest.get_tag_version("latest"),
+            Some(&Version::parse("0.4.1").unwrap())
+        );
+        assert!(manifest.verified);
+
+        // a manifest signed with another key is refused
+        let (_, other_public_key) = sign(MANIFEST.as_bytes());
+        assert!(
+            fetch_manifest_with_key(&mockito::server_url(), None, Some(&other_public_key)).is_err()
+        );
+
+        // a modified manifest is refused
+        let _m = mockito::mock("GET", "/manifest.json")
+            .with_status(200)
+            .with_body(MANIFEST.replace("0.4.1", "0.4.2"))
+            .create();
+        assert!(fetch_manifest_with_key(&mockito::server_url(), None, Some(&public_key)).is_err());
+    }
+
+    #[test]
+    fn test_verify_release_checksum() {
+        let tarball = b"not really a tarball";
+        let mut sha256 = Sha256::new();
+        sha256.update(tarball);
+        let manifest = format!(
+            r#"{{
+              "tags": {{ "latest": "0.4.1" }},
+              "versions": [ "0.4.1" ],
+              "checksums": {{ "0.4.1": {{ "x86_64-linux": "{}" }} }}
+            }}"#,
+            hex::encode(sha256.finish())
+        );
+        let manifest = Manifest {
+            verified: true,
+            ..serde_json::from_str(&manifest).unwrap()
+        };
+        let version = Version::parse("0.4.1").unwrap();
+        let _d = mockito::mock("GET", "/downloads/dfx/0.4.1/x86_64-linux/dfx-0.4.1.tar.gz")
+            .with_status(200)
+            .with_body(&tarball[..])
+            .create();
+        assert!(download_release(
+            &mockito::server_url(),
+            &version,
+            "x86_64-linux",
+            Some(&manifest)
+        )
+        .is_ok());
+
+        assert!(manifest
+            .verify_release(&version, "x86_64-linux", b"a different tarball")
+            .is_err());
+
+        // the manifest must list the checksum
+        assert!(manifest
+            .verify_release(&version, "x86_64-darwin", tarball)
+            .is_err());
+
+        // a manifest that was not verified against the release key is only checked
+        // for the checksums it lists
+        let unverified = Manifest {
+            verified: false,
+            ..manifest
+        };
+        assert!(unverified
+            .verify_release(&version, "x86_64-linux", tarball)
+            .is_ok());
+        assert!(unverified
+            .verify_release(&version, "x86_64-linux", b"a different tarball")
+            .is_err());
+        assert!(unverified
+            .verify_release(&version, "x86_64-darwin", tarball)
+            .is_ok());
+    }
+
     #[test]
     fn test_get_latest_version() {
         let _ = env_logger::try_init();
@@ -0,0 +1,380 @@
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+
+use anyhow::Context;
+use fn_error_context::context;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the buckets of latency histograms, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+lazy_static! {
+    /// The metrics of this process, served by the webserver of `dfx start` at `/_/metrics`.
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Default)]
+struct Families {
+    help: BTreeMap<&'static str, &'static str>,
+    counters: BTreeMap<&'static str, BTreeMap<Labels, u64>>,
+    gauges: BTreeMap<&'static str, BTreeMap<Labels, f64>>,
+    histograms: BTreeMap<&'static str, BTreeMap<Labels, Histogram>>,
+}
+
+/// A minimal registry of counters, gauges and histograms, rendered in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    families: Mutex<Families>,
+}
+
+fn labels(labels: &[(&str, &str)]) -> Labels {
+    let mut labels: Labels = labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    labels.sort();
+    labels
+}
+
+impl Metrics {
+    pub fn inc_counter(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        label_values: &[(&str, &str)],
+    ) {
+        if let Ok(mut families) = self.families.lock() {
+            families.help.insert(name, help);
+            *families
+                .counters
+                .entry(name)
+                .or_default()
+                .entry(labels(label_values))
+                .or_default() += 1;
+        }
+    }
+
+    pub fn set_gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        label_values: &[(&str, &str)],
+        value: f64,
+    ) {
+        if let Ok(mut families) = self.families.lock() {
+            families.help.insert(name, help);
+            families
+                .gauges
+                .entry(name)
+                .or_default()
+                .insert(labels(label_values), value);
+        }
+    }
+
+    pub fn observe_duration(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        label_values: &[(&str, &str)],
+        duration: Duration,
+    ) {
+        if let Ok(mut families) = self.families.lock() {
+            families.help.insert(name, help);
+            families
+                .histograms
+                .entry(name)
+                .or_default()
+                .entry(labels(label_values))
+                .or_default()
+                .observe(duration.as_secs_f64());
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let families = match self.families.lock() {
+            Ok(families) => families,
+            Err(_) => return out,
+        };
+        let help = |name: &str| families.help.get(name).copied().unwrap_or_default();
+
+        for (name, series) in &families.counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help(name));
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (labels, value) in series {
+                let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), value);
+            }
+        }
+        for (name, series) in &families.gauges {
+            let _ = writeln!(out, "# HELP {} {}", name, help(name));
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for (labels, value) in series {
+                let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), value);
+            }
+        }
+        for (name, series) in &families.histograms {
+            let _ = writeln!(out, "# HELP {} {}", name, help(name));
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            for (labels, histogram) in series {
+                for (bucket, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                    let le = bound.to_string();
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{} {}",
+                        name,
+                        format_labels(labels, Some(&le)),
+                        bucket
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{} {}",
+                    name,
+                    format_labels(labels, Some("+Inf")),
+                    histogram.count
+                );
+                let _ = writeln!(
+                    out,
+                    "{}_sum{} {}",
+                    name,
+                    format_labels(labels, None),
+                    histogram.sum
+                );
+                let _ = writeln!(
+                    out,
+                    "{}_count{} {}",
+                    name,
+                    format_labels(labels, None),
+                    histogram.count
+                );
+            }
+        }
+        out
+    }
+}
+
+fn format_labels(labels: &[(String, String)], le: Option<&str>) -> String {
+    let mut parts: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+        .collect();
+    if let Some(le) = le {
+        parts.push(format!("le=\"{}\"", le));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", parts.join(","))
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Durations of dfx commands run against a local network. These run in their own
+/// processes, so each run is appended as a line to a file that the webserver of `dfx start` reads.
+#[derive(Clone, Debug, Default)]
+pub struct CommandTimings {
+    pub commands: BTreeMap<String, CommandTiming>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CommandTiming {
+    pub count: u64,
+    pub sum_secs: f64,
+    pub last_secs: f64,
+}
+
+/// A line of the command timings file.
+#[derive(Debug, Serialize, Deserialize)]
+struct CommandRun {
+    command: String,
+    secs: f64,
+}
+
+pub fn command_timings_path(temp_dir: &Path, network_name: &str) -> PathBuf {
+    temp_dir.join(network_name).join("command_timings.jsonl")
+}
+
+pub fn load_command_timings(path: &Path) -> CommandTimings {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    let mut timings = CommandTimings::default();
+    // A line that is still being written is skipped until it is complete.
+    for run in content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CommandRun>(line).ok())
+    {
+        let timing = timings.commands.entry(run.command).or_default();
+        timing.count += 1;
+        timing.sum_secs += run.secs;
+        timing.last_secs = run.secs;
+    }
+    timings
+}
+
+/// Records how long a command such as `build` or `deploy` took, unless it ran against the IC.
+#[context("Failed to record duration of '{}'.", command)]
+pub fn record_command_duration(
+    env: &dyn Environment,
+    command: &str,
+    duration: Duration,
+) -> DfxResult {
+    let network = match env.get_network_descriptor() {
+        Some(network) if !network.is_ic => network,
+        _ => return Ok(()),
+    };
+    let path = command_timings_path(env.get_temp_dir(), &network.name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}.", parent.to_string_lossy()))?;
+    }
+    let mut line = serde_json::to_string(&CommandRun {
+        command: command.to_string(),
+        secs: duration.as_secs_f64(),
+    })
+    .context("Failed to serialize command timing.")?;
+    line.push('\n');
+    // Commands may finish at the same time, so each one appends its line in a single write
+    // rather than rewriting the file.
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to write {}.", path.to_string_lossy()))
+}
+
+/// Renders command timings in the Prometheus text exposition format.
+pub fn render_command_timings(timings: &CommandTimings) -> String {
+    let mut out = String::new();
+    if timings.commands.is_empty() {
+        return out;
+    }
+    let name = "dfx_command_duration_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {} Duration of dfx commands run against this network.",
+        name
+    );
+    let _ = writeln!(out, "# TYPE {} summary", name);
+    for (command, timing) in &timings.commands {
+        let _ = writeln!(
+            out,
+            "{}_sum{{command=\"{}\"}} {}",
+            name, command, timing.sum_secs
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{{command=\"{}\"}} {}",
+            name, command, timing.count
+        );
+    }
+    let name = "dfx_command_last_duration_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {} Duration of the last run of each dfx command.",
+        name
+    );
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (command, timing) in &timings.commands {
+        let _ = writeln!(
+            out,
+            "{}{{command=\"{}\"}} {}",
+            name, command, timing.last_secs
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.inc_counter("requests_total", "Requests.", &[("kind", "query")]);
+        metrics.inc_counter("requests_total", "Requests.", &[("kind", "query")]);
+        metrics.set_gauge("up", "Up.", &[], 1.0);
+        metrics.observe_duration(
+            "latency_seconds",
+            "Latency.",
+            &[("kind", "call")],
+            Duration::from_millis(20),
+        );
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE requests_total counter\n"));
+        assert!(text.contains("requests_total{kind=\"query\"} 2\n"));
+        assert!(text.contains("up 1\n"));
+        assert!(text.contains("latency_seconds_bucket{kind=\"call\",le=\"0.01\"} 0\n"));
+        assert!(text.contains("latency_seconds_bucket{kind=\"call\",le=\"0.025\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{kind=\"call\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("latency_seconds_count{kind=\"call\"} 1\n"));
+    }
+
+    #[test]
+    fn sums_appended_command_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("command_timings.jsonl");
+        std::fs::write(
+            &path,
+            "{\"command\":\"build\",\"secs\":1.5}\n{\"command\":\"build\",\"secs\":0.5}\n{\"command\":\"dep",
+        )
+        .unwrap();
+
+        let timings = load_command_timings(&path);
+        assert_eq!(timings.commands.len(), 1);
+        let build = &timings.commands["build"];
+        assert_eq!(build.count, 2);
+        assert_eq!(build.sum_secs, 2.0);
+        assert_eq!(build.last_secs, 0.5);
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(
+            format_labels(&labels(&[("method", "a\"b")]), None),
+            "{method=\"a\\\"b\"}"
+        );
+    }
+}
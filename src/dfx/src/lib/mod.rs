@@ -1,30 +1,41 @@
 pub mod bitcoin;
 pub mod builders;
+pub mod candid_extraction;
 pub mod canister_http;
 pub mod canister_info;
+pub mod certificate;
 pub mod config;
+pub mod cycles_ledger;
 pub mod dist;
 pub mod environment;
 pub mod error;
 pub mod ic_attributes;
+pub mod icrc1;
 pub mod identity;
 pub mod installers;
+pub mod ledger_journal;
 pub mod ledger_types;
 pub mod locations;
 pub mod logger;
 pub mod manifest;
+pub mod metrics;
 pub mod migrate;
 pub mod models;
+pub mod motoko_packages;
 pub mod named_canister;
 pub mod network;
+pub mod nns;
 pub mod nns_types;
 pub mod operations;
 pub mod package_arguments;
 pub mod progress_bar;
 pub mod provider;
+pub mod recording;
+pub mod replica_bootstrap;
 pub mod replica_config;
 pub mod root_key;
 pub mod sign;
+pub mod subnets;
 pub mod toolchain;
 pub mod waiter;
 pub mod webserver;
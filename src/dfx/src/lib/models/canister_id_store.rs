@@ -3,7 +3,7 @@ use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
 use crate::lib::network::network_descriptor::NetworkDescriptor;
 
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use fn_error_context::context;
 use ic_types::principal::Principal as CanisterId;
 use std::collections::BTreeMap;
@@ -17,6 +17,10 @@ type CanisterIdString = String;
 type NetworkNametoCanisterId = BTreeMap<NetworkName, CanisterIdString>;
 type CanisterIds = BTreeMap<CanisterName, NetworkNametoCanisterId>;
 
+type SubnetName = String;
+type NetworkNameToSubnet = BTreeMap<NetworkName, SubnetName>;
+type CanisterSubnets = BTreeMap<CanisterName, NetworkNameToSubnet>;
+
 #[derive(Clone, Debug)]
 pub struct CanisterIdStore {
     network_descriptor: NetworkDescriptor,
@@ -28,6 +32,11 @@ pub struct CanisterIdStore {
 
     // Remote ids read from dfx.json, never written to canister_ids.json
     remote_ids: Option<CanisterIds>,
+
+    // The named subnets of the local replica canisters were created on,
+    // stored next to canister_ids.json in canister_subnets.json
+    subnets_path: PathBuf,
+    subnets: CanisterSubnets,
 }
 
 impl CanisterIdStore {
@@ -60,12 +69,20 @@ impl CanisterIdStore {
         } else {
             CanisterIds::new()
         };
+        let subnets_path = path.with_file_name("canister_subnets.json");
+        let subnets = if subnets_path.is_file() {
+            CanisterIdStore::load_subnets(&subnets_path)?
+        } else {
+            CanisterSubnets::new()
+        };
 
         Ok(CanisterIdStore {
             network_descriptor: network_descriptor.clone(),
             path,
             ids,
             remote_ids: None,
+            subnets_path,
+            subnets,
         })
     }
 
@@ -123,8 +140,48 @@ impl CanisterIdStore {
             .and_then(|s| CanisterId::from_text(s).ok())
     }
 
+    /// The names and ids of all canisters created on this network, without remote canisters.
+    pub fn get_all(&self) -> Vec<(CanisterName, CanisterId)> {
+        self.ids
+            .keys()
+            .filter_map(|canister_name| {
+                self.find_in(canister_name, &self.ids)
+                    .map(|canister_id| (canister_name.clone(), canister_id))
+            })
+            .collect()
+    }
+
+    /// The id of the canister, to send messages to it. Fails if the canister was created on
+    /// another subnet of the local replica than the one this store's network points at,
+    /// because the same id may belong to another canister on this subnet.
     #[context("Failed to determine id for canister '{}'.", canister_name)]
     pub fn get(&self, canister_name: &str) -> DfxResult<CanisterId> {
+        let canister_id = self.get_on_any_subnet(canister_name)?;
+        match (
+            self.get_subnet(canister_name),
+            self.network_descriptor.subnet.as_ref(),
+        ) {
+            (Some(subnet), Some(current)) if subnet == current => {}
+            (None, None) => {}
+            (Some(subnet), _) => bail!(
+                "Canister '{}' was created on subnet '{}' of the local replica. Name it in a 'dfx canister' command, such as 'dfx canister call {} <method>', to reach it there.",
+                canister_name,
+                subnet,
+                canister_name
+            ),
+            (None, Some(current)) => bail!(
+                "Canister '{}' was not created on subnet '{}'.",
+                canister_name,
+                current
+            ),
+        }
+        Ok(canister_id)
+    }
+
+    /// The id of the canister, whichever subnet of the local replica it was created on.
+    /// Only for uses that do not send messages to the canister, such as building it.
+    #[context("Failed to determine id for canister '{}'.", canister_name)]
+    pub fn get_on_any_subnet(&self, canister_name: &str) -> DfxResult<CanisterId> {
         self.find(canister_name).ok_or_else(|| {
             let network = if self.network_descriptor.name == "local" {
                 "".to_string()
@@ -168,8 +225,50 @@ impl CanisterIdStore {
         if let Some(network_name_to_canister_id) = self.ids.get_mut(canister_name) {
             network_name_to_canister_id.remove(&network_name.to_string());
         }
+        if let Some(network_name_to_subnet) = self.subnets.get_mut(canister_name) {
+            if network_name_to_subnet.remove(network_name).is_some() {
+                self.save_subnets()?;
+            }
+        }
         self.save_ids()
     }
+
+    #[context("Failed to load subnets from storage at {}.", path.to_string_lossy())]
+    fn load_subnets(path: &Path) -> DfxResult<CanisterSubnets> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read from file at '{}'.", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Cannot decode contents of file at '{}'.", path.display()))
+    }
+
+    fn save_subnets(&self) -> DfxResult {
+        let content =
+            serde_json::to_string_pretty(&self.subnets).context("Failed to serialize subnets.")?;
+        std::fs::write(&self.subnets_path, content)
+            .with_context(|| format!("Cannot write to file at '{}'.", self.subnets_path.display()))
+    }
+
+    /// The named subnet of the local replica the canister was created on, if any.
+    pub fn get_subnet(&self, canister_name: &str) -> Option<&String> {
+        self.subnets
+            .get(canister_name)
+            .and_then(|network_name_to_subnet| {
+                network_name_to_subnet.get(&self.network_descriptor.name)
+            })
+    }
+
+    #[context(
+        "Failed to record subnet '{}' of canister '{}' in canister id store.",
+        subnet,
+        canister_name
+    )]
+    pub fn set_subnet(&mut self, canister_name: &str, subnet: &str) -> DfxResult {
+        self.subnets
+            .entry(canister_name.to_string())
+            .or_default()
+            .insert(self.network_descriptor.name.clone(), subnet.to_string());
+        self.save_subnets()
+    }
 }
 
 #[context("Failed to get remote ids.")]
@@ -0,0 +1,321 @@
+use crate::config::cache::get_cache_root;
+use crate::config::dfinity::ConfigMotokoPackage;
+use crate::lib::error::DfxResult;
+
+use anyhow::{anyhow, bail, Context};
+use fn_error_context::context;
+use openssl::sha::Sha256;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The lockfile, next to dfx.json, that records the resolved package set.
+pub const LOCKFILE_NAME: &str = "motoko.lock";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub packages: Vec<LockedPackage>,
+}
+
+/// A package from a git repository, pinned to a commit and the checksum of its contents.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub repo: String,
+    /// The version or rev requested in dfx.json.
+    pub requested: String,
+    pub commit: String,
+    pub sha256: String,
+}
+
+impl Lockfile {
+    #[context("Failed to load {}.", path.to_string_lossy())]
+    fn load(path: &Path) -> DfxResult<Lockfile> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        let content = std::fs::read(path)
+            .with_context(|| format!("Failed to read {}.", path.to_string_lossy()))?;
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}.", path.to_string_lossy()))
+    }
+
+    #[context("Failed to save {}.", path.to_string_lossy())]
+    fn save(&self, path: &Path) -> DfxResult {
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize lockfile.")?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write {}.", path.to_string_lossy()))
+    }
+
+    fn get(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+}
+
+/// Resolves the packages declared in dfx.json into `moc` `--package` arguments.
+/// Git packages are pinned in the lockfile and fetched into the dfx cache, local
+/// packages are used in place.
+#[context("Failed to resolve Motoko packages.")]
+pub fn resolve(project_root: &Path, packages: &[ConfigMotokoPackage]) -> DfxResult<Vec<String>> {
+    let lockfile_path = project_root.join(LOCKFILE_NAME);
+    let lockfile = Lockfile::load(&lockfile_path)?;
+    let mut resolved = Lockfile::default();
+    let mut arguments = vec![];
+
+    for package in packages {
+        let package_root = match (&package.path, &package.repo) {
+            (Some(path), None) => project_root.join(path),
+            (None, Some(repo)) => {
+                let requested = package
+                    .rev
+                    .as_ref()
+                    .or_else(|| package.version.as_ref())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Package '{}' needs a 'version' or a 'rev' to fetch it from {}.",
+                            package.name,
+                            repo
+                        )
+                    })?;
+                validate_revision(requested)?;
+                let locked = match lockfile.get(&package.name) {
+                    Some(locked) if &locked.repo == repo && &locked.requested == requested => {
+                        ensure_fetched(locked)?;
+                        locked.clone()
+                    }
+                    _ => fetch(&package.name, repo, requested)?,
+                };
+                let root = package_cache_path(&locked.name, &locked.commit)?;
+                resolved.packages.push(locked);
+                root
+            }
+            _ => bail!(
+                "Package '{}' must specify exactly one of 'repo' and 'path'.",
+                package.name
+            ),
+        };
+        let src = package_root.join(package.src.as_deref().unwrap_or_else(|| Path::new("src")));
+        arguments.push("--package".to_string());
+        arguments.push(package.name.clone());
+        arguments.push(src.to_string_lossy().to_string());
+    }
+
+    if resolved != lockfile {
+        resolved.save(&lockfile_path)?;
+    }
+
+    Ok(arguments)
+}
+
+#[context("Failed to determine cache path of package '{}'.", name)]
+fn package_cache_path(name: &str, commit: &str) -> DfxResult<PathBuf> {
+    Ok(get_cache_root()?.join("packages").join(name).join(commit))
+}
+
+/// Makes sure the locked package is in the cache with the locked checksum, fetching it if needed.
+#[context("Failed to fetch locked package '{}'.", locked.name)]
+fn ensure_fetched(locked: &LockedPackage) -> DfxResult {
+    if !is_commit_hash(&locked.commit) {
+        bail!(
+            "Locked commit '{}' is not a commit hash. Remove the package from {} to lock it again.",
+            locked.commit,
+            LOCKFILE_NAME
+        );
+    }
+    let path = package_cache_path(&locked.name, &locked.commit)?;
+    if !path.exists() {
+        let fetched = fetch(&locked.name, &locked.repo, &locked.commit)?;
+        if fetched.commit != locked.commit {
+            bail!(
+                "Fetched commit {} instead of locked commit {}.",
+                fetched.commit,
+                locked.commit
+            );
+        }
+    }
+    let sha256 = checksum(&path)?;
+    if sha256 != locked.sha256 {
+        bail!(
+            "Checksum mismatch for package '{}' at {}: expected {}, found {}. Delete {} to fetch it again, or remove the package from {} to accept the new contents.",
+            locked.name,
+            locked.commit,
+            locked.sha256,
+            sha256,
+            path.to_string_lossy(),
+            LOCKFILE_NAME
+        );
+    }
+    Ok(())
+}
+
+/// Clones the repository at the requested version, tag, branch or commit into the cache.
+#[context("Failed to fetch package '{}' from {} at '{}'.", name, repo, requested)]
+fn fetch(name: &str, repo: &str, requested: &str) -> DfxResult<LockedPackage> {
+    let packages_root = get_cache_root()?.join("packages").join(name);
+    std::fs::create_dir_all(&packages_root)
+        .with_context(|| format!("Failed to create {}.", packages_root.to_string_lossy()))?;
+    let checkout =
+        tempfile::tempdir_in(&packages_root).context("Failed to create temporary directory.")?;
+
+    git(&packages_root, &["clone", "--quiet", "--", repo])
+        .arg(checkout.path())
+        .status()
+        .context("Failed to run 'git clone'.")
+        .and_then(|status| {
+            if status.success() {
+                Ok(())
+            } else {
+                Err(anyhow!("'git clone' failed with {}.", status))
+            }
+        })?;
+    validate_revision(requested)?;
+    let status = git(checkout.path(), &["checkout", "--quiet", requested, "--"])
+        .status()
+        .context("Failed to run 'git checkout'.")?;
+    if !status.success() {
+        bail!("'git checkout {}' failed with {}.", requested, status);
+    }
+    let output = git(checkout.path(), &["rev-parse", "HEAD"])
+        .output()
+        .context("Failed to run 'git rev-parse'.")?;
+    if !output.status.success() {
+        bail!("'git rev-parse HEAD' failed with {}.", output.status);
+    }
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !is_commit_hash(&commit) {
+        bail!(
+            "'git rev-parse HEAD' returned '{}', not a commit hash.",
+            commit
+        );
+    }
+
+    let git_dir = checkout.path().join(".git");
+    std::fs::remove_dir_all(&git_dir)
+        .with_context(|| format!("Failed to remove {}.", git_dir.to_string_lossy()))?;
+    let sha256 = checksum(checkout.path())?;
+
+    // A cached copy of the commit is only kept if it still has the contents just fetched.
+    let path = packages_root.join(&commit);
+    if path.exists() && checksum(&path)? != sha256 {
+        std::fs::remove_dir_all(&path)
+            .with_context(|| format!("Failed to remove {}.", path.to_string_lossy()))?;
+    }
+    if !path.exists() {
+        std::fs::rename(checkout.into_path(), &path)
+            .with_context(|| format!("Failed to move package to {}.", path.to_string_lossy()))?;
+    }
+
+    Ok(LockedPackage {
+        name: name.to_string(),
+        repo: repo.to_string(),
+        requested: requested.to_string(),
+        commit,
+        sha256,
+    })
+}
+
+/// Rejects revisions git would parse as an option.
+fn validate_revision(requested: &str) -> DfxResult {
+    if requested.is_empty() || requested.starts_with('-') {
+        bail!("'{}' is not a valid version or rev.", requested);
+    }
+    Ok(())
+}
+
+/// Whether `commit` is a full SHA-1 or SHA-256 commit hash, as printed by `git rev-parse`.
+fn is_commit_hash(commit: &str) -> bool {
+    matches!(commit.len(), 40 | 64)
+        && commit
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn git(dir: &Path, args: &[&str]) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(dir).args(args);
+    cmd
+}
+
+/// A sha256 over the relative paths and contents of all files below `root`, in sorted order.
+#[context("Failed to compute checksum of {}.", root.to_string_lossy())]
+pub fn checksum(root: &Path) -> DfxResult<String> {
+    let mut sha256 = Sha256::new();
+    for entry in walkdir::WalkDir::new(root).sort_by_file_name() {
+        let entry = entry.context("Failed to walk package directory.")?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(root)
+            .context("Failed to determine relative path.")?;
+        let content = std::fs::read(entry.path())
+            .with_context(|| format!("Failed to read {}.", entry.path().to_string_lossy()))?;
+        sha256.update(relative.to_string_lossy().as_bytes());
+        sha256.update(&[0]);
+        sha256.update(&(content.len() as u64).to_le_bytes());
+        sha256.update(&content);
+    }
+    Ok(hex::encode(sha256.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_depends_on_paths_and_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.mo"), "module {}").unwrap();
+        let original = checksum(dir.path()).unwrap();
+        assert_eq!(original, checksum(dir.path()).unwrap());
+
+        std::fs::write(dir.path().join("src/lib.mo"), "module { }").unwrap();
+        let changed = checksum(dir.path()).unwrap();
+        assert_ne!(original, changed);
+
+        std::fs::rename(dir.path().join("src/lib.mo"), dir.path().join("src/Lib.mo")).unwrap();
+        assert_ne!(changed, checksum(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn revisions_and_commits_are_validated() {
+        assert!(validate_revision("v0.6.0").is_ok());
+        assert!(validate_revision("--upload-pack=touch pwned").is_err());
+        assert!(validate_revision("").is_err());
+
+        assert!(is_commit_hash("4b5c1e0d4c6a3d8ab54d1e0d2c8b2f6f7e9a0b1c"));
+        assert!(!is_commit_hash("../../../etc"));
+        assert!(!is_commit_hash("-4b5c1e0d4c6a3d8ab54d1e0d2c8b2f6f7e9a0b1"));
+        assert!(!is_commit_hash("4B5C1E0D4C6A3D8AB54D1E0D2C8B2F6F7E9A0B1C"));
+    }
+
+    #[test]
+    fn local_packages_resolve_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let packages = vec![ConfigMotokoPackage {
+            name: "rate".to_string(),
+            repo: None,
+            version: None,
+            rev: None,
+            path: Some(PathBuf::from("vendor/rate")),
+            src: None,
+        }];
+        let arguments = resolve(dir.path(), &packages).unwrap();
+        assert_eq!(
+            arguments,
+            vec![
+                "--package".to_string(),
+                "rate".to_string(),
+                dir.path()
+                    .join("vendor/rate/src")
+                    .to_string_lossy()
+                    .to_string()
+            ]
+        );
+        // only git packages are locked
+        assert!(!dir.path().join(LOCKFILE_NAME).exists());
+    }
+}
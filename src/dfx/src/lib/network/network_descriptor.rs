@@ -7,6 +7,8 @@ pub struct NetworkDescriptor {
     pub providers: Vec<String>,
     pub r#type: NetworkType,
     pub is_ic: bool,
+    /// The named subnet of the local replica the providers point at, if not the main one.
+    pub subnet: Option<String>,
 }
 
 impl NetworkDescriptor {
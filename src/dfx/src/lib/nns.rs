@@ -0,0 +1,490 @@
+use crate::lib::certificate::{certificate_labels, decode_certificate};
+use crate::lib::environment::Environment;
+use crate::lib::error::DfxResult;
+use crate::lib::identity::identity_manager::IdentityManager;
+use crate::lib::ledger_types::{MAINNET_CYCLE_MINTER_CANISTER_ID, MAINNET_LEDGER_CANISTER_ID};
+use crate::lib::nns_types::account_identifier::{AccountIdentifier, Subaccount};
+use crate::lib::nns_types::icpts::ICPTs;
+use crate::lib::waiter::waiter_with_timeout;
+use crate::util::expiry_duration;
+
+use anyhow::{anyhow, bail, Context};
+use candid::{CandidType, Decode, Encode};
+use flate2::read::GzDecoder;
+use fn_error_context::context;
+use ic_agent::agent::http_transport::ReqwestHttpReplicaV2Transport;
+use ic_agent::agent::ReplicaV2Transport;
+use ic_agent::{Agent, AgentError};
+use ic_types::Principal;
+use ic_utils::interfaces::management_canister::builders::InstallMode;
+use ic_utils::interfaces::ManagementCanister;
+use openssl::sha::Sha256;
+use serde_cbor::Value;
+use slog::{info, warn};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The file names of the wasms in the cache.
+pub const LEDGER_WASM: &str = "ledger.wasm";
+pub const CYCLES_MINTING_CANISTER_WASM: &str = "cycles-minting-canister.wasm";
+
+/// ICP given to the selected identity if dfx.json does not configure initial balances.
+const DEFAULT_INITIAL_BALANCE: &str = "1000";
+
+/// Cycles the canisters are created with.
+const NNS_CANISTER_CYCLES: u128 = 100_000_000_000_000;
+
+/// Locally, 1 ICP is worth 1 XDR, which is 1T cycles.
+const XDR_PERMYRIAD_PER_ICP: u64 = 10_000;
+
+/// Tokens are minted from and burned into this subaccount of the selected identity, which
+/// stands in for the governance canister.
+const MINTING_SUBACCOUNT: Subaccount = Subaccount([0xff; 32]);
+
+#[derive(CandidType)]
+struct LedgerInitArgs {
+    minting_account: AccountIdentifier,
+    initial_values: Vec<(AccountIdentifier, ICPTs)>,
+    send_whitelist: Vec<Principal>,
+}
+
+#[derive(CandidType)]
+struct CyclesMintingInitArgs {
+    ledger_canister_id: Principal,
+    governance_canister_id: Principal,
+    minting_account_id: Option<AccountIdentifier>,
+}
+
+#[derive(CandidType)]
+struct SetAuthorizedSubnetworkListArgs {
+    who: Option<Principal>,
+    subnets: Vec<Principal>,
+}
+
+#[derive(CandidType)]
+struct UpdateIcpXdrConversionRatePayload {
+    data_source: String,
+    timestamp_seconds: u64,
+    xdr_permyriad_per_icp: u64,
+}
+
+/// The revision of the IC that the wasms are downloaded from. It must be updated together
+/// with the replica in nix/sources.json.
+const IC_REVISION: &str = "dcb2d23dfcd9de200f235110e618713cc884cb19";
+
+/// The file in the state of the local replica that marks it as running the NNS canisters,
+/// so that it is started as a system subnet. `dfx start --clean` removes it with the state.
+const NNS_MARKER: &str = "nns";
+
+/// The path of a wasm: the given one, or the file of that name in the cache. A wasm that
+/// is not in the cache yet is downloaded into it.
+#[context("Failed to find {}.", name)]
+pub fn wasm_path(env: &dyn Environment, given: Option<PathBuf>, name: &str) -> DfxResult<PathBuf> {
+    if let Some(path) = given {
+        return Ok(path);
+    }
+    let path = env.get_cache().get_binary_command_path(name)?;
+    if !path.exists() {
+        download_wasm(env, name, &path)?;
+    }
+    Ok(path)
+}
+
+/// The name a wasm is published under, gzipped, in the canisters of an IC release.
+fn published_name(name: &str) -> String {
+    match name {
+        LEDGER_WASM => "ledger-canister_notify-method.wasm.gz".to_string(),
+        _ => format!("{}.gz", name),
+    }
+}
+
+/// The checksum of a file in a SHA256SUMS file.
+fn checksum_of(sums: &str, file: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let checksum = parts.next()?;
+        match parts.next()?.trim_start_matches('*') == file {
+            true => Some(checksum.to_lowercase()),
+            false => None,
+        }
+    })
+}
+
+/// Downloads a wasm from the canisters of the IC release the replica is built from,
+/// verifies it against the SHA256SUMS of the release and writes it to `path`.
+#[context("Failed to download {} into the dfx cache.", name)]
+fn download_wasm(env: &dyn Environment, name: &str, path: &Path) -> DfxResult {
+    let base = format!(
+        "https://download.dfinity.systems/ic/{}/canisters/",
+        IC_REVISION
+    );
+    let published = published_name(name);
+    info!(env.get_logger(), "Downloading {}{}", base, published);
+    let client = reqwest::blocking::Client::builder()
+        .build()
+        .context("Failed to build client.")?;
+    let fetch = |file: &str| -> DfxResult<Vec<u8>> {
+        let url = format!("{}{}", base, file);
+        let response = client
+            .get(&url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to fetch {}.", url))?;
+        Ok(response
+            .bytes()
+            .with_context(|| format!("Failed to read {}.", url))?
+            .to_vec())
+    };
+
+    let sums = fetch("SHA256SUMS")?;
+    let expected = checksum_of(&String::from_utf8_lossy(&sums), &published).ok_or_else(|| {
+        anyhow!(
+            "The SHA256SUMS of the release have no checksum for {}.",
+            published
+        )
+    })?;
+    let gzipped = fetch(&published)?;
+    let mut sha256 = Sha256::new();
+    sha256.update(&gzipped);
+    let actual = hex::encode(sha256.finish());
+    if actual != expected {
+        bail!(
+            "Checksum mismatch for {}: expected {}, found {}.",
+            published,
+            expected,
+            actual
+        );
+    }
+    let mut wasm = vec![];
+    GzDecoder::new(gzipped.as_slice())
+        .read_to_end(&mut wasm)
+        .with_context(|| format!("Failed to decompress {}.", published))?;
+
+    // Written next to its final place first, so that an interrupted download is not mistaken
+    // for the wasm.
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, &wasm)
+        .with_context(|| format!("Failed to write {}.", partial.to_string_lossy()))?;
+    std::fs::rename(&partial, path).with_context(|| {
+        format!(
+            "Failed to move {} to {}.",
+            partial.to_string_lossy(),
+            path.to_string_lossy()
+        )
+    })?;
+    Ok(())
+}
+
+/// Whether the local replica whose state is in `state_dir` runs the NNS canisters.
+pub fn is_nns_subnet(state_dir: &Path) -> bool {
+    state_dir.join(NNS_MARKER).exists()
+}
+
+/// Marks the local replica whose state is in `state_dir` as running the NNS canisters.
+#[context("Failed to mark the local replica as running the NNS canisters.")]
+pub fn mark_nns_subnet(state_dir: &Path) -> DfxResult {
+    std::fs::create_dir_all(state_dir)
+        .with_context(|| format!("Failed to create {}.", state_dir.to_string_lossy()))?;
+    let marker = state_dir.join(NNS_MARKER);
+    std::fs::write(&marker, "")
+        .with_context(|| format!("Failed to write {}.", marker.to_string_lossy()))?;
+    Ok(())
+}
+
+/// The position of a canister id in the range of the local subnet.
+fn canister_index(canister_id: &Principal) -> Option<u64> {
+    let bytes = canister_id.as_slice();
+    match bytes.len() == 10 && bytes[8..] == [1, 1] {
+        true => Some(u64::from_be_bytes(bytes[..8].try_into().ok()?)),
+        false => None,
+    }
+}
+
+/// The accounts that receive ICP when the ledger is installed, from `defaults.ledger`
+/// in dfx.json.
+#[context("Failed to determine initial balances.")]
+fn initial_balances(env: &dyn Environment) -> DfxResult<Vec<(AccountIdentifier, ICPTs)>> {
+    let mut identity_manager = IdentityManager::new(env)?;
+    let balances = env
+        .get_config()
+        .and_then(|config| {
+            config
+                .get_config()
+                .get_defaults()
+                .get_ledger()
+                .initial_balances
+                .clone()
+        })
+        .unwrap_or_else(|| {
+            let mut balances = BTreeMap::new();
+            balances.insert(
+                identity_manager.get_selected_identity_name().clone(),
+                DEFAULT_INITIAL_BALANCE.to_string(),
+            );
+            balances
+        });
+    balances
+        .iter()
+        .map(|(name, amount)| {
+            let amount = amount
+                .parse::<ICPTs>()
+                .map_err(|e| anyhow!("Invalid initial balance {} for {}: {}", amount, name, e))?;
+            let identity = identity_manager.instantiate_identity_from_name(name)?;
+            let principal = ic_agent::Identity::sender(identity.as_ref())
+                .map_err(|e| anyhow!("Failed to get principal of {}: {}", name, e))?;
+            Ok((AccountIdentifier::new(principal, None), amount))
+        })
+        .collect()
+}
+
+/// Reads the ids of the subnets from the replica. A read_state request for `subnet`
+/// does not need to be signed, so it is sent as the anonymous principal.
+#[context("Failed to read the subnets of the replica at {}.", url)]
+async fn get_subnet_ids(url: &str) -> DfxResult<Vec<Principal>> {
+    let transport =
+        ReqwestHttpReplicaV2Transport::create(url).context("Failed to create transport object.")?;
+    let ingress_expiry = (SystemTime::now() + Duration::from_secs(4 * 60))
+        .duration_since(UNIX_EPOCH)
+        .expect("Time wrapped around.")
+        .as_nanos() as u64;
+    let mut content = BTreeMap::new();
+    content.insert(
+        Value::Text("request_type".to_string()),
+        Value::Text("read_state".to_string()),
+    );
+    content.insert(
+        Value::Text("sender".to_string()),
+        Value::Bytes(Principal::anonymous().as_slice().to_vec()),
+    );
+    content.insert(
+        Value::Text("paths".to_string()),
+        Value::Array(vec![Value::Array(vec![Value::Bytes(b"subnet".to_vec())])]),
+    );
+    content.insert(
+        Value::Text("ingress_expiry".to_string()),
+        Value::Integer(ingress_expiry as i128),
+    );
+    let mut envelope = BTreeMap::new();
+    envelope.insert(Value::Text("content".to_string()), Value::Map(content));
+    let envelope = serde_cbor::to_vec(&Value::Map(envelope))
+        .context("Failed to encode read_state request.")?;
+
+    let response = transport
+        .read_state(MAINNET_LEDGER_CANISTER_ID, envelope)
+        .await?;
+    let response: Value =
+        serde_cbor::from_slice(&response).context("Failed to decode read_state response.")?;
+    let certificate = decode_certificate(&response)
+        .ok_or_else(|| anyhow!("The read_state response has no certificate."))?;
+    Ok(certificate_labels(&certificate, &[&b"subnet"[..]])
+        .iter()
+        .map(|id| Principal::from_slice(id))
+        .collect())
+}
+
+/// Whether a module is installed in the canister.
+async fn is_installed(agent: &Agent, canister_id: Principal) -> DfxResult<bool> {
+    match agent
+        .read_state_canister_info(canister_id, "module_hash", false)
+        .await
+    {
+        Ok(_) => Ok(true),
+        // If the canister is empty or does not exist, this path does not exist.
+        Err(AgentError::LookupPathUnknown(_)) | Err(AgentError::LookupPathAbsent(_)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Creates canisters until the canister at the id of the cycles minting canister on mainnet
+/// exists. Canister ids are handed out in order, so this only works on a fresh replica.
+#[context("Failed to create the canisters at the ids of the NNS canisters.")]
+async fn create_nns_canisters(agent: &Agent) -> DfxResult {
+    let mgr = ManagementCanister::create(agent);
+    let ledger = canister_index(&MAINNET_LEDGER_CANISTER_ID).unwrap();
+    let last = canister_index(&MAINNET_CYCLE_MINTER_CANISTER_ID).unwrap();
+    let mut first = true;
+    loop {
+        let (canister_id,) = mgr
+            .create_canister()
+            .as_provisional_create_with_amount(Some(NNS_CANISTER_CYCLES))
+            .call_and_wait(waiter_with_timeout(expiry_duration()))
+            .await
+            .context("Canister creation call failed.")?;
+        let index = canister_index(&canister_id).unwrap_or(u64::MAX);
+        if first && index > ledger {
+            bail!(
+                "Canister {} was created, past the id of the ledger. The ledger can only be installed on a replica without canisters, e.g. after `dfx start --clean`.",
+                canister_id
+            );
+        }
+        first = false;
+        if index >= last {
+            return Ok(());
+        }
+    }
+}
+
+#[context("Failed to call {} on {}.", method, canister_id)]
+async fn update<A: CandidType>(
+    agent: &Agent,
+    canister_id: &Principal,
+    method: &str,
+    arg: &A,
+) -> DfxResult<Vec<u8>> {
+    Ok(agent
+        .update(canister_id, method)
+        .with_arg(Encode!(arg).context("Failed to encode arguments.")?)
+        .call_and_wait(waiter_with_timeout(expiry_duration()))
+        .await?)
+}
+
+/// Installs a ledger and a cycles minting canister on the local replica, at the ids they
+/// have on mainnet, so that `dfx ledger` works against them. The selected identity acts
+/// as the governance canister.
+#[context("Failed to install the ledger and the cycles minting canister.")]
+pub async fn install_nns(
+    env: &dyn Environment,
+    agent: &Agent,
+    url: &str,
+    ledger_wasm: &Path,
+    cycles_minting_wasm: &Path,
+) -> DfxResult {
+    let log = env.get_logger();
+    if is_installed(agent, MAINNET_LEDGER_CANISTER_ID).await? {
+        info!(
+            log,
+            "The ledger is already installed at {}.", MAINNET_LEDGER_CANISTER_ID
+        );
+        return Ok(());
+    }
+    let read = |path: &Path| {
+        std::fs::read(path).with_context(|| format!("Failed to read {}.", path.to_string_lossy()))
+    };
+    let ledger_wasm = read(ledger_wasm)?;
+    let cycles_minting_wasm = read(cycles_minting_wasm)?;
+    let initial_values = initial_balances(env)?;
+    let governance = env
+        .get_selected_identity_principal()
+        .ok_or_else(|| anyhow!("Cannot get the principal of the selected identity."))?;
+    let minting_account = AccountIdentifier::new(governance, Some(MINTING_SUBACCOUNT));
+
+    create_nns_canisters(agent).await?;
+
+    let mgr = ManagementCanister::create(agent);
+    info!(
+        log,
+        "Installing the ledger at {}", MAINNET_LEDGER_CANISTER_ID
+    );
+    let ledger_args = LedgerInitArgs {
+        minting_account,
+        initial_values,
+        send_whitelist: vec![MAINNET_CYCLE_MINTER_CANISTER_ID],
+    };
+    mgr.install_code(&MAINNET_LEDGER_CANISTER_ID, &ledger_wasm)
+        .with_raw_arg(Encode!(&ledger_args).context("Failed to encode ledger arguments.")?)
+        .with_mode(InstallMode::Install)
+        .build()
+        .context("Failed to build install call.")?
+        .call_and_wait(waiter_with_timeout(expiry_duration()))
+        .await
+        .context("Failed to install the ledger.")?;
+
+    info!(
+        log,
+        "Installing the cycles minting canister at {}", MAINNET_CYCLE_MINTER_CANISTER_ID
+    );
+    let cycles_minting_args = CyclesMintingInitArgs {
+        ledger_canister_id: MAINNET_LEDGER_CANISTER_ID,
+        governance_canister_id: governance,
+        minting_account_id: Some(minting_account),
+    };
+    mgr.install_code(&MAINNET_CYCLE_MINTER_CANISTER_ID, &cycles_minting_wasm)
+        .with_raw_arg(
+            Encode!(&cycles_minting_args)
+                .context("Failed to encode cycles minting canister arguments.")?,
+        )
+        .with_mode(InstallMode::Install)
+        .build()
+        .context("Failed to build install call.")?
+        .call_and_wait(waiter_with_timeout(expiry_duration()))
+        .await
+        .context("Failed to install the cycles minting canister.")?;
+
+    let timestamp_seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time wrapped around.")
+        .as_secs();
+    let result = update(
+        agent,
+        &MAINNET_CYCLE_MINTER_CANISTER_ID,
+        "set_icp_xdr_conversion_rate",
+        &UpdateIcpXdrConversionRatePayload {
+            data_source: "dfx".to_string(),
+            timestamp_seconds,
+            xdr_permyriad_per_icp: XDR_PERMYRIAD_PER_ICP,
+        },
+    )
+    .await?;
+    Decode!(&result, Result<(), String>)
+        .context("Failed to decode set_icp_xdr_conversion_rate response.")?
+        .map_err(|e| anyhow!("Failed to set the ICP/XDR conversion rate: {}", e))?;
+
+    // The cycles minting canister creates canisters on the subnets it is told about.
+    match get_subnet_ids(url).await {
+        Ok(subnets) if !subnets.is_empty() => {
+            update(
+                agent,
+                &MAINNET_CYCLE_MINTER_CANISTER_ID,
+                "set_authorized_subnetwork_list",
+                &SetAuthorizedSubnetworkListArgs { who: None, subnets },
+            )
+            .await?;
+        }
+        result => warn!(
+            log,
+            "Could not determine the subnet of the replica, so `dfx ledger create-canister` will not work: {}",
+            result
+                .err()
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| "no subnet found".to_string())
+        ),
+    }
+
+    info!(
+        log,
+        "Installed the ledger at {} and the cycles minting canister at {}.",
+        MAINNET_LEDGER_CANISTER_ID,
+        MAINNET_CYCLE_MINTER_CANISTER_ID
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canister_index() {
+        assert_eq!(canister_index(&MAINNET_LEDGER_CANISTER_ID), Some(2));
+        assert_eq!(canister_index(&MAINNET_CYCLE_MINTER_CANISTER_ID), Some(4));
+        assert_eq!(canister_index(&Principal::anonymous()), None);
+    }
+
+    #[test]
+    fn test_checksum_of() {
+        let sums =
+            "0a1b  cycles-minting-canister.wasm.gz\n2C3D *ledger-canister_notify-method.wasm.gz\n";
+        assert_eq!(
+            checksum_of(sums, &published_name(LEDGER_WASM)),
+            Some("2c3d".to_string())
+        );
+        assert_eq!(
+            checksum_of(sums, &published_name(CYCLES_MINTING_CANISTER_WASM)),
+            Some("0a1b".to_string())
+        );
+        assert_eq!(checksum_of(sums, "governance-canister.wasm.gz"), None);
+    }
+}
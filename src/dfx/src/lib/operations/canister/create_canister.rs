@@ -1,3 +1,4 @@
+use crate::lib::cycles_ledger;
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
 use crate::lib::ic_attributes::CanisterSettings;
@@ -10,6 +11,7 @@ use crate::lib::waiter::waiter_with_timeout;
 use anyhow::{anyhow, bail, Context};
 use fn_error_context::context;
 use ic_agent::AgentError;
+use ic_types::Principal;
 use ic_utils::interfaces::ManagementCanister;
 use slog::info;
 use std::format;
@@ -21,6 +23,15 @@ const CANISTER_CREATE_FEE: u128 = 1_000_000_000_000_u128;
 // For now create the canister with 3T cycle balance.
 const CANISTER_INITIAL_CYCLE_BALANCE: u128 = 3_000_000_000_000_u128;
 
+/// The cycles sent along when a wallet or the cycles ledger creates a canister.
+pub fn creation_cycles(with_cycles: Option<&str>) -> u128 {
+    // amount has been validated by cycle_amount_validator
+    with_cycles.map_or(
+        CANISTER_CREATE_FEE + CANISTER_INITIAL_CYCLE_BALANCE,
+        |amount| amount.parse::<u128>().unwrap(),
+    )
+}
+
 #[context("Failed to create canister '{}'.", canister_name)]
 pub async fn create_canister(
     env: &dyn Environment,
@@ -29,6 +40,7 @@ pub async fn create_canister(
     with_cycles: Option<&str>,
     call_sender: &CallSender,
     settings: CanisterSettings,
+    cycles_ledger: Option<Principal>,
 ) -> DfxResult {
     let log = env.get_logger();
     info!(log, "Creating canister {}...", canister_name);
@@ -73,8 +85,13 @@ pub async fn create_canister(
                 .get_agent()
                 .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
             let mgr = ManagementCanister::create(agent);
-            let cid = match call_sender {
-                CallSender::SelectedId => {
+            let cid = match (cycles_ledger, call_sender) {
+                (Some(ledger), _) => {
+                    let cycles = creation_cycles(with_cycles);
+                    cycles_ledger::create_canister(agent, &ledger, cycles, settings, timeout)
+                        .await?
+                }
+                (None, CallSender::SelectedId) => {
                     // amount has been validated by cycle_amount_validator, which is u128
                     let cycles = with_cycles.and_then(|amount| amount.parse::<u128>().ok());
                     let mut builder = mgr
@@ -94,13 +111,9 @@ pub async fn create_canister(
                         .context("Canister creation call failed.")?
                         .0
                 }
-                CallSender::Wallet(wallet_id) => {
+                (None, CallSender::Wallet(wallet_id)) => {
                     let wallet = Identity::build_wallet_canister(*wallet_id, env).await?;
-                    // amount has been validated by cycle_amount_validator
-                    let cycles = with_cycles.map_or(
-                        CANISTER_CREATE_FEE + CANISTER_INITIAL_CYCLE_BALANCE,
-                        |amount| amount.parse::<u128>().unwrap(),
-                    );
+                    let cycles = creation_cycles(with_cycles);
                     match wallet
                         .wallet_create_canister(
                             cycles,
@@ -1,19 +1,22 @@
 use crate::config::dfinity::Config;
 use crate::lib::builders::BuildConfig;
 use crate::lib::canister_info::CanisterInfo;
+use crate::lib::cycles_ledger::CYCLES_LEDGER_FEE;
 use crate::lib::environment::Environment;
 use crate::lib::error::DfxResult;
 use crate::lib::ic_attributes::CanisterSettings;
 use crate::lib::identity::identity_utils::CallSender;
 use crate::lib::models::canister::CanisterPool;
 use crate::lib::models::canister_id_store::CanisterIdStore;
-use crate::lib::operations::canister::{create_canister, install_canister};
+use crate::lib::operations::canister::{create_canister, creation_cycles, install_canister};
+use crate::lib::subnets::canister_subnet_environment;
 use crate::util::{blob_from_arguments, get_candid_init_type};
 
 use anyhow::{anyhow, bail};
 use fn_error_context::context;
 use humanize_rs::bytes::Bytes;
 use ic_agent::AgentError;
+use ic_types::Principal;
 use ic_utils::interfaces::management_canister::attributes::{
     ComputeAllocation, FreezingThreshold, MemoryAllocation,
 };
@@ -22,6 +25,13 @@ use slog::info;
 use std::convert::TryFrom;
 use std::time::Duration;
 
+// Fees for an ingress message on a 13-node application subnet.
+const INGRESS_MESSAGE_RECEPTION_FEE: u128 = 1_200_000;
+const INGRESS_BYTE_RECEPTION_FEE: u128 = 2_000;
+const UPDATE_MESSAGE_EXECUTION_FEE: u128 = 590_000;
+// The size limit of an ingress message, assumed for modules that are not built yet.
+const MAX_INSTALL_MESSAGE_SIZE: u128 = 2 * 1024 * 1024;
+
 #[allow(clippy::too_many_arguments)]
 #[context("Failed while trying to deploy canisters.")]
 pub async fn deploy_canisters(
@@ -35,6 +45,7 @@ pub async fn deploy_canisters(
     with_cycles: Option<&str>,
     call_sender: &CallSender,
     create_call_sender: &CallSender,
+    cycles_ledger: Option<Principal>,
 ) -> DfxResult {
     let log = env.get_logger();
 
@@ -87,6 +98,7 @@ pub async fn deploy_canisters(
         timeout,
         with_cycles,
         create_call_sender,
+        cycles_ledger,
         &config,
     )
     .await?;
@@ -112,6 +124,72 @@ pub async fn deploy_canisters(
     Ok(())
 }
 
+/// One part of the cycles a deploy is estimated to spend.
+pub struct DeployCostItem {
+    pub description: String,
+    pub cycles: u128,
+}
+
+/// Estimates the cycles `deploy_canisters` spends: the cycles sent to the canisters
+/// it creates, and the fees for the messages that install their modules. The cost of
+/// running the modules' init and upgrade code is not included.
+#[context("Failed to estimate the cycles cost of the deploy.")]
+pub fn estimate_deploy_cost(
+    env: &dyn Environment,
+    some_canister: Option<&str>,
+    with_cycles: Option<&str>,
+    create_call_sender: &CallSender,
+    cycles_ledger: Option<Principal>,
+) -> DfxResult<Vec<DeployCostItem>> {
+    let config = env.get_config_or_anyhow()?;
+    let network = env.get_network_descriptor().unwrap();
+    let canister_id_store = CanisterIdStore::for_env(env)?;
+
+    let mut items = vec![];
+    for canister_name in canister_with_dependencies(&config, some_canister)? {
+        if config
+            .get_config()
+            .is_remote_canister(&canister_name, &network.name)?
+        {
+            continue;
+        }
+        if canister_id_store.find(&canister_name).is_none() {
+            let cycles = match (cycles_ledger, create_call_sender) {
+                (Some(_), _) => creation_cycles(with_cycles) + CYCLES_LEDGER_FEE,
+                (None, CallSender::Wallet(_)) => creation_cycles(with_cycles),
+                // Provisional cycles, which only a local replica hands out.
+                (None, CallSender::SelectedId) => 0,
+            };
+            items.push(DeployCostItem {
+                description: format!("create {}", canister_name),
+                cycles,
+            });
+        }
+        let canister_info = CanisterInfo::load(&config, &canister_name, None)?;
+        let wasm_size = canister_info
+            .get_output_wasm_path()?
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|metadata| u128::from(metadata.len()));
+        let (description, size) = match wasm_size {
+            Some(size) => (format!("install {} ({} bytes)", canister_name, size), size),
+            None => (
+                format!(
+                    "install {} (not built yet, at most {} bytes)",
+                    canister_name, MAX_INSTALL_MESSAGE_SIZE
+                ),
+                MAX_INSTALL_MESSAGE_SIZE,
+            ),
+        };
+        items.push(DeployCostItem {
+            description,
+            cycles: INGRESS_MESSAGE_RECEPTION_FEE
+                + INGRESS_BYTE_RECEPTION_FEE * size
+                + UPDATE_MESSAGE_EXECUTION_FEE,
+        });
+    }
+    Ok(items)
+}
+
 #[context("Failed to collect canisters and their dependencies.")]
 fn canister_with_dependencies(
     config: &Config,
@@ -124,6 +202,7 @@ fn canister_with_dependencies(
     Ok(canister_names)
 }
 
+#[allow(clippy::too_many_arguments)]
 #[context("Failed while trying to register all canisters.")]
 async fn register_canisters(
     env: &dyn Environment,
@@ -132,6 +211,7 @@ async fn register_canisters(
     timeout: Duration,
     with_cycles: Option<&str>,
     call_sender: &CallSender,
+    cycles_ledger: Option<Principal>,
     config: &Config,
 ) -> DfxResult {
     let canisters_to_create = canister_names
@@ -185,6 +265,7 @@ async fn register_canisters(
                     memory_allocation,
                     freezing_threshold,
                 },
+                cycles_ledger,
             )
             .await?;
         }
@@ -220,10 +301,20 @@ async fn install_canisters(
     let agent = env
         .get_agent()
         .ok_or_else(|| anyhow!("Cannot find dfx configuration file in the current working directory. Did you forget to create one?"))?;
-
-    let canister_id_store = CanisterIdStore::for_env(env)?;
+    let selected_id = CallSender::SelectedId;
 
     for canister_name in canister_names {
+        // A canister on a named subnet of the local replica is installed at the replica of
+        // that subnet, by the selected identity, because wallets live on the main subnet.
+        let subnet_env = canister_subnet_environment(env, canister_name, timeout).await?;
+        let (env, agent, call_sender) = match &subnet_env {
+            Some(subnet_env) => (
+                subnet_env as &dyn Environment,
+                subnet_env.get_agent().unwrap(),
+                &selected_id,
+            ),
+            None => (env, agent, call_sender),
+        };
         let (install_mode, installed_module_hash) = if force_reinstall {
             (InstallMode::Reinstall, None)
         } else {
@@ -248,7 +339,7 @@ async fn install_canisters(
             }
         };
 
-        let canister_id = canister_id_store.get(canister_name)?;
+        let canister_id = CanisterIdStore::for_env(env)?.get(canister_name)?;
         let canister_info = CanisterInfo::load(config, canister_name, Some(canister_id))?;
 
         let maybe_path = canister_info.get_output_idl_path();
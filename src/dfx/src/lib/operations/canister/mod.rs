@@ -2,8 +2,8 @@ mod create_canister;
 mod deploy_canisters;
 mod install_canister;
 
-pub use create_canister::create_canister;
-pub use deploy_canisters::deploy_canisters;
+pub use create_canister::{create_canister, creation_cycles};
+pub use deploy_canisters::{deploy_canisters, estimate_deploy_cost, DeployCostItem};
 use fn_error_context::context;
 use ic_utils::Argument;
 pub use install_canister::{install_canister, install_canister_wasm, install_wallet};
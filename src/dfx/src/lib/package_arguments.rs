@@ -1,17 +1,31 @@
 use crate::config::cache::Cache;
+use crate::config::dfinity::ConfigMotokoPackage;
 use crate::lib::error::{BuildError, DfxError, DfxResult};
+use crate::lib::motoko_packages;
 
 use anyhow::{anyhow, bail};
 use fn_error_context::context;
+use std::path::Path;
 use std::process::Command;
 
 /// Package arguments for moc or mo-ide as returned by
 /// a package tool like https://github.com/kritzcreek/vessel
-/// or, if there is no package tool, the base library.
+/// or, if there is no package tool, the base library,
+/// followed by the packages declared in dfx.json.
 pub type PackageArguments = Vec<String>;
 
 #[context("Failed to load package arguments.")]
-pub fn load(cache: &dyn Cache, packtool: &Option<String>) -> DfxResult<PackageArguments> {
+pub fn load(
+    cache: &dyn Cache,
+    packtool: &Option<String>,
+    packages: &[ConfigMotokoPackage],
+    project_root: &Path,
+) -> DfxResult<PackageArguments> {
+    let declared = motoko_packages::resolve(project_root, packages)?;
+    Ok([load_packtool(cache, packtool)?, declared].concat())
+}
+
+fn load_packtool(cache: &dyn Cache, packtool: &Option<String>) -> DfxResult<PackageArguments> {
     if packtool.is_none() {
         let stdlib_path = cache
             .get_binary_command_path("base")?
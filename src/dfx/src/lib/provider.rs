@@ -61,6 +61,7 @@ pub fn get_network_descriptor<'a>(
                 r#type: network_provider.r#type,
                 is_ic: NetworkDescriptor::is_ic(&network_name.to_string(), &provider_urls),
                 providers: provider_urls,
+                subnet: None,
             })
         }
         Some(ConfigNetwork::ConfigLocalProvider(local_provider)) => {
@@ -74,6 +75,7 @@ pub fn get_network_descriptor<'a>(
                 providers: provider_urls,
                 r#type: local_provider.r#type,
                 is_ic: false,
+                subnet: None,
             })
         }
         None => {
@@ -89,6 +91,7 @@ pub fn get_network_descriptor<'a>(
                     providers: vec![url],
                     r#type: NetworkType::Ephemeral,
                     is_ic,
+                    subnet: None,
                 })
             } else {
                 Err(anyhow!("ComputeNetworkNotFound({})", network_name))
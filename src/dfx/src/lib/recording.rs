@@ -0,0 +1,441 @@
+use crate::lib::certificate::{decode_certificate, field, lookup_path, strip_tags};
+use crate::lib::error::DfxResult;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use fn_error_context::context;
+use ic_agent::agent::ReplicaV2Transport;
+use ic_agent::{AgentError, RequestId};
+use ic_types::Principal;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use slog::{warn, Logger};
+use std::convert::TryFrom;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// The kind of request sent to the replica.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExchangeKind {
+    Call,
+    Query,
+    ReadState,
+    Status,
+}
+
+impl ExchangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExchangeKind::Call => "call",
+            ExchangeKind::Query => "query",
+            ExchangeKind::ReadState => "read_state",
+            ExchangeKind::Status => "status",
+        }
+    }
+}
+
+/// A request to the replica and its response, as written by `dfx --record <dir>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedExchange {
+    /// The position of the request among those sent by the same dfx command.
+    pub sequence: usize,
+    pub kind: ExchangeKind,
+    pub effective_canister_id: Option<String>,
+
+    /// The request id of a call, or of the call whose status a read_state asks for.
+    pub request_id: Option<String>,
+
+    pub time: String,
+    pub duration_ms: u64,
+
+    /// The request envelope as sent, hex encoded.
+    pub envelope: String,
+    pub decoded_envelope: serde_json::Value,
+
+    /// The response body, hex encoded. Calls have no response body.
+    pub response: Option<String>,
+    pub decoded_response: Option<serde_json::Value>,
+
+    /// The certificate in the response of a read_state request.
+    pub certificate: Option<serde_json::Value>,
+
+    pub error: Option<String>,
+}
+
+/// A transport that passes requests on to another transport and writes every
+/// request and response to a directory.
+pub struct RecordingReplicaV2Transport<T> {
+    inner: T,
+    dir: PathBuf,
+    logger: Logger,
+    sequence: AtomicUsize,
+    /// Tells the files of this command apart from those of commands recording into the
+    /// same directory at the same time.
+    suffix: String,
+}
+
+impl<T: ReplicaV2Transport + Send + Sync> RecordingReplicaV2Transport<T> {
+    /// Files are named after the time the request was sent, so recordings of later
+    /// commands sort after the ones already in `dir`.
+    pub fn new(inner: T, dir: &Path, logger: Logger) -> Self {
+        let suffix = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(6)
+            .map(char::from)
+            .collect::<String>()
+            .to_lowercase();
+        RecordingReplicaV2Transport {
+            inner,
+            dir: dir.to_path_buf(),
+            logger,
+            sequence: AtomicUsize::new(0),
+            suffix,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record(
+        &self,
+        sequence: usize,
+        kind: ExchangeKind,
+        effective_canister_id: Option<&Principal>,
+        request_id: Option<String>,
+        envelope: &[u8],
+        started: (DateTime<Utc>, Instant),
+        response: Option<&[u8]>,
+        error: Option<String>,
+    ) {
+        let (time, start) = started;
+        let path = self.dir.join(format!(
+            "{}-{}-{:05}-{}.json",
+            time.format("%Y%m%dT%H%M%S%.9fZ"),
+            self.suffix,
+            sequence,
+            kind.as_str()
+        ));
+        let decoded_envelope = serde_cbor::from_slice::<Value>(envelope).ok();
+        let request_id = request_id.or_else(|| {
+            decoded_envelope
+                .as_ref()
+                .and_then(requested_status_id)
+                .map(hex::encode)
+        });
+        let decoded_response = response.and_then(|r| serde_cbor::from_slice::<Value>(r).ok());
+        let certificate = decoded_response
+            .as_ref()
+            .filter(|_| kind == ExchangeKind::ReadState)
+            .and_then(decode_certificate);
+        let exchange = RecordedExchange {
+            sequence,
+            kind,
+            effective_canister_id: effective_canister_id.map(|id| id.to_text()),
+            request_id,
+            time: time.to_rfc3339(),
+            duration_ms: start.elapsed().as_millis() as u64,
+            envelope: hex::encode(envelope),
+            decoded_envelope: decoded_envelope
+                .as_ref()
+                .map(cbor_to_json)
+                .unwrap_or(serde_json::Value::Null),
+            response: response.map(hex::encode),
+            decoded_response: decoded_response.as_ref().map(cbor_to_json),
+            certificate: certificate.as_ref().map(cbor_to_json),
+            error,
+        };
+
+        let written = serde_json::to_string_pretty(&exchange)
+            .context("Failed to serialize recorded request.")
+            .and_then(|content| {
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(content.as_bytes()))
+                    .with_context(|| format!("Failed to write {}.", path.to_string_lossy()))
+            });
+        if let Err(e) = written {
+            warn!(self.logger, "Failed to record request: {:#}", e);
+        }
+    }
+
+    fn start(&self) -> (usize, (DateTime<Utc>, Instant)) {
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+        (sequence, (Utc::now(), Instant::now()))
+    }
+}
+
+fn split_result(result: &Result<Vec<u8>, AgentError>) -> (Option<&[u8]>, Option<String>) {
+    match result {
+        Ok(response) => (Some(response.as_slice()), None),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+impl<T: ReplicaV2Transport + Send + Sync> ReplicaV2Transport for RecordingReplicaV2Transport<T> {
+    fn call<'a>(
+        &'a self,
+        effective_canister_id: Principal,
+        envelope: Vec<u8>,
+        request_id: RequestId,
+    ) -> Pin<Box<dyn Future<Output = Result<(), AgentError>> + Send + 'a>> {
+        Box::pin(async move {
+            let (sequence, started) = self.start();
+            let result = self
+                .inner
+                .call(effective_canister_id.clone(), envelope.clone(), request_id)
+                .await;
+            self.record(
+                sequence,
+                ExchangeKind::Call,
+                Some(&effective_canister_id),
+                Some(String::from(request_id)),
+                &envelope,
+                started,
+                None,
+                result.as_ref().err().map(|e| e.to_string()),
+            );
+            result
+        })
+    }
+
+    fn read_state<'a>(
+        &'a self,
+        effective_canister_id: Principal,
+        envelope: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, AgentError>> + Send + 'a>> {
+        Box::pin(async move {
+            let (sequence, started) = self.start();
+            let result = self
+                .inner
+                .read_state(effective_canister_id.clone(), envelope.clone())
+                .await;
+            let (response, error) = split_result(&result);
+            self.record(
+                sequence,
+                ExchangeKind::ReadState,
+                Some(&effective_canister_id),
+                None,
+                &envelope,
+                started,
+                response,
+                error,
+            );
+            result
+        })
+    }
+
+    fn query<'a>(
+        &'a self,
+        effective_canister_id: Principal,
+        envelope: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, AgentError>> + Send + 'a>> {
+        Box::pin(async move {
+            let (sequence, started) = self.start();
+            let result = self
+                .inner
+                .query(effective_canister_id.clone(), envelope.clone())
+                .await;
+            let (response, error) = split_result(&result);
+            self.record(
+                sequence,
+                ExchangeKind::Query,
+                Some(&effective_canister_id),
+                None,
+                &envelope,
+                started,
+                response,
+                error,
+            );
+            result
+        })
+    }
+
+    fn status<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, AgentError>> + Send + 'a>> {
+        Box::pin(async move {
+            let (sequence, started) = self.start();
+            let result = self.inner.status().await;
+            let (response, error) = split_result(&result);
+            self.record(
+                sequence,
+                ExchangeKind::Status,
+                None,
+                None,
+                &[],
+                started,
+                response,
+                error,
+            );
+            result
+        })
+    }
+}
+
+/// Reads all requests recorded in `dir`, in the order they were sent.
+/// File names start with the time the request was sent, so they sort in that order.
+#[context("Failed to load recording from {}.", dir.to_string_lossy())]
+pub fn load_recording(dir: &Path) -> DfxResult<Vec<RecordedExchange>> {
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}.", dir.to_string_lossy()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut exchanges = vec![];
+    for path in paths {
+        let content = std::fs::read(&path)
+            .with_context(|| format!("Failed to read {}.", path.to_string_lossy()))?;
+        let exchange: RecordedExchange = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}.", path.to_string_lossy()))?;
+        exchanges.push(exchange);
+    }
+    Ok(exchanges)
+}
+
+/// The request id in the paths of a read_state envelope that asks for the status of a call.
+fn requested_status_id(envelope: &Value) -> Option<Vec<u8>> {
+    let paths = match field(envelope, "content").and_then(|c| field(c, "paths"))? {
+        Value::Array(paths) => paths,
+        _ => return None,
+    };
+    paths.iter().find_map(|path| match strip_tags(path) {
+        Value::Array(labels) => match labels.as_slice() {
+            [Value::Bytes(first), Value::Bytes(id), ..]
+                if first.as_slice() == b"request_status" =>
+            {
+                Some(id.clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+fn decode_leb128(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(10)
+        .enumerate()
+        .fold(0, |n, (i, b)| n | (((b & 0x7f) as u64) << (7 * i)))
+}
+
+/// The status of a call as certified in a read_state response, with its reply or reject.
+/// Returns `None` if the certificate does not know the request.
+pub fn call_outcome(certificate: &Value, request_id: &[u8]) -> Option<serde_json::Value> {
+    let tree = field(certificate, "tree")?;
+    let lookup =
+        |name: &str| lookup_path(tree, &[&b"request_status"[..], request_id, name.as_bytes()]);
+    let status = String::from_utf8_lossy(lookup("status")?).to_string();
+    let mut outcome = serde_json::json!({ "status": status });
+    if let Some(reply) = lookup("reply") {
+        outcome["reply"] = serde_json::Value::String(hex::encode(reply));
+    }
+    if let Some(reject_code) = lookup("reject_code") {
+        outcome["reject_code"] = serde_json::json!(decode_leb128(reject_code));
+    }
+    if let Some(reject_message) = lookup("reject_message") {
+        outcome["reject_message"] =
+            serde_json::Value::String(String::from_utf8_lossy(reject_message).to_string());
+    }
+    Some(outcome)
+}
+
+/// Converts CBOR to JSON. Byte strings become hex strings.
+pub fn cbor_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(*b),
+        Value::Integer(i) => {
+            if let Ok(i) = i64::try_from(*i) {
+                serde_json::json!(i)
+            } else if let Ok(u) = u64::try_from(*i) {
+                serde_json::json!(u)
+            } else {
+                serde_json::Value::String(i.to_string())
+            }
+        }
+        Value::Float(f) => serde_json::json!(f),
+        Value::Bytes(bytes) => serde_json::Value::String(hex::encode(bytes)),
+        Value::Text(text) => serde_json::Value::String(text.clone()),
+        Value::Array(items) => serde_json::Value::Array(items.iter().map(cbor_to_json).collect()),
+        Value::Map(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Value::Text(text) => text.clone(),
+                        k => cbor_to_json(k).to_string(),
+                    };
+                    (key, cbor_to_json(v))
+                })
+                .collect(),
+        ),
+        Value::Tag(_, inner) => cbor_to_json(inner),
+        _ => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn labeled(label: &str, subtree: Value) -> Value {
+        Value::Array(vec![
+            Value::Integer(2),
+            Value::Bytes(label.as_bytes().to_vec()),
+            subtree,
+        ])
+    }
+
+    fn leaf(data: &[u8]) -> Value {
+        Value::Array(vec![Value::Integer(3), Value::Bytes(data.to_vec())])
+    }
+
+    #[test]
+    fn reads_call_outcome_from_certificate() {
+        let request = labeled(
+            "id",
+            Value::Array(vec![
+                Value::Integer(1),
+                labeled("reply", leaf(b"DIDL\x00\x00")),
+                labeled("status", leaf(b"replied")),
+            ]),
+        );
+        let tree = Value::Array(vec![
+            Value::Integer(1),
+            labeled("request_status", request),
+            labeled("time", leaf(&[0x80, 0x01])),
+        ]);
+        let mut certificate = BTreeMap::new();
+        certificate.insert(Value::Text("tree".to_string()), tree);
+        let certificate = Value::Map(certificate);
+
+        assert_eq!(
+            call_outcome(&certificate, b"id"),
+            Some(serde_json::json!({ "status": "replied", "reply": "4449444c0000" }))
+        );
+        assert_eq!(call_outcome(&certificate, b"other"), None);
+    }
+
+    #[test]
+    fn converts_cbor_to_json() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Text("arg".to_string()), Value::Bytes(vec![0xab]));
+        map.insert(Value::Text("nonce".to_string()), Value::Integer(7));
+        assert_eq!(
+            cbor_to_json(&Value::Tag(55799, Box::new(Value::Map(map)))),
+            serde_json::json!({ "arg": "ab", "nonce": 7 })
+        );
+        assert_eq!(decode_leb128(&[0xe5, 0x8e, 0x26]), 624485);
+    }
+}
@@ -0,0 +1,89 @@
+//! Prepares a freshly started local replica for development: creates the cycles
+//! wallets of the identities in `defaults.replica.bootstrap_identities` and
+//! installs the Candid UI canister, so that the first command that needs them
+//! does not have to.
+use crate::lib::environment::{AgentEnvironment, Environment, EnvironmentImpl};
+use crate::lib::error::DfxResult;
+use crate::lib::identity::Identity;
+use crate::lib::named_canister;
+use crate::lib::provider::get_network_descriptor;
+use crate::lib::root_key::fetch_root_key_if_needed;
+use crate::lib::waiter::waiter_with_timeout;
+use crate::util::expiry_duration;
+
+use anyhow::{anyhow, Context};
+use fn_error_context::context;
+use ic_utils::interfaces::ManagementCanister;
+use slog::{info, warn, Logger};
+use tokio::runtime::Runtime;
+
+/// Cycles each bootstrapped wallet is created with.
+pub const BOOTSTRAP_WALLET_CYCLES: u128 = 100_000_000_000_000;
+
+/// Creates a wallet for each of the identities that does not have one on the local
+/// network yet, then installs the Candid UI canister if it is missing.
+/// The replica is reached at `frontend_url`, which is where `dfx start` is serving,
+/// even if that differs from the bind address in dfx.json.
+/// An identity that cannot be bootstrapped is reported and skipped.
+#[context("Failed to bootstrap the local replica.")]
+pub fn bootstrap_replica(logger: Logger, frontend_url: &str, identities: &[String]) -> DfxResult {
+    let runtime = Runtime::new().expect("Unable to create a runtime");
+    for identity in identities {
+        let env = EnvironmentImpl::new()?
+            .with_logger(logger.clone())
+            .with_identity_override(Some(identity.clone()));
+        let result = agent_environment(&env, frontend_url)
+            .and_then(|agent_env| runtime.block_on(bootstrap_wallet(&agent_env, identity)));
+        if let Err(err) = result {
+            warn!(logger, "{:#}", err);
+        }
+    }
+
+    let env = EnvironmentImpl::new()?.with_logger(logger);
+    let agent_env = agent_environment(&env, frontend_url)?;
+    let network = agent_env
+        .get_network_descriptor()
+        .ok_or_else(|| anyhow!("Cannot get network descriptor from environment."))?;
+    if named_canister::get_ui_canister_id(network).is_none() {
+        runtime.block_on(named_canister::install_ui_canister(
+            &agent_env, network, None,
+        ))?;
+    }
+    Ok(())
+}
+
+fn agent_environment<'a>(
+    env: &'a EnvironmentImpl,
+    frontend_url: &str,
+) -> DfxResult<AgentEnvironment<'a>> {
+    let mut network = get_network_descriptor(env, None)?;
+    network.providers = vec![frontend_url.to_string()];
+    AgentEnvironment::new(env, network, expiry_duration())
+}
+
+#[context("Failed to create a wallet for identity '{}'.", identity)]
+async fn bootstrap_wallet(env: &dyn Environment, identity: &str) -> DfxResult {
+    let network = env
+        .get_network_descriptor()
+        .ok_or_else(|| anyhow!("Cannot get network descriptor from environment."))?;
+    if let Ok(wallet) = Identity::wallet_canister_id(env, network, identity) {
+        info!(
+            env.get_logger(),
+            "Identity '{}' already has the wallet {}.", identity, wallet
+        );
+        return Ok(());
+    }
+
+    fetch_root_key_if_needed(env).await?;
+    let agent = env
+        .get_agent()
+        .ok_or_else(|| anyhow!("Cannot get HTTP client from environment."))?;
+    let (canister_id,) = ManagementCanister::create(agent)
+        .create_canister()
+        .as_provisional_create_with_amount(Some(BOOTSTRAP_WALLET_CYCLES))
+        .call_and_wait(waiter_with_timeout(expiry_duration()))
+        .await
+        .context("Create canister call failed.")?;
+    Identity::create_wallet(env, network, identity, Some(canister_id)).await?;
+    Ok(())
+}
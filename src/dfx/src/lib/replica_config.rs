@@ -2,7 +2,41 @@ use serde::{Deserialize, Serialize};
 use std::default::Default;
 use std::path::{Path, PathBuf};
 
-use crate::config::dfinity::ReplicaSubnetType;
+use crate::config::dfinity::{ConfigReplicaSettings, ReplicaSubnetType};
+use crate::lib::error::DfxResult;
+
+use anyhow::bail;
+
+/// ic-starter flags that dfx sets itself, and which therefore cannot be passed through.
+const MANAGED_IC_STARTER_FLAGS: &[&str] = &[
+    "--replica-path",
+    "--state-dir",
+    "--http-port",
+    "--http-port-file",
+    "--subnet-type",
+    "--subnet-features",
+    "--bitcoin-testnet-uds-path",
+    "--canister-http-uds-path",
+    "--create-funds-whitelist",
+];
+
+/// ic-starter flags that can be passed through from dfx.json or `dfx start --replica-arg`,
+/// and whether they take a value.
+const KNOWN_IC_STARTER_FLAGS: &[(&str, bool)] = &[
+    ("--log-level", true),
+    ("--debug-overrides", true),
+    ("--metrics-port", true),
+    ("--metrics-addr", true),
+    ("--provisional-whitelist", true),
+    ("--max-ingress-bytes-per-message", true),
+    ("--max-ingress-messages-per-block", true),
+    ("--max-block-payload-size", true),
+    ("--consensus-pool-backend", true),
+    ("--initial-notary-delay-millis", true),
+    ("--unit-delay-millis", true),
+    ("--dkg-interval-length", true),
+    ("--detect-consensus-starvation", false),
+];
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct HttpHandlerConfig {
@@ -52,6 +86,10 @@ pub struct ReplicaConfig {
     pub subnet_type: ReplicaSubnetType,
     pub btc_adapter: BtcAdapterConfig,
     pub canister_http_adapter: CanisterHttpAdapterConfig,
+    /// Additional ic-starter arguments, from dfx.json and the command line.
+    pub ic_starter_args: Vec<String>,
+    /// The named subnet from defaults.replica.subnets this replica runs, if not the main one.
+    pub subnet_name: Option<String>,
 }
 
 impl ReplicaConfig {
@@ -79,9 +117,32 @@ impl ReplicaConfig {
                 enabled: false,
                 socket_path: None,
             },
+            ic_starter_args: vec![],
+            subnet_name: None,
+        }
+    }
+
+    pub fn with_ic_starter_args(self, ic_starter_args: Vec<String>) -> Self {
+        ReplicaConfig {
+            ic_starter_args,
+            ..self
+        }
+    }
+
+    pub fn with_subnet_name(self, subnet_name: &str) -> Self {
+        ReplicaConfig {
+            subnet_name: Some(subnet_name.to_string()),
+            ..self
         }
     }
 
+    /// Whether the additional ic-starter arguments set the given flag.
+    pub fn has_ic_starter_flag(&self, flag: &str) -> bool {
+        self.ic_starter_args
+            .iter()
+            .any(|arg| flag_name(arg) == Some(flag))
+    }
+
     #[allow(dead_code)]
     pub fn with_port(self, port: u16) -> Self {
         ReplicaConfig {
@@ -172,3 +233,264 @@ impl HttpHandlerConfig {
         }
     }
 }
+
+fn flag_name(arg: &str) -> Option<&str> {
+    if arg.starts_with("--") {
+        Some(arg.split('=').next().unwrap_or(arg))
+    } else {
+        None
+    }
+}
+
+/// Checks that the arguments are a sequence of flags of ic-starter that dfx knows about and
+/// doesn't set itself, each as `--flag`, `--flag=value` or `--flag value` as the flag requires.
+pub fn validate_ic_starter_args(args: &[String]) -> DfxResult {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = match flag_name(arg) {
+            Some(flag) => flag,
+            None => bail!(
+                "Unexpected replica argument '{}'. Only flags starting with '--' can be passed to the replica.",
+                arg
+            ),
+        };
+        if MANAGED_IC_STARTER_FLAGS.contains(&flag) {
+            bail!(
+                "The replica flag '{}' is set by dfx and cannot be overridden.",
+                flag
+            );
+        }
+        let takes_value = match KNOWN_IC_STARTER_FLAGS
+            .iter()
+            .find(|(known, _)| *known == flag)
+        {
+            Some((_, takes_value)) => *takes_value,
+            None => bail!(
+                "Unknown replica flag '{}'. Known flags are: {}.",
+                flag,
+                KNOWN_IC_STARTER_FLAGS
+                    .iter()
+                    .map(|(known, _)| *known)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let inline_value = arg.len() > flag.len();
+        match (takes_value, inline_value) {
+            (true, true) | (false, false) => {}
+            (true, false) => {
+                if args
+                    .next()
+                    .filter(|value| flag_name(value).is_none())
+                    .is_none()
+                {
+                    bail!("The replica flag '{}' requires a value.", flag);
+                }
+            }
+            (false, true) => bail!("The replica flag '{}' does not take a value.", flag),
+        }
+    }
+    Ok(())
+}
+
+/// Splits arguments into groups of a flag and the values that follow it.
+fn group_by_flag(args: &[String]) -> Vec<&[String]> {
+    let mut groups = vec![];
+    let mut start = 0;
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        if flag_name(arg).is_some() {
+            groups.push(&args[start..i]);
+            start = i;
+        }
+    }
+    if start < args.len() {
+        groups.push(&args[start..]);
+    }
+    groups
+}
+
+/// The arguments without the given flag and its value, for replicas that cannot share
+/// the value with the main replica, such as a port.
+pub fn without_ic_starter_flag(args: &[String], flag: &str) -> Vec<String> {
+    group_by_flag(args)
+        .into_iter()
+        .filter(|group| flag_name(&group[0]) != Some(flag))
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// The ic-starter arguments for the replica settings in dfx.json, followed by those
+/// given on the command line. A flag given on the command line replaces the same flag
+/// from dfx.json.
+pub fn get_ic_starter_args(
+    settings: Option<&ConfigReplicaSettings>,
+    command_line_args: &[String],
+) -> DfxResult<Vec<String>> {
+    let mut args = vec![];
+    if let Some(settings) = settings {
+        let mut push = |flag: &str, value: Option<String>| {
+            if let Some(value) = value {
+                args.push(flag.to_string());
+                args.push(value);
+            }
+        };
+        push(
+            "--log-level",
+            settings.log_level.map(|l| l.as_ic_starter_string()),
+        );
+        push(
+            "--metrics-port",
+            settings.metrics_port.map(|v| v.to_string()),
+        );
+        push(
+            "--max-ingress-bytes-per-message",
+            settings
+                .max_ingress_bytes_per_message
+                .map(|v| v.to_string()),
+        );
+        push(
+            "--max-ingress-messages-per-block",
+            settings
+                .max_ingress_messages_per_block
+                .map(|v| v.to_string()),
+        );
+        push(
+            "--max-block-payload-size",
+            settings.max_block_payload_size.map(|v| v.to_string()),
+        );
+        push(
+            "--consensus-pool-backend",
+            settings
+                .consensus_pool_backend
+                .map(|b| b.as_ic_starter_string()),
+        );
+        push(
+            "--initial-notary-delay-millis",
+            settings.initial_notary_delay_millis.map(|v| v.to_string()),
+        );
+        push(
+            "--unit-delay-millis",
+            settings.unit_delay_millis.map(|v| v.to_string()),
+        );
+        push(
+            "--dkg-interval-length",
+            settings.dkg_interval_length.map(|v| v.to_string()),
+        );
+
+        if let Some(extra_args) = &settings.extra_args {
+            validate_ic_starter_args(extra_args)?;
+            args.extend(extra_args.iter().cloned());
+        }
+    }
+    validate_ic_starter_args(command_line_args)?;
+    let overridden: Vec<&str> = command_line_args
+        .iter()
+        .filter_map(|arg| flag_name(arg))
+        .collect();
+    let mut args: Vec<String> = group_by_flag(&args)
+        .into_iter()
+        .filter(|group| match flag_name(&group[0]) {
+            Some(flag) => !overridden.contains(&flag),
+            None => true,
+        })
+        .flatten()
+        .cloned()
+        .collect();
+    args.extend(command_line_args.iter().cloned());
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::dfinity::ReplicaLogLevel;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn validates_flags() {
+        assert!(validate_ic_starter_args(&args(&["--log-level", "debug"])).is_ok());
+        assert!(validate_ic_starter_args(&args(&["--metrics-port=9090"])).is_ok());
+        assert!(validate_ic_starter_args(&args(&["--state-dir", "/tmp"])).is_err());
+        assert!(validate_ic_starter_args(&args(&["--log-levle", "debug"])).is_err());
+        assert!(validate_ic_starter_args(&args(&["--detect-consensus-starvation"])).is_ok());
+    }
+
+    #[test]
+    fn rejects_arguments_outside_the_allow_list() {
+        assert!(validate_ic_starter_args(&args(&["debug"])).is_err());
+        assert!(validate_ic_starter_args(&args(&["-s", "/tmp"])).is_err());
+        assert!(validate_ic_starter_args(&args(&["--log-level"])).is_err());
+        assert!(validate_ic_starter_args(&args(&["--log-level", "--metrics-port=9090"])).is_err());
+        assert!(
+            validate_ic_starter_args(&args(&["--log-level", "debug", "--state-dir=/tmp"])).is_err()
+        );
+        assert!(validate_ic_starter_args(&args(&["--metrics-port", "9090", "/tmp"])).is_err());
+        assert!(validate_ic_starter_args(&args(&["--detect-consensus-starvation=yes"])).is_err());
+    }
+
+    #[test]
+    fn settings_come_before_command_line_args() {
+        let settings = ConfigReplicaSettings {
+            log_level: Some(ReplicaLogLevel::Warning),
+            extra_args: Some(args(&["--detect-consensus-starvation"])),
+            ..Default::default()
+        };
+        let result = get_ic_starter_args(Some(&settings), &args(&["--metrics-port=9090"])).unwrap();
+        assert_eq!(
+            result,
+            args(&[
+                "--log-level",
+                "warning",
+                "--detect-consensus-starvation",
+                "--metrics-port=9090"
+            ])
+        );
+
+        let config = ReplicaConfig::default().with_ic_starter_args(result);
+        assert!(config.has_ic_starter_flag("--metrics-port"));
+        assert!(!config.has_ic_starter_flag("--initial-notary-delay-millis"));
+    }
+
+    #[test]
+    fn command_line_args_replace_settings() {
+        let settings = ConfigReplicaSettings {
+            log_level: Some(ReplicaLogLevel::Warning),
+            unit_delay_millis: Some(1000),
+            extra_args: Some(args(&["--metrics-port", "8080"])),
+            ..Default::default()
+        };
+        let result = get_ic_starter_args(
+            Some(&settings),
+            &args(&["--log-level", "debug", "--metrics-port=9090"]),
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            args(&[
+                "--unit-delay-millis",
+                "1000",
+                "--log-level",
+                "debug",
+                "--metrics-port=9090"
+            ])
+        );
+    }
+
+    #[test]
+    fn removes_a_flag_with_its_value() {
+        let starter_args = args(&[
+            "--metrics-port",
+            "9090",
+            "--log-level=debug",
+            "--metrics-port=9091",
+        ]);
+        assert_eq!(
+            without_ic_starter_flag(&starter_args, "--metrics-port"),
+            args(&["--log-level=debug"])
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Named subnets of the local replica.
+//!
+//! `dfx start` runs a replica for each subnet in defaults.replica.subnets, next to the
+//! main replica. Each of them has its own state, port and root key, so canisters created
+//! on a subnet with `dfx canister create --subnet <name>` are reached directly at the
+//! replica of that subnet, rather than through the webserver of `dfx start`.
+//!
+//! These are not subnets of one Internet Computer: the replicas do not route messages to
+//! each other, so canisters on different subnets cannot call each other, and the same
+//! canister id can be given out by more than one of them. That is why the canister id
+//! store records the subnet of each canister, and why messages to a canister go to the
+//! replica of its subnet.
+use crate::lib::environment::{AgentEnvironment, Environment};
+use crate::lib::error::DfxResult;
+use crate::lib::models::canister_id_store::CanisterIdStore;
+use crate::lib::network::network_descriptor::NetworkDescriptor;
+use crate::lib::root_key::fetch_root_key_if_needed;
+
+use anyhow::{anyhow, bail};
+use fn_error_context::context;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where the replica of the subnet keeps its port and pid files.
+pub fn configuration_dir(temp_dir: &Path, subnet: &str) -> PathBuf {
+    temp_dir
+        .join("replica-configuration")
+        .join("subnets")
+        .join(subnet)
+}
+
+/// The file the replica of the subnet writes its port to.
+pub fn port_path(temp_dir: &Path, subnet: &str) -> PathBuf {
+    configuration_dir(temp_dir, subnet).join("replica-1.port")
+}
+
+/// Where the replica of the subnet keeps its state.
+pub fn state_root(state_root: &Path, subnet: &str) -> PathBuf {
+    state_root.join("subnets").join(subnet)
+}
+
+/// The subnet the canister was created on, if it was created on a named subnet.
+#[context("Failed to look up the subnet of canister '{}'.", canister_name)]
+pub fn get_canister_subnet(
+    network_descriptor: &NetworkDescriptor,
+    canister_name: &str,
+) -> DfxResult<Option<String>> {
+    Ok(CanisterIdStore::for_network(network_descriptor)?
+        .get_subnet(canister_name)
+        .cloned())
+}
+
+/// A network descriptor that points at the replica of the named subnet of the local network.
+#[context("Failed to connect to subnet '{}'.", subnet)]
+pub fn subnet_network_descriptor(
+    env: &dyn Environment,
+    network_descriptor: &NetworkDescriptor,
+    subnet: &str,
+) -> DfxResult<NetworkDescriptor> {
+    if network_descriptor.is_ic || network_descriptor.name != "local" {
+        bail!(
+            "Subnets can only be selected on the local replica, not on network '{}'.",
+            network_descriptor.name
+        );
+    }
+    let config = env.get_config_or_anyhow()?;
+    let subnets = config
+        .get_config()
+        .get_defaults()
+        .get_replica()
+        .get_subnets()?;
+    if !subnets.contains_key(subnet) {
+        bail!(
+            "Subnet '{}' is not configured in defaults.replica.subnets. Configured subnets: {}.",
+            subnet,
+            if subnets.is_empty() {
+                "none".to_string()
+            } else {
+                subnets.keys().cloned().collect::<Vec<_>>().join(", ")
+            }
+        );
+    }
+    let port_path = port_path(env.get_temp_dir(), subnet);
+    let port = std::fs::read_to_string(&port_path)
+        .ok()
+        .and_then(|port| port.trim().parse::<u16>().ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "The replica of subnet '{}' is not running. Run 'dfx start' to start it.",
+                subnet
+            )
+        })?;
+    Ok(NetworkDescriptor {
+        providers: vec![format!("http://localhost:{}", port)],
+        subnet: Some(subnet.to_string()),
+        ..network_descriptor.clone()
+    })
+}
+
+/// An environment that reaches the canister at the replica of the named subnet it was
+/// created on, with the root key of that replica fetched, or None if `env` already
+/// reaches the replica the canister is on.
+#[context("Failed to connect to the subnet of canister '{}'.", canister_name)]
+pub async fn canister_subnet_environment<'a>(
+    env: &'a dyn Environment,
+    canister_name: &str,
+    timeout: Duration,
+) -> DfxResult<Option<AgentEnvironment<'a>>> {
+    let network_descriptor = env
+        .get_network_descriptor()
+        .ok_or_else(|| anyhow!("Cannot get network descriptor from environment."))?;
+    match get_canister_subnet(network_descriptor, canister_name)? {
+        Some(subnet) if network_descriptor.subnet.as_ref() != Some(&subnet) => {
+            let subnet_descriptor = subnet_network_descriptor(env, network_descriptor, &subnet)?;
+            let subnet_env = AgentEnvironment::new(env, subnet_descriptor, timeout)?;
+            fetch_root_key_if_needed(&subnet_env).await?;
+            Ok(Some(subnet_env))
+        }
+        _ => Ok(None),
+    }
+}
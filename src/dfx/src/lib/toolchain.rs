@@ -1,8 +1,9 @@
-use crate::config::cache;
+use crate::config::{cache, dfx_version};
 use crate::lib::dist;
 use crate::lib::error::{DfxError, DfxResult};
+use crate::lib::manifest::Manifest;
 
-use anyhow::{bail, Context};
+use anyhow::{anyhow, bail, Context};
 use fn_error_context::context;
 use semver::{Version, VersionReq};
 use std::fmt;
@@ -58,40 +59,29 @@ impl fmt::Display for Toolchain {
 
 impl Toolchain {
     // Update the toolchain, install it if nonexisting
+    // The release manifest and the download are verified unless `insecure` is set.
+    pub fn update(&self, insecure: bool) -> DfxResult<()> {
+        let manifest = dist::get_manifest(insecure)?;
+        self.update_with_manifest(&manifest, insecure)
+    }
+
+    /// Updates the toolchain like `update`, with a release manifest fetched already.
     #[context("Failed to update toolchain.")]
-    pub fn update(&self) -> DfxResult<()> {
+    fn update_with_manifest(&self, manifest: &Manifest, insecure: bool) -> DfxResult<()> {
         eprintln!("Syncing toolchain: {}", self);
 
-        let toolchain_path = self.get_path()?;
-
-        let mut installed_version: Option<Version> = None;
-        if let Ok(meta) = std::fs::symlink_metadata(&toolchain_path) {
-            match meta.file_type().is_symlink() {
-                true => {
-                    let src = std::fs::read_link(&toolchain_path).with_context(|| {
-                        format!(
-                            "Failed to read symlink {}.",
-                            toolchain_path.to_string_lossy()
-                        )
-                    })?;
-                    let src_name = src.file_name().unwrap().to_str().unwrap();
-                    installed_version = Some(Version::parse(src_name).unwrap());
-                    eprintln!(
-                        "Toolchain {0} has been installed with SDK version {1}",
-                        self, src_name
-                    );
-                }
-                false => bail!(
-                    "{} should be a symlink to a SDK version",
-                    toolchain_path.to_string_lossy()
-                ),
-            }
+        let installed_version = self.installed_version()?;
+        if let Some(v) = &installed_version {
+            eprintln!(
+                "Toolchain {0} has been installed with SDK version {1}",
+                self, v
+            );
         }
 
         let resolved_version: Version = match self {
-            Toolchain::CompleteVersion(v) => is_version_available(v)?,
-            Toolchain::MajorMinor(major, minor) => get_compatible_version(major, minor)?,
-            Toolchain::Tag(t) => get_tag_version(t)?,
+            Toolchain::CompleteVersion(v) => is_version_available(manifest, v)?,
+            Toolchain::MajorMinor(major, minor) => get_compatible_version(manifest, major, minor)?,
+            Toolchain::Tag(t) => get_tag_version(manifest, t)?,
         };
         eprintln!("The latest compatible SDK version is {}", resolved_version);
 
@@ -104,22 +94,14 @@ impl Toolchain {
         if status != "unchanged" {
             match cache::is_version_installed(&resolved_version.to_string())? {
                 true => eprintln!("SDK version {} already installed", resolved_version),
-                false => dist::install_version(&resolved_version)?,
+                false => dist::install_version(
+                    dist::DEFAULT_RELEASE_ROOT,
+                    &resolved_version,
+                    if insecure { None } else { Some(manifest) },
+                )?,
             };
 
-            let cache_path = cache::get_bin_cache(&resolved_version.to_string())?;
-            if toolchain_path.exists() {
-                std::fs::remove_file(&toolchain_path).with_context(|| {
-                    format!("Failed to remove {}.", toolchain_path.to_string_lossy())
-                })?;
-            }
-            std::os::unix::fs::symlink(&cache_path, &toolchain_path).with_context(|| {
-                format!(
-                    "Failed to create symlink from {} to {}.",
-                    toolchain_path.to_string_lossy(),
-                    cache_path.to_string_lossy()
-                )
-            })?;
+            self.link(&resolved_version)?;
         }
 
         eprintln!(
@@ -130,6 +112,62 @@ impl Toolchain {
         Ok(())
     }
 
+    /// The SDK version the toolchain points to, if it is installed.
+    #[context("Failed to get the SDK version of toolchain {}.", self)]
+    pub fn installed_version(&self) -> DfxResult<Option<Version>> {
+        let toolchain_path = self.get_path()?;
+        match std::fs::symlink_metadata(&toolchain_path) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let src = std::fs::read_link(&toolchain_path).with_context(|| {
+                    format!(
+                        "Failed to read symlink {}.",
+                        toolchain_path.to_string_lossy()
+                    )
+                })?;
+                let src_name = src.file_name().unwrap().to_str().unwrap();
+                Ok(Some(Version::parse(src_name).with_context(|| {
+                    format!("Failed to parse version from {}.", src_name)
+                })?))
+            }
+            Ok(_) => bail!(
+                "{} should be a symlink to a SDK version",
+                toolchain_path.to_string_lossy()
+            ),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Points the toolchain to an SDK version that is installed in the cache.
+    #[context("Failed to link toolchain {} to SDK version {}.", self, version)]
+    pub fn link(&self, version: &Version) -> DfxResult<()> {
+        let toolchain_path = self.get_path()?;
+        let cache_path = cache::get_bin_cache(&version.to_string())?;
+        if std::fs::symlink_metadata(&toolchain_path).is_ok() {
+            std::fs::remove_file(&toolchain_path).with_context(|| {
+                format!("Failed to remove {}.", toolchain_path.to_string_lossy())
+            })?;
+        }
+        std::os::unix::fs::symlink(&cache_path, &toolchain_path).with_context(|| {
+            format!(
+                "Failed to create symlink from {} to {}.",
+                toolchain_path.to_string_lossy(),
+                cache_path.to_string_lossy()
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Whether the toolchain can point to the given version, without looking up the manifest.
+    pub fn accepts(&self, version: &Version) -> bool {
+        match self {
+            Toolchain::CompleteVersion(v) => v == version,
+            Toolchain::MajorMinor(major, minor) => {
+                version.major == *major as u64 && version.minor == *minor as u64
+            }
+            Toolchain::Tag(_) => false,
+        }
+    }
+
     #[context("Failed to uninistall toolchain {}.", self)]
     pub fn uninstall(&self) -> DfxResult<()> {
         eprintln!("Uninstalling toolchain: {}", self);
@@ -160,8 +198,8 @@ impl Toolchain {
     }
 
     #[context("Failed to set '{}' as default toolchain.", self)]
-    pub fn set_default(&self) -> DfxResult<()> {
-        self.update()?;
+    pub fn set_default(&self, insecure: bool) -> DfxResult<()> {
+        self.update(insecure)?;
         let default_path = get_default_path()?;
         let toolchain_path = self.get_path()?;
         if default_path.exists() {
@@ -184,12 +222,162 @@ impl Toolchain {
     }
 }
 
+/// Where the version of dfx to run in the current directory comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSource {
+    /// The DFX_VERSION environment variable.
+    Environment,
+    /// The `dfx` field of dfx.json.
+    Project,
+    /// Neither is set, so the running dfx is used.
+    Running,
+}
+
+/// The version of dfx to run in the current directory, and why.
+#[derive(Debug, Clone)]
+pub struct VersionSelection {
+    /// None if dfx.json asks for a range of versions and no installed version matches it.
+    /// The range is resolved against the release manifest when a version is installed.
+    pub version: Option<Version>,
+    pub source: VersionSource,
+    /// The `dfx` field of dfx.json, if any, even if DFX_VERSION overrides it.
+    pub project_requirement: Option<String>,
+    pub reason: String,
+}
+
+/// Parses the `dfx` field of dfx.json: either a version such as `0.10.0`, which must match
+/// exactly, or a semver range such as `^0.10`.
+#[context("Failed to parse dfx version requirement '{}'.", requirement)]
+pub fn parse_version_requirement(requirement: &str) -> DfxResult<VersionReq> {
+    let requirement = requirement.trim();
+    if Version::parse(requirement).is_ok() {
+        VersionReq::parse(&format!("={}", requirement)).map_err(DfxError::from)
+    } else {
+        VersionReq::parse(requirement).map_err(DfxError::from)
+    }
+}
+
+/// Figures out which version of dfx to run, using the following fallback sequence:
+///   1. DFX_VERSION environment variable
+///   2. dfx.json "dfx" field, a version or a semver range
+///   3. this binary's version
+/// If DFX_VERSION is an empty string, we stop the fallback and use the current version.
+/// For a range, the running dfx is preferred, then the highest installed version that
+/// matches. This never goes to the network: if no installed version matches the range,
+/// no version is selected, and `install_matching_version` resolves the range once the user
+/// agrees to install a version.
+#[context("Failed to select the version of dfx to run.")]
+pub fn select_version(project_requirement: Option<&str>) -> DfxResult<VersionSelection> {
+    let running = dfx_version();
+    let selection = |version: &Version, source: VersionSource, reason: String| VersionSelection {
+        version: Some(version.clone()),
+        source,
+        project_requirement: project_requirement.map(|r| r.to_string()),
+        reason,
+    };
+
+    if let Ok(v) = std::env::var("DFX_VERSION") {
+        return if v.is_empty() {
+            Ok(selection(
+                running,
+                VersionSource::Environment,
+                "DFX_VERSION is empty, which selects the running dfx".to_string(),
+            ))
+        } else {
+            let version = Version::parse(&v)
+                .with_context(|| format!("Failed to parse version from '{}'.", &v))?;
+            Ok(selection(
+                &version,
+                VersionSource::Environment,
+                format!("DFX_VERSION is set to {}", v),
+            ))
+        };
+    }
+
+    let requirement = match project_requirement {
+        None => {
+            return Ok(selection(
+                running,
+                VersionSource::Running,
+                "neither DFX_VERSION nor the dfx field of dfx.json is set".to_string(),
+            ))
+        }
+        Some(requirement) => requirement,
+    };
+    if let Ok(version) = Version::parse(requirement.trim()) {
+        return Ok(selection(
+            &version,
+            VersionSource::Project,
+            format!("dfx.json pins dfx to {}", requirement),
+        ));
+    }
+    let req = parse_version_requirement(requirement)?;
+    if req.matches(running) {
+        return Ok(selection(
+            running,
+            VersionSource::Project,
+            format!(
+                "dfx.json asks for dfx {} and the running dfx matches",
+                requirement
+            ),
+        ));
+    }
+    if let Some(version) = cache::list_versions()?
+        .into_iter()
+        .filter(|v| req.matches(v))
+        .max()
+    {
+        return Ok(selection(
+            &version,
+            VersionSource::Project,
+            format!(
+                "dfx.json asks for dfx {} and {} is the highest installed version that matches",
+                requirement, version
+            ),
+        ));
+    }
+    Ok(VersionSelection {
+        version: None,
+        source: VersionSource::Project,
+        project_requirement: Some(requirement.to_string()),
+        reason: format!(
+            "dfx.json asks for dfx {} and no installed version matches",
+            requirement
+        ),
+    })
+}
+
+/// Installs the highest version available for download that matches the `dfx` field of
+/// dfx.json, through its toolchain, and returns it. The release manifest and the download
+/// are verified unless `insecure` is set.
+#[context("Failed to install a version of dfx that matches '{}'.", requirement)]
+pub fn install_matching_version(requirement: &str, insecure: bool) -> DfxResult<Version> {
+    let req = parse_version_requirement(requirement)?;
+    let manifest = dist::get_manifest(insecure)?;
+    let version = manifest
+        .get_versions()
+        .into_iter()
+        .filter(|v| req.matches(v))
+        .max()
+        .ok_or_else(|| {
+            anyhow!(
+                "dfx.json asks for dfx {}, but no version that matches is available.",
+                requirement
+            )
+        })?;
+    Toolchain::CompleteVersion(version.clone()).update_with_manifest(&manifest, insecure)?;
+    Ok(version)
+}
+
 #[context("Failed to get installed toolchains.")]
 pub fn list_installed_toolchains() -> DfxResult<Vec<Toolchain>> {
     let home = std::env::var("HOME").context("Failed to resolve env var 'HOME'.")?;
     let home = Path::new(&home);
     let toolchains_dir = home.join(TOOLCHAINS_ROOT);
     let mut toolchains = vec![];
+    if !toolchains_dir.exists() {
+        return Ok(toolchains);
+    }
     for entry in std::fs::read_dir(&toolchains_dir).with_context(|| {
         format!(
             "Failed to read toolchain dir {}.",
@@ -240,8 +428,7 @@ fn get_default_path() -> DfxResult<PathBuf> {
 }
 
 #[context("Failed to determine if version {} is available.", v)]
-fn is_version_available(v: &Version) -> DfxResult<Version> {
-    let manifest = dist::get_manifest()?;
+fn is_version_available(manifest: &Manifest, v: &Version) -> DfxResult<Version> {
     let versions = manifest.get_versions();
     match versions.contains(v) {
         true => Ok(v.clone()),
@@ -254,8 +441,7 @@ fn is_version_available(v: &Version) -> DfxResult<Version> {
     major,
     minor
 )]
-fn get_compatible_version(major: &u8, minor: &u8) -> DfxResult<Version> {
-    let manifest = dist::get_manifest()?;
+fn get_compatible_version(manifest: &Manifest, major: &u8, minor: &u8) -> DfxResult<Version> {
     let versions = manifest.get_versions();
     let req = VersionReq::parse(&format!("{}.{}", major, minor)).unwrap();
     let compatible_version = versions.iter().filter(|v| req.matches(v)).max();
@@ -270,8 +456,7 @@ fn get_compatible_version(major: &u8, minor: &u8) -> DfxResult<Version> {
 }
 
 #[context("Failed to get tag version '{}'.", tag)]
-fn get_tag_version(tag: &str) -> DfxResult<Version> {
-    let manifest = dist::get_manifest()?;
+fn get_tag_version(manifest: &Manifest, tag: &str) -> DfxResult<Version> {
     let tag_version = manifest.get_tag_version(tag);
     match tag_version {
         Some(v) => Ok(v.clone()),
@@ -305,4 +490,17 @@ mod tests {
         assert!(Toolchain::from_str("0.06").is_err());
         assert!(Toolchain::from_str("unknown").is_err());
     }
+
+    #[test]
+    fn test_version_requirement() {
+        let exact = parse_version_requirement("0.10.0").unwrap();
+        assert!(exact.matches(&Version::new(0, 10, 0)));
+        assert!(!exact.matches(&Version::new(0, 10, 1)));
+
+        let range = parse_version_requirement("^0.10").unwrap();
+        assert!(range.matches(&Version::new(0, 10, 1)));
+        assert!(!range.matches(&Version::new(0, 11, 0)));
+
+        assert!(parse_version_requirement("latest").is_err());
+    }
 }
@@ -1,20 +1,49 @@
+use crate::lib::environment::create_agent;
 use crate::lib::error::DfxResult;
 use crate::lib::locations::canister_did_location;
+use crate::lib::metrics::{load_command_timings, render_command_timings, METRICS};
 use crate::lib::models::canister_id_store::CanisterIdStore;
 use crate::lib::network::network_descriptor::NetworkDescriptor;
-use crate::util::check_candid_file;
+use crate::lib::waiter::waiter_with_timeout;
+use crate::util::{check_candid_file, expiry_duration};
 
 use actix_cors::Cors;
-use actix_web::error::ErrorInternalServerError;
+use actix_web::error::{ErrorBadGateway, ErrorInternalServerError, ErrorServiceUnavailable};
 use actix_web::http::StatusCode;
-use actix_web::{http, middleware, web, App, Error, HttpResponse, HttpServer};
+use actix_web::{http, middleware, web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use anyhow::{anyhow, Context};
 use fn_error_context::context;
+use ic_agent::{Agent, Identity, Signature};
+use ic_types::Principal;
+use ic_utils::interfaces::ManagementCanister;
 use serde::Deserialize;
-use slog::{info, Logger};
+use slog::{debug, info, Logger};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the webserver refreshes the canister gauges served at `/_/metrics`.
+const CANISTER_STATUS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Headers that only apply to a single connection, and the ones the proxy sets itself.
+const UNFORWARDED_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+fn is_forwarded_header(name: &str) -> bool {
+    !UNFORWARDED_HEADERS.contains(&name)
+}
 
 struct CandidData {
     pub build_output_root: PathBuf,
@@ -75,16 +104,277 @@ async fn candid(
     Ok(response)
 }
 
+/// What the webserver of `dfx start` needs to forward requests to the local replica
+/// and to report metrics at `/_/metrics`.
+pub struct ReplicaProxyConfig {
+    /// The file the replica (or the emulator) writes its port to.
+    pub replica_port_path: PathBuf,
+
+    /// The durations of dfx commands recorded by `lib::metrics::record_command_duration`.
+    pub command_timings_path: PathBuf,
+
+    /// Polls the cycle balances and memory sizes of the project's canisters in the
+    /// background with this identity, if set.
+    pub canister_status_identity: Option<SharedIdentity>,
+}
+
+/// An identity that can be handed to several agents, as the canister status poller
+/// creates a new agent whenever the replica comes back on another port.
+#[derive(Clone)]
+pub struct SharedIdentity(Arc<dyn Identity + Send + Sync>);
+
+impl SharedIdentity {
+    pub fn new(identity: Box<dyn Identity + Send + Sync>) -> Self {
+        SharedIdentity(Arc::from(identity))
+    }
+}
+
+impl Identity for SharedIdentity {
+    fn sender(&self) -> Result<Principal, String> {
+        self.0.sender()
+    }
+
+    fn sign(&self, blob: &[u8]) -> Result<Signature, String> {
+        self.0.sign(blob)
+    }
+}
+
+/// Reads the port the replica writes to its port file.
+async fn read_replica_port(replica_port_path: &Path) -> Option<u16> {
+    tokio::fs::read_to_string(replica_port_path)
+        .await
+        .ok()
+        .and_then(|port| port.trim().parse::<u16>().ok())
+}
+
+struct ReplicaProxyData {
+    config: ReplicaProxyConfig,
+    client: reqwest::Client,
+    /// The port of the replica, read once and read again after the replica stops answering on it.
+    replica_port: Mutex<Option<u16>>,
+}
+
+impl ReplicaProxyData {
+    async fn replica_port(&self) -> Option<u16> {
+        if let Some(port) = *self.replica_port.lock().unwrap() {
+            return Some(port);
+        }
+        let port = read_replica_port(&self.config.replica_port_path).await?;
+        *self.replica_port.lock().unwrap() = Some(port);
+        Some(port)
+    }
+
+    /// Forgets the port, unless it was already replaced by a newer one.
+    fn forget_replica_port(&self, port: u16) {
+        let mut cached = self.replica_port.lock().unwrap();
+        if *cached == Some(port) {
+            *cached = None;
+        }
+    }
+}
+
+/// The canister and the kind of request (call, query, read_state, status) of an `/api` path.
+fn request_kind(path: &str) -> (String, String) {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["api", _, "canister", canister, kind] => (canister.to_string(), kind.to_string()),
+        ["api", _, kind] => (String::new(), kind.to_string()),
+        _ => (String::new(), "other".to_string()),
+    }
+}
+
+/// Forwards a request of icx-proxy to the replica, recording its count and latency.
+async fn forward_to_replica(
+    request: HttpRequest,
+    body: web::Bytes,
+    data: web::Data<ReplicaProxyData>,
+) -> Result<HttpResponse, Error> {
+    let (canister, kind) = request_kind(request.path());
+    let method = reqwest::Method::from_bytes(request.method().as_str().as_bytes())
+        .map_err(ErrorInternalServerError)?;
+    let forward = |port: u16| {
+        let url = format!("http://localhost:{}{}", port, request.uri());
+        let mut forwarded = data
+            .client
+            .request(method.clone(), &url)
+            .body(body.to_vec());
+        for (name, value) in request.headers() {
+            if is_forwarded_header(name.as_str()) {
+                forwarded = forwarded.header(name.as_str(), value.as_bytes());
+            }
+        }
+        forwarded.send()
+    };
+    let replica_not_running = || ErrorServiceUnavailable(anyhow!("The replica is not running."));
+
+    let start = Instant::now();
+    let port = data.replica_port().await.ok_or_else(replica_not_running)?;
+    let response = match forward(port).await {
+        // The replica may have been restarted on another port.
+        Err(err) if err.is_connect() => {
+            data.forget_replica_port(port);
+            let port = data.replica_port().await.ok_or_else(replica_not_running)?;
+            forward(port).await
+        }
+        response => response,
+    }
+    .map_err(ErrorBadGateway)?;
+    let status = response.status().as_u16();
+    let headers: Vec<(String, Vec<u8>)> = response
+        .headers()
+        .iter()
+        .filter(|(name, _)| is_forwarded_header(name.as_str()))
+        .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+        .collect();
+    let content = response.bytes().await.map_err(ErrorBadGateway)?;
+
+    let status_label = status.to_string();
+    METRICS.inc_counter(
+        "dfx_replica_requests_total",
+        "Requests forwarded to the replica, by canister and kind of request.",
+        &[
+            ("canister", &canister),
+            ("kind", &kind),
+            ("status", &status_label),
+        ],
+    );
+    METRICS.observe_duration(
+        "dfx_replica_request_duration_seconds",
+        "Latency of requests forwarded to the replica, by canister and kind of request.",
+        &[("canister", &canister), ("kind", &kind)],
+        start.elapsed(),
+    );
+
+    let mut builder =
+        HttpResponse::build(StatusCode::from_u16(status).map_err(ErrorInternalServerError)?);
+    for (name, value) in headers {
+        builder.append_header((name, value));
+    }
+    Ok(builder.body(content))
+}
+
+/// Keeps the cycle balance and memory size gauges of all canisters of the network up to date,
+/// so that `/_/metrics` never waits for the replica. The poller talks to the replica directly,
+/// so that its requests don't show up in the metrics of forwarded requests.
+async fn poll_canister_status(
+    logger: Logger,
+    identity: SharedIdentity,
+    replica_port_path: PathBuf,
+    network_descriptor: NetworkDescriptor,
+) {
+    let mut agent: Option<(u16, Agent)> = None;
+    let mut root_key_fetched = false;
+    loop {
+        if let Some(port) = read_replica_port(&replica_port_path).await {
+            if agent.as_ref().map(|(agent_port, _)| *agent_port) != Some(port) {
+                agent = create_agent(
+                    logger.clone(),
+                    &format!("http://localhost:{}", port),
+                    Box::new(identity.clone()),
+                    expiry_duration(),
+                    None,
+                )
+                .map(|agent| (port, agent));
+                root_key_fetched = network_descriptor.is_ic;
+            }
+        }
+        if let Some((_, agent)) = &agent {
+            if !root_key_fetched {
+                // The replica may not be up yet, in which case we try again next time.
+                root_key_fetched = agent.fetch_root_key().await.is_ok();
+            }
+            if root_key_fetched {
+                update_canister_gauges(&logger, agent, &network_descriptor).await;
+            }
+        }
+        tokio::time::sleep(CANISTER_STATUS_POLL_INTERVAL).await;
+    }
+}
+
+/// Updates the cycle balance and memory size gauges of all canisters of the network.
+async fn update_canister_gauges(
+    logger: &Logger,
+    agent: &Agent,
+    network_descriptor: &NetworkDescriptor,
+) {
+    let store = match CanisterIdStore::for_network(network_descriptor) {
+        Ok(store) => store,
+        Err(_) => return,
+    };
+    let mgr = ManagementCanister::create(agent);
+    for (canister_name, canister_id) in store.get_all() {
+        let status = mgr
+            .canister_status(&canister_id)
+            .call_and_wait(waiter_with_timeout(Duration::from_secs(5)))
+            .await;
+        match status {
+            Err(err) => debug!(
+                logger,
+                "Failed to get the status of canister {}: {}", canister_name, err
+            ),
+            Ok((status,)) => {
+                let canister_id = canister_id.to_text();
+                let labels = [
+                    ("canister", canister_name.as_str()),
+                    ("canister_id", canister_id.as_str()),
+                ];
+                if let Ok(cycles) = status.cycles.to_string().parse::<f64>() {
+                    METRICS.set_gauge(
+                        "dfx_canister_cycles",
+                        "Cycle balance of the canister.",
+                        &labels,
+                        cycles,
+                    );
+                }
+                if let Ok(memory_size) = status.memory_size.to_string().parse::<f64>() {
+                    METRICS.set_gauge(
+                        "dfx_canister_memory_size_bytes",
+                        "Memory size of the canister.",
+                        &labels,
+                        memory_size,
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn metrics(data: web::Data<ReplicaProxyData>) -> HttpResponse {
+    let mut content = METRICS.render();
+    content.push_str(&render_command_timings(&load_command_timings(
+        &data.config.command_timings_path,
+    )));
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(content)
+}
+
 /// Run the webserver in another thread.
+/// With a `replica_proxy`, it also forwards `/api` requests to the replica and serves `/_/metrics`.
 #[context("Failed to run webserver.")]
 pub fn run_webserver(
     logger: Logger,
     build_output_root: PathBuf,
     network_descriptor: NetworkDescriptor,
     bind: SocketAddr,
+    replica_proxy: Option<ReplicaProxyConfig>,
 ) -> DfxResult {
     const SHUTDOWN_WAIT_TIME: u64 = 60;
     info!(logger, "binding to: {:?}", bind);
+    let canister_status_poller = replica_proxy.as_ref().and_then(|config| {
+        config
+            .canister_status_identity
+            .clone()
+            .map(|identity| (identity, config.replica_port_path.clone()))
+    });
+    let poller_network_descriptor = network_descriptor.clone();
+    let replica_proxy_data = replica_proxy.map(|config| {
+        web::Data::new(ReplicaProxyData {
+            config,
+            client: reqwest::Client::new(),
+            replica_port: Mutex::new(None),
+        })
+    });
     let candid_data = web::Data::new(CandidData {
         build_output_root,
         network_descriptor,
@@ -92,8 +382,14 @@ pub fn run_webserver(
 
     let handler =
         HttpServer::new(move || {
-            App::new()
-                .app_data(candid_data.clone())
+            let mut app = App::new();
+            if let Some(replica_proxy_data) = &replica_proxy_data {
+                app = app
+                    .app_data(replica_proxy_data.clone())
+                    .service(web::resource("/_/metrics").route(web::get().to(metrics)))
+                    .service(web::resource("/api/{tail:.*}").to(forward_to_replica));
+            }
+            app.app_data(candid_data.clone())
                 .wrap(
                     Cors::default()
                         .allowed_methods(vec!["POST"])
@@ -114,8 +410,16 @@ pub fn run_webserver(
         // N.B. This is an arbitrary timeout for now.
         .shutdown_timeout(SHUTDOWN_WAIT_TIME)
         .run();
-    thread::spawn(|| {
-        actix::run(async {
+    thread::spawn(move || {
+        actix::run(async move {
+            if let Some((identity, replica_port_path)) = canister_status_poller {
+                actix::spawn(poll_canister_status(
+                    logger,
+                    identity,
+                    replica_port_path,
+                    poller_network_descriptor,
+                ));
+            }
             handler.await.unwrap();
         })
     });
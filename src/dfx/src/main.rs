@@ -1,7 +1,13 @@
-use crate::config::{dfx_version, dfx_version_str};
+use crate::config::{cache, dfx_version, dfx_version_str};
 use crate::lib::environment::{Environment, EnvironmentImpl};
+use crate::lib::error::DfxResult;
 use crate::lib::logger::{create_root_logger, LoggingMode};
+use crate::lib::toolchain::{
+    install_matching_version, parse_version_requirement, select_version, Toolchain,
+    VersionSelection, VersionSource,
+};
 
+use anyhow::{bail, Context};
 use clap::Parser;
 use semver::Version;
 use std::path::PathBuf;
@@ -31,6 +37,20 @@ pub struct CliOpts {
     #[clap(long)]
     identity: Option<String>,
 
+    /// Records every request to the replica, and its response, into this directory.
+    /// Use `dfx replay` to send the recorded requests again.
+    #[clap(long)]
+    record: Option<PathBuf>,
+
+    /// Installs the version of dfx that the project asks for without prompting, if it is missing.
+    #[clap(long)]
+    install_dfx: bool,
+
+    /// Installs the version of dfx that the project asks for without verifying the release
+    /// manifest and the download.
+    #[clap(long)]
+    insecure: bool,
+
     #[clap(subcommand)]
     command: commands::Command,
 }
@@ -50,7 +70,17 @@ fn is_warning_disabled(warning: &str) -> bool {
 ///
 /// Note: the right return type for communicating this would be [Option<!>], but since the
 /// never type is experimental, we just assert on the calling site.
-fn maybe_redirect_dfx(version: &Version) -> Option<()> {
+fn maybe_redirect_dfx(
+    selection: &VersionSelection,
+    install_dfx: bool,
+    insecure: bool,
+) -> Option<()> {
+    // No installed version matches the range in dfx.json, so one has to be installed first.
+    let version = match &selection.version {
+        Some(version) => version.clone(),
+        None => install_selected_version_or_exit(selection, install_dfx, insecure),
+    };
+    let version = &version;
     // Verify we're using the same version as the dfx.json, and if not just redirect the
     // call to the cache.
     if dfx_version() != version {
@@ -60,17 +90,28 @@ fn maybe_redirect_dfx(version: &Version) -> Option<()> {
                 concat!(
                     "Warning: The version of DFX used ({}) is different than the version ",
                     "being run ({}).\n",
-                    "This might happen because your dfx.json specifies an older version, or ",
-                    "DFX_VERSION is set in your environment.\n",
-                    "We are forwarding the command line to the old version. To disable this ",
+                    "It was selected because {}.\n",
+                    "We are forwarding the command line to that version. To disable this ",
                     "warning, set the DFX_WARNING=-version_check environment variable.\n"
                 ),
                 version,
-                dfx_version()
+                dfx_version(),
+                selection.reason
             );
+            if selection.source == VersionSource::Project && dfx_version() > version {
+                eprintln!(
+                    "Warning: You are running dfx {}, which is newer than the version this project is pinned to.\n",
+                    dfx_version()
+                );
+            }
+        }
+
+        if !cache::is_version_installed(&version.to_string()).unwrap_or(false) {
+            install_selected_version_or_exit(selection, install_dfx, insecure);
         }
 
-        match crate::config::cache::call_cached_dfx(version) {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match cache::call_cached_dfx(version, &strip_dfx_only_args(&args)) {
             Ok(status) => std::process::exit(status.code().unwrap_or(0)),
             Err(e) => {
                 eprintln!("Error when trying to forward to project dfx:\n{:?}", e);
@@ -80,9 +121,120 @@ fn maybe_redirect_dfx(version: &Version) -> Option<()> {
         };
     }
 
+    // DFX_VERSION overrides the dfx field of dfx.json, so the project may run on a version it
+    // does not expect.
+    if let (VersionSource::Environment, Some(requirement)) =
+        (&selection.source, &selection.project_requirement)
+    {
+        let matches = parse_version_requirement(requirement)
+            .map(|req| req.matches(version))
+            .unwrap_or(false);
+        if !matches && !is_warning_disabled("version_check") {
+            eprintln!(
+                concat!(
+                    "Warning: This project is pinned to dfx {}, but dfx {} is running because ",
+                    "DFX_VERSION is set. To disable this warning, set the ",
+                    "DFX_WARNING=-version_check environment variable.\n"
+                ),
+                requirement, version
+            );
+        }
+    }
+
     None
 }
 
+/// Global options that older versions of dfx may not know, with whether they take a value.
+const DFX_ONLY_GLOBAL_OPTIONS: &[(&str, bool)] = &[
+    ("--record", true),
+    ("--install-dfx", false),
+    ("--insecure", false),
+];
+
+/// Global options that take a value, which has to be skipped to find the subcommand.
+const GLOBAL_OPTIONS_WITH_VALUE: &[&str] = &["--log", "--logfile", "--identity", "--record"];
+
+/// Removes the global options that only this version of dfx knows from the arguments
+/// forwarded to another version. Arguments of the subcommand are forwarded as they are.
+fn strip_dfx_only_args(args: &[String]) -> Vec<String> {
+    let mut forwarded = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            // The subcommand: everything from here on belongs to it.
+            forwarded.push(arg.clone());
+            forwarded.extend(args.cloned());
+            break;
+        }
+        let name = arg.split('=').next().unwrap_or(arg);
+        let separate_value = !arg.contains('=');
+        if let Some((_, takes_value)) = DFX_ONLY_GLOBAL_OPTIONS.iter().find(|(o, _)| *o == name) {
+            if *takes_value && separate_value {
+                args.next();
+            }
+            continue;
+        }
+        forwarded.push(arg.clone());
+        if GLOBAL_OPTIONS_WITH_VALUE.contains(&name) && separate_value {
+            forwarded.extend(args.next().cloned());
+        }
+    }
+    forwarded
+}
+
+/// Installs the version of dfx selected for the current directory through a toolchain,
+/// after asking the user unless `install_dfx` is set, and returns it. Only then is the
+/// release manifest fetched, verified unless `insecure` is set.
+fn install_selected_version(
+    selection: &VersionSelection,
+    install_dfx: bool,
+    insecure: bool,
+) -> DfxResult<Version> {
+    let requirement = selection.project_requirement.as_deref().unwrap_or_default();
+    let wanted = match &selection.version {
+        Some(version) => version.to_string(),
+        None => requirement.to_string(),
+    };
+    if !install_dfx {
+        // Not using dialoguer because it doesn't support non terminal env like bats e2e
+        eprintln!(
+            "dfx {} is not installed. It was selected because {}.\nInstall it now? [y/N]",
+            wanted, selection.reason
+        );
+        let mut input = String::new();
+        std::io::stdin()
+            .read_line(&mut input)
+            .context("Failed to read stdin.")?;
+        if !["y", "yes"].contains(&input.to_lowercase().trim()) {
+            bail!(
+                "dfx {0} is not installed. Install it with `dfx toolchain install {0}`, or pass --install-dfx to install it automatically.",
+                wanted
+            );
+        }
+    }
+    match &selection.version {
+        Some(version) => {
+            Toolchain::CompleteVersion(version.clone()).update(insecure)?;
+            Ok(version.clone())
+        }
+        None => install_matching_version(requirement, insecure),
+    }
+}
+
+fn install_selected_version_or_exit(
+    selection: &VersionSelection,
+    install_dfx: bool,
+    insecure: bool,
+) -> Version {
+    match install_selected_version(selection, install_dfx, insecure) {
+        Ok(version) => version,
+        Err(e) => {
+            eprintln!("Error when trying to install project dfx:\n{:?}", e);
+            std::process::exit(1)
+        }
+    }
+}
+
 /// Setup a logger with the proper configuration, based on arguments.
 /// Returns a topple of whether or not to have a progress bar, and a logger.
 fn setup_logging(opts: &CliOpts) -> (bool, slog::Logger) {
@@ -103,27 +255,35 @@ fn main() {
     let cli_opts = CliOpts::parse();
     let (progress_bar, log) = setup_logging(&cli_opts);
     let identity = cli_opts.identity;
+    let record_dir = cli_opts.record;
+    let install_dfx = cli_opts.install_dfx;
+    let insecure = cli_opts.insecure;
     let command = cli_opts.command;
-    let result = match EnvironmentImpl::new() {
-        Ok(env) => {
-            maybe_redirect_dfx(env.get_version()).map_or((), |_| unreachable!());
-            match EnvironmentImpl::new().map(|env| {
-                env.with_logger(log)
-                    .with_progress_bar(progress_bar)
-                    .with_identity_override(identity)
-            }) {
-                Ok(env) => {
-                    slog::trace!(
-                        env.get_logger(),
-                        "Trace mode enabled. Lots of logs coming up."
-                    );
-                    commands::exec(&env, command)
-                }
-                Err(e) => Err(e),
-            }
+    let result = EnvironmentImpl::new().and_then(|env| {
+        // The toolchain and cache commands manage the versions of dfx, so they run on this one.
+        if !matches!(
+            command,
+            commands::Command::Toolchain(_) | commands::Command::Cache(_)
+        ) {
+            let selection = select_version(
+                env.get_config()
+                    .as_ref()
+                    .and_then(|c| c.get_config().get_dfx())
+                    .as_deref(),
+            )?;
+            maybe_redirect_dfx(&selection, install_dfx, insecure).map_or((), |_| unreachable!());
         }
-        Err(e) => Err(e),
-    };
+        let env = env
+            .with_logger(log)
+            .with_progress_bar(progress_bar)
+            .with_identity_override(identity)
+            .with_record_dir(record_dir);
+        slog::trace!(
+            env.get_logger(),
+            "Trace mode enabled. Lots of logs coming up."
+        );
+        commands::exec(&env, command)
+    });
     if let Err(err) = result {
         for (level, cause) in err.chain().enumerate() {
             if level == 0 {
@@ -139,3 +299,50 @@ fn main() {
         std::process::exit(255);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn strips_dfx_only_global_options() {
+        assert_eq!(
+            strip_dfx_only_args(&args(&[
+                "--record",
+                "recording",
+                "--identity",
+                "alice",
+                "--install-dfx",
+                "--insecure",
+                "deploy"
+            ])),
+            args(&["--identity", "alice", "deploy"])
+        );
+        assert_eq!(
+            strip_dfx_only_args(&args(&[
+                "-v",
+                "--record=recording",
+                "--log",
+                "tee",
+                "build"
+            ])),
+            args(&["-v", "--log", "tee", "build"])
+        );
+    }
+
+    #[test]
+    fn forwards_subcommand_args_as_they_are() {
+        assert_eq!(
+            strip_dfx_only_args(&args(&["replay", "--record", "recording", "--install-dfx"])),
+            args(&["replay", "--record", "recording", "--install-dfx"])
+        );
+        assert_eq!(
+            strip_dfx_only_args(&args(&["upgrade", "--insecure"])),
+            args(&["upgrade", "--insecure"])
+        );
+    }
+}
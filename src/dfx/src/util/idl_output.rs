@@ -0,0 +1,364 @@
+use crate::lib::error::DfxResult;
+
+use anyhow::bail;
+use candid::parser::typing::TypeEnv;
+use candid::parser::value::{IDLField, IDLValue, VariantValue};
+use candid::types::{Label, Type};
+use candid::IDLArgs;
+use serde_json::{Map, Number, Value};
+
+/// How `blob` (`vec nat8`) values are rendered in JSON output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobFormat {
+    Hex,
+    Base64,
+}
+
+impl BlobFormat {
+    pub fn from_name(name: Option<&str>) -> DfxResult<Self> {
+        match name.unwrap_or("hex") {
+            "hex" => Ok(BlobFormat::Hex),
+            "base64" => Ok(BlobFormat::Base64),
+            v => bail!("Invalid blob format: {}", v),
+        }
+    }
+
+    fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            BlobFormat::Hex => hex::encode(bytes),
+            BlobFormat::Base64 => base64::encode(bytes),
+        }
+    }
+}
+
+fn label_name(label: &Label) -> String {
+    match label {
+        Label::Named(name) => name.clone(),
+        Label::Id(id) | Label::Unnamed(id) => id.to_string(),
+    }
+}
+
+fn as_blob(values: &[IDLValue]) -> Option<Vec<u8>> {
+    values
+        .iter()
+        .map(|v| match v {
+            IDLValue::Nat8(b) => Some(*b),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The type of a value, if the method's types are known.
+type Typed<'a> = Option<(&'a TypeEnv, &'a Type)>;
+
+/// The type of the field with the given label in a record or variant type.
+fn field_type<'a>(ty: &'a Option<Type>, label: &Label) -> Option<&'a Type> {
+    match ty {
+        Some(Type::Record(fields)) | Some(Type::Variant(fields)) => fields
+            .iter()
+            .find(|f| f.id.get_id() == label.get_id())
+            .map(|f| &f.ty),
+        _ => None,
+    }
+}
+
+/// Converts decoded return values to JSON. A single return value is emitted as is,
+/// multiple return values as an array.
+/// With the return types of the method, empty blobs are told apart from empty vectors.
+pub fn idl_args_to_json(
+    args: &IDLArgs,
+    types: Option<(&TypeEnv, &[Type])>,
+    blob_format: BlobFormat,
+) -> Value {
+    let typed = |i: usize| types.and_then(|(env, types)| types.get(i).map(|t| (env, t)));
+    match args.args.as_slice() {
+        [value] => idl_value_to_json(value, typed(0), blob_format),
+        values => Value::Array(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| idl_value_to_json(v, typed(i), blob_format))
+                .collect(),
+        ),
+    }
+}
+
+/// Converts a Candid value to JSON.
+/// Values which do not fit into a JavaScript number (nat64, int64, nat, int) are emitted
+/// as strings, blobs are encoded according to `blob_format`, tuples become arrays and
+/// variants become single-key objects.
+pub fn idl_value_to_json(value: &IDLValue, ty: Typed, blob_format: BlobFormat) -> Value {
+    let env = ty.map(|(env, _)| env);
+    let ty = ty.and_then(|(env, ty)| env.trace_type(ty).ok());
+    match value {
+        IDLValue::Bool(b) => Value::Bool(*b),
+        IDLValue::Null | IDLValue::None | IDLValue::Reserved => Value::Null,
+        IDLValue::Text(s) => Value::String(s.clone()),
+        IDLValue::Number(n) => Value::String(n.clone()),
+        IDLValue::Float64(f) => Number::from_f64(*f).map_or(Value::Null, Value::Number),
+        IDLValue::Float32(f) => Number::from_f64(f64::from(*f)).map_or(Value::Null, Value::Number),
+        IDLValue::Opt(v) => {
+            let inner = match &ty {
+                Some(Type::Opt(t)) => Some(t.as_ref()),
+                _ => None,
+            };
+            idl_value_to_json(v, env.zip(inner), blob_format)
+        }
+        IDLValue::Vec(values) => {
+            let elem = match &ty {
+                Some(Type::Vec(t)) => Some(t.as_ref()),
+                _ => None,
+            };
+            let typed_blob = env
+                .zip(elem)
+                .and_then(|(env, t)| env.trace_type(t).ok())
+                .map_or(false, |t| matches!(t, Type::Nat8));
+            match as_blob(values) {
+                Some(bytes) if typed_blob || !bytes.is_empty() => {
+                    Value::String(blob_format.encode(&bytes))
+                }
+                _ => Value::Array(
+                    values
+                        .iter()
+                        .map(|v| idl_value_to_json(v, env.zip(elem), blob_format))
+                        .collect(),
+                ),
+            }
+        }
+        IDLValue::Record(fields) => {
+            if !fields.is_empty() && fields.iter().all(|f| matches!(f.id, Label::Unnamed(_))) {
+                Value::Array(
+                    fields
+                        .iter()
+                        .map(|f| {
+                            idl_value_to_json(&f.val, env.zip(field_type(&ty, &f.id)), blob_format)
+                        })
+                        .collect(),
+                )
+            } else {
+                Value::Object(fields_to_json(fields, env, &ty, blob_format))
+            }
+        }
+        IDLValue::Variant(VariantValue(field, _)) => Value::Object(fields_to_json(
+            std::slice::from_ref(field.as_ref()),
+            env,
+            &ty,
+            blob_format,
+        )),
+        IDLValue::Principal(p) | IDLValue::Service(p) => Value::String(p.to_text()),
+        IDLValue::Func(p, method) => {
+            let mut map = Map::new();
+            map.insert("principal".to_string(), Value::String(p.to_text()));
+            map.insert("method".to_string(), Value::String(method.clone()));
+            Value::Object(map)
+        }
+        IDLValue::Int(i) => Value::String(i.to_string()),
+        IDLValue::Nat(n) => Value::String(n.to_string()),
+        IDLValue::Nat64(n) => Value::String(n.to_string()),
+        IDLValue::Int64(i) => Value::String(i.to_string()),
+        IDLValue::Nat8(n) => Value::from(*n),
+        IDLValue::Nat16(n) => Value::from(*n),
+        IDLValue::Nat32(n) => Value::from(*n),
+        IDLValue::Int8(i) => Value::from(*i),
+        IDLValue::Int16(i) => Value::from(*i),
+        IDLValue::Int32(i) => Value::from(*i),
+    }
+}
+
+fn fields_to_json(
+    fields: &[IDLField],
+    env: Option<&TypeEnv>,
+    ty: &Option<Type>,
+    blob_format: BlobFormat,
+) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|f| {
+            let typed = env.zip(field_type(ty, &f.id));
+            (
+                label_name(&f.id),
+                idl_value_to_json(&f.val, typed, blob_format),
+            )
+        })
+        .collect()
+}
+
+/// Text displayed in a table cell: text and numbers without quotes or type annotations,
+/// everything else in Candid syntax.
+fn table_cell(value: &IDLValue) -> String {
+    match value {
+        IDLValue::Text(s) => s.clone(),
+        IDLValue::Principal(p) => p.to_text(),
+        IDLValue::Opt(v) => table_cell(v),
+        IDLValue::None | IDLValue::Null => String::new(),
+        IDLValue::Bool(b) => b.to_string(),
+        IDLValue::Number(n) => n.clone(),
+        IDLValue::Int(i) => i.to_string(),
+        IDLValue::Nat(n) => n.to_string(),
+        IDLValue::Nat64(n) => n.to_string(),
+        IDLValue::Nat32(n) => n.to_string(),
+        IDLValue::Nat16(n) => n.to_string(),
+        IDLValue::Nat8(n) => n.to_string(),
+        IDLValue::Int64(i) => i.to_string(),
+        IDLValue::Int32(i) => i.to_string(),
+        IDLValue::Int16(i) => i.to_string(),
+        IDLValue::Int8(i) => i.to_string(),
+        IDLValue::Float64(f) => f.to_string(),
+        IDLValue::Float32(f) => f.to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// Renders a vector of records (the only return value of the method) as a table,
+/// one row per record and one column per field.
+pub fn idl_args_to_table(args: &IDLArgs) -> DfxResult<String> {
+    let rows = match args.args.as_slice() {
+        [IDLValue::Vec(rows)] => rows,
+        _ => bail!("Table output requires the method to return a single vector of records."),
+    };
+
+    let mut columns: Vec<String> = vec![];
+    let mut cells: Vec<Vec<(String, String)>> = vec![];
+    for row in rows {
+        let fields = match row {
+            IDLValue::Record(fields) => fields,
+            _ => bail!("Table output requires the method to return a single vector of records."),
+        };
+        let mut row_cells = vec![];
+        for field in fields {
+            let name = label_name(&field.id);
+            if !columns.contains(&name) {
+                columns.push(name.clone());
+            }
+            row_cells.push((name, table_cell(&field.val)));
+        }
+        cells.push(row_cells);
+    }
+
+    let rows: Vec<Vec<String>> = cells
+        .into_iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|column| {
+                    row.iter()
+                        .find(|(name, _)| name == column)
+                        .map(|(_, cell)| cell.clone())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let format_row = |row: &[String]| {
+        row.iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = *width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![format_row(&columns)];
+    lines.push(
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("  "),
+    );
+    lines.extend(rows.iter().map(|row| format_row(row)));
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> IDLArgs {
+        args.parse::<IDLArgs>().unwrap()
+    }
+
+    #[test]
+    fn json_records_and_variants() {
+        let args =
+            parse(r#"(record { name = "alice"; balance = 12 : nat; state = variant { Active } })"#);
+        let json = idl_args_to_json(&args, None, BlobFormat::Hex);
+        assert_eq!(
+            json,
+            serde_json::json!({ "name": "alice", "balance": "12", "state": { "Active": null } })
+        );
+    }
+
+    #[test]
+    fn json_blobs_and_big_numbers() {
+        let args =
+            parse(r#"(vec { 1 : nat8; 255 : nat8 }, 18446744073709551615 : nat64, 7 : nat32)"#);
+        assert_eq!(
+            idl_args_to_json(&args, None, BlobFormat::Hex),
+            serde_json::json!(["01ff", "18446744073709551615", 7])
+        );
+        assert_eq!(
+            idl_args_to_json(&args, None, BlobFormat::Base64),
+            serde_json::json!(["Af8=", "18446744073709551615", 7])
+        );
+    }
+
+    #[test]
+    fn json_tuples_and_principals() {
+        let args = parse(r#"(record { principal "aaaaa-aa"; opt "x"; null })"#);
+        assert_eq!(
+            idl_args_to_json(&args, None, BlobFormat::Hex),
+            serde_json::json!(["aaaaa-aa", "x", null])
+        );
+    }
+
+    #[test]
+    fn json_empty_blob_with_types() {
+        let env = TypeEnv::new();
+        let blob = Type::Vec(Box::new(Type::Nat8));
+        let vec = Type::Vec(Box::new(Type::Nat32));
+        let args = parse("(vec {}, vec {})");
+        assert_eq!(
+            idl_args_to_json(&args, Some((&env, &[blob.clone(), vec])), BlobFormat::Hex),
+            serde_json::json!(["", []])
+        );
+        let args = parse("(opt vec {})");
+        let opt_blob = Type::Opt(Box::new(blob));
+        assert_eq!(
+            idl_args_to_json(&args, Some((&env, &[opt_blob])), BlobFormat::Base64),
+            serde_json::json!("")
+        );
+        // Without types, an empty vector cannot be told apart from an empty blob.
+        assert_eq!(
+            idl_args_to_json(&parse("(vec {})"), None, BlobFormat::Hex),
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn table_of_records() {
+        let args = parse(
+            r#"(vec { record { name = "alice"; age = 30 : nat8 }; record { name = "bob"; age = 4 : nat8 } })"#,
+        );
+        // Columns follow the order of the field ids, and "age" hashes lower than "name".
+        assert_eq!(
+            idl_args_to_table(&args).unwrap(),
+            "age  name\n---  -----\n30   alice\n4    bob"
+        );
+        assert!(idl_args_to_table(&parse("(42 : nat)")).is_err());
+    }
+}
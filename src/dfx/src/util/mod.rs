@@ -1,10 +1,10 @@
 use crate::lib::error::DfxResult;
 use crate::{error_invalid_argument, error_invalid_data, error_unknown};
 
-use anyhow::Context;
-use candid::parser::typing::{pretty_check_file, TypeEnv};
+use anyhow::{bail, Context};
+use candid::parser::typing::{check_prog, pretty_check_file, TypeEnv};
 use candid::types::{Function, Type};
-use candid::{parser::value::IDLValue, IDLArgs};
+use candid::{parser::value::IDLValue, IDLArgs, IDLProg};
 use fn_error_context::context;
 use net2::TcpListenerExt;
 use net2::{unix::UnixTcpBuilderExt, TcpBuilder};
@@ -14,6 +14,7 @@ use std::time::Duration;
 pub mod assets;
 pub mod clap;
 pub mod currency_conversion;
+pub mod idl_output;
 
 // The user can pass in port "0" to dfx start or dfx bootstrap i.e. "127.0.0.1:0" or "[::1]:0",
 // thus, we need to recreate SocketAddr with the kernel provided dynmically allocated port here.
@@ -52,11 +53,21 @@ pub fn network_to_pathcompat(network_name: &str) -> String {
     network_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
 }
 
+/// Check that `--blob-format` is only combined with `--output json`. Commands call this
+/// before sending anything, so that an update call is never made only to fail on output.
+pub fn check_blob_format(output_type: Option<&str>, blob_format: Option<&str>) -> DfxResult {
+    if blob_format.is_some() && output_type != Some("json") {
+        bail!("--blob-format can only be used with --output json.");
+    }
+    Ok(())
+}
+
 /// Deserialize and print return values from canister method.
 #[context("Failed to deserialize idl blob: Invalid data.")]
 pub fn print_idl_blob(
     blob: &[u8],
     output_type: Option<&str>,
+    blob_format: Option<&str>,
     method_type: &Option<(TypeEnv, Function)>,
 ) -> DfxResult<()> {
     let output_type = output_type.unwrap_or("pp");
@@ -65,7 +76,7 @@ pub fn print_idl_blob(
             let hex_string = hex::encode(blob);
             println!("{}", hex_string);
         }
-        "idl" | "pp" => {
+        "idl" | "pp" | "json" | "table" => {
             let result = match method_type {
                 None => candid::IDLArgs::from_bytes(blob),
                 Some((env, func)) => candid::IDLArgs::from_bytes_with_types(blob, env, &func.rets),
@@ -74,10 +85,22 @@ pub fn print_idl_blob(
                 let hex_string = hex::encode(blob);
                 eprintln!("Error deserializing blob 0x{}", hex_string);
             }
-            if output_type == "idl" {
-                println!("{:?}", result?);
-            } else {
-                println!("{}", result?);
+            match output_type {
+                "idl" => println!("{:?}", result?),
+                "json" => {
+                    let blob_format = idl_output::BlobFormat::from_name(blob_format)?;
+                    let types = method_type
+                        .as_ref()
+                        .map(|(env, func)| (env, func.rets.as_slice()));
+                    let json = idl_output::idl_args_to_json(&result?, types, blob_format);
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&json)
+                            .context("Failed to serialize JSON output.")?
+                    );
+                }
+                "table" => println!("{}", idl_output::idl_args_to_table(&result?)?),
+                _ => println!("{}", result?),
             }
         }
         v => return Err(error_unknown!("Invalid output type: {}", v)),
@@ -114,6 +137,16 @@ pub fn get_candid_type(
     Some((env, method))
 }
 
+/// Parse a Candid service description (e.g. the `candid:service` metadata of a
+/// deployed canister) into a TypeEnv. Like [get_candid_type], this is best effort
+/// and returns None if anything fails.
+pub fn get_candid_type_from_str(candid: &str, method_name: &str) -> Option<(TypeEnv, Function)> {
+    let (env, ty) = check_candid_str(candid).ok()?;
+    let actor = ty?;
+    let method = env.get_method(&actor, method_name).ok()?.clone();
+    Some((env, method))
+}
+
 pub fn get_candid_init_type(idl_path: &std::path::Path) -> Option<(TypeEnv, Function)> {
     let (env, ty) = check_candid_file(idl_path).ok()?;
     let actor = ty?;
@@ -139,6 +172,15 @@ pub fn check_candid_file(idl_path: &std::path::Path) -> DfxResult<(TypeEnv, Opti
     })
 }
 
+pub fn check_candid_str(candid: &str) -> DfxResult<(TypeEnv, Option<Type>)> {
+    let ast = candid
+        .parse::<IDLProg>()
+        .context("Failed to parse candid service description.")?;
+    let mut env = TypeEnv::new();
+    let actor = check_prog(&mut env, &ast).context("Candid service type check failed.")?;
+    Ok((env, actor))
+}
+
 #[context("Failed to create argument blob.")]
 pub fn blob_from_arguments(
     arguments: Option<&str>,